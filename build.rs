@@ -0,0 +1,213 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+// generates the opcode tables of gbasm.rs from src/opcodes.txt, which describes the format
+
+const DESCRIPTION: &str = "src/opcodes.txt";
+
+const REGS: [&str; 8] = ["b", "c", "d", "e", "h", "l", "[hl]", "a"];
+const ALU: [&str; 8] = ["add", "adc", "sub", "sbc", "and", "xor", "or", "cp"];
+const SHIFT: [&str; 8] = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+const COND: [&str; 4] = ["nz", "z", "nc", "c"];
+const RR: [&str; 4] = ["bc", "de", "hl", "sp"];
+const QQ: [&str; 4] = ["bc", "de", "hl", "af"];
+
+const KINDS: [(&str, &str); 7] =
+[
+    ("none", "None"),
+    ("imm", "Undefined"),
+    ("code", "Code"),
+    ("rel", "CodeRelative"),
+    ("data", "Data"),
+    ("hram", "DataHram"),
+    ("long", "LongOpcode"),
+];
+
+const FLAGS: [(&str, &str); 6] =
+[
+    ("jump", "OPCODE_FLAG_JUMP"),
+    ("call", "OPCODE_FLAG_CALL"),
+    ("cond", "OPCODE_FLAG_CONDITIONAL"),
+    ("write", "OPCODE_FLAG_WRITE_MEM"),
+    ("read", "OPCODE_FLAG_READ_MEM"),
+    ("invalid", "OPCODE_FLAG_INVALID"),
+];
+
+fn parse_opcodes(text: &str) -> Result<Vec<u8>, String>
+{
+    // `3E`, `40-7F` or `46-7E/8`, separated by commas
+
+    let hex = |text: &str| u8::from_str_radix(text.trim(), 16).map_err(|_| format!("bad opcode `{}`", text.trim()));
+    let mut result = vec![];
+
+    for part in text.split(',')
+    {
+        let (range, step) = match part.split_once('/')
+        {
+            Some((range, step)) => (range, step.trim().parse::<usize>().map_err(|_| format!("bad step `{}`", step.trim()))?),
+            None => (part, 1),
+        };
+
+        let (beg, end) = match range.split_once('-')
+        {
+            Some((beg, end)) => (hex(beg)?, hex(end)?),
+            None => (hex(range)?, hex(range)?),
+        };
+
+        if end < beg || step == 0 {
+            return Err(format!("bad range `{}`", part.trim())); }
+
+        result.extend((beg ..= end).step_by(step));
+    }
+
+    Ok(result)
+}
+
+fn expand_format(fmt: &str, opcode: u8) -> Result<String, String>
+{
+    let (lo, hi) = ((opcode & 7) as usize, ((opcode >> 3) & 7) as usize);
+
+    let mut result = fmt.to_string();
+
+    for (placeholder, value) in
+    [
+        ("{lo}", REGS[lo].to_string()),
+        ("{hi}", REGS[hi].to_string()),
+        ("{bit}", hi.to_string()),
+        ("{alu}", ALU[hi].to_string()),
+        ("{shift}", SHIFT[hi].to_string()),
+        ("{cond}", COND[hi & 3].to_string()),
+        ("{rr}", RR[hi >> 1].to_string()),
+        ("{qq}", QQ[hi >> 1].to_string()),
+        ("{rst}", format!("${:X}", hi * 8)),
+    ] {
+        result = result.replace(placeholder, &value); }
+
+    match result.contains('{')
+    {
+        true => Err(format!("unknown placeholder in `{}`", fmt)),
+        false => Ok(result),
+    }
+}
+
+fn parse_entry(columns: &[&str], opcode: u8) -> Result<String, String>
+{
+    // the `opi(...)` for one opcode
+
+    let column = |i: usize| columns.get(i).copied().unwrap_or("");
+
+    let fmt = expand_format(column(2), opcode)?;
+
+    let (cycles_taken, cycles) = match column(1).split_once('/')
+    {
+        Some((taken, not_taken)) => (taken, not_taken),
+        None => (column(1), column(1)),
+    };
+
+    let cycles_taken: u8 = cycles_taken.trim().parse().map_err(|_| format!("bad cycles `{}`", column(1)))?;
+    let cycles: u8 = cycles.trim().parse().map_err(|_| format!("bad cycles `{}`", column(1)))?;
+
+    let (kind, len) = match column(3)
+    {
+        "" => ("none", "0"),
+        operand => operand.split_once(':').ok_or_else(|| format!("bad operand `{}`", operand))?,
+    };
+
+    let kind = KINDS.iter().find(|(name, _)| *name == kind).ok_or_else(|| format!("unknown operand kind `{}`", kind))?.1;
+    let len: u8 = len.parse().map_err(|_| format!("bad operand length `{}`", len))?;
+
+    let mut flags = vec![];
+
+    for word in column(4).split_whitespace() {
+        flags.push(FLAGS.iter().find(|(name, _)| *name == word).ok_or_else(|| format!("unknown flag `{}`", word))?.1); }
+
+    let flags = match flags.is_empty()
+    {
+        true => "0".to_string(),
+        false => flags.join(" | "),
+    };
+
+    Ok(match cycles_taken == cycles
+    {
+        true => format!("opi({:?}, {}, OperandKind::{}, {}, {})", fmt, len, kind, flags, cycles),
+        false => format!("opi_cond({:?}, {}, OperandKind::{}, {}, {}, {})", fmt, len, kind, flags, cycles_taken, cycles),
+    })
+}
+
+fn generate(description: &str) -> Result<String, String>
+{
+    let mut tables: Vec<(String, BTreeMap<u8, String>)> = vec![];
+
+    for (i, line) in description.lines().enumerate()
+    {
+        let at = |message: String| format!("{}:{}: {}", DESCRIPTION, i + 1, message);
+
+        let line = line.split('#').next().unwrap_or("").trim();
+
+        if line.is_empty() {
+            continue; }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']'))
+        {
+            tables.push((name.to_string(), BTreeMap::new()));
+            continue;
+        }
+
+        let table = match tables.last_mut()
+        {
+            Some((_, table)) => table,
+            None => return Err(at("opcodes before the first table".to_string())),
+        };
+
+        let columns: Vec<&str> = line.split('|').map(str::trim).collect();
+
+        if columns.len() < 3 || columns.len() > 5 {
+            return Err(at(format!("expected 3 to 5 columns, got {}", columns.len()))); }
+
+        for opcode in parse_opcodes(columns[0]).map_err(at)? {
+            table.insert(opcode, parse_entry(&columns, opcode).map_err(at)?); }
+    }
+
+    let mut result = String::new();
+
+    for (name, table) in tables
+    {
+        if let Some(missing) = (0 ..= 0xFF).find(|opcode| !table.contains_key(opcode)) {
+            return Err(format!("{}: opcode {:02X} of {} isn't given", DESCRIPTION, missing, name)); }
+
+        writeln!(result, "const {}: [OpcodeInfo; 0x100] =\n[", name).unwrap();
+
+        for (opcode, entry) in table {
+            writeln!(result, "    /* {:02X} */ {},", opcode, entry).unwrap(); }
+
+        writeln!(result, "];\n").unwrap();
+    }
+
+    Ok(result)
+}
+
+fn main()
+{
+    println!("cargo:rerun-if-changed={}", DESCRIPTION);
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let description = std::fs::read_to_string(DESCRIPTION).expect("couldn't read the opcode description");
+
+    match generate(&description)
+    {
+        Ok(tables) =>
+        {
+            let out_dir = std::env::var("OUT_DIR").unwrap();
+            std::fs::write(Path::new(&out_dir).join("opcodes.rs"), tables).expect("couldn't write the opcode tables");
+        }
+
+        Err(message) => panic!("{}", message),
+    }
+}
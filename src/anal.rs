@@ -8,6 +8,22 @@ use super::util;
 use super::xaddr::prelude::*;
 use super::gbasm;
 use super::tags;
+use super::regs;
+use super::mbc::{Mbc, BankSelect};
+use super::gbs::Gbs;
+use super::memmap::{self, Region};
+use super::jumptable::{self, JumpTable, Dispatch};
+use super::inline::{self, InlineArgs, InlineData};
+use super::progress;
+
+use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+const OPCODE_HALT: u8 = 0x76;
+const OPCODE_DI: u8 = 0xF3;
+const OPCODE_EI: u8 = 0xFB;
+const OPCODE_RETI: u8 = 0xD9;
 
 #[derive(Clone, Copy, Debug)]
 pub struct RomInfo
@@ -15,16 +31,241 @@ pub struct RomInfo
     pub big_rom: bool,
     pub cgb_ram: bool,
     pub sram_count: usize,
+    pub mbc: Mbc,
+    pub stop_padding: bool, // `stop` is followed by a padding byte, unless tagged otherwise
+    pub boot_rom: bool,     // a boot rom image mapped at $0000, not a cartridge
+    pub org: Option<XAddr>, // a headerless image loaded there (an overlay, an extracted bank, a ram dump), not a cartridge
+    pub gbs: Option<Gbs>,   // a sound rip, mapped as its player does
+}
+
+#[cfg(test)]
+impl RomInfo
+{
+    pub fn for_test(big_rom: bool, mbc: Mbc) -> Self
+    {
+        // a plain cartridge, without sram
+        RomInfo { big_rom: big_rom, cgb_ram: false, sram_count: 0, mbc: mbc, stop_padding: true, boot_rom: false, org: None, gbs: None }
+    }
 }
 
 #[derive(Debug)]
 pub struct AnalInfo<'a>
 {
     pub rom: &'a [u8],
+    pub sram: &'a [u8], // dump of cartridge ram, for code copied there
+    pub regions: &'a [Region], // extra hardware, from a memory map file
+    pub excluded_banks: &'a [u16], // rom banks known to hold no code
     pub rom_info: RomInfo,
     pub tags: &'a [(XAddr, tags::Tag)],
 }
 
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct BankCtx
+{
+    pub romb: Option<u16>,
+    pub ramb: Option<u16>,
+    pub srmb: Option<u16>,
+    pub vramb: Option<u16>,
+    // MBC1 banking mode 1, where $0000-$3FFF shows the bank selected by the upper rom bank bits
+    pub mode1: bool,
+}
+
+impl BankCtx
+{
+    pub fn with_bank(mut self, kind: tags::BankKind, bank: u16) -> Self
+    {
+        *self.bank_mut(kind) = Some(bank);
+        self
+    }
+
+    pub fn bank(&self, kind: tags::BankKind) -> Option<u16>
+    {
+        match kind
+        {
+            tags::BankKind::Rom => self.romb,
+            tags::BankKind::Ram => self.ramb,
+            tags::BankKind::Srm => self.srmb,
+            tags::BankKind::Vram => self.vramb,
+        }
+    }
+
+    fn bank_mut(&mut self, kind: tags::BankKind) -> &mut Option<u16>
+    {
+        match kind
+        {
+            tags::BankKind::Rom => &mut self.romb,
+            tags::BankKind::Ram => &mut self.ramb,
+            tags::BankKind::Srm => &mut self.srmb,
+            tags::BankKind::Vram => &mut self.vramb,
+        }
+    }
+}
+
+const BANK_KINDS: [tags::BankKind; 4] = [tags::BankKind::Rom, tags::BankKind::Ram, tags::BankKind::Srm, tags::BankKind::Vram];
+
+const REG_VBK: u16 = 0xFF4F;
+
+// most distinct bank states an address is analysed with
+const MAX_BANK_VARIANTS: usize = 4;
+
+#[derive(Clone, Default, PartialEq, Debug)]
+struct BankContexts
+{
+    // bank state known on entry to code addresses, propagated along calls, jumps and fallthrough
+    known: HashMap<XAddr, BankCtx>,
+    // from .entrybank tags, these are never overridden
+    fixed: HashSet<XAddr>,
+    // addresses reached with different banks selected, where that bank is left unknown
+    conflicts: HashSet<(XAddr, tags::BankKind)>,
+    // every distinct bank state an address is reached with, analysed one by one where they conflict
+    variants: HashMap<XAddr, Vec<BankCtx>>,
+}
+
+impl BankContexts
+{
+    fn from_tags(info: &AnalInfo) -> Self
+    {
+        let mut result = Self::default();
+
+        for (xa, tag) in info.tags
+        {
+            if let tags::Tag::EntryBank(kind, bank) = tag
+            {
+                let ctx = result.known.entry(*xa).or_default();
+                *ctx = ctx.with_bank(*kind, *bank);
+
+                result.fixed.insert(*xa);
+            }
+        }
+
+        result
+    }
+
+    fn get(&self, xa: &XAddr) -> BankCtx
+    {
+        self.known.get(xa).copied().unwrap_or_default()
+    }
+
+    fn variants(&self, xa: &XAddr) -> &[BankCtx]
+    {
+        // only where they disagree, otherwise the known state says it all
+
+        match self.variants.get(xa)
+        {
+            Some(variants) if variants.len() > 1 => variants,
+            _ => &[],
+        }
+    }
+
+    fn merge(&mut self, xa: XAddr, ctx: BankCtx)
+    {
+        use log::info;
+
+        if self.fixed.contains(&xa) {
+            return; }
+
+        // code in the switchable area always knows its own rom bank
+        let variant = if xa.addr >= 0x4000 { BankCtx { romb: None, ..ctx } } else { ctx };
+
+        if variant != BankCtx::default()
+        {
+            let variants = self.variants.entry(xa).or_default();
+
+            if !variants.contains(&variant) && variants.len() < MAX_BANK_VARIANTS {
+                variants.push(variant); }
+        }
+
+        for kind in BANK_KINDS.iter().copied()
+        {
+            let bank = match ctx.bank(kind)
+            {
+                Some(bank) => bank,
+                None => continue,
+            };
+
+            // code in the switchable area always knows its own rom bank
+            if kind == tags::BankKind::Rom && xa.addr >= 0x4000 {
+                continue; }
+
+            if self.conflicts.contains(&(xa, kind)) {
+                continue; }
+
+            let current = self.known.entry(xa).or_default().bank_mut(kind);
+
+            match *current
+            {
+                None => *current = Some(bank),
+                Some(prev) if prev == bank => {}
+
+                Some(prev) =>
+                {
+                    info!("{} is reached with both {:?} bank {} and {} selected, leaving it unknown", xa, kind, prev, bank);
+
+                    *current = None;
+                    self.conflicts.insert((xa, kind));
+                }
+            }
+        }
+
+        // reached in mode 1 at all is enough for $0000-$3FFF not to be assumed to be bank 0
+        if ctx.mode1 {
+            self.known.entry(xa).or_default().mode1 = true; }
+    }
+}
+
+// how much a code block can be trusted to really be code, from least to most
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Confidence
+{
+    Speculative, // linear sweep
+    Heuristic,   // only reached through guesses (computed jumps, detected jump tables, plausible vectors)
+    Flow,        // reached through plain control flow
+    Tagged,      // seeded from a tag or the command line
+}
+
+#[derive(Error, Debug)]
+#[error("Unknown confidence level `{0}` (expected `speculative`, `heuristic`, `flow` or `tagged`)")]
+pub struct ParseConfidenceError(String);
+
+impl std::str::FromStr for Confidence
+{
+    type Err = ParseConfidenceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "speculative" => Ok(Confidence::Speculative),
+            "heuristic" => Ok(Confidence::Heuristic),
+            "flow" => Ok(Confidence::Flow),
+            "tagged" => Ok(Confidence::Tagged),
+            _ => Err(ParseConfidenceError(s.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for Confidence
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        match self
+        {
+            Confidence::Speculative => write!(f, "speculative"),
+            Confidence::Heuristic => write!(f, "heuristic"),
+            Confidence::Flow => write!(f, "flow"),
+            Confidence::Tagged => write!(f, "tagged"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct CodeBlock
+{
+    pub xa: XAddr,
+    pub len: usize,
+    pub ctx: BankCtx, // bank state on entry
+}
+
 #[derive(Debug)]
 pub enum RomSliceError
 {
@@ -38,20 +279,61 @@ impl<'a> AnalInfo<'a>
 {
     pub fn new(rom_info: RomInfo, rom: &'a [u8], tags: &'a [(XAddr, tags::Tag)]) -> Self
     {
-        assert_eq!(rom.len() % 0x4000, 0);
+        assert!(rom_info.boot_rom || rom_info.org.is_some() || rom.len().is_multiple_of(0x4000));
 
         Self
         {
             rom: rom,
+            sram: &[],
+            regions: &[],
+            excluded_banks: &[],
             rom_info: rom_info,
             tags: tags,
         }
     }
 
+    pub fn with_sram(mut self, sram: &'a [u8]) -> Self
+    {
+        self.sram = sram;
+        self
+    }
+
+    pub fn with_regions(mut self, regions: &'a [Region]) -> Self
+    {
+        self.regions = regions;
+        self
+    }
+
+    pub fn with_excluded_banks(mut self, excluded_banks: &'a [u16]) -> Self
+    {
+        self.excluded_banks = excluded_banks;
+        self
+    }
+
+    pub fn is_excluded(&self, xa: XAddr) -> bool
+    {
+        xa.addr < 0x8000 && self.excluded_banks.contains(&xa.bank)
+    }
+
     pub fn rom_slice(&self, xa: XAddr, len: usize) -> Result<&[u8], RomSliceError>
     {
         use std::cmp;
 
+        if let Some(org) = self.rom_info.org
+        {
+            // nothing else is there
+
+            let off = match xa.addr.checked_sub(org.addr)
+            {
+                Some(off) if xa.bank == org.bank && off as usize <= self.rom.len() => off as usize,
+                _ => return Err(RomSliceError::NonRomAddr),
+            };
+
+            let end = cmp::min(off + len, self.rom.len());
+
+            return Ok(&self.rom[off .. end]);
+        }
+
         match xa.addr
         {
             0x0000 ..= 0x3FFF =>
@@ -61,20 +343,15 @@ impl<'a> AnalInfo<'a>
                     return Err(RomSliceError::BankedRomAddr);
                 }
 
-                if self.rom_info.big_rom
-                {
-                    let off = xa.addr as usize;
-                    let end = cmp::min(off + len, 0x4000);
+                let off = xa.addr as usize;
+                let end = cmp::min(off + len, if self.rom_info.big_rom { 0x4000 } else { self.rom.len() });
 
-                    Ok(&self.rom[off .. end])
-                }
-                else
+                if off > self.rom.len()
                 {
-                    let off = xa.addr as usize;
-                    let end = cmp::min(off + len, self.rom.len());
-
-                    Ok(&self.rom[off .. end])
+                    return Err(RomSliceError::BankTooHigh);
                 }
+
+                Ok(&self.rom[off .. cmp::min(end, self.rom.len())])
             }
 
             0x4000 ..= 0x7FFF =>
@@ -89,6 +366,11 @@ impl<'a> AnalInfo<'a>
                     let off = xa.addr as usize;
                     let end = cmp::min(off + len, self.rom.len());
 
+                    if off > self.rom.len()
+                    {
+                        return Err(RomSliceError::BankTooHigh);
+                    }
+
                     Ok(&self.rom[off .. end])
                 }
                 else
@@ -111,22 +393,131 @@ impl<'a> AnalInfo<'a>
                 }
             }
 
+            // not rom, but what the sram dump has there is just as good
+            0xA000 ..= 0xBFFF =>
+            {
+                let bnk = 0x2000 * (xa.bank as usize);
+                let off = xa.addr as usize - 0xA000;
+                let end = cmp::min(off + len, 0x2000);
+
+                if bnk + off >= self.sram.len()
+                {
+                    return Err(RomSliceError::NonRomAddr);
+                }
+
+                Ok(&self.sram[bnk + off .. cmp::min(bnk + end, self.sram.len())])
+            }
+
             _ => Err(RomSliceError::NonRomAddr)
         }
     }
 
+    pub fn stop_padding_at(&self, xa: XAddr) -> bool
+    {
+        // whether a `stop` at xa is followed by a padding byte
+
+        tags::get_tags_at(self.tags, &xa).iter().rev().find_map(|(_, tag)| match tag
+        {
+            tags::Tag::StopPadding(padding) => Some(*padding),
+            _ => None,
+        }).unwrap_or(self.rom_info.stop_padding)
+    }
+
+    pub fn rom_offset(&self, xa: XAddr) -> Option<usize>
+    {
+        // file offset of a rom address (bank n maps $4000-$7FFF to n * $4000)
+
+        self.rom_slice(xa, 0).ok()?;
+
+        match self.rom_info.org
+        {
+            Some(org) => Some((xa.addr - org.addr) as usize),
+            None if (0xA000 .. 0xC000).contains(&xa.addr) => None,
+            None => Some((xa.bank as usize).saturating_sub(1) * 0x4000 + xa.addr as usize),
+        }
+    }
+
+    pub fn expand_addr(&self, ctx: &BankCtx, addr: u16) -> Option<XAddr>
+    {
+        // extra hardware is there whatever the banks
+        if memmap::find_region(self.regions, addr).is_some() {
+            return Some(XAddr::new(0, addr)); }
+
+        match addr
+        {
+            // MBC1 mode 1 maps bank $20, $40 or $60 there, which has no address of its own
+            // (code running from there can only assume its own bank is still mapped when the bank isn't known)
+            0x0000 ..= 0x3FFF if ctx.mode1 && ctx.romb.is_some_and(|b| (b & 0x60) != 0) && self.rom_bank_count() > 0x20 =>
+                return None,
+
+            // bank numbers wrap around past the rom's last address line, banks missing from the image stay unknown
+            0x4000 ..= 0x7FFF if self.rom_info.big_rom && self.rom_info.org.is_none() =>
+            {
+                let bank_count = self.rom_bank_count();
+
+                return ctx.romb.map(|b| self.rom_info.mbc.mapped_rom_bank(b) & (bank_count.next_power_of_two() - 1) as u16)
+                    .filter(|b| (*b as usize) < bank_count)
+                    .map(|b| XAddr::new(b, addr));
+            }
+
+            0x4000 ..= 0x7FFF =>
+                if self.rom_info.big_rom {
+                    return ctx.romb.map(|b| XAddr::new(self.rom_info.mbc.mapped_rom_bank(b), addr)); },
+
+            // MBC2's built-in ram is 512 half-bytes, echoed all over the area
+            0xA000 ..= 0xBFFF if self.rom_info.mbc == Mbc::Mbc2 =>
+                return Some(XAddr::new(0, 0xA000 | (addr & 0x01FF))),
+
+            // a single sram bank needs no tag to be known
+            0xA000 ..= 0xBFFF if self.rom_info.sram_count > 1 =>
+                return ctx.srmb.map(|b| XAddr::new(b, addr)),
+
+            // bank 1 only once it's known to be selected, most code never leaves bank 0
+            0x8000 ..= 0x9FFF if self.rom_info.cgb_ram =>
+                return Some(XAddr::new(ctx.vramb.unwrap_or(0), addr)),
+
+            0xD000 ..= 0xDFFF =>
+                if self.rom_info.cgb_ram {
+                    return ctx.ramb.map(|b| XAddr::new(b, addr)); },
+
+            _ => {}
+        }
+
+        Some(XAddr::new(0, addr))
+    }
+
+    pub fn header_range(&self) -> Option<(u16, u16)>
+    {
+        // the cartridge header, or where cgb boot roms leave it visible (they skip $0100-$01FF)
+
+        if self.rom_info.org.is_some() {
+            return None; }
+
+        match (self.rom_info.boot_rom, self.rom.len())
+        {
+            (false, _) => Some((0x0104, 0x0150)),
+            (true, 0x0000 ..= 0x0100) => None,
+            (true, _) => Some((0x0100, 0x0200)),
+        }
+    }
+
     pub fn rom_bank_count(&self) -> usize
     {
         match self.rom_info.big_rom
         {
-            true => (self.rom.len() + 0x3FFF) / 0x4000,
-            false => 1
+            true if self.rom_info.org.is_none() => self.rom.len().div_ceil(0x4000),
+            _ => 1
         }
     }
 
     pub fn rom_bank_block(&self, bank: usize) -> (XAddr, usize)
     {
-        if self.rom_info.big_rom
+        if let Some(org) = self.rom_info.org
+        {
+            assert_eq!(bank, 0);
+            (org, self.rom.len())
+        }
+        else if self.rom_info.big_rom
         {
             assert!(bank < self.rom_bank_count());
             (XAddr::new(bank as u16, if bank == 0 { 0x0000 } else { 0x4000 }), 0x4000)
@@ -138,6 +529,14 @@ impl<'a> AnalInfo<'a>
         }
     }
 
+    pub fn sram_blocks(&self) -> Vec<(XAddr, usize)>
+    {
+        if self.rom_info.org.is_some() {
+            return vec![]; }
+
+        self.sram.chunks(0x2000).enumerate().map(|(bank, chunk)| (XAddr::new(bank as u16, 0xA000), chunk.len())).collect()
+    }
+
     pub fn rom_bank_blocks(&self) -> Vec<(XAddr, usize)>
     {
         let bank_count = self.rom_bank_count();
@@ -152,6 +551,40 @@ impl<'a> AnalInfo<'a>
     }
 }
 
+// the address an instruction refers to, in the bank it refers to
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Operand
+{
+    None,
+    Code(XAddr),          // where it jumps, calls or `rst`s to, computed jumps included
+    Data(XAddr),          // what it reads or writes, or the address it loads
+    UnresolvedCode(u16),  // the bank isn't known there
+    UnresolvedData(u16),
+}
+
+pub type ResolvedResult = Result<(gbasm::Instruction, Operand), gbasm::DecodeError>;
+
+impl Operand
+{
+    pub fn code(&self) -> Option<XAddr>
+    {
+        match self
+        {
+            Operand::Code(xa) => Some(*xa),
+            _ => None,
+        }
+    }
+
+    pub fn xaddr(&self) -> Option<XAddr>
+    {
+        match self
+        {
+            Operand::Code(xa) | Operand::Data(xa) => Some(*xa),
+            _ => None,
+        }
+    }
+}
+
 pub struct AnalEmu<'a>
 {
     info: &'a AnalInfo<'a>,
@@ -159,11 +592,23 @@ pub struct AnalEmu<'a>
     romb: Option<u16>,
     ramb: Option<u16>,
     srmb: Option<u16>,
+    vramb: Option<u16>,
+    mode1: bool,
+    regs: regs::RegState,
+    // last decoded instruction, whose effect on registers is applied when decoding the next one
+    pending: Option<gbasm::Instruction>,
+    // last decoded instruction is a `halt` tagged as hitting the halt bug
+    halt_bug: bool,
 }
 
 impl<'a> AnalEmu<'a>
 {
     pub fn with_bound(info: &'a AnalInfo, xa: XAddr, len: usize) -> Self
+    {
+        Self::with_ctx(info, xa, len, BankCtx::default())
+    }
+
+    pub fn with_ctx(info: &'a AnalInfo, xa: XAddr, len: usize, ctx: BankCtx) -> Self
     {
         let slice = match info.rom_slice(xa, len)
         {
@@ -175,9 +620,32 @@ impl<'a> AnalEmu<'a>
         {
             info: info,
             decoder: gbasm::decode_slice(xa, slice),
-            romb: if let 0x4000 ..= 0x7FFF = xa.addr { Some(xa.bank) } else { None },
-            ramb: None,
-            srmb: None,
+            // code running from a banked area knows its own bank
+            romb: if let 0x4000 ..= 0x7FFF = xa.addr { Some(xa.bank) } else { ctx.romb },
+            ramb: if let 0xD000 ..= 0xDFFF = xa.addr { Some(xa.bank) } else { ctx.ramb },
+            srmb: if let 0xA000 ..= 0xBFFF = xa.addr { Some(xa.bank) } else { ctx.srmb },
+            vramb: ctx.vramb,
+            mode1: ctx.mode1,
+            regs: regs::RegState::default(),
+            pending: None,
+            halt_bug: false,
+        }
+    }
+
+    pub fn for_block(info: &'a AnalInfo, block: &CodeBlock) -> Self
+    {
+        Self::with_ctx(info, block.xa, block.len, block.ctx)
+    }
+
+    pub fn ctx(&self) -> BankCtx
+    {
+        BankCtx
+        {
+            romb: self.romb,
+            ramb: self.ramb,
+            srmb: self.srmb,
+            vramb: self.vramb,
+            mode1: self.mode1,
         }
     }
 
@@ -188,62 +656,161 @@ impl<'a> AnalEmu<'a>
 
     pub fn expand_addr(&self, addr: u16) -> Option<XAddr>
     {
-        match addr
-        {
-            0x4000 ..= 0x7FFF =>
-                if self.info.rom_info.big_rom {
-                    return self.romb.map(|b| XAddr::new(b, addr)); },
+        self.info.expand_addr(&self.ctx(), addr)
+    }
 
-            0xA000 ..= 0xBFFF =>
-                return self.srmb.map(|b| XAddr::new(b, addr)),
+    pub fn site_ctx(&self, xa: XAddr) -> BankCtx
+    {
+        // bank state as seen by the jump/call at xa, including per-callsite overrides
 
-            0xD000 ..= 0xDFFF =>
-                if self.info.rom_info.cgb_ram {
-                    return self.ramb.map(|b| XAddr::new(b, addr)); },
+        let mut ctx = self.ctx();
 
-            _ => {}
+        for (_, tag) in tags::get_tags_at(self.info.tags, &xa)
+        {
+            if let tags::Tag::CallBank(kind, bank) = tag {
+                ctx = ctx.with_bank(*kind, *bank); }
         }
 
-        Some(XAddr::new(0, addr))
+        ctx
     }
-}
 
-impl<'a> Iterator for AnalEmu<'a>
-{
-    type Item = (XAddr, gbasm::DecodeResult);
+    pub fn expand_target(&self, xa: XAddr, addr: u16) -> Option<XAddr>
+    {
+        // code in $0000-$3FFF is always analysed as bank 0, even where MBC1 mode 1 could have mapped another bank there
 
-    fn next(&mut self) -> Option<(XAddr, gbasm::DecodeResult)>
+        self.info.expand_addr(&BankCtx { mode1: false, ..self.site_ctx(xa) }, addr)
+    }
+
+    fn mbc_write(&mut self, addr: u16, value: u8)
     {
-        if let Some((xa, ins)) = self.decoder.next()
+        match self.info.rom_info.mbc.bank_select(addr, value, self.romb, self.info.rom_bank_count())
         {
-            for (_, tag) in tags::get_tags_at(self.info.tags, &xa)
-            {
-                match tag
-                {
-                    tags::Tag::RomBank(bank) => self.romb = Some(*bank),
-                    tags::Tag::RamBank(bank) => self.ramb = Some(*bank),
-                    tags::Tag::SrmBank(bank) => self.srmb = Some(*bank),
-                    _ => {}
-                }
-            }
+            Some(BankSelect::Rom(bank)) => self.romb = Some(bank),
+            Some(BankSelect::Mode(mode1)) => self.mode1 = mode1,
 
-            return Some((xa, ins));
+            // values past the SRAM bank count select other things (MBC1 upper ROM bits)
+            Some(BankSelect::Sram(bank)) if (bank as usize) < self.info.rom_info.sram_count => self.srmb = Some(bank),
+
+            _ => {}
         }
+    }
 
-        None
+    pub fn regs(&self) -> &regs::RegState
+    {
+        // register values as seen by the last decoded instruction
+        &self.regs
     }
-}
 
-fn scan_head_block(info: &AnalInfo, xa: XAddr, max_len: usize) -> Option<(XAddr, usize)>
-{
-    // returns the range corresponding to the head code block in input block
-    // a code block is a sequence of instructions ending at a flow intersection (either a jump or jump target)
-    // it is assumed that input block does not contain any jump targets/entry points beyond the very start of it
+    pub fn computed_target(&self, ins: &gbasm::Instruction) -> Option<u16>
+    {
+        // target of a `jp hl` or `push / ret` computed jump that was just decoded
+        self.regs.computed_target(ins)
+    }
+
+    pub fn target(&self, xa: XAddr, ins: &gbasm::Instruction) -> Option<XAddr>
+    {
+        // where the instruction just decoded at xa goes, computed jumps included, when that's known
+
+        ins.get_jump_target().or_else(|| self.computed_target(ins)).and_then(|addr| self.expand_target(xa, addr))
+    }
+
+    pub fn resolve(&self, xa: XAddr, ins: &gbasm::Instruction) -> Operand
+    {
+        // what the instruction just decoded at xa refers to: jump targets as seen by the jump, operands that are
+        // addresses (by their kind or an `.operand_addr` tag) with the banks selected there
+
+        if let Some(addr) = ins.get_jump_target().or_else(|| self.computed_target(ins)) {
+            return self.expand_target(xa, addr).map_or(Operand::UnresolvedCode(addr), Operand::Code); }
+
+        if ins.is_addr_operand() || tags::get_tags_at(self.info.tags, &xa).iter().any(|(_, tag)| matches!(tag, tags::Tag::OperandAddr)) {
+            return self.expand_addr(ins.operand).map_or(Operand::UnresolvedData(ins.operand), Operand::Data); }
+
+        Operand::None
+    }
+
+    pub fn next_resolved(&mut self) -> Option<(XAddr, ResolvedResult)>
+    {
+        // next, with what the instruction refers to
+
+        let (xa, ins) = self.next()?;
+        Some((xa, ins.map(|ins| (ins, self.resolve(xa, &ins)))))
+    }
+}
+
+impl<'a> Iterator for AnalEmu<'a>
+{
+    type Item = (XAddr, gbasm::DecodeResult);
+
+    fn next(&mut self) -> Option<(XAddr, gbasm::DecodeResult)>
+    {
+        if let Some(ins) = self.pending.take() {
+            self.regs.step(&ins); }
+
+        let halt_bug = std::mem::take(&mut self.halt_bug);
+        let stop_padding = self.info.stop_padding_at(self.decoder.position());
+
+        let decoded = self.decoder.next_with(|addr, slice|
+            if halt_bug { gbasm::decode_halt_bug(addr, slice) } else { gbasm::decode_stop(addr, slice, stop_padding) });
+
+        if let Some((xa, ins)) = decoded
+        {
+            let mut halt_bug = false;
+
+            for (_, tag) in tags::get_tags_at(self.info.tags, &xa)
+            {
+                match tag
+                {
+                    tags::Tag::RomBank(bank) => self.romb = Some(*bank),
+                    tags::Tag::RamBank(bank) => self.ramb = Some(*bank),
+                    tags::Tag::SrmBank(bank) => self.srmb = Some(*bank),
+                    tags::Tag::HaltBug => halt_bug = true,
+                    _ => {}
+                }
+            }
+
+            match ins
+            {
+                Ok(ins) =>
+                {
+                    self.halt_bug = halt_bug && ins.opcode == OPCODE_HALT && !ins.halt_bug;
+
+                    // `ld a, N / ld [$2000], a` (or through hl) switches banks so that banked references resolve without tags
+
+                    match self.regs.mem_write(&ins)
+                    {
+                        Some((REG_VBK, value)) => self.vramb = Some((value & 1) as u16),
+                        Some((addr, value)) => self.mbc_write(addr, value),
+                        None => {}
+                    }
+
+                    self.pending = Some(ins);
+                }
+
+                Err(_) => self.regs.forget(),
+            }
+
+            return Some((xa, ins));
+        }
+
+        None
+    }
+}
+
+fn scan_head_block(info: &AnalInfo, xa: XAddr, max_len: usize) -> Result<(XAddr, usize), XAddr>
+{
+    // returns the range corresponding to the head code block in input block, or where it fails to decode
+    // a code block is a sequence of instructions ending at a flow intersection (either a jump or jump target)
+    // it is assumed that input block does not contain any jump targets/entry points beyond the very start of it
+
+    // it also ends where fill starts (a run of `nop`s has no jump to end it), for the search to find it
 
     let mut offset = 0;
 
-    for (_, ins) in AnalEmu::with_bound(info, xa, max_len)
+    for (ins_xa, ins) in AnalEmu::with_bound(info, xa, max_len)
     {
+        if offset > 0 && padding_run(info, ins_xa, max_len - offset) >= MIN_CODE_PADDING_RUN {
+            return Ok((xa, offset)); }
+
         match ins
         {
             Ok(ins) =>
@@ -252,47 +819,78 @@ fn scan_head_block(info: &AnalInfo, xa: XAddr, max_len: usize) -> Option<(XAddr,
 
                 if (ins.info().flags & gbasm::OPCODE_FLAG_JUMP) != 0
                 {
-                    return Some((xa, offset));
+                    return Ok((xa, offset));
                 }
             }
 
             // this is pretty much the only time we accept bad decode
-            Err(_) => return None
+            Err(_) => return Err(ins_xa)
         }
     }
 
     // we reached the end of the scan range without encountering a jump
     // this would mean that this block flows into the next one
 
-    Some((xa, max_len))
+    Ok((xa, max_len))
+}
+
+// runs of the same single byte instruction at least this long are padding, not code
+const MIN_CODE_PADDING_RUN: usize = 16;
+
+fn padding_run(info: &AnalInfo, xa: XAddr, max_len: usize) -> usize
+{
+    // length of the run of identical single byte instructions at xa (`rst $38` for $FF fill, `nop` for $00)
+
+    let slice = info.rom_slice(xa, max_len).unwrap_or(&[]);
+
+    match slice.first()
+    {
+        Some(&byte) if gbasm::is_valid_opcode(byte) && gbasm::encoded_len(byte) == 1 => slice.iter().take_while(|b| **b == byte).count(),
+        _ => 0,
+    }
 }
 
-fn search_for_code(info: &AnalInfo, parent_blocks: &[(XAddr, usize)]) -> Vec<(XAddr, usize)>
+fn search_for_code(info: &AnalInfo, parent_blocks: &[CodeBlock], noreturn: &HashSet<XAddr>, padding: &mut Vec<(XAddr, usize)>, decode_failures: &mut Vec<XAddr>) -> Vec<CodeBlock>
 {
     let mut result = vec![];
 
-    for &(xstart, max_len) in parent_blocks
+    for &CodeBlock { xa: xstart, len: max_len, ctx } in parent_blocks
     {
         let mut offset = 0;
 
         'lop_scan:
         while offset < max_len
         {
-            let (xa, len) = match scan_head_block(&info, xstart + offset as u16, max_len - offset)
+            // code running into fill is most likely a misanalysis, and would otherwise go on for thousands of lines
+
+            let run = padding_run(info, xstart + offset as u16, max_len - offset);
+
+            if run >= MIN_CODE_PADDING_RUN
             {
-                Some(code_block) => code_block,
-                None => break 'lop_scan
+                padding.push((xstart + offset as u16, run));
+                break 'lop_scan;
+            }
+
+            let (xa, len) = match scan_head_block(info, xstart + offset as u16, max_len - offset)
+            {
+                Ok(code_block) => code_block,
+
+                Err(bad_xa) =>
+                {
+                    decode_failures.push(bad_xa);
+                    break 'lop_scan;
+                }
             };
 
-            result.push((xa, len));
+            result.push(CodeBlock { xa: xa, len: len, ctx: ctx });
 
             // scan for unconditional end instruction
             // if we find one, this is an end block
             // which means we shouldn't continue searching past it
 
-            let mut emu = AnalEmu::with_bound(info, xa, len);
+            let mut emu = AnalEmu::with_ctx(info, xa, len, ctx);
 
-            while let Some((_, Ok(ins))) = emu.next()
+            while let Some((ins_xa, Ok(ins))) = emu.next()
             {
                 let flags = ins.info().flags;
 
@@ -307,11 +905,10 @@ fn search_for_code(info: &AnalInfo, parent_blocks: &[(XAddr, usize)]) -> Vec<(XA
 
                     if (flags & gbasm::OPCODE_FLAG_CALL) != 0
                     {
-                        if let Some(xa) = ins.get_jump_target().map(|addr| emu.expand_addr(addr)).flatten()
+                        if let Some(target) = emu.target(ins_xa, &ins)
                         {
-                            for (_, tag) in tags::get_tags_at(info.tags, &xa) {
-                                if let tags::Tag::NoReturn = tag {
-                                    break 'lop_scan; } }
+                            if noreturn.contains(&target) {
+                                break 'lop_scan; }
                         }
                     }
                 }
@@ -324,20 +921,20 @@ fn search_for_code(info: &AnalInfo, parent_blocks: &[(XAddr, usize)]) -> Vec<(XA
     result
 }
 
-fn cut_blocks(info: &AnalInfo, points: &[XAddr]) -> Vec<(XAddr, usize)>
+fn cut_blocks(info: &AnalInfo, points: &[XAddr], contexts: &BankContexts) -> Vec<CodeBlock>
 {
     use superslice::*;
 
-    let bank_count = info.rom_bank_count();
     let mut result = Vec::with_capacity(points.len());
 
-    for i in 0 .. bank_count
-    {
-        let (bank_xa, bank_len) = info.rom_bank_block(i);
+    // code copied to sram is analysed from the dump, when there is one
 
+    for (bank_xa, bank_len) in info.rom_bank_blocks().into_iter().chain(info.sram_blocks())
+    {
+        // the end is past the region: a point there (wram right after the sram dump) isn't in it
         let (point_beg, point_end) = (
             points.lower_bound(&bank_xa),
-            points.upper_bound(&(bank_xa + bank_len as u16)));
+            points.lower_bound(&(bank_xa + bank_len as u16)));
 
         for j in point_beg .. point_end
         {
@@ -352,31 +949,58 @@ fn cut_blocks(info: &AnalInfo, points: &[XAddr]) -> Vec<(XAddr, usize)>
                 (points[j + 1].addr - xa.addr) as usize
             };
 
-            result.push((xa, len));
+            result.push(CodeBlock { xa: xa, len: len, ctx: contexts.get(&xa) });
         }
     }
 
     result
 }
 
-fn scan_xrefs(info: &AnalInfo, code_blocks: &[(XAddr, usize)]) -> Vec<XAddr>
+fn scan_xrefs(info: &AnalInfo, code_blocks: &[CodeBlock], contexts: &mut BankContexts) -> Vec<XAddr>
 {
     let mut result = vec![];
 
-    for &(xa, len) in code_blocks
+    for block in code_blocks
     {
-        let mut emu = AnalEmu::with_bound(info, xa, len);
+        let mut emu = AnalEmu::for_block(info, block);
+        let mut falls_through = true;
 
-        while let Some((_, Ok(ins))) = emu.next()
+        while let Some((xa, Ok(ins))) = emu.next()
         {
-            if let Some(addr) = ins.get_jump_target()
+            let flags = ins.info().flags;
+            falls_through = (flags & gbasm::OPCODE_FLAG_JUMP) == 0 || (flags & (gbasm::OPCODE_FLAG_CALL | gbasm::OPCODE_FLAG_CONDITIONAL)) != 0;
+
+            if let Some(target) = emu.target(xa, &ins)
             {
-                match emu.expand_addr(addr)
+                result.push(target);
+
+                // the bank state at the call site (including per-callsite overrides) carries over to the target
+                contexts.merge(target, emu.site_ctx(xa));
+            }
+        }
+
+        // and so does the bank state at the end of the block to the block that follows it
+
+        if falls_through {
+            contexts.merge(block.xa + block.len as u16, emu.ctx()); }
+
+        // code reached with different banks selected goes again with each of them, so what it refers to in every bank is found
+
+        for ctx in contexts.variants(&block.xa).to_vec()
+        {
+            let mut emu = AnalEmu::with_ctx(info, block.xa, block.len, ctx);
+
+            while let Some((xa, Ok(ins))) = emu.next()
+            {
+                if let Some(target) = emu.target(xa, &ins)
                 {
-                    Some(xa) => result.push(xa),
-                    None => (),
+                    result.push(target);
+                    contexts.merge(target, emu.site_ctx(xa));
                 }
             }
+
+            if falls_through {
+                contexts.merge(block.xa + block.len as u16, emu.ctx()); }
         }
     }
 
@@ -386,36 +1010,721 @@ fn scan_xrefs(info: &AnalInfo, code_blocks: &[(XAddr, usize)]) -> Vec<XAddr>
     result
 }
 
-pub fn anal(info: &AnalInfo, entry_points: &[XAddr]) -> Vec<(XAddr, usize)>
+const RST_VECTORS: &[u16] = &[0x0000, 0x0008, 0x0010, 0x0018, 0x0020, 0x0028, 0x0030, 0x0038];
+const INTERRUPT_VECTORS: &[u16] = &[0x0040, 0x0048, 0x0050, 0x0058, 0x0060];
+const INTERRUPT_HANDLER_NAMES: &[&str] = &["VBlankHandler", "StatHandler", "TimerHandler", "SerialHandler", "JoypadHandler"];
+
+// jumps followed from an interrupt vector to its handler at most
+const MAX_HANDLER_HOPS: usize = 4;
+
+pub fn is_plausible_vector(info: &AnalInfo, addr: u16) -> bool
+{
+    // unused vectors are usually padding ($00 or $FF fill)
+    // anything else that starts with a valid instruction is assumed to be code
+
+    let slice = match info.rom_slice(XAddr::new(0, addr), 8)
+    {
+        Ok(slice) => slice,
+        Err(_) => return false,
+    };
+
+    if slice.is_empty() || slice.iter().all(|&b| b == slice[0] && (b == 0x00 || b == 0xFF)) {
+        return false; }
+
+    gbasm::decode(addr, slice).is_ok()
+}
+
+fn flows_into(info: &AnalInfo, addr: u16, next_addr: u16) -> bool
+{
+    // whether code at addr can run into next_addr without an unconditional jump or return
+
+    for (xa, ins) in AnalEmu::with_bound(info, XAddr::new(0, addr), (next_addr - addr) as usize)
+    {
+        match ins
+        {
+            Ok(ins) =>
+            {
+                let flags = ins.info().flags;
+
+                if (flags & gbasm::OPCODE_FLAG_JUMP) != 0 && (flags & (gbasm::OPCODE_FLAG_CALL | gbasm::OPCODE_FLAG_CONDITIONAL)) == 0 {
+                    return false; }
+            }
+
+            // an instruction straddling the vector boundary
+            Err(_) => return xa.addr < next_addr,
+        }
+    }
+
+    true
+}
+
+pub fn default_entry_points(info: &AnalInfo) -> Vec<XAddr>
+{
+    // the header entry stub, and the rst and interrupt vectors that look like code
+    // a vector that the previous one runs into is a continuation of it, not an entry point: cutting a block there
+    // would split routines spread over several vectors (jump table dispatchers at rst $00 often are) in two
+
+    if info.rom_info.boot_rom {
+        return vec![XAddr::new(0, 0x0000)]; }
+
+    if let Some(org) = info.rom_info.org {
+        return vec![org]; }
+
+    if let Some(gbs) = info.rom_info.gbs {
+        return gbs_entry_points(info, &gbs).into_iter().map(|(xa, _)| xa).collect(); }
+
+    let mut result: Vec<XAddr> = vec![];
+
+    for &addr in RST_VECTORS.iter().chain(INTERRUPT_VECTORS)
+    {
+        if let Some(prev) = result.last()
+        {
+            if flows_into(info, prev.addr, addr) {
+                continue; }
+        }
+
+        if is_plausible_vector(info, addr) {
+            result.push(XAddr::new(0, addr)); }
+    }
+
+    result.push(XAddr::new(0, 0x0100));
+    result
+}
+
+fn trampoline_target(info: &AnalInfo, xa: XAddr, len: usize) -> Option<XAddr>
+{
+    // where code jumps right away, with nothing but plain instructions in the first len bytes before the jump
+
+    let mut emu = AnalEmu::new(info, xa);
+
+    while let Some((ins_xa, Ok(ins))) = emu.next()
+    {
+        if ins_xa.addr as usize >= xa.addr as usize + len {
+            return None; }
+
+        let flags = ins.info().flags;
+
+        if (flags & gbasm::OPCODE_FLAG_JUMP) == 0 {
+            continue; }
+
+        if (flags & (gbasm::OPCODE_FLAG_CALL | gbasm::OPCODE_FLAG_CONDITIONAL)) != 0 {
+            return None; }
+
+        return ins.get_jump_target().and_then(|addr| emu.expand_target(ins_xa, addr));
+    }
+
+    None
+}
+
+fn gbs_entry_points(info: &AnalInfo, gbs: &Gbs) -> Vec<(XAddr, &'static str)>
+{
+    // the player starts with bank 1 selected
+
+    let ctx = BankCtx::default().with_bank(tags::BankKind::Rom, 1);
+
+    [(gbs.init, "GbsInit"), (gbs.play, "GbsPlay")].iter()
+        .filter_map(|&(addr, name)| info.expand_addr(&ctx, addr).filter(|xa| info.rom_offset(*xa).is_some()).map(|xa| (xa, name))).collect()
+}
+
+pub fn find_interrupt_handlers(info: &AnalInfo) -> Vec<(XAddr, &'static str)>
+{
+    // the code each used interrupt vector ends up running, following the jumps vectors usually hold
+    // (for sound rips, what the player calls instead)
+
+    if let Some(gbs) = info.rom_info.gbs {
+        return gbs_entry_points(info, &gbs); }
+
+    let mut result = vec![];
+
+    for (&addr, &name) in INTERRUPT_VECTORS.iter().zip(INTERRUPT_HANDLER_NAMES)
+    {
+        if !is_plausible_vector(info, addr) || info.rom_slice(XAddr::new(0, addr), 1).map_or(true, |slice| slice[0] == OPCODE_RETI) {
+            continue; }
+
+        let mut xa = XAddr::new(0, addr);
+
+        // the vector slot may set things up before jumping, further jumps must be all there is
+        let mut len = 8;
+
+        for _ in 0 .. MAX_HANDLER_HOPS
+        {
+            match trampoline_target(info, xa, len)
+            {
+                Some(target) if info.rom_offset(target).is_some() => xa = target,
+                _ => break,
+            }
+
+            len = 1;
+        }
+
+        result.push((xa, name));
+    }
+
+    result
+}
+
+const MIN_SWEEP_INSTRUCTIONS: usize = 3;
+const MIN_PADDING_RUN: usize = 4;
+
+fn sweep_gap(info: &AnalInfo, xa: XAddr, len: usize, result: &mut Vec<CodeBlock>)
+{
+    let slice = info.rom_slice(xa, len).unwrap_or(&[]);
+    let mut pos = 0;
+
+    while pos < slice.len()
+    {
+        // skip padding: `rst $38` ($FF) hardly ever starts real code, `nop` ($00) runs don't either
+
+        let byte = slice[pos];
+        let run = slice[pos ..].iter().take_while(|b| **b == byte).count();
+
+        if byte == 0xFF || (byte == 0x00 && run >= MIN_PADDING_RUN)
+        {
+            pos += run;
+            continue;
+        }
+
+        // decode up to something that can't be code or the end of the flow
+
+        let start = pos;
+        let mut count = 0;
+
+        while pos < slice.len()
+        {
+            let ins = match gbasm::decode_stop(xa.addr + pos as u16, &slice[pos ..], info.stop_padding_at(xa + pos as u16))
+            {
+                Ok(ins) if ins.is_valid() => ins,
+                _ => break,
+            };
+
+            pos += ins.encoded_len();
+            count += 1;
+
+            let flags = ins.info().flags;
+
+            if (flags & gbasm::OPCODE_FLAG_JUMP) != 0 && (flags & (gbasm::OPCODE_FLAG_CALL | gbasm::OPCODE_FLAG_CONDITIONAL)) == 0 {
+                break; }
+        }
+
+        if count >= MIN_SWEEP_INSTRUCTIONS {
+            result.push(CodeBlock { xa: xa + start as u16, len: pos - start, ctx: BankCtx::default() }); }
+
+        if pos == start {
+            pos += 1; }
+    }
+}
+
+pub fn find_halts_after_di(info: &AnalInfo, code_blocks: &[CodeBlock]) -> Vec<XAddr>
+{
+    // `di / ... / halt` without `.haltbug` tags: on DMG, if an interrupt is already pending, the byte
+    // after the halt is read twice (only looks within blocks, which catches the usual idiom)
+
+    let mut result = vec![];
+
+    for block in code_blocks
+    {
+        let mut disabled = false;
+
+        for (xa, ins) in AnalEmu::for_block(info, block)
+        {
+            let ins = match ins { Ok(ins) => ins, Err(_) => break };
+
+            match ins.opcode
+            {
+                OPCODE_DI => disabled = true,
+                OPCODE_EI | OPCODE_RETI => disabled = false,
+
+                OPCODE_HALT if disabled && !tags::get_tags_at(info.tags, &xa).iter().any(|(_, tag)| matches!(tag, tags::Tag::HaltBug)) =>
+                    result.push(xa),
+
+                _ => {}
+            }
+        }
+    }
+
+    result
+}
+
+pub fn find_stray_rom_writes(info: &AnalInfo, code_blocks: &[CodeBlock]) -> Vec<(XAddr, u16)>
+{
+    // stores to rom that don't hit a register of the mbc: usually data decoded as code, or a bug in the game
+
+    let mut result = vec![];
+
+    for block in code_blocks
+    {
+        let mut emu = AnalEmu::for_block(info, block);
+
+        while let Some((xa, Ok(ins))) = emu.next()
+        {
+            let regs = emu.regs();
+
+            if let Some(addr) = regs.mem_write_addr(&ins).filter(|addr| *addr < 0x8000 && memmap::find_region(info.regions, *addr).is_none())
+            {
+                if info.rom_info.mbc.describe_write(addr, regs.mem_write(&ins).map(|(_, value)| value)).is_none() {
+                    result.push((xa, addr)); }
+            }
+        }
+    }
+
+    result
+}
+
+pub fn find_bank_loads(info: &AnalInfo, code_blocks: &[CodeBlock]) -> Vec<(XAddr, XAddr)>
+{
+    // `ld r, N` whose N is written to the rom bank register before jumping to banked code in bank N
+    // (the load and the code jumped to, only looks within blocks: calls end them)
+
+    let mut result = vec![];
+
+    for block in code_blocks
+    {
+        let mut emu = AnalEmu::for_block(info, block);
+
+        // where each register was last loaded with an immediate, and the last bank switch from one
+        let mut loads: [Option<XAddr>; 8] = [None; 8];
+        let mut switch: Option<(XAddr, u16)> = None;
+
+        while let Some((xa, Ok(ins))) = emu.next()
+        {
+            // MBC1's upper rom bank bits at $4000-$5FFF aren't a bank number on their own
+            if let Some((_, value)) = emu.regs().mem_write(&ins).filter(|(addr, value)| *addr < 0x4000 &&
+                matches!(info.rom_info.mbc.bank_select(*addr, *value, None, info.rom_bank_count()), Some(BankSelect::Rom(_))))
+            {
+                let load = match ins.opcode
+                {
+                    0x36 => Some(xa),
+                    0x70 ..= 0x75 => loads[(ins.opcode & 0x07) as usize],
+                    _ => loads[regs::REG_A as usize],
+                };
+
+                switch = load.map(|load| (load, value as u16));
+            }
+
+            if let Some((load, bank)) = switch
+            {
+                let target = ins.get_jump_target().and_then(|addr| emu.expand_target(xa, addr));
+
+                if let Some(target) = target.filter(|target| target.bank == bank && target.addr >= 0x4000 && target.addr < 0x8000)
+                {
+                    result.push((load, target));
+                    switch = None;
+                }
+            }
+
+            let (_, written) = regs::reg_effects(&ins);
+
+            for (reg, load) in loads.iter_mut().enumerate()
+            {
+                if (written & 1 << reg) != 0 {
+                    *load = None; }
+            }
+
+            if (ins.opcode & 0xC7) == 0x06 && ins.opcode != 0x36 {
+                loads[(ins.opcode >> 3) as usize] = Some(xa); }
+        }
+    }
+
+    result
+}
+
+pub fn uncovered_ranges(info: &AnalInfo, covered: &[(XAddr, usize)]) -> Vec<(XAddr, usize)>
+{
+    // what's left of the rom outside of the covered ranges (which are sorted) and the cartridge header
+
+    let mut result = vec![];
+
+    for (bank_xa, bank_len) in info.rom_bank_blocks()
+    {
+        let bank_end = bank_xa.addr as usize + bank_len;
+        let mut pos = bank_xa.addr as usize;
+
+        let mut push_gap = |beg: usize, end: usize|
+        {
+            for (gap_beg, gap_end) in split_header(info, beg, end, bank_xa.bank)
+            {
+                if gap_end > gap_beg {
+                    result.push((XAddr::new(bank_xa.bank, gap_beg as u16), gap_end - gap_beg)); }
+            }
+        };
+
+        for &(xa, len) in covered.iter().filter(|(xa, _)| xa.bank == bank_xa.bank && (xa.addr as usize) < bank_end)
+        {
+            push_gap(pos, xa.addr as usize);
+            pos = pos.max(xa.addr as usize + len);
+        }
+
+        push_gap(pos, bank_end);
+    }
+
+    result
+}
+
+pub fn linear_sweep(info: &AnalInfo, covered: &[(XAddr, usize)]) -> Vec<CodeBlock>
+{
+    // speculatively disassembles what analysis never reached,
+    // in runs of at least a few instructions ending at invalid opcodes, padding or unconditional jumps
+
+    let mut result = vec![];
+
+    for (xa, len) in uncovered_ranges(info, covered) {
+        sweep_gap(info, xa, len, &mut result); }
+
+    result
+}
+
+fn split_header(info: &AnalInfo, beg: usize, end: usize, bank: u16) -> Vec<(usize, usize)>
+{
+    // removes the cartridge header ($0104-$014F) from a range
+
+    let header = match info.header_range()
+    {
+        Some((header_beg, header_end)) => (header_beg as usize, header_end as usize),
+        None => return vec![(beg, end)],
+    };
+
+    if bank != 0 || end <= header.0 || beg >= header.1 {
+        return vec![(beg, end)]; }
+
+    vec![(beg, header.0.max(beg)), (header.1.min(end), end)]
+}
+
+fn scan_jump_tables(info: &AnalInfo, code_blocks: &[CodeBlock], points: &[XAddr], noreturn: &mut HashSet<XAddr>) -> Vec<JumpTable>
+{
+    // tagged tables, tables found from dispatch code, and inline tables following calls to dispatchers
+
+    let mut result = vec![];
+    let mut table_xas = vec![];
+    let mut dispatchers = HashSet::new();
+
+    for (xa, tag) in info.tags
+    {
+        if let tags::Tag::JumpTable(count) = tag {
+            table_xas.push((*xa, BankCtx::default(), *count)); }
+    }
+
+    for block in code_blocks
+    {
+        match jumptable::match_dispatch(info, block)
+        {
+            Some(Dispatch::Table(xa)) => table_xas.push((xa, block.ctx, None)),
+            Some(Dispatch::Inline) => { dispatchers.insert(block.xa); }
+            None => {}
+        }
+    }
+
+    // calls may reach the dispatcher through a vector jump
+
+    for block in code_blocks
+    {
+        if let Some(target) = jumptable::follow_jump(info, block)
+        {
+            if dispatchers.contains(&target) {
+                dispatchers.insert(block.xa); }
+        }
+    }
+
+    for block in code_blocks
+    {
+        let mut emu = AnalEmu::for_block(info, block);
+
+        while let Some((xa, Ok(ins))) = emu.next()
+        {
+            if (ins.info().flags & gbasm::OPCODE_FLAG_CALL) == 0 {
+                continue; }
+
+            if let Some(target) = emu.target(xa, &ins)
+            {
+                if dispatchers.contains(&target) {
+                    table_xas.push((xa + ins.encoded_len() as u16, emu.ctx(), None)); }
+            }
+        }
+    }
+
+    table_xas.sort_by_key(|t| t.0);
+    table_xas.dedup_by_key(|t| t.0);
+
+    let stops: Vec<XAddr> = util::sorted_merge(points, &table_xas.iter().map(|t| t.0).collect::<Vec<_>>());
+
+    for (xa, ctx, count) in table_xas
+    {
+        if info.rom_slice(xa, 2).is_ok() {
+            result.push(jumptable::read_table(info, xa, ctx, count, &stops)); }
+    }
+
+    noreturn.extend(dispatchers);
+
+    result
+}
+
+fn scan_inline_data(info: &AnalInfo, code_blocks: &[CodeBlock], noreturn: &mut HashSet<XAddr>, contexts: &mut BankContexts) -> Vec<InlineData>
+{
+    // calls to callees reading data that follows the call (text printers, script runners):
+    // the data is left out of the code, which resumes after it
+
+    let mut callees: HashMap<XAddr, InlineArgs> = HashMap::new();
+
+    for block in code_blocks
+    {
+        if let Some(args) = inline::match_callee(info, block.xa, block.ctx) {
+            callees.insert(block.xa, args); }
+    }
+
+    // calls may reach the callee through a vector jump
+
+    for block in code_blocks
+    {
+        if let Some(args) = jumptable::follow_jump(info, block).and_then(|target| callees.get(&target).copied()) {
+            callees.insert(block.xa, args); }
+    }
+
+    let mut result = vec![];
+
+    for block in code_blocks
+    {
+        let mut emu = AnalEmu::for_block(info, block);
+
+        while let Some((xa, Ok(ins))) = emu.next()
+        {
+            if (ins.info().flags & gbasm::OPCODE_FLAG_CALL) == 0 {
+                continue; }
+
+            let callee = match emu.target(xa, &ins)
+            {
+                Some(callee) => callee,
+                None => continue,
+            };
+
+            if let Some(args) = callees.get(&callee)
+            {
+                let data_xa = xa + ins.encoded_len() as u16;
+
+                if let Some(len) = args.len_at(info, data_xa)
+                {
+                    contexts.merge(data_xa + len as u16, emu.ctx());
+                    result.push(InlineData { xa: data_xa, len: len, callee: callee });
+                }
+            }
+        }
+    }
+
+    noreturn.extend(callees.keys());
+
+    result.sort_by_key(|data| data.xa);
+    result.dedup_by_key(|data| data.xa);
+
+    result
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Analysis
+{
+    pub code_blocks: Vec<CodeBlock>,
+    pub jump_tables: Vec<JumpTable>,
+    pub noreturn: HashSet<XAddr>,
+    pub padding: Vec<(XAddr, usize)>, // padding runs reached as code, left out
+    pub decode_failures: Vec<XAddr>, // invalid opcodes (or the end of the rom) reached as code
+    pub inline_data: Vec<InlineData>,
+    pub bank_variants: HashMap<XAddr, Vec<BankCtx>>, // bank states of code reached with conflicting ones
+}
+
+pub fn anal(info: &AnalInfo, entry_points: &[XAddr]) -> Analysis
 {
     use log::info;
 
-    let mut points = entry_points.to_vec();
+    // nothing is analysed in excluded banks, whatever seems to lead there
+    let entry_points: Vec<XAddr> = entry_points.iter().copied().filter(|xa| !info.is_excluded(*xa)).collect();
+
+    let mut points = entry_points.clone();
     points.dedup();
 
+    let mut contexts = BankContexts::from_tags(info);
+
+    // data tags end any code flowing into them
+
+    let tag_data_points: Vec<XAddr> = info.tags.iter().filter_map(|(xa, tag)|
+    {
+        match tag
+        {
+            tags::Tag::Data | tags::Tag::Compressed { .. } | tags::Tag::Script(_) => Some(*xa),
+            _ => None,
+        }
+    }).collect();
+
+    // cgb boot roms leave the cartridge header mapped in the middle of them
+
+    let mut tag_data_points = tag_data_points;
+
+    if let (true, Some((header_beg, _))) = (info.rom_info.boot_rom, info.header_range())
+    {
+        tag_data_points.push(XAddr::new(0, header_beg));
+        tag_data_points.sort();
+    }
+
+    let mut data_points = tag_data_points.clone();
+
+    let mut noreturn: HashSet<XAddr> = info.tags.iter().filter_map(|(xa, tag)|
+        if let tags::Tag::NoReturn = tag { Some(*xa) } else { None }).collect();
+
     let mut lop_count = 0;
 
     loop
     {
         lop_count += 1;
         info!("start analysis cycle #{}: {} analysis point(s)", lop_count, points.len());
+        progress::status(|| format!("analysis cycle #{}: {} analysis point(s)", lop_count, points.len()));
+
+        let mut cut_points = util::sorted_merge(&points, &data_points);
+        cut_points.dedup();
 
-        let cut_blocks = cut_blocks(info, &points);
-        let code_blocks = search_for_code(info, &cut_blocks);
+        let mut cut_blocks = cut_blocks(info, &cut_points, &contexts);
+        cut_blocks.retain(|block| data_points.binary_search(&block.xa).is_err());
+
+        let mut padding = vec![];
+        let mut decode_failures = vec![];
+        let code_blocks = search_for_code(info, &cut_blocks, &noreturn, &mut padding, &mut decode_failures);
+        progress::status(|| format!("analysis cycle #{}: {} analysis point(s), {} code block(s)", lop_count, points.len(), code_blocks.len()));
         let prev_points = points;
+        let prev_contexts = contexts.clone();
+        let prev_noreturn_count = noreturn.len();
+
+        let mut code_xrefs = scan_xrefs(info, &code_blocks, &mut contexts);
+        let jump_tables = scan_jump_tables(info, &code_blocks, &prev_points, &mut noreturn);
+        let inline_data = scan_inline_data(info, &code_blocks, &mut noreturn, &mut contexts);
+
+        code_xrefs.extend(inline_data.iter().map(|data| data.xa + data.len as u16));
+
+        for table in &jump_tables
+        {
+            code_xrefs.extend(table.code_targets());
+
+            for target in table.code_targets() {
+                contexts.merge(target, table.ctx); }
+        }
 
-        let code_xrefs = scan_xrefs(&info, &code_blocks);
+        code_xrefs.retain(|xa| !info.is_excluded(*xa));
+        code_xrefs.sort();
+        code_xrefs.dedup();
 
-        info!("analysis cycle #{} ended, finding {} code ranges and {} code xrefs",
-            lop_count, code_blocks.len(), code_xrefs.len());
+        info!("analysis cycle #{} ended, finding {} code ranges, {} code xrefs and {} jump tables",
+            lop_count, code_blocks.len(), code_xrefs.len(), jump_tables.len());
 
         points = util::sorted_merge(&entry_points, &code_xrefs);
         points.dedup();
 
-        if points == prev_points
+        let prev_data_points = data_points;
+
+        data_points = util::sorted_merge(&tag_data_points, &jump_tables.iter().map(|t| t.xa).collect::<Vec<_>>());
+        data_points = util::sorted_merge(&data_points, &inline_data.iter().map(|data| data.xa).collect::<Vec<_>>());
+        data_points.dedup();
+
+        if points == prev_points && contexts == prev_contexts && noreturn.len() == prev_noreturn_count && data_points == prev_data_points
         {
             info!("no new xrefs found, ending analysis");
-            return code_blocks;
+
+            return Analysis
+            {
+                code_blocks: code_blocks,
+                jump_tables: jump_tables,
+                noreturn: noreturn,
+                padding: padding,
+                decode_failures: decode_failures,
+                inline_data: inline_data,
+                bank_variants: contexts.variants.iter().filter(|(_, variants)| variants.len() > 1).map(|(xa, variants)| (*xa, variants.clone())).collect(),
+            };
         }
     }
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn cut_blocks_stop_at_region_ends()
+    {
+        // a point right past a region (wram after the sram dump here) starts no block in it
+
+        let rom = vec![0x00; 0x8000];
+        let sram = vec![0x00; 0x2000];
+        let rom_info = RomInfo { sram_count: 1, ..RomInfo::for_test(false, Mbc::RomOnly) };
+        let info = AnalInfo::new(rom_info, &rom, &[]).with_sram(&sram);
+
+        let points = [XAddr::new(0, 0x0100), XAddr::new(0, 0xA000), XAddr::new(0, 0xC000)];
+        let blocks: Vec<(XAddr, usize)> = cut_blocks(&info, &points, &BankContexts::from_tags(&info)).iter().map(|block| (block.xa, block.len)).collect();
+
+        assert_eq!(blocks, vec![(XAddr::new(0, 0x0100), 0x7F00), (XAddr::new(0, 0xA000), 0x2000)]);
+    }
+
+    #[test]
+    fn fill_after_code_is_padding()
+    {
+        // `nop` fill has no jump to end a block, it's found after the code running into it all the same
+
+        let mut rom = vec![0x00; 0x8000];
+        rom[0x0150 .. 0x0152].copy_from_slice(&[0x3E, 0x01]); // ld a, 1
+        rom[0x0190] = 0xC9; // ret
+
+        let rom_info = RomInfo::for_test(false, Mbc::RomOnly);
+        let info = AnalInfo::new(rom_info, &rom, &[]);
+
+        let analysis = anal(&info, &[XAddr::new(0, 0x0150)]);
+        let blocks: Vec<(XAddr, usize)> = analysis.code_blocks.iter().map(|block| (block.xa, block.len)).collect();
+
+        assert_eq!(blocks, vec![(XAddr::new(0, 0x0150), 2)]);
+        assert_eq!(analysis.padding, vec![(XAddr::new(0, 0x0152), 0x3E)]);
+    }
+
+    #[test]
+    fn vectors_run_into_are_not_entry_points()
+    {
+        // a dispatcher at rst $00 running on into rst $08, a `reti` at rst $10
+
+        let mut rom = vec![0xFF; 0x8000];
+        rom[0x00 .. 0x0C].copy_from_slice(&[0x87, 0x4F, 0x06, 0x00, 0xE1, 0x09, 0x00, 0x00, 0x2A, 0x66, 0x6F, 0xE9]);
+        rom[0x10] = 0xD9;
+
+        let rom_info = RomInfo::for_test(false, Mbc::RomOnly);
+        let info = AnalInfo::new(rom_info, &rom, &[]);
+
+        let entry_points = default_entry_points(&info);
+
+        assert!(entry_points.contains(&XAddr::new(0, 0x0000)));
+        assert!(!entry_points.contains(&XAddr::new(0, 0x0008)));
+        assert!(entry_points.contains(&XAddr::new(0, 0x0010)));
+    }
+
+    #[test]
+    fn operands_resolve()
+    {
+        // on a banked rom run from home code, with no rom bank known
+
+        let mut rom = vec![0x00; 0x10000];
+        rom[0x0150 .. 0x0163].copy_from_slice(&[
+            0xCD, 0x00, 0x02, // call $0200
+            0xCD, 0x00, 0x40, // call $4000
+            0xFA, 0x00, 0xC0, // ld a, [$C000]
+            0xFA, 0x00, 0x40, // ld a, [$4000]
+            0x21, 0x00, 0xC1, // ld hl, $C100, tagged .operand_addr
+            0x01, 0x00, 0xC2, // ld bc, $C200
+            0xC9]);
+
+        let tags = [(XAddr::new(0, 0x015C), tags::Tag::OperandAddr)];
+        let rom_info = RomInfo::for_test(true, Mbc::Mbc1);
+        let info = AnalInfo::new(rom_info, &rom, &tags);
+
+        let mut emu = AnalEmu::new(&info, XAddr::new(0, 0x0150));
+        let operands: Vec<Operand> = std::iter::from_fn(|| emu.next_resolved()).take(6).map(|(_, result)| result.unwrap().1).collect();
+
+        assert_eq!(operands,
+        [
+            Operand::Code(XAddr::new(0, 0x0200)),
+            Operand::UnresolvedCode(0x4000),
+            Operand::Data(XAddr::new(0, 0xC000)),
+            Operand::UnresolvedData(0x4000),
+            Operand::Data(XAddr::new(0, 0xC100)),
+            Operand::None,
+        ]);
+    }
+}
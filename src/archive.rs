@@ -0,0 +1,83 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::io::{Cursor, Read};
+use thiserror::Error;
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+
+const ROM_EXTENSIONS: &[&str] = &[".gb", ".gbc"];
+
+#[derive(Error, Debug)]
+pub enum ArchiveError
+{
+    #[error("IO error")]
+    Io(#[from] std::io::Error),
+
+    #[error("Zip error: {0}")]
+    Zip(#[from] zip::result::ZipError),
+
+    #[error("No `.gb` or `.gbc` file in the archive")]
+    NoRom,
+
+    #[error("Several ROMs in the archive ({0}), pick one with --member")]
+    SeveralRoms(String),
+
+    #[error("No `{0}` in the archive")]
+    MissingMember(String),
+}
+
+pub fn is_archive(file: &[u8]) -> bool
+{
+    file.starts_with(ZIP_MAGIC) || file.starts_with(GZIP_MAGIC)
+}
+
+fn is_rom_name(name: &str) -> bool
+{
+    ROM_EXTENSIONS.iter().any(|ext| name.to_lowercase().ends_with(ext))
+}
+
+pub fn extract_rom(file: &[u8], member: Option<&str>) -> Result<Vec<u8>, ArchiveError>
+{
+    // gzip holds a single file, zip the given member or the only rom in it
+
+    let mut result = vec![];
+
+    if file.starts_with(GZIP_MAGIC)
+    {
+        flate2::read::GzDecoder::new(file).read_to_end(&mut result)?;
+        return Ok(result);
+    }
+
+    let mut zip = zip::ZipArchive::new(Cursor::new(file))?;
+
+    let name = match member
+    {
+        Some(member) => match zip.file_names().any(|name| name == member)
+        {
+            true => member.to_string(),
+            false => return Err(ArchiveError::MissingMember(member.to_string())),
+        },
+
+        None =>
+        {
+            let mut names: Vec<&str> = zip.file_names().filter(|name| is_rom_name(name)).collect();
+            names.sort();
+
+            match names.len()
+            {
+                0 => return Err(ArchiveError::NoRom),
+                1 => names[0].to_string(),
+                _ => return Err(ArchiveError::SeveralRoms(names.join(", "))),
+            }
+        }
+    };
+
+    zip.by_name(&name)?.read_to_end(&mut result)?;
+
+    Ok(result)
+}
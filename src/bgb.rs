@@ -0,0 +1,181 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::io::{BufRead, Read};
+use thiserror::Error;
+
+use super::xaddr::prelude::*;
+use super::tags::BankKind;
+
+#[derive(Error, Debug)]
+pub enum BgbError
+{
+    #[error("IO error")]
+    Io(#[from] std::io::Error),
+
+    #[error("Parse Int error")]
+    ParseInt(#[from] std::num::ParseIntError),
+
+    #[error("Missing label in symbol file line `{0}`")]
+    MissingLabel(String),
+
+    #[error("Truncated savestate")]
+    TruncatedSaveState,
+}
+
+// a line of a symbol file, naming an address, commenting it or both
+pub struct Symbol
+{
+    pub xa: XAddr,
+    pub name: Option<String>,
+    pub comment: Option<String>,
+}
+
+pub fn parse_sym<R>(read: &mut R) -> Result<Vec<Symbol>, BgbError>
+    where R: BufRead
+{
+    // the symbol files BGB loads (and rgblink writes): `bank:addr Label`, `;` comments
+    // Emulicious keeps the comments made in its debugger after the label, or alone: `bank:addr Label ; comment`, `bank:addr ; comment`
+
+    let mut result = vec![];
+
+    for line in read.lines()
+    {
+        let line = line?;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with(';') {
+            continue; }
+
+        let (symbol, comment) = match line.split_once(';')
+        {
+            Some((symbol, comment)) => (symbol.trim(), Some(comment.trim().to_string()).filter(|comment| !comment.is_empty())),
+            None => (line, None),
+        };
+
+        let (str_addr, name) = match symbol.split_once(char::is_whitespace)
+        {
+            Some((str_addr, name)) => (str_addr, Some(name.trim().to_string())),
+            None if comment.is_some() => (symbol, None),
+            None => return Err(BgbError::MissingLabel(line.to_string())),
+        };
+
+        result.push(Symbol { xa: str_addr.parse()?, name: name, comment: comment });
+    }
+
+    Ok(result)
+}
+
+// a breakpoint set in the debugger, on code that ran or was expected to
+pub struct Breakpoint
+{
+    pub xa: XAddr,
+    pub comment: Option<String>,
+}
+
+pub fn parse_breakpoints<R>(read: &mut R) -> Result<Vec<Breakpoint>, BgbError>
+    where R: BufRead
+{
+    // BGB's breakpoint list as copied out of its window: `bank:addr` (or `ROMbank:addr`) first on each line,
+    // what follows it (a condition, a hit count) left out except for `;` comments
+
+    let mut result = vec![];
+
+    for line in read.lines()
+    {
+        let line = line?;
+        let line = line.trim();
+
+        let (line, comment) = match line.split_once(';')
+        {
+            Some((line, comment)) => (line.trim(), Some(comment.trim().to_string()).filter(|comment| !comment.is_empty())),
+            None => (line, None),
+        };
+
+        let str_addr = match line.split_whitespace().next()
+        {
+            Some(str_addr) => str_addr.strip_prefix("ROM").unwrap_or(str_addr),
+            None => continue,
+        };
+
+        result.push(Breakpoint { xa: str_addr.parse()?, comment: comment });
+    }
+
+    Ok(result)
+}
+
+// cpu and mapper state from a BGB savestate
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SaveState
+{
+    pub pc: Option<u16>,
+    pub romb: Option<u16>,
+    pub ramb: Option<u16>,
+    pub srmb: Option<u16>,
+}
+
+impl SaveState
+{
+    pub fn banks(&self) -> Vec<(BankKind, u16)>
+    {
+        [(BankKind::Rom, self.romb), (BankKind::Ram, self.ramb), (BankKind::Srm, self.srmb)].iter()
+            .filter_map(|(kind, bank)| bank.map(|bank| (*kind, bank)))
+            .collect()
+    }
+
+    pub fn pc_xa(&self, big_rom: bool) -> Option<XAddr>
+    {
+        // only rom is of interest, code running from ram has nowhere to be listed
+        // (32K roms have no banks, whatever the mapper registers say)
+
+        match self.pc?
+        {
+            pc @ 0x0000 ..= 0x3FFF => Some(XAddr::new(0, pc)),
+            pc @ 0x4000 ..= 0x7FFF if !big_rom => Some(XAddr::new(0, pc)),
+            pc @ 0x4000 ..= 0x7FFF => self.romb.map(|bank| XAddr::new(bank, pc)),
+            _ => None,
+        }
+    }
+}
+
+pub fn parse_savestate<R>(read: &mut R) -> Result<SaveState, BgbError>
+    where R: Read
+{
+    // a sequence of `name\0`, 32-bit little endian length and data blocks; unknown blocks are skipped
+
+    let mut data = vec![];
+    read.read_to_end(&mut data)?;
+
+    let mut result = SaveState::default();
+    let mut pos = 0;
+
+    while pos < data.len()
+    {
+        let name_len = data[pos ..].iter().position(|&b| b == 0).ok_or(BgbError::TruncatedSaveState)?;
+        let name = String::from_utf8_lossy(&data[pos .. pos + name_len]).into_owned();
+        pos += name_len + 1;
+
+        let len_bytes = data.get(pos .. pos + 4).ok_or(BgbError::TruncatedSaveState)?;
+        let len = u32::from_le_bytes([len_bytes[0], len_bytes[1], len_bytes[2], len_bytes[3]]) as usize;
+        pos += 4;
+
+        let block = data.get(pos .. pos + len).ok_or(BgbError::TruncatedSaveState)?;
+        pos += len;
+
+        let value = block.iter().take(4).rev().fold(0u32, |value, &b| value << 8 | b as u32) as u16;
+
+        match name.as_str()
+        {
+            "PC" => result.pc = Some(value),
+            "ROMBANK" => result.romb = Some(value),
+            "WRAMBANK" => result.ramb = Some(value),
+            "SRAMBANK" => result.srmb = Some(value),
+            _ => {}
+        }
+    }
+
+    Ok(result)
+}
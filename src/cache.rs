@@ -0,0 +1,62 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::xaddr::prelude::*;
+use super::anal::Analysis;
+use super::util;
+
+// analysis results of a rom, along with what they were computed from
+// (not names or xrefs, which depend on tags that don't make these stale, see the cache key)
+#[derive(Serialize, Deserialize)]
+pub struct CacheEntry
+{
+    pub key: String,
+    pub analysis: Analysis,
+    pub emulated: BTreeMap<XAddr, Vec<XAddr>>, // computed jump targets found by emulation, by site
+}
+
+#[derive(Error, Debug)]
+pub enum CacheError
+{
+    #[error("IO error")]
+    Io(#[from] std::io::Error),
+
+    #[error("JSON error")]
+    Json(#[from] serde_json::Error),
+}
+
+fn entry_filename(dir: &Path, rom: &[u8]) -> PathBuf
+{
+    // one file per rom, by its crc32 as dump databases list it
+
+    dir.join(format!("{:08X}.json", util::crc32(rom)))
+}
+
+pub fn load(dir: &Path, rom: &[u8], key: &str) -> Option<CacheEntry>
+{
+    // anything missing, unreadable or computed from other inputs is just analysed again
+
+    let text = std::fs::read_to_string(entry_filename(dir, rom)).ok()?;
+    let entry: CacheEntry = serde_json::from_str(&text).ok()?;
+
+    if entry.key != key {
+        return None; }
+
+    Some(entry)
+}
+
+pub fn store(dir: &Path, rom: &[u8], entry: &CacheEntry) -> Result<(), CacheError>
+{
+    std::fs::create_dir_all(dir)?;
+    std::fs::write(entry_filename(dir, rom), serde_json::to_string(entry)?)?;
+
+    Ok(())
+}
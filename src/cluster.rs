@@ -0,0 +1,155 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::xaddr::prelude::*;
+use super::func::{Function, FlowGraph};
+
+// shorter functions are too alike to be worth reporting
+const MIN_INSTRUCTIONS: usize = 8;
+
+// longer functions are left out to keep the comparisons affordable
+const MAX_INSTRUCTIONS: usize = 400;
+
+// up to one edit per this many instructions
+const EDIT_RATIO: usize = 10;
+
+// a group of functions whose code is nearly the same
+#[derive(Clone, Debug)]
+pub struct Cluster
+{
+    pub members: Vec<XAddr>,
+    pub len: usize, // instruction count of the shortest member
+}
+
+fn normalized_code(graph: &FlowGraph, function: &Function) -> Vec<u16>
+{
+    // opcodes only, operands (addresses, immediates) are what tells the copies apart
+
+    let mut blocks = function.blocks.clone();
+    blocks.sort_by_key(|&i| graph.block(i).xa);
+
+    let mut result = vec![];
+
+    for i in blocks
+    {
+        let mut emu = graph.emu(i);
+
+        while let Some((_, Ok(ins))) = emu.next()
+        {
+            result.push(match ins.opcode
+            {
+                0xCB => 0x100 | ins.operand,
+                opcode => opcode as u16,
+            });
+        }
+    }
+
+    result
+}
+
+fn edit_distance(a: &[u16], b: &[u16], limit: usize) -> Option<usize>
+{
+    // levenshtein, giving up once every path is over the limit
+
+    let mut prev: Vec<usize> = (0 ..= b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+
+    for (i, x) in a.iter().enumerate()
+    {
+        cur[0] = i + 1;
+
+        for (j, y) in b.iter().enumerate() {
+            cur[j + 1] = (prev[j] + (x != y) as usize).min(prev[j + 1] + 1).min(cur[j] + 1); }
+
+        if cur.iter().all(|&d| d > limit) {
+            return None; }
+
+        std::mem::swap(&mut prev, &mut cur);
+    }
+
+    Some(prev[b.len()]).filter(|&d| d <= limit)
+}
+
+fn find_root(parents: &mut [usize], i: usize) -> usize
+{
+    let mut i = i;
+
+    while parents[i] != i
+    {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+
+    i
+}
+
+pub fn find_clusters(graph: &FlowGraph, functions: &[Function]) -> Vec<Cluster>
+{
+    let mut codes: Vec<(XAddr, Vec<u16>)> = functions.iter()
+        .map(|function| (function.xa, normalized_code(graph, function)))
+        .filter(|(_, code)| (MIN_INSTRUCTIONS ..= MAX_INSTRUCTIONS).contains(&code.len()))
+        .collect();
+
+    codes.sort_by_key(|(xa, code)| (code.len(), *xa));
+
+    let mut parents: Vec<usize> = (0 .. codes.len()).collect();
+
+    for i in 0 .. codes.len()
+    {
+        // sorted by length, so once the length difference alone is over the limit so is everything after
+
+        for j in i + 1 .. codes.len()
+        {
+            let limit = codes[j].1.len() / EDIT_RATIO;
+
+            if codes[j].1.len() - codes[i].1.len() > limit {
+                break; }
+
+            if find_root(&mut parents, i) == find_root(&mut parents, j) {
+                continue; }
+
+            if edit_distance(&codes[i].1, &codes[j].1, limit).is_some()
+            {
+                let root = find_root(&mut parents, i);
+                parents[root] = find_root(&mut parents, j);
+            }
+        }
+    }
+
+    let mut result: Vec<Cluster> = vec![];
+    let mut cluster_of = vec![None; codes.len()];
+
+    for (i, (xa, code)) in codes.iter().enumerate()
+    {
+        let root = find_root(&mut parents, i);
+
+        match cluster_of[root]
+        {
+            Some(c) =>
+            {
+                let cluster: &mut Cluster = &mut result[c];
+                cluster.members.push(*xa);
+                cluster.len = cluster.len.min(code.len());
+            }
+
+            None =>
+            {
+                cluster_of[root] = Some(result.len());
+                result.push(Cluster { members: vec![*xa], len: code.len() });
+            }
+        }
+    }
+
+    result.retain(|cluster| cluster.members.len() > 1);
+
+    for cluster in &mut result {
+        cluster.members.sort(); }
+
+    // biggest families first
+    result.sort_by_key(|cluster| (std::cmp::Reverse(cluster.members.len()), cluster.members[0]));
+
+    result
+}
@@ -0,0 +1,330 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::io::BufRead;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CodecError
+{
+    #[error("Unknown codec `{0}`")]
+    UnknownCodec(String),
+
+    #[error("Compressed data ends unexpectedly")]
+    Truncated,
+
+    #[error("Invalid command byte ${0:02X}")]
+    InvalidCommand(u8),
+
+    #[error("Copy from outside of the data decompressed so far")]
+    InvalidOffset,
+}
+
+#[derive(Error, Debug)]
+pub enum ParseCodecError
+{
+    #[error("IO error")]
+    Io(#[from] std::io::Error),
+
+    #[error("Parse Int error")]
+    ParseInt(#[from] std::num::ParseIntError),
+
+    #[error("Unknown codec operation `{0}`")]
+    UnknownOp(String),
+
+    #[error("Invalid codec line `{0}`")]
+    InvalidLine(String),
+}
+
+pub struct Decompressed
+{
+    pub data: Vec<u8>,
+    pub consumed: usize, // how many compressed bytes were read
+}
+
+pub trait Codec
+{
+    fn name(&self) -> &str;
+    fn decompress(&self, src: &[u8]) -> Result<Decompressed, CodecError>;
+}
+
+pub struct Rle;
+
+impl Codec for Rle
+{
+    fn name(&self) -> &str { "rle" }
+
+    fn decompress(&self, src: &[u8]) -> Result<Decompressed, CodecError>
+    {
+        // control byte c:
+        // - $00 ends the stream
+        // - c & $80: repeat the next byte (c & $7F) times
+        // - otherwise: copy the next c bytes as-is
+
+        let mut data = vec![];
+        let mut i = 0;
+
+        loop
+        {
+            let c = *src.get(i).ok_or(CodecError::Truncated)?;
+            i += 1;
+
+            match c
+            {
+                0x00 => break,
+
+                0x80 ..= 0xFF =>
+                {
+                    let b = *src.get(i).ok_or(CodecError::Truncated)?;
+                    i += 1;
+
+                    data.resize(data.len() + (c & 0x7F) as usize, b);
+                }
+
+                _ =>
+                {
+                    let run = src.get(i .. i + c as usize).ok_or(CodecError::Truncated)?;
+                    i += c as usize;
+
+                    data.extend_from_slice(run);
+                }
+            }
+        }
+
+        Ok(Decompressed { data: data, consumed: i })
+    }
+}
+
+// what a command of a command byte codec does
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CommandOp
+{
+    Literal,      // the next len bytes
+    Fill,         // the next byte, len times
+    Fill2,        // the next two bytes alternating, len bytes in all
+    Zero,         // len zeros
+    Increment,    // the next byte, then one more each time
+    Copy,         // len bytes from earlier in the output
+    CopyFlipped,  // the same with the bits of each byte reversed
+    CopyReversed, // len bytes going backwards from an earlier point of the output
+}
+
+// how copies give the point of the output they copy from
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OffsetMode
+{
+    Mixed,    // a byte with bit 7 set counts back from the end of the output, otherwise two bytes (big endian) from its start
+    Absolute, // always two bytes (big endian) from the start
+}
+
+// the family of codecs most GB games use (Pokémon's LZ3, HAL's...): a command in the top 3 bits of each byte and
+// the length minus one in the other 5, with a long form for a 10 bit length
+#[derive(Clone, Debug)]
+pub struct CommandCodec
+{
+    pub name: String,
+    pub ops: [Option<CommandOp>; 8],
+    pub end: u8,
+    pub long: Option<u8>, // command taking its real command from the next 3 bits, and 2 more bits of length from the next byte
+    pub offset: OffsetMode,
+}
+
+impl CommandCodec
+{
+    pub fn lz() -> Self
+    {
+        // Pokémon Gold, Silver and Crystal
+
+        Self
+        {
+            name: "lz".to_string(),
+            ops: [Some(CommandOp::Literal), Some(CommandOp::Fill), Some(CommandOp::Fill2), Some(CommandOp::Zero),
+                Some(CommandOp::Copy), Some(CommandOp::CopyFlipped), Some(CommandOp::CopyReversed), None],
+            end: 0xFF,
+            long: Some(7),
+            offset: OffsetMode::Mixed,
+        }
+    }
+
+    fn read_offset(&self, src: &[u8], i: &mut usize, out_len: usize) -> Result<usize, CodecError>
+    {
+        let b = *src.get(*i).ok_or(CodecError::Truncated)?;
+        *i += 1;
+
+        if self.offset == OffsetMode::Mixed && (b & 0x80) != 0 {
+            return out_len.checked_sub((b & 0x7F) as usize + 1).ok_or(CodecError::InvalidOffset); }
+
+        let lo = *src.get(*i).ok_or(CodecError::Truncated)?;
+        *i += 1;
+
+        Ok(((b as usize) << 8) | lo as usize)
+    }
+}
+
+impl Codec for CommandCodec
+{
+    fn name(&self) -> &str { &self.name }
+
+    fn decompress(&self, src: &[u8]) -> Result<Decompressed, CodecError>
+    {
+        let mut data: Vec<u8> = vec![];
+        let mut i = 0;
+
+        loop
+        {
+            let c = *src.get(i).ok_or(CodecError::Truncated)?;
+            i += 1;
+
+            if c == self.end {
+                break; }
+
+            let (mut cmd, mut len) = (c >> 5, (c & 0x1F) as usize + 1);
+
+            if self.long == Some(cmd)
+            {
+                let lo = *src.get(i).ok_or(CodecError::Truncated)?;
+                i += 1;
+
+                cmd = (c >> 2) & 0x07;
+                len = ((((c & 0x03) as usize) << 8) | lo as usize) + 1;
+            }
+
+            match self.ops[cmd as usize].ok_or(CodecError::InvalidCommand(c))?
+            {
+                CommandOp::Literal =>
+                {
+                    data.extend_from_slice(src.get(i .. i + len).ok_or(CodecError::Truncated)?);
+                    i += len;
+                }
+
+                CommandOp::Fill =>
+                {
+                    let b = *src.get(i).ok_or(CodecError::Truncated)?;
+                    i += 1;
+
+                    data.resize(data.len() + len, b);
+                }
+
+                CommandOp::Fill2 =>
+                {
+                    let pair = src.get(i .. i + 2).ok_or(CodecError::Truncated)?;
+                    i += 2;
+
+                    data.extend((0 .. len).map(|k| pair[k % 2]));
+                }
+
+                CommandOp::Zero => data.resize(data.len() + len, 0),
+
+                CommandOp::Increment =>
+                {
+                    let b = *src.get(i).ok_or(CodecError::Truncated)?;
+                    i += 1;
+
+                    data.extend((0 .. len).map(|k| b.wrapping_add(k as u8)));
+                }
+
+                CommandOp::Copy | CommandOp::CopyFlipped =>
+                {
+                    let off = self.read_offset(src, &mut i, data.len())?;
+
+                    // the copy can run into what it's writing
+                    for k in 0 .. len
+                    {
+                        let b = *data.get(off + k).ok_or(CodecError::InvalidOffset)?;
+                        data.push(if self.ops[cmd as usize] == Some(CommandOp::CopyFlipped) { b.reverse_bits() } else { b });
+                    }
+                }
+
+                CommandOp::CopyReversed =>
+                {
+                    let off = self.read_offset(src, &mut i, data.len())?;
+
+                    for k in 0 .. len
+                    {
+                        let b = *off.checked_sub(k).and_then(|at| data.get(at)).ok_or(CodecError::InvalidOffset)?;
+                        data.push(b);
+                    }
+                }
+            }
+        }
+
+        Ok(Decompressed { data: data, consumed: i })
+    }
+}
+
+pub fn parse_codec<R>(name: &str, read: &mut R) -> Result<CommandCodec, ParseCodecError>
+    where R: BufRead
+{
+    // `N op` lines giving what command N does, and optional `end XX`, `long N` and `offset mixed|absolute` lines
+
+    let mut result = CommandCodec { name: name.to_string(), ops: [None; 8], end: 0xFF, long: None, offset: OffsetMode::Mixed };
+
+    for line in read.lines()
+    {
+        let line = line?;
+        let line = line.split(';').next().unwrap().trim();
+
+        if line.is_empty() {
+            continue; }
+
+        let (key, value) = match line.split_once(char::is_whitespace)
+        {
+            Some((key, value)) => (key, value.trim()),
+            None => return Err(ParseCodecError::InvalidLine(line.to_string())),
+        };
+
+        match key
+        {
+            "end" => result.end = u8::from_str_radix(value, 16)?,
+            "long" => result.long = Some(value.parse()?),
+
+            "offset" => result.offset = match value
+            {
+                "mixed" => OffsetMode::Mixed,
+                "absolute" => OffsetMode::Absolute,
+                _ => return Err(ParseCodecError::InvalidLine(line.to_string())),
+            },
+
+            _ =>
+            {
+                let cmd: usize = key.parse()?;
+
+                if cmd >= result.ops.len() {
+                    return Err(ParseCodecError::InvalidLine(line.to_string())); }
+
+                result.ops[cmd] = Some(match value
+                {
+                    "literal" => CommandOp::Literal,
+                    "fill" => CommandOp::Fill,
+                    "fill2" => CommandOp::Fill2,
+                    "zero" => CommandOp::Zero,
+                    "increment" => CommandOp::Increment,
+                    "copy" => CommandOp::Copy,
+                    "copy_flipped" => CommandOp::CopyFlipped,
+                    "copy_reversed" => CommandOp::CopyReversed,
+                    _ => return Err(ParseCodecError::UnknownOp(value.to_string())),
+                });
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+pub fn find_codec(name: &str, custom: &[CommandCodec]) -> Result<Box<dyn Codec>, CodecError>
+{
+    // codecs described in files come first, so they can stand in for built-in ones
+
+    if let Some(codec) = custom.iter().find(|codec| codec.name == name) {
+        return Ok(Box::new(codec.clone())); }
+
+    match name
+    {
+        "rle" => Ok(Box::new(Rle)),
+        "lz" => Ok(Box::new(CommandCodec::lz())),
+        _ => Err(CodecError::UnknownCodec(name.to_string())),
+    }
+}
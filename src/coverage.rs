@@ -0,0 +1,110 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::xaddr::prelude::*;
+use super::anal::AnalInfo;
+
+// how many bytes of a rom bank are accounted for
+#[derive(Clone, Copy, Default, Debug)]
+pub struct BankCoverage
+{
+    pub bank: u16,
+    pub code: usize,
+    pub data: usize,
+    pub unknown: usize,
+}
+
+impl BankCoverage
+{
+    pub fn byte_len(&self) -> usize
+    {
+        self.code + self.data + self.unknown
+    }
+
+    pub fn to_json(&self) -> String
+    {
+        format!("{{\"bank\": {}, \"code\": {}, \"data\": {}, \"unknown\": {}}}", self.bank, self.code, self.data, self.unknown)
+    }
+}
+
+impl std::fmt::Display for BankCoverage
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        let percent = |count: usize| if self.byte_len() == 0 { 0.0 } else { count as f64 * 100.0 / self.byte_len() as f64 };
+
+        write!(f, "{:6} code ({:5.1}%), {:6} data ({:5.1}%), {:6} unknown ({:5.1}%)",
+            self.code, percent(self.code), self.data, percent(self.data), self.unknown, percent(self.unknown))
+    }
+}
+
+pub fn bank_coverage(info: &AnalInfo, code: &[(XAddr, usize)], data: &[(XAddr, usize)]) -> Vec<BankCoverage>
+{
+    // code wins over data where both claim the same bytes
+
+    const UNKNOWN: u8 = 0;
+    const DATA: u8 = 1;
+    const CODE: u8 = 2;
+
+    let mut result = vec![];
+
+    for (bank_xa, bank_len) in info.rom_bank_blocks()
+    {
+        let mut kinds = vec![UNKNOWN; bank_len];
+
+        let mut mark = |ranges: &[(XAddr, usize)], kind: u8|
+        {
+            for &(xa, len) in ranges.iter().filter(|(xa, _)| xa.bank == bank_xa.bank && xa.addr >= bank_xa.addr)
+            {
+                let beg = ((xa.addr - bank_xa.addr) as usize).min(bank_len);
+                let end = (beg + len).min(bank_len);
+
+                for byte in &mut kinds[beg .. end] {
+                    *byte = kind; }
+            }
+        };
+
+        // the cartridge header is data whatever the tags say
+
+        if let (0, Some((header_beg, header_end))) = (bank_xa.bank, info.header_range()) {
+            mark(&[(XAddr::new(0, header_beg), (header_end - header_beg) as usize)], DATA); }
+
+        mark(data, DATA);
+        mark(code, CODE);
+
+        let count = |kind: u8| kinds.iter().filter(|&&byte| byte == kind).count();
+
+        result.push(BankCoverage
+        {
+            bank: bank_xa.bank,
+            code: count(CODE),
+            data: count(DATA),
+            unknown: count(UNKNOWN),
+        });
+    }
+
+    result
+}
+
+pub fn total(banks: &[BankCoverage]) -> BankCoverage
+{
+    banks.iter().fold(BankCoverage::default(), |total, bank| BankCoverage
+    {
+        bank: 0,
+        code: total.code + bank.code,
+        data: total.data + bank.data,
+        unknown: total.unknown + bank.unknown,
+    })
+}
+
+pub fn to_json(banks: &[BankCoverage]) -> String
+{
+    let total = total(banks);
+    let banks: Vec<_> = banks.iter().map(|bank| format!("    {}", bank.to_json())).collect();
+
+    format!("{{\n  \"banks\": [\n{}\n  ],\n  \"total\": {{\"code\": {}, \"data\": {}, \"unknown\": {}}}\n}}\n",
+        banks.join(",\n"), total.code, total.data, total.unknown)
+}
@@ -0,0 +1,90 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::io::Write;
+
+use super::xaddr::prelude::*;
+
+// the listing output, keeping count of lines so the line each address is written on is known
+// (and a crc32 of it, which symbol files give so the debugger can tell the listing was written again)
+pub struct ListingWriter<W>
+{
+    inner: W,
+    lines: usize,
+    marks: Vec<(XAddr, usize)>,
+    crc: flate2::Crc,
+}
+
+impl<W> ListingWriter<W>
+    where W: Write
+{
+    pub fn new(inner: W) -> Self
+    {
+        Self { inner: inner, lines: 0, marks: vec![], crc: flate2::Crc::new() }
+    }
+
+    pub fn mark(&mut self, xa: XAddr)
+    {
+        // what is written next (from the start of a line) is at xa
+
+        self.marks.push((xa, self.lines + 1));
+    }
+
+    pub fn marks(&self) -> &[(XAddr, usize)]
+    {
+        &self.marks
+    }
+
+    pub fn crc(&self) -> u32
+    {
+        self.crc.sum()
+    }
+}
+
+impl<W> Write for ListingWriter<W>
+    where W: Write
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize>
+    {
+        let len = self.inner.write(buf)?;
+        self.lines += buf[.. len].iter().filter(|byte| **byte == b'\n').count();
+        self.crc.update(&buf[.. len]);
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()>
+    {
+        self.inner.flush()
+    }
+}
+
+pub fn write_sym<W>(write: &mut W, labels: &[(XAddr, &str)], marks: &[(XAddr, usize)], listing_name: &str, listing_crc: u32, rom_crc: u32) -> std::io::Result<()>
+    where W: Write
+{
+    // a wla-dx symbol file, which emulicious (among others) loads for source level debugging: the labels,
+    // then the listing as the only source file and the line (counted from 1) each address is on, all in hex
+
+    writeln!(write, "[labels]")?;
+
+    let mut labels = labels.to_vec();
+    labels.sort();
+
+    for (xa, name) in labels {
+        writeln!(write, "{:02x}:{:04x} {}", xa.bank, xa.addr, name)?; }
+
+    writeln!(write, "\n[source files]\n0000 {:08x} {}", listing_crc, listing_name)?;
+    writeln!(write, "\n[rom checksum]\n{:08x}", rom_crc)?;
+    writeln!(write, "\n[addr-to-line mapping]")?;
+
+    let mut marks = marks.to_vec();
+    marks.sort();
+
+    for (xa, line) in marks {
+        writeln!(write, "{:02x}:{:04x} 0000:{:08x}", xa.bank, xa.addr, line)?; }
+
+    Ok(())
+}
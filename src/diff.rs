@@ -0,0 +1,245 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::xaddr::prelude::*;
+use super::tags;
+use super::anal::{self, AnalInfo};
+use super::func::{self, FlowGraph};
+use super::stats::{self, Stats};
+
+use std::collections::{HashMap, HashSet};
+
+// a function or data region, with what it's made of regardless of where it and what it refers to ended up
+#[derive(Clone, Debug)]
+pub struct Item
+{
+    pub xa: XAddr,
+    pub len: usize,
+    pub name: Option<String>,
+    pub print: Vec<u8>,
+}
+
+pub struct Summary
+{
+    pub functions: Vec<Item>,
+    pub data: Vec<Item>,
+    pub stats: Stats, // of the analysis, for --strict
+}
+
+#[derive(Clone, Debug)]
+pub enum Change
+{
+    Added(Item),
+    Removed(Item),
+    Moved(Item, Item),
+    Changed(Item, Item),
+}
+
+fn tag_name(info: &AnalInfo, xa: XAddr) -> Option<String>
+{
+    tags::get_tags_at(info.tags, &xa).iter().find_map(|(_, tag)| match tag
+    {
+        tags::Tag::Name(name) => Some(name.clone()),
+        _ => None,
+    })
+}
+
+fn function_print(graph: &FlowGraph, blocks: &[usize]) -> (Vec<u8>, usize)
+{
+    // opcodes and plain operands, leaving out addresses (which change as soon as anything before them moves)
+
+    let mut blocks = blocks.to_vec();
+    blocks.sort_by_key(|i| graph.block(*i).xa);
+
+    let mut print = vec![];
+    let mut len = 0;
+
+    for i in blocks
+    {
+        let mut emu = graph.emu(i);
+        len += graph.block(i).len;
+
+        while let Some((_, Ok(ins))) = emu.next()
+        {
+            print.push(ins.opcode);
+
+            if ins.get_jump_target().is_none() && !ins.is_addr_operand() {
+                print.extend_from_slice(&ins.operand.to_le_bytes()[.. ins.encoded_len().saturating_sub(1).min(2)]); }
+        }
+    }
+
+    (print, len)
+}
+
+fn data_regions(info: &AnalInfo, analysis: &anal::Analysis) -> Vec<Item>
+{
+    // whatever code doesn't cover, leaving out padding (all the same byte)
+
+    let mut code: Vec<(XAddr, usize)> = analysis.code_blocks.iter().map(|block| (block.xa, block.len)).collect();
+    code.sort();
+
+    let mut result = vec![];
+
+    for (bank_xa, bank_len) in info.rom_bank_blocks()
+    {
+        let mut push = |beg: usize, end: usize|
+        {
+            let xa = XAddr::new(bank_xa.bank, beg as u16);
+
+            let bytes = match info.rom_slice(xa, end.saturating_sub(beg))
+            {
+                Ok(bytes) if !bytes.is_empty() => bytes,
+                _ => return,
+            };
+
+            if bytes.iter().all(|byte| *byte == bytes[0]) {
+                return; }
+
+            result.push(Item { xa: xa, len: bytes.len(), name: tag_name(info, xa), print: bytes.to_vec() });
+        };
+
+        let bank_end = bank_xa.addr as usize + bank_len;
+        let mut beg = bank_xa.addr as usize;
+
+        for (xa, len) in code.iter().filter(|(xa, _)| xa.bank == bank_xa.bank && xa.addr >= bank_xa.addr && (xa.addr as usize) < bank_end)
+        {
+            push(beg, xa.addr as usize);
+            beg = std::cmp::max(beg, xa.addr as usize + len);
+        }
+
+        push(beg, bank_end);
+    }
+
+    result
+}
+
+pub fn summarize(info: &AnalInfo, entry_points: &[XAddr]) -> Summary
+{
+    let analysis = anal::anal(info, entry_points);
+    let graph = FlowGraph::new(info, &analysis.code_blocks, &analysis.noreturn);
+
+    // code only reached from the entry points counts as functions of its own
+
+    let functions = func::find_functions_from(&graph, entry_points).iter().map(|function|
+    {
+        let (print, len) = function_print(&graph, &function.blocks);
+        Item { xa: function.xa, len: len, name: tag_name(info, function.xa), print: print }
+    }).collect();
+
+    let stats = stats::collect(info, &analysis, &graph, &func::find_functions(&graph), &HashSet::new());
+
+    Summary { functions: functions, data: data_regions(info, &analysis), stats: stats }
+}
+
+fn pair_items(old_left: &mut [Option<&Item>], new_left: &mut [Option<&Item>], result: &mut Vec<Change>, matches: &dyn Fn(&Item, &Item) -> bool)
+{
+    for old_item in old_left.iter_mut()
+    {
+        let a = match old_item
+        {
+            Some(a) => *a,
+            None => continue,
+        };
+
+        if let Some(new_item) = new_left.iter_mut().find(|b| b.is_some_and(|b| matches(a, b)))
+        {
+            let b = new_item.take().unwrap();
+            old_item.take();
+
+            if a.print != b.print {
+                result.push(Change::Changed(a.clone(), b.clone())); }
+            else if a.xa != b.xa {
+                result.push(Change::Moved(a.clone(), b.clone())); }
+        }
+    }
+}
+
+pub fn diff_items(old: &[Item], new: &[Item]) -> Vec<Change>
+{
+    // paired by name where both sides have one, then by contents (moved), then by address (changed in place)
+
+    let mut result = vec![];
+    let mut old_left: Vec<Option<&Item>> = old.iter().map(Some).collect();
+    let mut new_left: Vec<Option<&Item>> = new.iter().map(Some).collect();
+
+    pair_items(&mut old_left, &mut new_left, &mut result, &|a, b| a.name.is_some() && a.name == b.name);
+
+    // identical contents can show up more than once, first come first served
+    {
+        let mut by_print: HashMap<&[u8], Vec<usize>> = HashMap::new();
+
+        for (i, b) in new_left.iter().enumerate()
+        {
+            if let Some(b) = b {
+                by_print.entry(&b.print).or_default().push(i); }
+        }
+
+        for old_item in old_left.iter_mut()
+        {
+            let a = match old_item
+            {
+                Some(a) => *a,
+                _ => continue,
+            };
+
+            let candidates = match by_print.get_mut(a.print.as_slice())
+            {
+                Some(candidates) => candidates,
+                None => continue,
+            };
+
+            // the one at the same address if it's there
+            let pos = candidates.iter().position(|i| new_left[*i].is_some_and(|b| b.xa == a.xa))
+                .or_else(|| candidates.iter().position(|i| new_left[*i].is_some()));
+
+            if let Some(pos) = pos
+            {
+                let b = new_left[candidates.remove(pos)].take().unwrap();
+                old_item.take();
+
+                if a.xa != b.xa {
+                    result.push(Change::Moved(a.clone(), b.clone())); }
+            }
+        }
+    }
+
+    pair_items(&mut old_left, &mut new_left, &mut result, &|a, b| a.xa == b.xa);
+
+    result.extend(old_left.into_iter().flatten().map(|a| Change::Removed(a.clone())));
+    result.extend(new_left.into_iter().flatten().map(|b| Change::Added(b.clone())));
+
+    result.sort_by_key(|change| match change
+    {
+        Change::Removed(a) | Change::Moved(a, _) | Change::Changed(a, _) => (a.xa, 0),
+        Change::Added(b) => (b.xa, 1),
+    });
+
+    result
+}
+
+fn display(item: &Item) -> String
+{
+    match &item.name
+    {
+        Some(name) => format!("{} ({})", name, item.xa),
+        None => item.xa.to_string(),
+    }
+}
+
+impl std::fmt::Display for Change
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        match self
+        {
+            Change::Added(b) => write!(f, "added: {}, {} bytes", display(b), b.len),
+            Change::Removed(a) => write!(f, "removed: {}, {} bytes", display(a), a.len),
+            Change::Moved(a, b) => write!(f, "moved: {} -> {}", display(a), display(b)),
+            Change::Changed(a, b) if a.xa == b.xa => write!(f, "changed: {}, {} -> {} bytes", display(a), a.len, b.len),
+            Change::Changed(a, b) => write!(f, "changed: {} -> {}, {} -> {} bytes", display(a), display(b), a.len, b.len),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::xaddr::prelude::*;
+use super::anal::AnalInfo;
+
+const WINDOW_LEN: usize = 0x100;
+
+// random data only reaches about 7.2 bits/byte over a window this small, while code,
+// tile data and text stay well below 6
+const MIN_ENTROPY: f64 = 6.5;
+
+// a run of high entropy windows
+#[derive(Clone, Copy, Debug)]
+pub struct Region
+{
+    pub xa: XAddr,
+    pub len: usize,
+    pub entropy: f64, // average over the windows, in bits per byte
+}
+
+fn entropy(bytes: &[u8]) -> f64
+{
+    let mut counts = [0usize; 0x100];
+
+    for &byte in bytes {
+        counts[byte as usize] += 1; }
+
+    counts.iter().filter(|&&count| count != 0).map(|&count|
+    {
+        let p = count as f64 / bytes.len() as f64;
+        -p * p.log2()
+    }).sum()
+}
+
+pub fn find_high_entropy(info: &AnalInfo, ranges: &[(XAddr, usize)]) -> Vec<Region>
+{
+    // windows that don't fit in a range are left out, so small gaps are never flagged
+
+    let mut result = vec![];
+
+    for &(xa, len) in ranges
+    {
+        let slice = match info.rom_slice(xa, len)
+        {
+            Ok(slice) => slice,
+            Err(_) => continue,
+        };
+
+        let mut current: Option<(Region, usize)> = None; // with its window count
+
+        for (i, window) in slice.chunks_exact(WINDOW_LEN).enumerate()
+        {
+            let window_entropy = entropy(window);
+
+            if window_entropy < MIN_ENTROPY
+            {
+                if let Some((region, _)) = current.take() {
+                    result.push(region); }
+
+                continue;
+            }
+
+            match &mut current
+            {
+                Some((region, count)) =>
+                {
+                    region.entropy = (region.entropy * *count as f64 + window_entropy) / (*count + 1) as f64;
+                    region.len += WINDOW_LEN;
+                    *count += 1;
+                }
+
+                None => current = Some((Region { xa: xa + (i * WINDOW_LEN) as u16, len: WINDOW_LEN, entropy: window_entropy }, 1)),
+            }
+        }
+
+        if let Some((region, _)) = current {
+            result.push(region); }
+    }
+
+    result
+}
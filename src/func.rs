@@ -0,0 +1,665 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::xaddr::prelude::*;
+use super::gbasm;
+use super::regs;
+use super::anal::{AnalInfo, AnalEmu, CodeBlock, Confidence};
+use super::jumptable::JumpTable;
+
+use std::collections::{HashMap, HashSet};
+
+const OPCODE_RET: u8 = 0xC9;
+const OPCODE_RETI: u8 = 0xD9;
+
+// a function is the code reachable from a call target without going through other calls
+#[derive(Clone, Debug)]
+pub struct Function
+{
+    pub xa: XAddr,
+    pub blocks: Vec<usize>, // indices into the code block list, starting with the entry block
+}
+
+pub struct FlowGraph<'a>
+{
+    info: &'a AnalInfo<'a>,
+    blocks: &'a [CodeBlock],
+    noreturn: &'a HashSet<XAddr>,
+    index: HashMap<XAddr, usize>,
+}
+
+impl<'a> FlowGraph<'a>
+{
+    pub fn new(info: &'a AnalInfo, blocks: &'a [CodeBlock], noreturn: &'a HashSet<XAddr>) -> Self
+    {
+        Self
+        {
+            info: info,
+            blocks: blocks,
+            noreturn: noreturn,
+            index: blocks.iter().enumerate().map(|(i, block)| (block.xa, i)).collect(),
+        }
+    }
+
+    pub fn block_at(&self, xa: &XAddr) -> Option<usize>
+    {
+        self.index.get(xa).copied()
+    }
+
+    pub fn block_count(&self) -> usize
+    {
+        self.blocks.len()
+    }
+
+    pub fn block(&self, i: usize) -> &CodeBlock
+    {
+        &self.blocks[i]
+    }
+
+    pub fn is_noreturn(&self, xa: &XAddr) -> bool
+    {
+        self.noreturn.contains(xa)
+    }
+
+    pub fn emu(&self, i: usize) -> AnalEmu<'a>
+    {
+        AnalEmu::for_block(self.info, &self.blocks[i])
+    }
+
+    pub fn successors(&self, i: usize) -> Vec<usize>
+    {
+        // blocks control can go to from the end of this one, not counting calls
+
+        let block = &self.blocks[i];
+        let mut emu = self.emu(i);
+        let mut result = vec![];
+        let mut falls_through = true;
+
+        while let Some((xa, Ok(ins))) = emu.next()
+        {
+            let flags = ins.info().flags;
+
+            if (flags & gbasm::OPCODE_FLAG_JUMP) == 0 {
+                continue; }
+
+            let target = emu.target(xa, &ins);
+
+            if (flags & gbasm::OPCODE_FLAG_CALL) != 0
+            {
+                if target.is_some_and(|target| self.noreturn.contains(&target)) {
+                    falls_through = false; }
+            }
+            else
+            {
+                if let Some(j) = target.and_then(|target| self.block_at(&target)) {
+                    result.push(j); }
+
+                if (flags & gbasm::OPCODE_FLAG_CONDITIONAL) == 0 {
+                    falls_through = false; }
+            }
+        }
+
+        if falls_through
+        {
+            if let Some(j) = self.block_at(&(block.xa + block.len as u16)) {
+                result.push(j); }
+        }
+
+        result
+    }
+
+    pub fn edges(&self, i: usize) -> Vec<(XAddr, bool)>
+    {
+        // where control goes from this block, including calls, and whether that was guessed
+        // (computed jumps) rather than read from the code
+
+        let block = &self.blocks[i];
+        let mut emu = self.emu(i);
+        let mut result = vec![];
+        let mut falls_through = true;
+
+        while let Some((xa, Ok(ins))) = emu.next()
+        {
+            let flags = ins.info().flags;
+
+            if (flags & gbasm::OPCODE_FLAG_JUMP) == 0 {
+                continue; }
+
+            let (target, guessed) = match ins.get_jump_target()
+            {
+                Some(addr) => (Some(addr), false),
+                None => (emu.computed_target(&ins), true),
+            };
+
+            let target = target.and_then(|addr| emu.expand_target(xa, addr));
+
+            if let Some(target) = target {
+                result.push((target, guessed)); }
+
+            if (flags & gbasm::OPCODE_FLAG_CALL) != 0
+            {
+                if target.is_some_and(|target| self.noreturn.contains(&target)) {
+                    falls_through = false; }
+            }
+            else if (flags & gbasm::OPCODE_FLAG_CONDITIONAL) == 0
+            {
+                falls_through = false;
+            }
+        }
+
+        if falls_through {
+            result.push((block.xa + block.len as u16, false)); }
+
+        result
+    }
+
+    pub fn references(&self, i: usize) -> Vec<XAddr>
+    {
+        // everything this block may lead to: jump and call targets, 16-bit operands (pointers to code or tables)
+        // and whatever follows it (fallthrough or inline data)
+
+        let block = &self.blocks[i];
+        let mut emu = self.emu(i);
+        let mut result = vec![block.xa + block.len as u16];
+
+        while let Some((xa, Ok(ins))) = emu.next()
+        {
+            if let Some(target) = emu.target(xa, &ins) {
+                result.push(target); }
+
+            if ins.info().operand_len == 2
+            {
+                if let Some(target) = emu.expand_addr(ins.operand) {
+                    result.push(target); }
+            }
+        }
+
+        result
+    }
+
+    pub fn call_targets(&self) -> Vec<XAddr>
+    {
+        let mut result = vec![];
+
+        for i in 0 .. self.blocks.len()
+        {
+            let mut emu = self.emu(i);
+
+            while let Some((xa, Ok(ins))) = emu.next()
+            {
+                if (ins.info().flags & gbasm::OPCODE_FLAG_CALL) == 0 {
+                    continue; }
+
+                if let Some(target) = emu.target(xa, &ins) {
+                    result.push(target); }
+            }
+        }
+
+        result.sort();
+        result.dedup();
+
+        result
+    }
+}
+
+pub fn find_functions(graph: &FlowGraph) -> Vec<Function>
+{
+    find_functions_from(graph, &[])
+}
+
+pub fn find_functions_from(graph: &FlowGraph, roots: &[XAddr]) -> Vec<Function>
+{
+    // jumps to another function's entry are tail calls and end the function
+    // roots start functions too, even though nothing calls them
+
+    let mut entries = graph.call_targets();
+    entries.extend(roots.iter().filter(|xa| graph.block_at(xa).is_some()));
+    entries.sort();
+    entries.dedup();
+
+    let entry_set: HashSet<XAddr> = entries.iter().copied().collect();
+
+    let mut result = vec![];
+
+    for xa in entries
+    {
+        let start = match graph.block_at(&xa)
+        {
+            Some(i) => i,
+            None => continue,
+        };
+
+        let mut blocks = vec![start];
+        let mut seen: HashSet<usize> = blocks.iter().copied().collect();
+        let mut next = 0;
+
+        while next < blocks.len()
+        {
+            for j in graph.successors(blocks[next])
+            {
+                if !entry_set.contains(&graph.block(j).xa) && seen.insert(j) {
+                    blocks.push(j); }
+            }
+
+            next += 1;
+        }
+
+        result.push(Function { xa: xa, blocks: blocks });
+    }
+
+    result
+}
+
+pub fn check_stack_balance(graph: &FlowGraph, function: &Function)
+{
+    // follows push/pop balance along every path of the function, warning about returns
+    // with a modified stack (misanalysis, or return address tricks worth tagging)
+
+    use log::warn;
+
+    let members: HashSet<usize> = function.blocks.iter().copied().collect();
+    let mut depths: HashMap<usize, i32> = HashMap::new();
+    let mut pending = vec![(function.blocks[0], 0)];
+
+    while let Some((i, depth_in)) = pending.pop()
+    {
+        match depths.get(&i)
+        {
+            Some(&depth) if depth == depth_in => continue,
+
+            Some(&depth) =>
+            {
+                warn!("function at {}: {} is reached with stack depth {:+} and {:+}", function.xa, graph.block(i).xa, depth, depth_in);
+                continue;
+            }
+
+            None => { depths.insert(i, depth_in); }
+        }
+
+        let mut emu = graph.emu(i);
+        let mut depth = depth_in;
+        let mut known = true;
+
+        while let Some((xa, Ok(ins))) = emu.next()
+        {
+            match ins.opcode
+            {
+                0xC5 | 0xD5 | 0xE5 | 0xF5 => depth += 1,
+                0xC1 | 0xD1 | 0xE1 | 0xF1 => depth -= 1,
+
+                // sp set to something else entirely
+                0x31 | 0x33 | 0x3B | 0xE8 | 0xF9 => { known = false; break; }
+
+                OPCODE_RET | OPCODE_RETI | 0xC0 | 0xC8 | 0xD0 | 0xD8 if depth != 0 =>
+                    warn!("function at {} returns at {} with stack depth {:+}", function.xa, xa, depth),
+
+                _ => {}
+            }
+        }
+
+        if !known {
+            continue; }
+
+        for j in graph.successors(i)
+        {
+            if members.contains(&j) {
+                pending.push((j, depth)); }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct RegUsage
+{
+    pub inputs: regs::RegMask,   // read before being written on some path
+    pub clobbers: regs::RegMask, // changed on return, not counting registers saved and restored with push/pop
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+struct UsageState
+{
+    defined: regs::RegMask, // written on every path so far
+    written: regs::RegMask, // written on some path so far
+    saved: Vec<regs::RegMask>, // registers pushed before being written, for each stack entry
+}
+
+fn function_reg_usage(graph: &FlowGraph, function: &Function, entries: &HashSet<XAddr>, summaries: &HashMap<XAddr, RegUsage>) -> RegUsage
+{
+    let members: HashSet<usize> = function.blocks.iter().copied().collect();
+    let mut states: HashMap<usize, UsageState> = HashMap::new();
+    let mut pending = vec![function.blocks[0]];
+    let mut usage = RegUsage::default();
+
+    states.insert(function.blocks[0], UsageState::default());
+
+    while let Some(i) = pending.pop()
+    {
+        let mut state = states[&i].clone();
+        let mut emu = graph.emu(i);
+
+        let call = |state: &mut UsageState, usage: &mut RegUsage, callee: &RegUsage|
+        {
+            usage.inputs |= callee.inputs & !state.defined;
+            state.written |= callee.clobbers;
+        };
+
+        while let Some((xa, Ok(ins))) = emu.next()
+        {
+            let (reads, writes) = regs::reg_effects(&ins);
+            let flags = ins.info().flags;
+
+            usage.inputs |= reads & !state.defined;
+
+            match ins.opcode
+            {
+                0xC5 | 0xD5 | 0xE5 | 0xF5 => state.saved.push(reads & !state.written),
+
+                0xC1 | 0xD1 | 0xE1 | 0xF1 if state.saved.last() == Some(&writes) =>
+                {
+                    // restores the value saved on entry
+                    state.saved.pop();
+                    state.written &= !writes;
+                    state.defined |= writes;
+                    continue;
+                }
+
+                0xC1 | 0xD1 | 0xE1 | 0xF1 => { state.saved.pop(); }
+
+                _ => {}
+            }
+
+            state.written |= writes;
+            state.defined |= writes;
+
+            if (flags & gbasm::OPCODE_FLAG_CALL) != 0
+            {
+                if let Some(callee) = emu.target(xa, &ins).and_then(|target| summaries.get(&target)) {
+                    call(&mut state, &mut usage, callee); }
+            }
+            else if matches!(ins.opcode, OPCODE_RET | OPCODE_RETI | 0xC0 | 0xC8 | 0xD0 | 0xD8)
+            {
+                usage.clobbers |= state.written;
+            }
+        }
+
+        for j in graph.successors(i)
+        {
+            let xa = graph.block(j).xa;
+
+            if !members.contains(&j)
+            {
+                // tail call, whatever it changes is changed on return
+
+                if entries.contains(&xa)
+                {
+                    let mut state = state.clone();
+
+                    if let Some(callee) = summaries.get(&xa) {
+                        call(&mut state, &mut usage, callee); }
+
+                    usage.clobbers |= state.written;
+                }
+
+                continue;
+            }
+
+            match states.get_mut(&j)
+            {
+                Some(prev) =>
+                {
+                    let (defined, written) = (prev.defined & state.defined, prev.written | state.written);
+
+                    if (defined, written) != (prev.defined, prev.written)
+                    {
+                        prev.defined = defined;
+                        prev.written = written;
+                        pending.push(j);
+                    }
+                }
+
+                None =>
+                {
+                    states.insert(j, state.clone());
+                    pending.push(j);
+                }
+            }
+        }
+    }
+
+    usage
+}
+
+pub fn infer_reg_usage(graph: &FlowGraph, functions: &[Function]) -> HashMap<XAddr, RegUsage>
+{
+    // what each function takes as input and clobbers, following calls through the other functions' usage
+    // until nothing changes
+
+    let entries: HashSet<XAddr> = functions.iter().map(|function| function.xa).collect();
+    let mut summaries: HashMap<XAddr, RegUsage> = HashMap::new();
+
+    loop
+    {
+        let mut changed = false;
+
+        for function in functions
+        {
+            // only ever growing, so that this settles
+            let prev = summaries.get(&function.xa).copied().unwrap_or_default();
+            let mut usage = function_reg_usage(graph, function, &entries, &summaries);
+
+            usage.inputs |= prev.inputs;
+            usage.clobbers |= prev.clobbers;
+
+            if usage != prev {
+                changed = true; }
+
+            summaries.insert(function.xa, usage);
+        }
+
+        if !changed {
+            return summaries; }
+    }
+}
+
+pub fn find_orphans(graph: &FlowGraph, roots: &[XAddr], jump_tables: &[JumpTable]) -> Vec<usize>
+{
+    // blocks that can't be reached from the roots, following every reference
+    // (a jump table is reached when its address is)
+
+    let tables: HashMap<XAddr, &JumpTable> = jump_tables.iter().map(|table| (table.xa, table)).collect();
+
+    let mut reached = vec![false; graph.blocks.len()];
+    let mut seen_tables = HashSet::new();
+    let mut pending = roots.to_vec();
+
+    while let Some(xa) = pending.pop()
+    {
+        if let Some(table) = tables.get(&xa)
+        {
+            if seen_tables.insert(xa) {
+                pending.extend(table.code_targets()); }
+        }
+
+        if let Some(i) = graph.block_at(&xa)
+        {
+            if !reached[i]
+            {
+                reached[i] = true;
+                pending.extend(graph.references(i));
+            }
+        }
+    }
+
+    (0 .. graph.blocks.len()).filter(|i| !reached[*i]).collect()
+}
+
+pub fn block_confidence(graph: &FlowGraph, roots: &[(XAddr, Confidence)]) -> Vec<Confidence>
+{
+    // each block is as trusted as the best path reaching it, a path being as trusted as its weakest step
+
+    let mut result = vec![Confidence::Speculative; graph.blocks.len()];
+    let mut pending = roots.to_vec();
+
+    while let Some((xa, confidence)) = pending.pop()
+    {
+        let i = match graph.block_at(&xa)
+        {
+            Some(i) if result[i] < confidence => i,
+            _ => continue,
+        };
+
+        result[i] = confidence;
+
+        for (target, guessed) in graph.edges(i) {
+            pending.push((target, if guessed { confidence.min(Confidence::Heuristic) } else { confidence.min(Confidence::Flow) })); }
+    }
+
+    result
+}
+
+// where a piece of data is read and written from, by function (or by block, for code outside any function)
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct DataAccess
+{
+    pub readers: Vec<XAddr>,
+    pub writers: Vec<XAddr>,
+}
+
+pub fn find_data_accesses(graph: &FlowGraph, functions: &[Function]) -> HashMap<XAddr, DataAccess>
+{
+    // direct `ld [nn]` / `ldh [n]` accesses, and accesses through register pairs whose value is known
+
+    let mut owners: HashMap<usize, Vec<XAddr>> = HashMap::new();
+
+    for function in functions
+    {
+        for &i in &function.blocks {
+            owners.entry(i).or_default().push(function.xa); }
+    }
+
+    let mut result: HashMap<XAddr, DataAccess> = HashMap::new();
+
+    for i in 0 .. graph.blocks.len()
+    {
+        let block_owners = owners.get(&i).cloned().unwrap_or_else(|| vec![graph.blocks[i].xa]);
+        let mut emu = graph.emu(i);
+
+        while let Some((_, Ok(ins))) = emu.next()
+        {
+            let flags = ins.info().flags;
+
+            if (flags & (gbasm::OPCODE_FLAG_READ_MEM | gbasm::OPCODE_FLAG_WRITE_MEM)) == 0 {
+                continue; }
+
+            let addr = if ins.is_addr_operand() { Some(ins.operand) } else { emu.regs().mem_pointer(&ins).map(|(_, addr)| addr) };
+
+            let target = match addr.and_then(|addr| emu.expand_addr(addr))
+            {
+                Some(target) => target,
+                None => continue,
+            };
+
+            let access = result.entry(target).or_default();
+
+            if (flags & gbasm::OPCODE_FLAG_READ_MEM) != 0 {
+                access.readers.extend(block_owners.iter().copied()); }
+
+            if (flags & gbasm::OPCODE_FLAG_WRITE_MEM) != 0 {
+                access.writers.extend(block_owners.iter().copied()); }
+        }
+    }
+
+    for access in result.values_mut()
+    {
+        access.readers.sort();
+        access.readers.dedup();
+        access.writers.sort();
+        access.writers.dedup();
+    }
+
+    result
+}
+
+pub fn find_owners(graph: &FlowGraph, functions: &[Function], region_starts: &HashSet<XAddr>) -> HashMap<usize, XAddr>
+{
+    // what each block is part of: the function it's in, or for code outside of functions, the start of the region it flows from
+    // (a block nothing jumps to, or one of region_starts, usually named by the user)
+
+    let mut owners: HashMap<usize, XAddr> = HashMap::new();
+
+    for function in functions
+    {
+        for &i in &function.blocks {
+            owners.entry(i).or_insert(function.xa); }
+    }
+
+    let mut has_pred = vec![false; graph.block_count()];
+
+    for i in 0 .. graph.block_count()
+    {
+        for j in graph.successors(i) {
+            has_pred[j] = true; }
+    }
+
+    let is_start: Vec<bool> = (0 .. graph.block_count())
+        .map(|i| !owners.contains_key(&i) && (!has_pred[i] || region_starts.contains(&graph.block(i).xa)))
+        .collect();
+
+    for start in (0 .. graph.block_count()).filter(|&i| is_start[i])
+    {
+        let mut queue = vec![start];
+        owners.insert(start, graph.block(start).xa);
+
+        while let Some(i) = queue.pop()
+        {
+            for j in graph.successors(i)
+            {
+                if !owners.contains_key(&j) && !is_start[j]
+                {
+                    owners.insert(j, graph.block(start).xa);
+                    queue.push(j);
+                }
+            }
+        }
+    }
+
+    owners
+}
+
+pub fn find_call_edges(graph: &FlowGraph, functions: &[Function], owners: &HashMap<usize, XAddr>) -> Vec<(XAddr, XAddr)>
+{
+    // (caller, callee) pairs for calls and tail calls; the caller is the owner of the block making the call (see find_owners)
+
+    let entries: HashSet<XAddr> = functions.iter().map(|function| function.xa).collect();
+
+    let mut result = vec![];
+
+    for i in 0 .. graph.block_count()
+    {
+        let caller = owners.get(&i).copied().unwrap_or(graph.block(i).xa);
+        let mut emu = graph.emu(i);
+
+        while let Some((xa, Ok(ins))) = emu.next()
+        {
+            if (ins.info().flags & gbasm::OPCODE_FLAG_JUMP) == 0 {
+                continue; }
+
+            let target = match emu.target(xa, &ins)
+            {
+                Some(target) => target,
+                None => continue,
+            };
+
+            if (ins.info().flags & gbasm::OPCODE_FLAG_CALL) != 0 || (entries.contains(&target) && target != caller) {
+                result.push((caller, target)); }
+        }
+    }
+
+    result.sort();
+    result.dedup();
+
+    result
+}
@@ -5,6 +5,8 @@
  */
 
 use std::ops::AddAssign;
+use std::sync::OnceLock;
+use thiserror::Error;
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum OperandKind
@@ -32,9 +34,12 @@ pub struct OpcodeInfo
     pub operand_len: u8,
     pub operand_kind: OperandKind,
     pub flags: u8,
+    pub cycles: u8,       // machine cycles (4 clocks each), with a conditional branch not taken
+    pub cycles_taken: u8, // with a conditional branch taken, the same as cycles for anything else
 }
 
 const OPCODE_BITOPS: u8 = 0xCB;
+const OPCODE_STOP: u8 = 0x10;
 const OPCODE_RST_00: u8 = 0xC7;
 const OPCODE_RST_08: u8 = 0xCF;
 const OPCODE_RST_10: u8 = 0xD7;
@@ -49,6 +54,8 @@ pub struct Instruction
 {
     pub opcode: u8,
     pub operand: u16,
+    pub halt_bug: bool, // decoded with its first byte read twice, after a `halt` hitting the DMG halt bug
+    pub bare_stop: bool, // `stop` not followed by its usual padding byte
 }
 
 impl Instruction
@@ -58,7 +65,9 @@ impl Instruction
         Instruction
         {
             opcode: 0,
-            operand: 0
+            operand: 0,
+            halt_bug: false,
+            bare_stop: false,
         }
     }
 
@@ -67,6 +76,7 @@ impl Instruction
         match self.opcode
         {
             OPCODE_BITOPS => &BITOPS_INFO[self.operand as usize],
+            OPCODE_STOP if self.bare_stop => &BARE_STOP_INFO,
             opcode => &OPCODE_INFO[opcode as usize],
         }
     }
@@ -78,7 +88,8 @@ impl Instruction
 
     pub fn encoded_len(&self) -> usize
     {
-        self.info().operand_len as usize + 1
+        // the byte read twice by the halt bug is only there once
+        self.info().operand_len as usize + 1 - self.halt_bug as usize
     }
 
     pub fn get_jump_target(&self) -> Option<u16>
@@ -104,6 +115,41 @@ impl Instruction
         }
     }
 
+    pub fn cycles(&self) -> (u32, u32)
+    {
+        // machine cycles with the branch taken and not taken, the same for anything not branching
+
+        let info = self.info();
+        (info.cycles_taken as u32, info.cycles as u32)
+    }
+
+    pub fn encode(&self, addr: u16) -> Vec<u8>
+    {
+        // the reverse of decode at addr: relative jump targets go back to offsets from the end of the instruction,
+        // `ldh` addresses lose their $FF00, and bit operations (or the padding of `stop`) are the byte after the opcode
+
+        let info = self.info();
+
+        // after the halt bug, relative jumps are relative to the real end of the instruction, one byte short
+        let addr = if self.halt_bug { addr.wrapping_sub(1) } else { addr };
+
+        let operand = match info.operand_kind
+        {
+            OperandKind::CodeRelative => self.operand.wrapping_sub(addr.wrapping_add(2)),
+            OperandKind::DataHram => self.operand.wrapping_sub(0xFF00),
+            _ => self.operand,
+        };
+
+        let mut result = vec![self.opcode];
+        result.extend_from_slice(&operand.to_le_bytes()[.. info.operand_len as usize]);
+
+        // the opcode the halt bug reads twice is the first byte of the operand
+        if self.halt_bug {
+            result.remove(0); }
+
+        result
+    }
+
     pub fn is_addr_operand(&self) -> bool
     {
         return self.info().flags & (OPCODE_FLAG_READ_MEM | OPCODE_FLAG_WRITE_MEM | OPCODE_FLAG_JUMP) != 0
@@ -112,6 +158,303 @@ impl Instruction
     }
 }
 
+// 8-bit registers, in the order opcodes encode them (6 being [hl])
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Reg
+{
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    HlMem,
+    A,
+}
+
+// register pairs of loads and arithmetic, in opcode order
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Reg16
+{
+    BC,
+    DE,
+    HL,
+    SP,
+}
+
+// register pairs of push and pop, af taking the place of sp
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Reg16Stack
+{
+    BC,
+    DE,
+    HL,
+    AF,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Cond
+{
+    NZ,
+    Z,
+    NC,
+    C,
+}
+
+// operations on a and an 8-bit operand, in opcode order
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Alu
+{
+    Add,
+    Adc,
+    Sub,
+    Sbc,
+    And,
+    Xor,
+    Or,
+    Cp,
+}
+
+// rotates and shifts after the $CB prefix, in opcode order
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Shift
+{
+    Rlc,
+    Rrc,
+    Rl,
+    Rr,
+    Sla,
+    Sra,
+    Swap,
+    Srl,
+}
+
+// where `rst` goes
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RstVector
+{
+    Rst00 = 0x00,
+    Rst08 = 0x08,
+    Rst10 = 0x10,
+    Rst18 = 0x18,
+    Rst20 = 0x20,
+    Rst28 = 0x28,
+    Rst30 = 0x30,
+    Rst38 = 0x38,
+}
+
+// a bit of an 8-bit register, 0 to 7
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Bit(u8);
+
+impl Bit
+{
+    pub fn new(bit: u8) -> Option<Self>
+    {
+        (bit < 8).then_some(Bit(bit))
+    }
+}
+
+// typed constructors, giving the instruction decode would for its encoding (absolute jr targets, $FF00-based ldh addresses)
+impl Instruction
+{
+    const fn op(opcode: u8, operand: u16) -> Self
+    {
+        Instruction { opcode: opcode, operand: operand, ..Instruction::new() }
+    }
+
+    const fn bitop(operand: u8) -> Self
+    {
+        Self::op(OPCODE_BITOPS, operand as u16)
+    }
+
+    pub const fn nop() -> Self
+    {
+        Self::op(0x00, 0)
+    }
+
+    pub const fn halt() -> Self
+    {
+        Self::op(0x76, 0)
+    }
+
+    pub const fn stop() -> Self
+    {
+        Self::op(OPCODE_STOP, 0)
+    }
+
+    pub const fn di() -> Self
+    {
+        Self::op(0xF3, 0)
+    }
+
+    pub const fn ei() -> Self
+    {
+        Self::op(0xFB, 0)
+    }
+
+    pub const fn ret() -> Self
+    {
+        Self::op(0xC9, 0)
+    }
+
+    pub const fn reti() -> Self
+    {
+        Self::op(0xD9, 0)
+    }
+
+    pub const fn jp_hl() -> Self
+    {
+        Self::op(0xE9, 0)
+    }
+
+    pub fn ld_r_r(dest: Reg, src: Reg) -> Option<Self>
+    {
+        // `ld [hl], [hl]` would be `halt`
+
+        match (dest, src)
+        {
+            (Reg::HlMem, Reg::HlMem) => None,
+            _ => Some(Self::op(0x40 | (dest as u8) << 3 | src as u8, 0)),
+        }
+    }
+
+    pub fn ld_r_imm(dest: Reg, value: u8) -> Self
+    {
+        Self::op(0x06 | (dest as u8) << 3, value as u16)
+    }
+
+    pub fn ld_rr_imm(dest: Reg16, value: u16) -> Self
+    {
+        Self::op(0x01 | (dest as u8) << 4, value)
+    }
+
+    pub fn ld_a_mem(addr: u16) -> Self
+    {
+        Self::op(0xFA, addr)
+    }
+
+    pub fn ld_mem_a(addr: u16) -> Self
+    {
+        Self::op(0xEA, addr)
+    }
+
+    pub fn ldh_a_mem(offset: u8) -> Self
+    {
+        Self::op(0xF0, 0xFF00 | offset as u16)
+    }
+
+    pub fn ldh_mem_a(offset: u8) -> Self
+    {
+        Self::op(0xE0, 0xFF00 | offset as u16)
+    }
+
+    pub fn inc_r(reg: Reg) -> Self
+    {
+        Self::op(0x04 | (reg as u8) << 3, 0)
+    }
+
+    pub fn dec_r(reg: Reg) -> Self
+    {
+        Self::op(0x05 | (reg as u8) << 3, 0)
+    }
+
+    pub fn inc_rr(pair: Reg16) -> Self
+    {
+        Self::op(0x03 | (pair as u8) << 4, 0)
+    }
+
+    pub fn dec_rr(pair: Reg16) -> Self
+    {
+        Self::op(0x0B | (pair as u8) << 4, 0)
+    }
+
+    pub fn add_hl(pair: Reg16) -> Self
+    {
+        Self::op(0x09 | (pair as u8) << 4, 0)
+    }
+
+    pub fn alu_r(alu: Alu, reg: Reg) -> Self
+    {
+        Self::op(0x80 | (alu as u8) << 3 | reg as u8, 0)
+    }
+
+    pub fn alu_imm(alu: Alu, value: u8) -> Self
+    {
+        Self::op(0xC6 | (alu as u8) << 3, value as u16)
+    }
+
+    pub fn push(pair: Reg16Stack) -> Self
+    {
+        Self::op(0xC5 | (pair as u8) << 4, 0)
+    }
+
+    pub fn pop(pair: Reg16Stack) -> Self
+    {
+        Self::op(0xC1 | (pair as u8) << 4, 0)
+    }
+
+    pub fn jp(addr: u16) -> Self
+    {
+        Self::op(0xC3, addr)
+    }
+
+    pub fn jp_cond(cond: Cond, addr: u16) -> Self
+    {
+        Self::op(0xC2 | (cond as u8) << 3, addr)
+    }
+
+    pub fn jr(target: u16) -> Self
+    {
+        Self::op(0x18, target)
+    }
+
+    pub fn jr_cond(cond: Cond, target: u16) -> Self
+    {
+        Self::op(0x20 | (cond as u8) << 3, target)
+    }
+
+    pub fn call(addr: u16) -> Self
+    {
+        Self::op(0xCD, addr)
+    }
+
+    pub fn call_cond(cond: Cond, addr: u16) -> Self
+    {
+        Self::op(0xC4 | (cond as u8) << 3, addr)
+    }
+
+    pub fn ret_cond(cond: Cond) -> Self
+    {
+        Self::op(0xC0 | (cond as u8) << 3, 0)
+    }
+
+    pub fn rst(vector: RstVector) -> Self
+    {
+        Self::op(0xC7 | vector as u8, 0)
+    }
+
+    pub fn shift(shift: Shift, reg: Reg) -> Self
+    {
+        Self::bitop((shift as u8) << 3 | reg as u8)
+    }
+
+    pub fn bit(bit: Bit, reg: Reg) -> Self
+    {
+        Self::bitop(0x40 | bit.0 << 3 | reg as u8)
+    }
+
+    pub fn res(bit: Bit, reg: Reg) -> Self
+    {
+        Self::bitop(0x80 | bit.0 << 3 | reg as u8)
+    }
+
+    pub fn set(bit: Bit, reg: Reg) -> Self
+    {
+        Self::bitop(0xC0 | bit.0 << 3 | reg as u8)
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum DecodeError
 {
@@ -119,6 +462,42 @@ pub enum DecodeError
     InvalidOpcode,
 }
 
+pub fn opcode_info(opcode: u8) -> &'static OpcodeInfo
+{
+    // what the first byte alone tells, for passes that don't need the operand; `CB` gives the prefix, not a bit operation
+
+    &OPCODE_INFO[opcode as usize]
+}
+
+pub fn is_valid_opcode(opcode: u8) -> bool
+{
+    (opcode_info(opcode).flags & OPCODE_FLAG_INVALID) == 0
+}
+
+pub fn encoded_len(opcode: u8) -> usize
+{
+    // `stop` counts its padding byte, as decode does by default
+
+    opcode_info(opcode).operand_len as usize + 1
+}
+
+pub fn cycles(opcode: u8) -> Option<(u32, u32)>
+{
+    // as Instruction::cycles; the second byte of a bit operation decides, see bitops_cycles
+
+    match opcode
+    {
+        OPCODE_BITOPS => None,
+        _ => Some((opcode_info(opcode).cycles_taken as u32, opcode_info(opcode).cycles as u32)),
+    }
+}
+
+pub fn bitops_cycles(bitop: u8) -> (u32, u32)
+{
+    let info = &BITOPS_INFO[bitop as usize];
+    (info.cycles_taken as u32, info.cycles as u32)
+}
+
 pub type DecodeResult = Result<Instruction, DecodeError>;
 
 pub fn decode(addr: u16, slice: &[u8]) -> DecodeResult
@@ -143,7 +522,7 @@ pub fn decode(addr: u16, slice: &[u8]) -> DecodeResult
         return Err(DecodeError::SliceTooSmall); }
 
     for i in 0 .. len-1 {
-        result.operand += (slice[1+i] as u16) << i*8; }
+        result.operand += (slice[1+i] as u16) << (i*8); }
 
     // fix operand if necessary
 
@@ -161,11 +540,43 @@ pub fn decode(addr: u16, slice: &[u8]) -> DecodeResult
     Ok(result)
 }
 
+pub fn decode_stop(addr: u16, slice: &[u8], stop_padding: bool) -> DecodeResult
+{
+    // `stop` is 2 bytes long by convention, some games leave out the second
+
+    match slice.first()
+    {
+        Some(&OPCODE_STOP) if !stop_padding => Ok(Instruction { opcode: OPCODE_STOP, bare_stop: true, ..Instruction::new() }),
+        _ => decode(addr, slice),
+    }
+}
+
+pub fn decode_halt_bug(addr: u16, slice: &[u8]) -> DecodeResult
+{
+    // decodes the instruction following a `halt` that hit the halt bug: the cpu fails to advance pc
+    // past the next byte, so that it's read twice (`3E 14` runs as `ld a, $3E / inc d`)
+
+    if slice.is_empty() {
+        return Err(DecodeError::SliceTooSmall); }
+
+    let doubled: Vec<u8> = std::iter::once(slice[0]).chain(slice.iter().copied()).take(3).collect();
+
+    // relative jumps are relative to the real end of the instruction, one byte short
+    let mut result = decode(addr.wrapping_sub(1), &doubled)?;
+    result.halt_bug = true;
+
+    Ok(result)
+}
+
 pub struct DecodeSliceIter<'a, T>
     where T: Copy + AddAssign<u16> + Into<u16>
 {
     addr: T,
     slice: &'a [u8],
+
+    // where the iterator started, for seeking
+    base: T,
+    whole: &'a [u8],
 }
 
 impl<'a, T> Iterator for DecodeSliceIter<'a, T>
@@ -175,7 +586,59 @@ impl<'a, T> Iterator for DecodeSliceIter<'a, T>
 
     fn next(&mut self) -> Option<(T, DecodeResult)>
     {
-        if self.slice.len() == 0 {
+        self.next_with(decode)
+    }
+}
+
+impl<'a, T> DecodeSliceIter<'a, T>
+    where T: Copy + AddAssign<u16> + Into<u16>
+{
+    pub fn position(&self) -> T
+    {
+        self.addr
+    }
+
+    pub fn peek(&self) -> Option<(T, DecodeResult)>
+    {
+        // what next would give, without moving
+
+        self.peek_with(decode)
+    }
+
+    pub fn peek_with<F>(&self, decode: F) -> Option<(T, DecodeResult)>
+        where F: FnOnce(u16, &[u8]) -> DecodeResult
+    {
+        // what next_with would give with the same decode, without moving
+
+        if self.slice.is_empty() {
+            return None; }
+
+        Some((self.addr, decode(self.addr.into(), self.slice)))
+    }
+
+    pub fn seek_to(&mut self, addr: T) -> bool
+    {
+        // addr is in the same address space as position; false (and not moving) if it's outside the slice
+
+        let offset = match addr.into().checked_sub(self.base.into())
+        {
+            Some(offset) if (offset as usize) <= self.whole.len() => offset,
+            _ => return false,
+        };
+
+        self.addr = self.base;
+        self.addr += offset;
+        self.slice = &self.whole[offset as usize ..];
+
+        true
+    }
+
+    pub fn next_with<F>(&mut self, decode: F) -> Option<(T, DecodeResult)>
+        where F: FnOnce(u16, &[u8]) -> DecodeResult
+    {
+        // like next, decoding with something else than the plain decode (halt bug, `stop` length)
+
+        if self.slice.is_empty() {
             return None; }
 
         let (addr, ins) = (self.addr, decode(self.addr.into(), self.slice));
@@ -197,10 +660,250 @@ pub fn decode_slice<'a, T>(addr: T, slice: &'a [u8]) -> DecodeSliceIter<'a, T>
     {
         addr: addr,
         slice: slice,
+
+        base: addr,
+        whole: slice,
     }
 }
 
-const fn opi(fmt: &'static str, operand_len: u8, operand_kind: OperandKind, flags: u8) -> OpcodeInfo
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Decoded
+{
+    Instruction(Instruction),
+    DataByte(u8), // an invalid opcode, or a byte of an instruction cut short by the end of the slice
+}
+
+pub struct GaplessIter<'a, T>
+    where T: Copy + AddAssign<u16> + Into<u16>
+{
+    inner: DecodeSliceIter<'a, T>,
+    tail: bool, // past an instruction cut short, what's left is data
+}
+
+impl<'a, T> Iterator for GaplessIter<'a, T>
+    where T: Copy + AddAssign<u16> + Into<u16>
+{
+    type Item = (T, Decoded);
+
+    fn next(&mut self) -> Option<(T, Decoded)>
+    {
+        if !self.tail
+        {
+            match self.inner.next()?
+            {
+                (addr, Ok(ins)) => return Some((addr, Decoded::Instruction(ins))),
+                (_, Err(DecodeError::SliceTooSmall)) => self.tail = true,
+                (_, Err(DecodeError::InvalidOpcode)) => {}
+            }
+        }
+
+        // decode doesn't move past what it fails on, the byte is skipped here
+
+        let (addr, byte) = (self.inner.addr, *self.inner.slice.first()?);
+
+        self.inner.addr += 1;
+        self.inner.slice = &self.inner.slice[1 ..];
+
+        Some((addr, Decoded::DataByte(byte)))
+    }
+}
+
+pub fn decode_slice_gapless<'a, T>(addr: T, slice: &'a [u8]) -> GaplessIter<'a, T>
+    where T: Copy + AddAssign<u16> + Into<u16>
+{
+    // every byte of the slice is covered, what doesn't decode coming as data bytes
+
+    GaplessIter
+    {
+        inner: decode_slice(addr, slice),
+        tail: false,
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ParseInstructionError
+{
+    #[error("Parse Int error")]
+    ParseInt(#[from] std::num::ParseIntError),
+
+    #[error("No instruction is written `{0}`")]
+    UnknownInstruction(String),
+
+    #[error("`{0}` doesn't fit in the operand of `{1}`")]
+    OperandTooLarge(String, String),
+}
+
+const ALU_MNEMONICS: [&str; 8] = ["add", "adc", "sub", "sbc", "and", "xor", "or", "cp"];
+
+pub fn normalize(text: &str) -> String
+{
+    // spaces, case and the usual spellings of post-increment/decrement don't matter;
+    // one space is kept after the mnemonic so that `rlc a` isn't `rlca`
+
+    let text = text.trim().to_lowercase().replace("hl+", "hli").replace("hl-", "hld");
+
+    match text.split_once(char::is_whitespace)
+    {
+        Some((mnemonic, operands)) => format!("{} {}", mnemonic, operands.replace(char::is_whitespace, "")),
+        None => text,
+    }
+}
+
+pub fn parse_number(text: &str) -> Result<u16, std::num::ParseIntError>
+{
+    // rgbds notation ($hex, %binary, decimal), and 0x for good measure; negative values wrap around
+
+    if let Some(positive) = text.strip_prefix('-') {
+        return Ok(parse_number(positive)?.wrapping_neg()); }
+
+    if let Some(hex) = text.strip_prefix('$').or_else(|| text.strip_prefix("0x")) {
+        u16::from_str_radix(hex, 16) }
+    else if let Some(bin) = text.strip_prefix('%') {
+        u16::from_str_radix(bin, 2) }
+    else {
+        text.parse() }
+}
+
+pub fn all_instructions() -> Vec<Instruction>
+{
+    // one of each valid opcode, bit operations included
+
+    let mut result = vec![];
+
+    for opcode in 0 ..= 0xFF
+    {
+        let ins = Instruction { opcode: opcode, ..Instruction::new() };
+
+        if opcode == OPCODE_BITOPS {
+            result.extend((0 ..= 0xFF).map(|operand| Instruction { opcode: opcode, operand: operand, ..Instruction::new() })); }
+        else if ins.is_valid() {
+            result.push(ins); }
+    }
+
+    result
+}
+
+fn shapes() -> &'static [(String, Instruction)]
+{
+    // every instruction with its normalized format, by opcode; made once as the parser and search go through it a lot
+
+    static SHAPES: OnceLock<Vec<(String, Instruction)>> = OnceLock::new();
+
+    SHAPES.get_or_init(|| all_instructions().into_iter().map(|ins| (normalize(ins.info().fmt), ins)).collect())
+}
+
+pub fn lookup_shape(shape: &str) -> Vec<Instruction>
+{
+    // the instructions written as shape, `%` standing for the operand (`ld a, [%]`, `rst $38`)
+
+    let shape = normalize(shape);
+
+    shapes().iter().filter(|(fmt, _)| *fmt == shape).map(|(_, ins)| *ins).collect()
+}
+
+pub fn lookup_mnemonic(mnemonic: &str) -> Vec<Instruction>
+{
+    // every instruction of a mnemonic (`ld` gives all loads), bit operations included
+
+    let mnemonic = mnemonic.trim().to_lowercase();
+
+    shapes().iter().filter(|(fmt, _)| fmt.split(' ').next() == Some(mnemonic.as_str())).map(|(_, ins)| *ins).collect()
+}
+
+pub fn candidates(text: &str) -> Vec<(Instruction, Option<String>)>
+{
+    // the instructions whose format could give text, with the text standing for the operand of those having one;
+    // the operand text isn't checked, `ld a, [hl]` is a candidate for `ld a, [%]` with `hl`
+
+    let wanted = normalize(text);
+    let mut result = vec![];
+
+    for (fmt, ins) in shapes()
+    {
+        match fmt.split_once('%')
+        {
+            Some((prefix, suffix)) => match wanted.strip_prefix(prefix).and_then(|rest| rest.strip_suffix(suffix))
+            {
+                Some(operand) if !operand.is_empty() => result.push((*ins, Some(operand.to_string()))),
+                _ => {}
+            },
+
+            None if *fmt == wanted => result.push((*ins, None)),
+            None => {}
+        }
+    }
+
+    result
+}
+
+fn match_instruction(wanted: &str, text: &str) -> Result<Option<Instruction>, ParseInstructionError>
+{
+    // the instruction whose normalized format gives wanted, if any
+
+    for (ins, operand) in candidates(wanted)
+    {
+        let info = ins.info();
+
+        let operand = match operand
+        {
+            Some(operand) => operand,
+            None => return Ok(Some(ins)),
+        };
+
+        let value = match parse_number(&operand)
+        {
+            Ok(value) => value,
+            Err(_) => continue, // another instruction reads the same up to the operand (`ld a, [hl]` for `ld a, %`)
+        };
+
+        let too_large = || ParseInstructionError::OperandTooLarge(operand.clone(), text.trim().to_string());
+
+        let value = match (info.operand_kind, info.operand_len)
+        {
+            (OperandKind::CodeRelative, _) => value,
+            (OperandKind::DataHram, _) if value >= 0xFF00 => value,
+            (OperandKind::DataHram, _) if value <= 0xFF => 0xFF00 + value,
+            (OperandKind::DataHram, _) => return Err(too_large()),
+
+            // small negative values, as with `add sp, -2`
+            (_, 1) if value >= 0xFF80 => value & 0xFF,
+            (_, 1) if value > 0xFF => return Err(too_large()),
+            _ => value,
+        };
+
+        return Ok(Some(Instruction { operand: value, ..ins }));
+    }
+
+    Ok(None)
+}
+
+pub fn parse_instruction(text: &str) -> Result<Instruction, ParseInstructionError>
+{
+    // an instruction as the listing writes it (`ld a, [$C000]`, `jr nz, $4010`, `ldh [$FF40], a`), jump targets being absolute
+    // rgbds shorthands are accepted too: `cp 5` for `cp a, 5`, `ldh [$40], a`, `[hl+]`, `ld hl, sp-2`
+
+    let wanted = normalize(text).replace("sp-", "sp+-");
+
+    if let Some(ins) = match_instruction(&wanted, text)? {
+        return Ok(ins); }
+
+    // operations on a without the `a, `
+
+    if let Some((mnemonic, operand)) = wanted.split_once(' ').filter(|(mnemonic, _)| ALU_MNEMONICS.contains(mnemonic))
+    {
+        if let Some(ins) = match_instruction(&format!("{} a,{}", mnemonic, operand), text)? {
+            return Ok(ins); }
+    }
+
+    Err(ParseInstructionError::UnknownInstruction(text.trim().to_string()))
+}
+
+const fn opi(fmt: &'static str, operand_len: u8, operand_kind: OperandKind, flags: u8, cycles: u8) -> OpcodeInfo
+{
+    opi_cond(fmt, operand_len, operand_kind, flags, cycles, cycles)
+}
+
+const fn opi_cond(fmt: &'static str, operand_len: u8, operand_kind: OperandKind, flags: u8, cycles_taken: u8, cycles: u8) -> OpcodeInfo
 {
     OpcodeInfo
     {
@@ -208,525 +911,288 @@ const fn opi(fmt: &'static str, operand_len: u8, operand_kind: OperandKind, flag
         operand_len: operand_len,
         operand_kind: operand_kind,
         flags: flags,
+        cycles: cycles,
+        cycles_taken: cycles_taken,
     }
 }
 
-const OPCODE_INFO: [OpcodeInfo; 0x100] =
-[
-    /* 00 */ opi("nop", 0, OperandKind::None, 0),
-    /* 01 */ opi("ld bc, %", 2, OperandKind::Undefined, 0),
-    /* 02 */ opi("ld [bc], a", 0, OperandKind::None, OPCODE_FLAG_WRITE_MEM),
-    /* 03 */ opi("inc bc", 0, OperandKind::None, 0),
-    /* 04 */ opi("inc b", 0, OperandKind::None, 0),
-    /* 05 */ opi("dec b", 0, OperandKind::None, 0),
-    /* 06 */ opi("ld b, %", 1, OperandKind::Undefined, 0),
-    /* 07 */ opi("rlca", 0, OperandKind::None, 0),
-    /* 08 */ opi("ld [%], sp", 2, OperandKind::Data, OPCODE_FLAG_WRITE_MEM),
-    /* 09 */ opi("add hl, bc", 0, OperandKind::None, 0),
-    /* 0A */ opi("ld a, [bc]", 0, OperandKind::None, OPCODE_FLAG_READ_MEM),
-    /* 0B */ opi("dec bc", 0, OperandKind::None, 0),
-    /* 0C */ opi("inc c", 0, OperandKind::None, 0),
-    /* 0D */ opi("dec c", 0, OperandKind::None, 0),
-    /* 0E */ opi("ld c, %", 1, OperandKind::Undefined, 0),
-    /* 0F */ opi("rrca", 0, OperandKind::None, 0),
-    /* 10 */ opi("stop", 1, OperandKind::LongOpcode, 0),
-    /* 11 */ opi("ld de, %", 2, OperandKind::Undefined, 0),
-    /* 12 */ opi("ld [de], a", 0, OperandKind::None, OPCODE_FLAG_WRITE_MEM),
-    /* 13 */ opi("inc de", 0, OperandKind::None, 0),
-    /* 14 */ opi("inc d", 0, OperandKind::None, 0),
-    /* 15 */ opi("dec d", 0, OperandKind::None, 0),
-    /* 16 */ opi("ld d, %", 1, OperandKind::Undefined, 0),
-    /* 17 */ opi("rla", 0, OperandKind::None, 0),
-    /* 18 */ opi("jr %", 1, OperandKind::CodeRelative, OPCODE_FLAG_JUMP),
-    /* 19 */ opi("add hl, de", 0, OperandKind::None, 0),
-    /* 1A */ opi("ld a, [de]", 0, OperandKind::None, OPCODE_FLAG_READ_MEM),
-    /* 1B */ opi("dec de", 0, OperandKind::None, 0),
-    /* 1C */ opi("inc e", 0, OperandKind::None, 0),
-    /* 1D */ opi("dec e", 0, OperandKind::None, 0),
-    /* 1E */ opi("ld e, %", 1, OperandKind::Undefined, 0),
-    /* 1F */ opi("rra", 0, OperandKind::None, 0),
-    /* 20 */ opi("jr nz, %", 1, OperandKind::CodeRelative, OPCODE_FLAG_JUMP | OPCODE_FLAG_CONDITIONAL),
-    /* 21 */ opi("ld hl, %", 2, OperandKind::Undefined, 0),
-    /* 22 */ opi("ld [hli], a", 0, OperandKind::None, OPCODE_FLAG_WRITE_MEM),
-    /* 23 */ opi("inc hl", 0, OperandKind::None, 0),
-    /* 24 */ opi("inc h", 0, OperandKind::None, 0),
-    /* 25 */ opi("dec h", 0, OperandKind::None, 0),
-    /* 26 */ opi("ld h, %", 1, OperandKind::Undefined, 0),
-    /* 27 */ opi("daa", 0, OperandKind::None, 0),
-    /* 28 */ opi("jr z, %", 1, OperandKind::CodeRelative, OPCODE_FLAG_JUMP | OPCODE_FLAG_CONDITIONAL),
-    /* 29 */ opi("add hl, hl", 0, OperandKind::None, 0),
-    /* 2A */ opi("ld a, [hli]", 0, OperandKind::None, OPCODE_FLAG_READ_MEM),
-    /* 2B */ opi("dec hl", 0, OperandKind::None, 0),
-    /* 2C */ opi("inc l", 0, OperandKind::None, 0),
-    /* 2D */ opi("dec l", 0, OperandKind::None, 0),
-    /* 2E */ opi("ld l, %", 1, OperandKind::Undefined, 0),
-    /* 2F */ opi("cpl", 0, OperandKind::None, 0),
-    /* 30 */ opi("jr nc, %", 1, OperandKind::CodeRelative, OPCODE_FLAG_JUMP | OPCODE_FLAG_CONDITIONAL),
-    /* 31 */ opi("ld sp, %", 2, OperandKind::Undefined, 0),
-    /* 32 */ opi("ld [hld], a", 0, OperandKind::None, OPCODE_FLAG_WRITE_MEM),
-    /* 33 */ opi("inc sp", 0, OperandKind::None, 0),
-    /* 34 */ opi("inc [hl]", 0, OperandKind::None, OPCODE_FLAG_WRITE_MEM | OPCODE_FLAG_READ_MEM),
-    /* 35 */ opi("dec [hl]", 0, OperandKind::None, OPCODE_FLAG_WRITE_MEM | OPCODE_FLAG_READ_MEM),
-    /* 36 */ opi("ld [hl], %", 1, OperandKind::Undefined, OPCODE_FLAG_WRITE_MEM),
-    /* 37 */ opi("scf", 0, OperandKind::None, 0),
-    /* 38 */ opi("jr c, %", 1, OperandKind::CodeRelative, OPCODE_FLAG_JUMP | OPCODE_FLAG_CONDITIONAL),
-    /* 39 */ opi("add hl, sp", 0, OperandKind::None, 0),
-    /* 3A */ opi("ld a, [hld]", 0, OperandKind::None, OPCODE_FLAG_READ_MEM),
-    /* 3B */ opi("dec sp", 0, OperandKind::None, 0),
-    /* 3C */ opi("inc a", 0, OperandKind::None, 0),
-    /* 3D */ opi("dec a", 0, OperandKind::None, 0),
-    /* 3E */ opi("ld a, %", 1, OperandKind::Undefined, 0),
-    /* 3F */ opi("ccf", 0, OperandKind::None, 0),
-    /* 40 */ opi("ld b, b", 0, OperandKind::None, 0),
-    /* 41 */ opi("ld b, c", 0, OperandKind::None, 0),
-    /* 42 */ opi("ld b, d", 0, OperandKind::None, 0),
-    /* 43 */ opi("ld b, e", 0, OperandKind::None, 0),
-    /* 44 */ opi("ld b, h", 0, OperandKind::None, 0),
-    /* 45 */ opi("ld b, l", 0, OperandKind::None, 0),
-    /* 46 */ opi("ld b, [hl]", 0, OperandKind::None, OPCODE_FLAG_READ_MEM),
-    /* 47 */ opi("ld b, a", 0, OperandKind::None, 0),
-    /* 48 */ opi("ld c, b", 0, OperandKind::None, 0),
-    /* 49 */ opi("ld c, c", 0, OperandKind::None, 0),
-    /* 4A */ opi("ld c, d", 0, OperandKind::None, 0),
-    /* 4B */ opi("ld c, e", 0, OperandKind::None, 0),
-    /* 4C */ opi("ld c, h", 0, OperandKind::None, 0),
-    /* 4D */ opi("ld c, l", 0, OperandKind::None, 0),
-    /* 4E */ opi("ld c, [hl]", 0, OperandKind::None, OPCODE_FLAG_READ_MEM),
-    /* 4F */ opi("ld c, a", 0, OperandKind::None, 0),
-    /* 50 */ opi("ld d, b", 0, OperandKind::None, 0),
-    /* 51 */ opi("ld d, c", 0, OperandKind::None, 0),
-    /* 52 */ opi("ld d, d", 0, OperandKind::None, 0),
-    /* 53 */ opi("ld d, e", 0, OperandKind::None, 0),
-    /* 54 */ opi("ld d, h", 0, OperandKind::None, 0),
-    /* 55 */ opi("ld d, l", 0, OperandKind::None, 0),
-    /* 56 */ opi("ld d, [hl]", 0, OperandKind::None, OPCODE_FLAG_READ_MEM),
-    /* 57 */ opi("ld d, a", 0, OperandKind::None, 0),
-    /* 58 */ opi("ld e, b", 0, OperandKind::None, 0),
-    /* 59 */ opi("ld e, c", 0, OperandKind::None, 0),
-    /* 5A */ opi("ld e, d", 0, OperandKind::None, 0),
-    /* 5B */ opi("ld e, e", 0, OperandKind::None, 0),
-    /* 5C */ opi("ld e, h", 0, OperandKind::None, 0),
-    /* 5D */ opi("ld e, l", 0, OperandKind::None, 0),
-    /* 5E */ opi("ld e, [hl]", 0, OperandKind::None, OPCODE_FLAG_READ_MEM),
-    /* 5F */ opi("ld e, a", 0, OperandKind::None, 0),
-    /* 60 */ opi("ld h, b", 0, OperandKind::None, 0),
-    /* 61 */ opi("ld h, c", 0, OperandKind::None, 0),
-    /* 62 */ opi("ld h, d", 0, OperandKind::None, 0),
-    /* 63 */ opi("ld h, e", 0, OperandKind::None, 0),
-    /* 64 */ opi("ld h, h", 0, OperandKind::None, 0),
-    /* 65 */ opi("ld h, l", 0, OperandKind::None, 0),
-    /* 66 */ opi("ld h, [hl]", 0, OperandKind::None, OPCODE_FLAG_READ_MEM),
-    /* 67 */ opi("ld h, a", 0, OperandKind::None, 0),
-    /* 68 */ opi("ld l, b", 0, OperandKind::None, 0),
-    /* 69 */ opi("ld l, c", 0, OperandKind::None, 0),
-    /* 6A */ opi("ld l, d", 0, OperandKind::None, 0),
-    /* 6B */ opi("ld l, e", 0, OperandKind::None, 0),
-    /* 6C */ opi("ld l, h", 0, OperandKind::None, 0),
-    /* 6D */ opi("ld l, l", 0, OperandKind::None, 0),
-    /* 6E */ opi("ld l, [hl]", 0, OperandKind::None, OPCODE_FLAG_READ_MEM),
-    /* 6F */ opi("ld l, a", 0, OperandKind::None, 0),
-    /* 70 */ opi("ld [hl], b", 0, OperandKind::None, OPCODE_FLAG_WRITE_MEM),
-    /* 71 */ opi("ld [hl], c", 0, OperandKind::None, OPCODE_FLAG_WRITE_MEM),
-    /* 72 */ opi("ld [hl], d", 0, OperandKind::None, OPCODE_FLAG_WRITE_MEM),
-    /* 73 */ opi("ld [hl], e", 0, OperandKind::None, OPCODE_FLAG_WRITE_MEM),
-    /* 74 */ opi("ld [hl], h", 0, OperandKind::None, OPCODE_FLAG_WRITE_MEM),
-    /* 75 */ opi("ld [hl], l", 0, OperandKind::None, OPCODE_FLAG_WRITE_MEM),
-    /* 76 */ opi("halt", 0, OperandKind::None, 0),
-    /* 77 */ opi("ld [hl], a", 0, OperandKind::None, OPCODE_FLAG_WRITE_MEM),
-    /* 78 */ opi("ld a, b", 0, OperandKind::None, 0),
-    /* 79 */ opi("ld a, c", 0, OperandKind::None, 0),
-    /* 7A */ opi("ld a, d", 0, OperandKind::None, 0),
-    /* 7B */ opi("ld a, e", 0, OperandKind::None, 0),
-    /* 7C */ opi("ld a, h", 0, OperandKind::None, 0),
-    /* 7D */ opi("ld a, l", 0, OperandKind::None, 0),
-    /* 7E */ opi("ld a, [hl]", 0, OperandKind::None, OPCODE_FLAG_READ_MEM),
-    /* 7F */ opi("ld a, a", 0, OperandKind::None, 0),
-    /* 80 */ opi("add a, b", 0, OperandKind::None, 0),
-    /* 81 */ opi("add a, c", 0, OperandKind::None, 0),
-    /* 82 */ opi("add a, d", 0, OperandKind::None, 0),
-    /* 83 */ opi("add a, e", 0, OperandKind::None, 0),
-    /* 84 */ opi("add a, h", 0, OperandKind::None, 0),
-    /* 85 */ opi("add a, l", 0, OperandKind::None, 0),
-    /* 86 */ opi("add a, [hl]", 0, OperandKind::None, OPCODE_FLAG_READ_MEM),
-    /* 87 */ opi("add a, a", 0, OperandKind::None, 0),
-    /* 88 */ opi("adc a, b", 0, OperandKind::None, 0),
-    /* 89 */ opi("adc a, c", 0, OperandKind::None, 0),
-    /* 8A */ opi("adc a, d", 0, OperandKind::None, 0),
-    /* 8B */ opi("adc a, e", 0, OperandKind::None, 0),
-    /* 8C */ opi("adc a, h", 0, OperandKind::None, 0),
-    /* 8D */ opi("adc a, l", 0, OperandKind::None, 0),
-    /* 8E */ opi("adc a, [hl]", 0, OperandKind::None, OPCODE_FLAG_READ_MEM),
-    /* 8F */ opi("adc a, a", 0, OperandKind::None, 0),
-    /* 90 */ opi("sub a, b", 0, OperandKind::None, 0),
-    /* 91 */ opi("sub a, c", 0, OperandKind::None, 0),
-    /* 92 */ opi("sub a, d", 0, OperandKind::None, 0),
-    /* 93 */ opi("sub a, e", 0, OperandKind::None, 0),
-    /* 94 */ opi("sub a, h", 0, OperandKind::None, 0),
-    /* 95 */ opi("sub a, l", 0, OperandKind::None, 0),
-    /* 96 */ opi("sub a, [hl]", 0, OperandKind::None, OPCODE_FLAG_READ_MEM),
-    /* 97 */ opi("sub a, a", 0, OperandKind::None, 0),
-    /* 98 */ opi("sbc a, b", 0, OperandKind::None, 0),
-    /* 99 */ opi("sbc a, c", 0, OperandKind::None, 0),
-    /* 9A */ opi("sbc a, d", 0, OperandKind::None, 0),
-    /* 9B */ opi("sbc a, e", 0, OperandKind::None, 0),
-    /* 9C */ opi("sbc a, h", 0, OperandKind::None, 0),
-    /* 9D */ opi("sbc a, l", 0, OperandKind::None, 0),
-    /* 9E */ opi("sbc a, [hl]", 0, OperandKind::None, OPCODE_FLAG_READ_MEM),
-    /* 9F */ opi("sbc a, a", 0, OperandKind::None, 0),
-    /* A0 */ opi("and a, b", 0, OperandKind::None, 0),
-    /* A1 */ opi("and a, c", 0, OperandKind::None, 0),
-    /* A2 */ opi("and a, d", 0, OperandKind::None, 0),
-    /* A3 */ opi("and a, e", 0, OperandKind::None, 0),
-    /* A4 */ opi("and a, h", 0, OperandKind::None, 0),
-    /* A5 */ opi("and a, l", 0, OperandKind::None, 0),
-    /* A6 */ opi("and a, [hl]", 0, OperandKind::None, OPCODE_FLAG_READ_MEM),
-    /* A7 */ opi("and a, a", 0, OperandKind::None, 0),
-    /* A8 */ opi("xor a, b", 0, OperandKind::None, 0),
-    /* A9 */ opi("xor a, c", 0, OperandKind::None, 0),
-    /* AA */ opi("xor a, d", 0, OperandKind::None, 0),
-    /* AB */ opi("xor a, e", 0, OperandKind::None, 0),
-    /* AC */ opi("xor a, h", 0, OperandKind::None, 0),
-    /* AD */ opi("xor a, l", 0, OperandKind::None, 0),
-    /* AE */ opi("xor a, [hl]", 0, OperandKind::None, OPCODE_FLAG_READ_MEM),
-    /* AF */ opi("xor a, a", 0, OperandKind::None, 0),
-    /* B0 */ opi("or a, b", 0, OperandKind::None, 0),
-    /* B1 */ opi("or a, c", 0, OperandKind::None, 0),
-    /* B2 */ opi("or a, d", 0, OperandKind::None, 0),
-    /* B3 */ opi("or a, e", 0, OperandKind::None, 0),
-    /* B4 */ opi("or a, h", 0, OperandKind::None, 0),
-    /* B5 */ opi("or a, l", 0, OperandKind::None, 0),
-    /* B6 */ opi("or a, [hl]", 0, OperandKind::None, OPCODE_FLAG_READ_MEM),
-    /* B7 */ opi("or a, a", 0, OperandKind::None, 0),
-    /* B8 */ opi("cp a, b", 0, OperandKind::None, 0),
-    /* B9 */ opi("cp a, c", 0, OperandKind::None, 0),
-    /* BA */ opi("cp a, d", 0, OperandKind::None, 0),
-    /* BB */ opi("cp a, e", 0, OperandKind::None, 0),
-    /* BC */ opi("cp a, h", 0, OperandKind::None, 0),
-    /* BD */ opi("cp a, l", 0, OperandKind::None, 0),
-    /* BE */ opi("cp a, [hl]", 0, OperandKind::None, OPCODE_FLAG_READ_MEM),
-    /* BF */ opi("cp a, a", 0, OperandKind::None, 0),
-    /* C0 */ opi("ret nz", 0, OperandKind::None, OPCODE_FLAG_JUMP | OPCODE_FLAG_CONDITIONAL),
-    /* C1 */ opi("pop bc", 0, OperandKind::None, 0),
-    /* C2 */ opi("jp nz, %", 2, OperandKind::Code, OPCODE_FLAG_JUMP | OPCODE_FLAG_CONDITIONAL),
-    /* C3 */ opi("jp %", 2, OperandKind::Code, OPCODE_FLAG_JUMP),
-    /* C4 */ opi("call nz, %", 2, OperandKind::Code, OPCODE_FLAG_JUMP | OPCODE_FLAG_CALL | OPCODE_FLAG_CONDITIONAL),
-    /* C5 */ opi("push bc", 0, OperandKind::None, 0),
-    /* C6 */ opi("add a, %", 1, OperandKind::Undefined, 0),
-    /* C7 */ opi("rst $0", 0, OperandKind::None, OPCODE_FLAG_JUMP | OPCODE_FLAG_CALL),
-    /* C8 */ opi("ret z", 0, OperandKind::None, OPCODE_FLAG_JUMP | OPCODE_FLAG_CONDITIONAL),
-    /* C9 */ opi("ret", 0, OperandKind::None, OPCODE_FLAG_JUMP),
-    /* CA */ opi("jp z, %", 2, OperandKind::Code, OPCODE_FLAG_JUMP | OPCODE_FLAG_CONDITIONAL),
-    /* CB */ opi("bitops", 1, OperandKind::None, 0),
-    /* CC */ opi("call z, %", 2, OperandKind::Code, OPCODE_FLAG_JUMP | OPCODE_FLAG_CALL | OPCODE_FLAG_CONDITIONAL),
-    /* CD */ opi("call %", 2, OperandKind::Code, OPCODE_FLAG_JUMP | OPCODE_FLAG_CALL),
-    /* CE */ opi("adc a, %", 1, OperandKind::Undefined, 0),
-    /* CF */ opi("rst $8", 0, OperandKind::None, OPCODE_FLAG_JUMP | OPCODE_FLAG_CALL),
-    /* D0 */ opi("ret nc", 0, OperandKind::None, OPCODE_FLAG_JUMP | OPCODE_FLAG_CONDITIONAL),
-    /* D1 */ opi("pop de", 0, OperandKind::None, 0),
-    /* D2 */ opi("jp nc, %", 2, OperandKind::Code, OPCODE_FLAG_JUMP | OPCODE_FLAG_CONDITIONAL),
-    /* D3 */ opi("", 0, OperandKind::None, OPCODE_FLAG_INVALID),
-    /* D4 */ opi("call nc, %", 2, OperandKind::Code, OPCODE_FLAG_JUMP | OPCODE_FLAG_CALL | OPCODE_FLAG_CONDITIONAL),
-    /* D5 */ opi("push de", 0, OperandKind::None, 0),
-    /* D6 */ opi("sub a, %", 1, OperandKind::Undefined, 0),
-    /* D7 */ opi("rst $10", 0, OperandKind::None, OPCODE_FLAG_JUMP | OPCODE_FLAG_CALL),
-    /* D8 */ opi("ret c", 0, OperandKind::None, OPCODE_FLAG_JUMP | OPCODE_FLAG_CONDITIONAL),
-    /* D9 */ opi("reti", 0, OperandKind::None, OPCODE_FLAG_JUMP),
-    /* DA */ opi("jp c, %", 2, OperandKind::Code, OPCODE_FLAG_JUMP | OPCODE_FLAG_CONDITIONAL),
-    /* DB */ opi("", 0, OperandKind::None, OPCODE_FLAG_INVALID),
-    /* DC */ opi("call c, %", 2, OperandKind::Code, OPCODE_FLAG_JUMP | OPCODE_FLAG_CALL | OPCODE_FLAG_CONDITIONAL),
-    /* DD */ opi("", 2, OperandKind::None, OPCODE_FLAG_INVALID),
-    /* DE */ opi("sbc a, %", 1, OperandKind::Undefined, 0),
-    /* DF */ opi("rst $18", 0, OperandKind::None, OPCODE_FLAG_JUMP | OPCODE_FLAG_CALL),
-    /* E0 */ opi("ldh [%], a", 1, OperandKind::DataHram, OPCODE_FLAG_WRITE_MEM),
-    /* E1 */ opi("pop hl", 0, OperandKind::None, 0),
-    /* E2 */ opi("ld [$FF00+c], a", 0, OperandKind::None, OPCODE_FLAG_WRITE_MEM),
-    /* E3 */ opi("", 0, OperandKind::None, OPCODE_FLAG_INVALID),
-    /* E4 */ opi("", 0, OperandKind::None, OPCODE_FLAG_INVALID),
-    /* E5 */ opi("push hl", 0, OperandKind::None, 0),
-    /* E6 */ opi("and a, %", 1, OperandKind::Undefined, 0),
-    /* E7 */ opi("rst $20", 0, OperandKind::None, OPCODE_FLAG_JUMP | OPCODE_FLAG_CALL),
-    /* E8 */ opi("add sp, %", 1, OperandKind::Undefined, 0),
-    /* E9 */ opi("jp hl", 0, OperandKind::None, OPCODE_FLAG_JUMP),
-    /* EA */ opi("ld [%], a", 2, OperandKind::Data, OPCODE_FLAG_WRITE_MEM),
-    /* EB */ opi("", 0, OperandKind::None, OPCODE_FLAG_INVALID),
-    /* EC */ opi("", 2, OperandKind::None, OPCODE_FLAG_INVALID),
-    /* ED */ opi("", 2, OperandKind::None, OPCODE_FLAG_INVALID),
-    /* EE */ opi("xor a, %", 1, OperandKind::Undefined, 0),
-    /* EF */ opi("rst $28", 0, OperandKind::None, OPCODE_FLAG_JUMP | OPCODE_FLAG_CALL),
-    /* F0 */ opi("ldh a, [%]", 1, OperandKind::DataHram, OPCODE_FLAG_READ_MEM),
-    /* F1 */ opi("pop af", 0, OperandKind::None, 0),
-    /* F2 */ opi("ld a, [$FF00+c]", 0, OperandKind::None, OPCODE_FLAG_READ_MEM),
-    /* F3 */ opi("di", 0, OperandKind::None, 0),
-    /* F4 */ opi("", 0, OperandKind::None, OPCODE_FLAG_INVALID),
-    /* F5 */ opi("push af", 0, OperandKind::None, 0),
-    /* F6 */ opi("or a, %", 1, OperandKind::Undefined, 0),
-    /* F7 */ opi("rst $30", 0, OperandKind::None, OPCODE_FLAG_JUMP | OPCODE_FLAG_CALL),
-    /* F8 */ opi("ld hl, sp+%", 1, OperandKind::Undefined, 0),
-    /* F9 */ opi("ld sp, hl", 0, OperandKind::None, 0),
-    /* FA */ opi("ld a, [%]", 2, OperandKind::Data, OPCODE_FLAG_READ_MEM),
-    /* FB */ opi("ei", 0, OperandKind::None, 0),
-    /* FC */ opi("", 2, OperandKind::None, OPCODE_FLAG_INVALID),
-    /* FD */ opi("", 2, OperandKind::None, OPCODE_FLAG_INVALID),
-    /* FE */ opi("cp a, %", 1, OperandKind::Undefined, 0),
-    /* FF */ opi("rst $38", 0, OperandKind::None, OPCODE_FLAG_JUMP | OPCODE_FLAG_CALL),
-];
-
-const BITOPS_INFO: [OpcodeInfo; 0x100] =
-[
-    /* 00 */ opi("rlc b", 1, OperandKind::LongOpcode, 0),
-    /* 01 */ opi("rlc c", 1, OperandKind::LongOpcode, 0),
-    /* 02 */ opi("rlc d", 1, OperandKind::LongOpcode, 0),
-    /* 03 */ opi("rlc e", 1, OperandKind::LongOpcode, 0),
-    /* 04 */ opi("rlc h", 1, OperandKind::LongOpcode, 0),
-    /* 05 */ opi("rlc l", 1, OperandKind::LongOpcode, 0),
-    /* 06 */ opi("rlc [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM | OPCODE_FLAG_READ_MEM),
-    /* 07 */ opi("rlc a", 1, OperandKind::LongOpcode, 0),
-    /* 08 */ opi("rrc b", 1, OperandKind::LongOpcode, 0),
-    /* 09 */ opi("rrc c", 1, OperandKind::LongOpcode, 0),
-    /* 0A */ opi("rrc d", 1, OperandKind::LongOpcode, 0),
-    /* 0B */ opi("rrc e", 1, OperandKind::LongOpcode, 0),
-    /* 0C */ opi("rrc h", 1, OperandKind::LongOpcode, 0),
-    /* 0D */ opi("rrc l", 1, OperandKind::LongOpcode, 0),
-    /* 0E */ opi("rrc [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM | OPCODE_FLAG_READ_MEM),
-    /* 0F */ opi("rrc a", 1, OperandKind::LongOpcode, 0),
-    /* 10 */ opi("rl b", 1, OperandKind::LongOpcode, 0),
-    /* 11 */ opi("rl c", 1, OperandKind::LongOpcode, 0),
-    /* 12 */ opi("rl d", 1, OperandKind::LongOpcode, 0),
-    /* 13 */ opi("rl e", 1, OperandKind::LongOpcode, 0),
-    /* 14 */ opi("rl h", 1, OperandKind::LongOpcode, 0),
-    /* 15 */ opi("rl l", 1, OperandKind::LongOpcode, 0),
-    /* 16 */ opi("rl [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM | OPCODE_FLAG_READ_MEM),
-    /* 17 */ opi("rl a", 1, OperandKind::LongOpcode, 0),
-    /* 18 */ opi("rr b", 1, OperandKind::LongOpcode, 0),
-    /* 19 */ opi("rr c", 1, OperandKind::LongOpcode, 0),
-    /* 1A */ opi("rr d", 1, OperandKind::LongOpcode, 0),
-    /* 1B */ opi("rr e", 1, OperandKind::LongOpcode, 0),
-    /* 1C */ opi("rr h", 1, OperandKind::LongOpcode, 0),
-    /* 1D */ opi("rr l", 1, OperandKind::LongOpcode, 0),
-    /* 1E */ opi("rr [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM | OPCODE_FLAG_READ_MEM),
-    /* 1F */ opi("rr a", 1, OperandKind::LongOpcode, 0),
-    /* 20 */ opi("sla b", 1, OperandKind::LongOpcode, 0),
-    /* 21 */ opi("sla c", 1, OperandKind::LongOpcode, 0),
-    /* 22 */ opi("sla d", 1, OperandKind::LongOpcode, 0),
-    /* 23 */ opi("sla e", 1, OperandKind::LongOpcode, 0),
-    /* 24 */ opi("sla h", 1, OperandKind::LongOpcode, 0),
-    /* 25 */ opi("sla l", 1, OperandKind::LongOpcode, 0),
-    /* 26 */ opi("sla [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM | OPCODE_FLAG_READ_MEM),
-    /* 27 */ opi("sla a", 1, OperandKind::LongOpcode, 0),
-    /* 28 */ opi("sra b", 1, OperandKind::LongOpcode, 0),
-    /* 29 */ opi("sra c", 1, OperandKind::LongOpcode, 0),
-    /* 2A */ opi("sra d", 1, OperandKind::LongOpcode, 0),
-    /* 2B */ opi("sra e", 1, OperandKind::LongOpcode, 0),
-    /* 2C */ opi("sra h", 1, OperandKind::LongOpcode, 0),
-    /* 2D */ opi("sra l", 1, OperandKind::LongOpcode, 0),
-    /* 2E */ opi("sra [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM | OPCODE_FLAG_READ_MEM),
-    /* 2F */ opi("sra a", 1, OperandKind::LongOpcode, 0),
-    /* 30 */ opi("swap b", 1, OperandKind::LongOpcode, 0),
-    /* 31 */ opi("swap c", 1, OperandKind::LongOpcode, 0),
-    /* 32 */ opi("swap d", 1, OperandKind::LongOpcode, 0),
-    /* 33 */ opi("swap e", 1, OperandKind::LongOpcode, 0),
-    /* 34 */ opi("swap h", 1, OperandKind::LongOpcode, 0),
-    /* 35 */ opi("swap l", 1, OperandKind::LongOpcode, 0),
-    /* 36 */ opi("swap [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM | OPCODE_FLAG_READ_MEM),
-    /* 37 */ opi("swap a", 1, OperandKind::LongOpcode, 0),
-    /* 38 */ opi("srl b", 1, OperandKind::LongOpcode, 0),
-    /* 39 */ opi("srl c", 1, OperandKind::LongOpcode, 0),
-    /* 3A */ opi("srl d", 1, OperandKind::LongOpcode, 0),
-    /* 3B */ opi("srl e", 1, OperandKind::LongOpcode, 0),
-    /* 3C */ opi("srl h", 1, OperandKind::LongOpcode, 0),
-    /* 3D */ opi("srl l", 1, OperandKind::LongOpcode, 0),
-    /* 3E */ opi("srl [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM | OPCODE_FLAG_READ_MEM),
-    /* 3F */ opi("srl a", 1, OperandKind::LongOpcode, 0),
-    /* 40 */ opi("bit 0, b", 1, OperandKind::LongOpcode, 0),
-    /* 41 */ opi("bit 0, c", 1, OperandKind::LongOpcode, 0),
-    /* 42 */ opi("bit 0, d", 1, OperandKind::LongOpcode, 0),
-    /* 43 */ opi("bit 0, e", 1, OperandKind::LongOpcode, 0),
-    /* 44 */ opi("bit 0, h", 1, OperandKind::LongOpcode, 0),
-    /* 45 */ opi("bit 0, l", 1, OperandKind::LongOpcode, 0),
-    /* 46 */ opi("bit 0, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_READ_MEM),
-    /* 47 */ opi("bit 0, a", 1, OperandKind::LongOpcode, 0),
-    /* 48 */ opi("bit 1, b", 1, OperandKind::LongOpcode, 0),
-    /* 49 */ opi("bit 1, c", 1, OperandKind::LongOpcode, 0),
-    /* 4A */ opi("bit 1, d", 1, OperandKind::LongOpcode, 0),
-    /* 4B */ opi("bit 1, e", 1, OperandKind::LongOpcode, 0),
-    /* 4C */ opi("bit 1, h", 1, OperandKind::LongOpcode, 0),
-    /* 4D */ opi("bit 1, l", 1, OperandKind::LongOpcode, 0),
-    /* 4E */ opi("bit 1, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_READ_MEM),
-    /* 4F */ opi("bit 1, a", 1, OperandKind::LongOpcode, 0),
-    /* 50 */ opi("bit 2, b", 1, OperandKind::LongOpcode, 0),
-    /* 51 */ opi("bit 2, c", 1, OperandKind::LongOpcode, 0),
-    /* 52 */ opi("bit 2, d", 1, OperandKind::LongOpcode, 0),
-    /* 53 */ opi("bit 2, e", 1, OperandKind::LongOpcode, 0),
-    /* 54 */ opi("bit 2, h", 1, OperandKind::LongOpcode, 0),
-    /* 55 */ opi("bit 2, l", 1, OperandKind::LongOpcode, 0),
-    /* 56 */ opi("bit 2, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_READ_MEM),
-    /* 57 */ opi("bit 2, a", 1, OperandKind::LongOpcode, 0),
-    /* 58 */ opi("bit 3, b", 1, OperandKind::LongOpcode, 0),
-    /* 59 */ opi("bit 3, c", 1, OperandKind::LongOpcode, 0),
-    /* 5A */ opi("bit 3, d", 1, OperandKind::LongOpcode, 0),
-    /* 5B */ opi("bit 3, e", 1, OperandKind::LongOpcode, 0),
-    /* 5C */ opi("bit 3, h", 1, OperandKind::LongOpcode, 0),
-    /* 5D */ opi("bit 3, l", 1, OperandKind::LongOpcode, 0),
-    /* 5E */ opi("bit 3, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_READ_MEM),
-    /* 5F */ opi("bit 3, a", 1, OperandKind::LongOpcode, 0),
-    /* 60 */ opi("bit 4, b", 1, OperandKind::LongOpcode, 0),
-    /* 61 */ opi("bit 4, c", 1, OperandKind::LongOpcode, 0),
-    /* 62 */ opi("bit 4, d", 1, OperandKind::LongOpcode, 0),
-    /* 63 */ opi("bit 4, e", 1, OperandKind::LongOpcode, 0),
-    /* 64 */ opi("bit 4, h", 1, OperandKind::LongOpcode, 0),
-    /* 65 */ opi("bit 4, l", 1, OperandKind::LongOpcode, 0),
-    /* 66 */ opi("bit 4, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_READ_MEM),
-    /* 67 */ opi("bit 4, a", 1, OperandKind::LongOpcode, 0),
-    /* 68 */ opi("bit 5, b", 1, OperandKind::LongOpcode, 0),
-    /* 69 */ opi("bit 5, c", 1, OperandKind::LongOpcode, 0),
-    /* 6A */ opi("bit 5, d", 1, OperandKind::LongOpcode, 0),
-    /* 6B */ opi("bit 5, e", 1, OperandKind::LongOpcode, 0),
-    /* 6C */ opi("bit 5, h", 1, OperandKind::LongOpcode, 0),
-    /* 6D */ opi("bit 5, l", 1, OperandKind::LongOpcode, 0),
-    /* 6E */ opi("bit 5, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_READ_MEM),
-    /* 6F */ opi("bit 5, a", 1, OperandKind::LongOpcode, 0),
-    /* 70 */ opi("bit 6, b", 1, OperandKind::LongOpcode, 0),
-    /* 71 */ opi("bit 6, c", 1, OperandKind::LongOpcode, 0),
-    /* 72 */ opi("bit 6, d", 1, OperandKind::LongOpcode, 0),
-    /* 73 */ opi("bit 6, e", 1, OperandKind::LongOpcode, 0),
-    /* 74 */ opi("bit 6, h", 1, OperandKind::LongOpcode, 0),
-    /* 75 */ opi("bit 6, l", 1, OperandKind::LongOpcode, 0),
-    /* 76 */ opi("bit 6, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_READ_MEM),
-    /* 77 */ opi("bit 6, a", 1, OperandKind::LongOpcode, 0),
-    /* 78 */ opi("bit 7, b", 1, OperandKind::LongOpcode, 0),
-    /* 79 */ opi("bit 7, c", 1, OperandKind::LongOpcode, 0),
-    /* 7A */ opi("bit 7, d", 1, OperandKind::LongOpcode, 0),
-    /* 7B */ opi("bit 7, e", 1, OperandKind::LongOpcode, 0),
-    /* 7C */ opi("bit 7, h", 1, OperandKind::LongOpcode, 0),
-    /* 7D */ opi("bit 7, l", 1, OperandKind::LongOpcode, 0),
-    /* 7E */ opi("bit 7, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_READ_MEM),
-    /* 7F */ opi("bit 7, a", 1, OperandKind::LongOpcode, 0),
-    /* 80 */ opi("res 0, b", 1, OperandKind::LongOpcode, 0),
-    /* 81 */ opi("res 0, c", 1, OperandKind::LongOpcode, 0),
-    /* 82 */ opi("res 0, d", 1, OperandKind::LongOpcode, 0),
-    /* 83 */ opi("res 0, e", 1, OperandKind::LongOpcode, 0),
-    /* 84 */ opi("res 0, h", 1, OperandKind::LongOpcode, 0),
-    /* 85 */ opi("res 0, l", 1, OperandKind::LongOpcode, 0),
-    /* 86 */ opi("res 0, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM),
-    /* 87 */ opi("res 0, a", 1, OperandKind::LongOpcode, 0),
-    /* 88 */ opi("res 1, b", 1, OperandKind::LongOpcode, 0),
-    /* 89 */ opi("res 1, c", 1, OperandKind::LongOpcode, 0),
-    /* 8A */ opi("res 1, d", 1, OperandKind::LongOpcode, 0),
-    /* 8B */ opi("res 1, e", 1, OperandKind::LongOpcode, 0),
-    /* 8C */ opi("res 1, h", 1, OperandKind::LongOpcode, 0),
-    /* 8D */ opi("res 1, l", 1, OperandKind::LongOpcode, 0),
-    /* 8E */ opi("res 1, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM),
-    /* 8F */ opi("res 1, a", 1, OperandKind::LongOpcode, 0),
-    /* 90 */ opi("res 2, b", 1, OperandKind::LongOpcode, 0),
-    /* 91 */ opi("res 2, c", 1, OperandKind::LongOpcode, 0),
-    /* 92 */ opi("res 2, d", 1, OperandKind::LongOpcode, 0),
-    /* 93 */ opi("res 2, e", 1, OperandKind::LongOpcode, 0),
-    /* 94 */ opi("res 2, h", 1, OperandKind::LongOpcode, 0),
-    /* 95 */ opi("res 2, l", 1, OperandKind::LongOpcode, 0),
-    /* 96 */ opi("res 2, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM),
-    /* 97 */ opi("res 2, a", 1, OperandKind::LongOpcode, 0),
-    /* 98 */ opi("res 3, b", 1, OperandKind::LongOpcode, 0),
-    /* 99 */ opi("res 3, c", 1, OperandKind::LongOpcode, 0),
-    /* 9A */ opi("res 3, d", 1, OperandKind::LongOpcode, 0),
-    /* 9B */ opi("res 3, e", 1, OperandKind::LongOpcode, 0),
-    /* 9C */ opi("res 3, h", 1, OperandKind::LongOpcode, 0),
-    /* 9D */ opi("res 3, l", 1, OperandKind::LongOpcode, 0),
-    /* 9E */ opi("res 3, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM),
-    /* 9F */ opi("res 3, a", 1, OperandKind::LongOpcode, 0),
-    /* A0 */ opi("res 4, b", 1, OperandKind::LongOpcode, 0),
-    /* A1 */ opi("res 4, c", 1, OperandKind::LongOpcode, 0),
-    /* A2 */ opi("res 4, d", 1, OperandKind::LongOpcode, 0),
-    /* A3 */ opi("res 4, e", 1, OperandKind::LongOpcode, 0),
-    /* A4 */ opi("res 4, h", 1, OperandKind::LongOpcode, 0),
-    /* A5 */ opi("res 4, l", 1, OperandKind::LongOpcode, 0),
-    /* A6 */ opi("res 4, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM),
-    /* A7 */ opi("res 4, a", 1, OperandKind::LongOpcode, 0),
-    /* A8 */ opi("res 5, b", 1, OperandKind::LongOpcode, 0),
-    /* A9 */ opi("res 5, c", 1, OperandKind::LongOpcode, 0),
-    /* AA */ opi("res 5, d", 1, OperandKind::LongOpcode, 0),
-    /* AB */ opi("res 5, e", 1, OperandKind::LongOpcode, 0),
-    /* AC */ opi("res 5, h", 1, OperandKind::LongOpcode, 0),
-    /* AD */ opi("res 5, l", 1, OperandKind::LongOpcode, 0),
-    /* AE */ opi("res 5, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM),
-    /* AF */ opi("res 5, a", 1, OperandKind::LongOpcode, 0),
-    /* B0 */ opi("res 6, b", 1, OperandKind::LongOpcode, 0),
-    /* B1 */ opi("res 6, c", 1, OperandKind::LongOpcode, 0),
-    /* B2 */ opi("res 6, d", 1, OperandKind::LongOpcode, 0),
-    /* B3 */ opi("res 6, e", 1, OperandKind::LongOpcode, 0),
-    /* B4 */ opi("res 6, h", 1, OperandKind::LongOpcode, 0),
-    /* B5 */ opi("res 6, l", 1, OperandKind::LongOpcode, 0),
-    /* B6 */ opi("res 6, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM),
-    /* B7 */ opi("res 6, a", 1, OperandKind::LongOpcode, 0),
-    /* B8 */ opi("res 7, b", 1, OperandKind::LongOpcode, 0),
-    /* B9 */ opi("res 7, c", 1, OperandKind::LongOpcode, 0),
-    /* BA */ opi("res 7, d", 1, OperandKind::LongOpcode, 0),
-    /* BB */ opi("res 7, e", 1, OperandKind::LongOpcode, 0),
-    /* BC */ opi("res 7, h", 1, OperandKind::LongOpcode, 0),
-    /* BD */ opi("res 7, l", 1, OperandKind::LongOpcode, 0),
-    /* BE */ opi("res 7, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM),
-    /* BF */ opi("res 7, a", 1, OperandKind::LongOpcode, 0),
-    /* C0 */ opi("set 0, b", 1, OperandKind::LongOpcode, 0),
-    /* C1 */ opi("set 0, c", 1, OperandKind::LongOpcode, 0),
-    /* C2 */ opi("set 0, d", 1, OperandKind::LongOpcode, 0),
-    /* C3 */ opi("set 0, e", 1, OperandKind::LongOpcode, 0),
-    /* C4 */ opi("set 0, h", 1, OperandKind::LongOpcode, 0),
-    /* C5 */ opi("set 0, l", 1, OperandKind::LongOpcode, 0),
-    /* C6 */ opi("set 0, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM),
-    /* C7 */ opi("set 0, a", 1, OperandKind::LongOpcode, 0),
-    /* C8 */ opi("set 1, b", 1, OperandKind::LongOpcode, 0),
-    /* C9 */ opi("set 1, c", 1, OperandKind::LongOpcode, 0),
-    /* CA */ opi("set 1, d", 1, OperandKind::LongOpcode, 0),
-    /* CB */ opi("set 1, e", 1, OperandKind::LongOpcode, 0),
-    /* CC */ opi("set 1, h", 1, OperandKind::LongOpcode, 0),
-    /* CD */ opi("set 1, l", 1, OperandKind::LongOpcode, 0),
-    /* CE */ opi("set 1, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM),
-    /* CF */ opi("set 1, a", 1, OperandKind::LongOpcode, 0),
-    /* D0 */ opi("set 2, b", 1, OperandKind::LongOpcode, 0),
-    /* D1 */ opi("set 2, c", 1, OperandKind::LongOpcode, 0),
-    /* D2 */ opi("set 2, d", 1, OperandKind::LongOpcode, 0),
-    /* D3 */ opi("set 2, e", 1, OperandKind::LongOpcode, 0),
-    /* D4 */ opi("set 2, h", 1, OperandKind::LongOpcode, 0),
-    /* D5 */ opi("set 2, l", 1, OperandKind::LongOpcode, 0),
-    /* D6 */ opi("set 2, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM),
-    /* D7 */ opi("set 2, a", 1, OperandKind::LongOpcode, 0),
-    /* D8 */ opi("set 3, b", 1, OperandKind::LongOpcode, 0),
-    /* D9 */ opi("set 3, c", 1, OperandKind::LongOpcode, 0),
-    /* DA */ opi("set 3, d", 1, OperandKind::LongOpcode, 0),
-    /* DB */ opi("set 3, e", 1, OperandKind::LongOpcode, 0),
-    /* DC */ opi("set 3, h", 1, OperandKind::LongOpcode, 0),
-    /* DD */ opi("set 3, l", 1, OperandKind::LongOpcode, 0),
-    /* DE */ opi("set 3, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM),
-    /* DF */ opi("set 3, a", 1, OperandKind::LongOpcode, 0),
-    /* E0 */ opi("set 4, b", 1, OperandKind::LongOpcode, 0),
-    /* E1 */ opi("set 4, c", 1, OperandKind::LongOpcode, 0),
-    /* E2 */ opi("set 4, d", 1, OperandKind::LongOpcode, 0),
-    /* E3 */ opi("set 4, e", 1, OperandKind::LongOpcode, 0),
-    /* E4 */ opi("set 4, h", 1, OperandKind::LongOpcode, 0),
-    /* E5 */ opi("set 4, l", 1, OperandKind::LongOpcode, 0),
-    /* E6 */ opi("set 4, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM),
-    /* E7 */ opi("set 4, a", 1, OperandKind::LongOpcode, 0),
-    /* E8 */ opi("set 5, b", 1, OperandKind::LongOpcode, 0),
-    /* E9 */ opi("set 5, c", 1, OperandKind::LongOpcode, 0),
-    /* EA */ opi("set 5, d", 1, OperandKind::LongOpcode, 0),
-    /* EB */ opi("set 5, e", 1, OperandKind::LongOpcode, 0),
-    /* EC */ opi("set 5, h", 1, OperandKind::LongOpcode, 0),
-    /* ED */ opi("set 5, l", 1, OperandKind::LongOpcode, 0),
-    /* EE */ opi("set 5, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM),
-    /* EF */ opi("set 5, a", 1, OperandKind::LongOpcode, 0),
-    /* F0 */ opi("set 6, b", 1, OperandKind::LongOpcode, 0),
-    /* F1 */ opi("set 6, c", 1, OperandKind::LongOpcode, 0),
-    /* F2 */ opi("set 6, d", 1, OperandKind::LongOpcode, 0),
-    /* F3 */ opi("set 6, e", 1, OperandKind::LongOpcode, 0),
-    /* F4 */ opi("set 6, h", 1, OperandKind::LongOpcode, 0),
-    /* F5 */ opi("set 6, l", 1, OperandKind::LongOpcode, 0),
-    /* F6 */ opi("set 6, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM),
-    /* F7 */ opi("set 6, a", 1, OperandKind::LongOpcode, 0),
-    /* F8 */ opi("set 7, b", 1, OperandKind::LongOpcode, 0),
-    /* F9 */ opi("set 7, c", 1, OperandKind::LongOpcode, 0),
-    /* FA */ opi("set 7, d", 1, OperandKind::LongOpcode, 0),
-    /* FB */ opi("set 7, e", 1, OperandKind::LongOpcode, 0),
-    /* FC */ opi("set 7, h", 1, OperandKind::LongOpcode, 0),
-    /* FD */ opi("set 7, l", 1, OperandKind::LongOpcode, 0),
-    /* FE */ opi("set 7, [hl]", 1, OperandKind::LongOpcode, OPCODE_FLAG_WRITE_MEM),
-    /* FF */ opi("set 7, a", 1, OperandKind::LongOpcode, 0),
-];
+const BARE_STOP_INFO: OpcodeInfo = opi("stop", 0, OperandKind::None, 0, 1);
+
+// OPCODE_INFO and BITOPS_INFO, generated from opcodes.txt
+include!(concat!(env!("OUT_DIR"), "/opcodes.rs"));
+
+#[cfg(test)]
+mod tests
+{
+    // the tables are generated, these check they agree with themselves and with the rest of gbasm
+
+    use std::collections::HashSet;
+    use super::*;
+
+    fn all_infos() -> impl Iterator<Item = (&'static str, usize, &'static OpcodeInfo)>
+    {
+        let base = OPCODE_INFO.iter().enumerate().map(|(i, info)| ("OPCODE_INFO", i, info));
+        let bitops = BITOPS_INFO.iter().enumerate().map(|(i, info)| ("BITOPS_INFO", i, info));
+
+        base.chain(bitops)
+    }
+
+    fn is_invalid(info: &OpcodeInfo) -> bool
+    {
+        (info.flags & OPCODE_FLAG_INVALID) != 0
+    }
+
+    #[test]
+    fn operand_lengths_match_kinds()
+    {
+        for (table, i, info) in all_infos()
+        {
+            let expected: &[u8] = match info.operand_kind
+            {
+                OperandKind::None if is_invalid(info) => &[0, 2],
+                OperandKind::None if info.fmt == "bitops" => &[1],
+                OperandKind::None => &[0],
+                OperandKind::Undefined => &[1, 2],
+                OperandKind::LongOpcode | OperandKind::CodeRelative | OperandKind::DataHram => &[1],
+                OperandKind::Code | OperandKind::Data => &[2],
+            };
+
+            assert!(expected.contains(&info.operand_len), "{}[{:02X}] `{}`: {:?} operand of length {}", table, i, info.fmt, info.operand_kind, info.operand_len);
+        }
+    }
+
+    #[test]
+    fn operands_appear_in_formats()
+    {
+        for (table, i, info) in all_infos()
+        {
+            let written = match info.operand_kind
+            {
+                OperandKind::None | OperandKind::LongOpcode => 0,
+                _ => 1,
+            };
+
+            assert_eq!(info.fmt.matches('%').count(), written, "{}[{:02X}] `{}`", table, i, info.fmt);
+        }
+    }
+
+    #[test]
+    fn long_opcodes_are_bitops_and_stop()
+    {
+        for (table, i, info) in all_infos()
+        {
+            let long = table == "BITOPS_INFO" || i == OPCODE_STOP as usize;
+            assert_eq!(info.operand_kind == OperandKind::LongOpcode, long, "{}[{:02X}] `{}`", table, i, info.fmt);
+        }
+    }
+
+    #[test]
+    fn flags_are_consistent()
+    {
+        for (table, i, info) in all_infos()
+        {
+            let what = format!("{}[{:02X}] `{}`", table, i, info.fmt);
+            let has = |flag: u8| (info.flags & flag) != 0;
+
+            assert_eq!(is_invalid(info), info.fmt.is_empty(), "{}", what);
+            assert!(!has(OPCODE_FLAG_CALL) || has(OPCODE_FLAG_JUMP), "{}: calls are jumps", what);
+            assert!(!has(OPCODE_FLAG_CONDITIONAL) || has(OPCODE_FLAG_JUMP), "{}: only branches are conditional", what);
+
+            // memory is accessed through brackets, and only `[...]` operands access memory
+            let memory = has(OPCODE_FLAG_READ_MEM) || has(OPCODE_FLAG_WRITE_MEM);
+            assert_eq!(memory, info.fmt.contains('['), "{}", what);
+
+            // only conditional branches take more or less time
+            assert!(info.cycles >= 1, "{}", what);
+            assert_eq!(info.cycles != info.cycles_taken, has(OPCODE_FLAG_CONDITIONAL), "{}", what);
+            assert!(info.cycles <= info.cycles_taken, "{}", what);
+        }
+    }
+
+    #[test]
+    fn formats_are_unique()
+    {
+        let mut seen = HashSet::new();
+
+        for (table, i, info) in all_infos().filter(|(_, _, info)| !is_invalid(info)) {
+            assert!(seen.insert(info.fmt), "{}[{:02X}] `{}` is written like another opcode", table, i, info.fmt); }
+    }
+
+    #[test]
+    fn lookups_find_every_instruction()
+    {
+        for ins in all_instructions()
+        {
+            let fmt = ins.info().fmt;
+            let mnemonic = fmt.split(' ').next().unwrap();
+
+            assert_eq!(lookup_shape(fmt), vec![ins], "`{}`", fmt);
+            assert!(lookup_mnemonic(mnemonic).contains(&ins), "`{}` isn't a `{}`", fmt, mnemonic);
+        }
+
+        assert_eq!(lookup_mnemonic("ld").len(), 90);
+        assert!(lookup_shape("ld a, b, c").is_empty());
+    }
+
+    #[test]
+    fn decode_encode_roundtrip()
+    {
+        // every opcode, decoded from bytes then encoded back, with operands at the edges of their range
+        // and relative jumps going both ways, wrapping around the address space
+
+        for pc in [0x0000, 0x4000, 0xFFF0]
+        {
+            for opcode in 0 ..= 0xFF_u8
+            {
+                let seconds: Vec<u8> = match opcode
+                {
+                    OPCODE_BITOPS => (0 ..= 0xFF).collect(),
+                    _ => vec![0x00, 0x12, 0x7F, 0x80, 0xFF],
+                };
+
+                for second in seconds
+                {
+                    for third in [0x00, 0x34, 0xFF]
+                    {
+                        let bytes = [opcode, second, third];
+
+                        match decode(pc, &bytes)
+                        {
+                            Ok(ins) =>
+                            {
+                                assert_eq!(ins.encoded_len(), 1 + ins.info().operand_len as usize, "{:02X?}", bytes);
+                                assert_eq!(ins.encode(pc), bytes[.. ins.encoded_len()].to_vec(), "`{}` at {:04X}", ins.info().fmt, pc);
+                            }
+
+                            Err(_) => assert!(is_invalid(&OPCODE_INFO[opcode as usize]), "{:02X?} doesn't decode", bytes),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn opcode_queries_match_instructions()
+    {
+        for ins in all_instructions()
+        {
+            assert!(is_valid_opcode(ins.opcode));
+            assert_eq!(encoded_len(ins.opcode), ins.encoded_len(), "`{}`", ins.info().fmt);
+
+            match cycles(ins.opcode)
+            {
+                Some(cycles) => assert_eq!(cycles, ins.cycles(), "`{}`", ins.info().fmt),
+                None => assert_eq!(bitops_cycles(ins.operand as u8), ins.cycles(), "`{}`", ins.info().fmt),
+            }
+        }
+    }
+
+    #[test]
+    fn gapless_decode_covers_every_byte()
+    {
+        let bytes = [0x00, 0xD3, 0x3E, 0x05, 0xFA, 0x00];
+        let decoded: Vec<(u16, Decoded)> = decode_slice_gapless(0x100_u16, &bytes).collect();
+
+        let nop = Instruction::new();
+        let ld = Instruction { opcode: 0x3E, operand: 0x05, ..Instruction::new() };
+
+        assert_eq!(decoded,
+        [
+            (0x100, Decoded::Instruction(nop)),
+            (0x101, Decoded::DataByte(0xD3)),
+            (0x102, Decoded::Instruction(ld)),
+            (0x104, Decoded::DataByte(0xFA)), // cut short, the `00` after it isn't a `nop`
+            (0x105, Decoded::DataByte(0x00)),
+        ]);
+    }
+
+    #[test]
+    fn peek_and_seek_dont_lose_the_position()
+    {
+        let bytes = [0x10, 0x3E, 0x05];
+        let mut iter = decode_slice(0x200_u16, &bytes);
+
+        let bare = |addr, slice: &[u8]| decode_stop(addr, slice, false);
+        let stop = Instruction { opcode: OPCODE_STOP, operand: 0x3E, ..Instruction::new() }; // the `3E` read as padding
+        let bare_stop = Instruction { opcode: OPCODE_STOP, bare_stop: true, ..Instruction::new() };
+        let ld = Instruction { opcode: 0x3E, operand: 0x05, ..Instruction::new() };
+
+        // peeking gives what next would, with the same decode, and stays put
+        assert_eq!(iter.peek().map(|(addr, ins)| (addr, ins.ok())), Some((0x200, Some(stop))));
+        assert_eq!(iter.peek_with(bare).map(|(addr, ins)| (addr, ins.ok())), Some((0x200, Some(bare_stop))));
+        assert_eq!(iter.position(), 0x200);
+
+        assert_eq!(iter.next_with(bare).map(|(addr, ins)| (addr, ins.ok())), Some((0x200, Some(bare_stop))));
+        assert_eq!(iter.position(), 0x201);
+        assert_eq!(iter.peek().map(|(addr, ins)| (addr, ins.ok())), Some((0x201, Some(ld))));
+
+        // outside the slice, nothing moves
+        assert!(!iter.seek_to(0x1FF));
+        assert!(!iter.seek_to(0x204));
+        assert_eq!(iter.position(), 0x201);
+
+        // the end is a valid place to be, with nothing left
+        assert!(iter.seek_to(0x203));
+        assert!(iter.peek().is_none());
+
+        assert!(iter.seek_to(0x200));
+        assert_eq!(iter.next().map(|(addr, ins)| (addr, ins.ok())), Some((0x200, Some(stop))));
+        assert_eq!(iter.position(), 0x202);
+        assert_eq!(iter.next().map(|(addr, ins)| (addr, ins.ok())), Some((0x202, Some(Instruction { opcode: 0x05, ..Instruction::new() }))));
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn formats_parse_back()
+    {
+        // what the listing writes for each instruction parses to the same instruction
+
+        for ins in all_instructions()
+        {
+            let info = ins.info();
+
+            let operand = match (info.operand_kind, info.operand_len)
+            {
+                (OperandKind::DataHram, _) => 0xFF80,
+                (OperandKind::CodeRelative, _) => 0x4002,
+                (OperandKind::Undefined, 1) => 0x12,
+                (OperandKind::None, _) | (OperandKind::LongOpcode, _) => ins.operand,
+                _ => 0x1234,
+            };
+
+            let ins = Instruction { operand: operand, ..ins };
+            let text = info.fmt.replace('%', &format!("${:X}", operand));
+
+            assert_eq!(parse_instruction(&text).ok(), Some(ins), "`{}`", text);
+        }
+    }
+
+    #[test]
+    fn constructors_match_parse()
+    {
+        let bit = |bit| Bit::new(bit).unwrap();
+
+        for (ins, text) in
+        [
+            (Instruction::ld_r_r(Reg::A, Reg::HlMem).unwrap(), "ld a, [hl]"),
+            (Instruction::ld_rr_imm(Reg16::SP, 0xDFFF), "ld sp, $DFFF"),
+            (Instruction::ldh_a_mem(0x44), "ldh a, [$FF44]"),
+            (Instruction::ldh_mem_a(0x80), "ldh [$FF80], a"),
+            (Instruction::add_hl(Reg16::DE), "add hl, de"),
+            (Instruction::push(Reg16Stack::AF), "push af"),
+            (Instruction::pop(Reg16Stack::HL), "pop hl"),
+            (Instruction::alu_imm(Alu::Cp, 0x90), "cp a, $90"),
+            (Instruction::jr_cond(Cond::NC, 0x4010), "jr nc, $4010"),
+            (Instruction::rst(RstVector::Rst38), "rst $38"),
+            (Instruction::shift(Shift::Swap, Reg::A), "swap a"),
+            (Instruction::bit(bit(7), Reg::H), "bit 7, h"),
+            (Instruction::res(bit(0), Reg::HlMem), "res 0, [hl]"),
+            (Instruction::set(bit(3), Reg::B), "set 3, b"),
+        ] {
+            assert_eq!(parse_instruction(text).ok(), Some(ins), "`{}`", text); }
+
+        assert_eq!(Instruction::ld_r_r(Reg::HlMem, Reg::HlMem), None);
+        assert_eq!(Bit::new(8), None);
+    }
+}
@@ -0,0 +1,110 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use thiserror::Error;
+
+pub const HEADER_LEN: usize = 0x70;
+
+const RST_VECTORS: &[u16] = &[0x0000, 0x0008, 0x0010, 0x0018, 0x0020, 0x0028, 0x0030, 0x0038];
+
+const OPCODE_JP_NN: u8 = 0xC3;
+
+// header of a Game Boy Sound rip: the sound driver and its data, loaded at `load` in a rom of its own
+#[derive(Clone, Copy, Debug)]
+pub struct Gbs
+{
+    pub song_count: u8,
+    pub first_song: u8,
+    pub load: u16,
+    pub init: u16, // called with the song number in a
+    pub play: u16, // called each frame (or on timer interrupt)
+    pub sp: u16,
+    pub timer_modulo: u8,
+    pub timer_control: u8,
+}
+
+#[derive(Error, Debug)]
+pub enum ParseGbsError
+{
+    #[error("Not a GBS file")]
+    NotGbs,
+
+    #[error("GBS version {0} isn't supported (expected 1)")]
+    Version(u8),
+
+    #[error("GBS load address ${0:04X} is outside of $0400-$7FFF")]
+    LoadAddr(u16),
+}
+
+impl Gbs
+{
+    pub fn parse(file: &[u8]) -> Result<Self, ParseGbsError>
+    {
+        if file.len() < HEADER_LEN || !file.starts_with(b"GBS") {
+            return Err(ParseGbsError::NotGbs); }
+
+        if file[0x03] != 1 {
+            return Err(ParseGbsError::Version(file[0x03])); }
+
+        let word = |offset: usize| u16::from_le_bytes([file[offset], file[offset + 1]]);
+
+        let load = word(0x06);
+
+        if !(0x0400 .. 0x8000).contains(&load) {
+            return Err(ParseGbsError::LoadAddr(load)); }
+
+        Ok(Self
+        {
+            song_count: file[0x04],
+            first_song: file[0x05],
+            load: load,
+            init: word(0x08),
+            play: word(0x0A),
+            sp: word(0x0C),
+            timer_modulo: file[0x0E],
+            timer_control: file[0x0F],
+        })
+    }
+
+    pub fn uses_timer(&self) -> bool
+    {
+        // play is called from the timer interrupt rather than vblank
+
+        (self.timer_control & 0x04) != 0
+    }
+
+    pub fn build_rom(&self, file: &[u8]) -> Vec<u8>
+    {
+        // what the player maps: the data at the load address, and rst vectors jumping to their relocated copies past it
+        // (bank 1 is at $4000-$7FFF to begin with, later banks are selected by writing to $2000 as with MBC1)
+
+        let mut rom = vec![0; self.load as usize];
+        rom.extend_from_slice(&file[HEADER_LEN ..]);
+        rom.resize(std::cmp::max(rom.len().next_multiple_of(0x4000), 0x8000), 0);
+
+        for &vector in RST_VECTORS
+        {
+            let target = (self.load + vector).to_le_bytes();
+            rom[vector as usize .. vector as usize + 3].copy_from_slice(&[OPCODE_JP_NN, target[0], target[1]]);
+        }
+
+        rom
+    }
+
+    pub fn file_offset(&self) -> isize
+    {
+        // from an offset into the rom to one into the file
+
+        HEADER_LEN as isize - self.load as isize
+    }
+}
+
+pub fn header_text(file: &[u8], offset: usize) -> String
+{
+    // title, author and copyright are 32 bytes each, zero padded
+
+    file[offset .. offset + 0x20].iter().take_while(|byte| **byte != 0).map(|byte| *byte as char).collect()
+}
@@ -0,0 +1,182 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::io::Write;
+use thiserror::Error;
+
+pub const TILE_LEN: usize = 16;
+
+// 4 colors of 2 bytes, as written to BCPD; there are 8 of them for backgrounds
+pub const PALETTE_LEN: usize = 8;
+pub const PALETTES_LEN: usize = 8 * PALETTE_LEN;
+
+// darkest last, as the default DMG palette has them
+const GRAY_SHADES: [[u8; 3]; 4] = [[0xFF, 0xFF, 0xFF], [0xAA, 0xAA, 0xAA], [0x55, 0x55, 0x55], [0x00, 0x00, 0x00]];
+
+// CGB map attributes
+const ATTR_PALETTE: u8 = 0x07;
+const ATTR_BANK: u8 = 0x08;
+const ATTR_HFLIP: u8 = 0x20;
+const ATTR_VFLIP: u8 = 0x40;
+
+#[derive(Error, Debug)]
+pub enum GfxError
+{
+    #[error("Tile ${0:02X} of VRAM bank {1} isn't in the tile data given")]
+    MissingTile(u8, usize),
+
+    #[error("Palette {0} isn't in the palette data given")]
+    MissingPalette(u8),
+}
+
+// where the tiles a map refers to are, per VRAM bank
+pub struct TileData<'a>
+{
+    pub banks: [Option<&'a [u8]>; 2],
+    pub signed: bool, // data starts at tile $80, as with LCDC bit 4 clear
+}
+
+pub struct Image
+{
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[u8; 3]>,
+}
+
+impl Image
+{
+    pub fn new(width: usize, height: usize) -> Self
+    {
+        Self { width: width, height: height, pixels: vec![[0xFF; 3]; width * height] }
+    }
+
+    pub fn write_png<W>(&self, write: W) -> Result<(), png::EncodingError>
+        where W: Write
+    {
+        let mut encoder = png::Encoder::new(write, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        encoder.write_header()?.write_image_data(&self.pixels.concat())
+    }
+}
+
+pub fn decode_tile(tile: &[u8]) -> [[u8; 8]; 8]
+{
+    // 2bpp: each row is a byte of low bits then a byte of high bits, leftmost pixel in bit 7
+
+    let mut result = [[0; 8]; 8];
+
+    for (y, row) in result.iter_mut().enumerate()
+    {
+        let (lo, hi) = (tile[y * 2], tile[y * 2 + 1]);
+
+        for (x, pixel) in row.iter_mut().enumerate() {
+            *pixel = ((lo >> (7 - x)) & 1) | (((hi >> (7 - x)) & 1) << 1); }
+    }
+
+    result
+}
+
+pub fn decode_color(color: u16) -> [u8; 3]
+{
+    // RGB555, red in the low bits; each 5-bit channel is stretched to 8 bits
+
+    let channel = |shift: u16| { let c = ((color >> shift) & 0x1F) as u8; (c << 3) | (c >> 2) };
+    [channel(0), channel(5), channel(10)]
+}
+
+pub fn render_swatches(palettes: &[[[u8; 3]; 4]], size: usize) -> Image
+{
+    // a row of squares per palette
+
+    let mut image = Image::new(4 * size, palettes.len() * size);
+
+    for (py, palette) in palettes.iter().enumerate()
+    {
+        for (px, color) in palette.iter().enumerate()
+        {
+            for y in py * size .. (py + 1) * size {
+                image.pixels[y * image.width + px * size .. y * image.width + (px + 1) * size].fill(*color); }
+        }
+    }
+
+    image
+}
+
+pub fn decode_palettes(data: &[u8]) -> Vec<[[u8; 3]; 4]>
+{
+    data.chunks_exact(PALETTE_LEN).map(|palette|
+    {
+        let mut colors = [[0; 3]; 4];
+
+        for (color, bytes) in colors.iter_mut().zip(palette.chunks_exact(2)) {
+            *color = decode_color(u16::from_le_bytes([bytes[0], bytes[1]])); }
+
+        colors
+    }).collect()
+}
+
+pub fn render_tile_sheet(data: &[u8], columns: usize) -> Image
+{
+    // tiles left to right then top to bottom, in shades of gray
+
+    let count = data.len() / TILE_LEN;
+    let rows = count.div_ceil(columns);
+    let mut image = Image::new(columns * 8, rows * 8);
+
+    for (i, tile) in data.chunks_exact(TILE_LEN).enumerate()
+    {
+        let (tx, ty) = ((i % columns) * 8, (i / columns) * 8);
+
+        for (y, row) in decode_tile(tile).iter().enumerate()
+        {
+            for (x, pixel) in row.iter().enumerate() {
+                image.pixels[(ty + y) * image.width + tx + x] = GRAY_SHADES[*pixel as usize]; }
+        }
+    }
+
+    image
+}
+
+pub fn render_tilemap(map: &[u8], attrs: Option<&[u8]>, width: usize, tiles: &TileData, palettes: Option<&[[[u8; 3]; 4]]>) -> Result<Image, GfxError>
+{
+    // without attributes every tile is from bank 0 with palette 0, without palettes colors are grays
+
+    let height = map.len() / width;
+    let mut image = Image::new(width * 8, height * 8);
+
+    for (i, index) in map.iter().enumerate().take(width * height)
+    {
+        let attr = attrs.map_or(0, |attrs| attrs[i]);
+        let bank = ((attr & ATTR_BANK) != 0) as usize;
+        let slot = if tiles.signed { *index ^ 0x80 } else { *index } as usize;
+
+        let tile = tiles.banks[bank].and_then(|data| data.get(slot * TILE_LEN .. (slot + 1) * TILE_LEN))
+            .ok_or(GfxError::MissingTile(*index, bank))?;
+
+        let shades = match palettes
+        {
+            Some(palettes) => palettes.get((attr & ATTR_PALETTE) as usize).ok_or(GfxError::MissingPalette(attr & ATTR_PALETTE))?,
+            None => &GRAY_SHADES,
+        };
+
+        let (tx, ty) = ((i % width) * 8, (i / width) * 8);
+
+        for (y, row) in decode_tile(tile).iter().enumerate()
+        {
+            let y = if (attr & ATTR_VFLIP) != 0 { 7 - y } else { y };
+
+            for (x, pixel) in row.iter().enumerate()
+            {
+                let x = if (attr & ATTR_HFLIP) != 0 { 7 - x } else { x };
+                image.pixels[(ty + y) * image.width + tx + x] = shades[*pixel as usize];
+            }
+        }
+    }
+
+    Ok(image)
+}
@@ -0,0 +1,189 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+// the cartridge header, $0100-$014F
+
+pub const HEADER_END: usize = 0x150;
+
+// licensee code $33 means the new two character code at $0144 is used instead
+const USE_NEW_LICENSEE: u8 = 0x33;
+
+#[derive(Clone, Debug)]
+pub struct Header
+{
+    pub title: String,
+    pub cgb_flag: u8,
+    pub new_licensee: String,
+    pub sgb_flag: u8,
+    pub cartridge_type: u8,
+    pub rom_size: u8,
+    pub ram_size: u8,
+    pub destination: u8,
+    pub old_licensee: u8,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+}
+
+pub fn header_checksum(rom: &[u8]) -> u8
+{
+    // over $0134-$014C, checked by the boot rom
+
+    rom[0x134 .. 0x14D].iter().fold(0u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1))
+}
+
+pub fn global_checksum(rom: &[u8]) -> u16
+{
+    // every byte but the checksum itself, never checked by the hardware
+
+    rom.iter().enumerate().filter(|(i, _)| *i != 0x14E && *i != 0x14F).fold(0u16, |sum, (_, byte)| sum.wrapping_add(*byte as u16))
+}
+
+pub fn fix_checksums(rom: &mut [u8])
+{
+    // the global checksum covers the header checksum, so that one goes first
+
+    rom[0x14D] = header_checksum(rom);
+
+    let global = global_checksum(rom).to_be_bytes();
+    rom[0x14E .. 0x150].copy_from_slice(&global);
+}
+
+fn text(bytes: &[u8]) -> String
+{
+    // up to the zero padding (or anything else that isn't printable)
+
+    bytes.iter().take_while(|byte| (0x20 .. 0x7F).contains(*byte)).map(|byte| *byte as char).collect::<String>().trim().to_string()
+}
+
+impl Header
+{
+    pub fn parse(rom: &[u8]) -> Option<Self>
+    {
+        if rom.len() < HEADER_END {
+            return None; }
+
+        // cgb games gave the last title bytes over to the cgb flag
+        let title_end = if matches!(rom[0x143], 0x80 | 0xC0) { 0x143 } else { 0x144 };
+
+        Some(Self
+        {
+            title: text(&rom[0x134 .. title_end]),
+            cgb_flag: rom[0x143],
+            new_licensee: text(&rom[0x144 .. 0x146]),
+            sgb_flag: rom[0x146],
+            cartridge_type: rom[0x147],
+            rom_size: rom[0x148],
+            ram_size: rom[0x149],
+            destination: rom[0x14A],
+            old_licensee: rom[0x14B],
+            version: rom[0x14C],
+            header_checksum: rom[0x14D],
+            global_checksum: u16::from_be_bytes([rom[0x14E], rom[0x14F]]),
+        })
+    }
+
+    pub fn cgb_support(&self) -> &'static str
+    {
+        match self.cgb_flag
+        {
+            0xC0 => "required",
+            0x80 => "supported",
+            _ => "no",
+        }
+    }
+
+    pub fn sgb_support(&self) -> &'static str
+    {
+        // the boot rom also wants the old licensee code to be $33
+
+        match (self.sgb_flag, self.old_licensee)
+        {
+            (0x03, USE_NEW_LICENSEE) => "supported",
+            (0x03, _) => "flagged, but the old licensee code isn't $33",
+            _ => "no",
+        }
+    }
+
+    pub fn cartridge_type_name(&self) -> &'static str
+    {
+        match self.cartridge_type
+        {
+            0x00 => "ROM only",
+            0x01 => "MBC1",
+            0x02 => "MBC1+RAM",
+            0x03 => "MBC1+RAM+BATTERY",
+            0x05 => "MBC2",
+            0x06 => "MBC2+BATTERY",
+            0x08 => "ROM+RAM",
+            0x09 => "ROM+RAM+BATTERY",
+            0x0B => "MMM01",
+            0x0C => "MMM01+RAM",
+            0x0D => "MMM01+RAM+BATTERY",
+            0x0F => "MBC3+TIMER+BATTERY",
+            0x10 => "MBC3+TIMER+RAM+BATTERY",
+            0x11 => "MBC3",
+            0x12 => "MBC3+RAM",
+            0x13 => "MBC3+RAM+BATTERY",
+            0x19 => "MBC5",
+            0x1A => "MBC5+RAM",
+            0x1B => "MBC5+RAM+BATTERY",
+            0x1C => "MBC5+RUMBLE",
+            0x1D => "MBC5+RUMBLE+RAM",
+            0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+            0x20 => "MBC6",
+            0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+            0xFC => "POCKET CAMERA",
+            0xFD => "BANDAI TAMA5",
+            0xFE => "HuC-3",
+            0xFF => "HuC-1+RAM+BATTERY",
+            _ => "unknown",
+        }
+    }
+
+    pub fn rom_len(&self) -> Option<usize>
+    {
+        match self.rom_size
+        {
+            0x00 ..= 0x08 => Some(0x8000 << self.rom_size),
+            _ => None,
+        }
+    }
+
+    pub fn ram_len(&self) -> Option<usize>
+    {
+        // $01 is unused, but taken as 2KiB by some emulators
+
+        match self.ram_size
+        {
+            0x00 => Some(0),
+            0x01 => Some(0x800),
+            0x02 => Some(0x2000),
+            0x03 => Some(0x8000),
+            0x04 => Some(0x20000),
+            0x05 => Some(0x10000),
+            _ => None,
+        }
+    }
+
+    pub fn licensee(&self) -> String
+    {
+        match self.old_licensee
+        {
+            USE_NEW_LICENSEE => format!("\"{}\" (new code)", self.new_licensee),
+            code => format!("${:02X}", code),
+        }
+    }
+
+    pub fn destination_name(&self) -> &'static str
+    {
+        match self.destination
+        {
+            0x00 => "Japan",
+            _ => "overseas",
+        }
+    }
+}
@@ -0,0 +1,163 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::xaddr::prelude::*;
+use super::gbasm;
+use super::regs;
+use super::anal::{AnalInfo, AnalEmu, BankCtx};
+
+use serde::{Deserialize, Serialize};
+
+const OPCODE_POP_HL: u8 = 0xE1;
+const OPCODE_PUSH_HL: u8 = 0xE5;
+const OPCODE_JP_HL: u8 = 0xE9;
+const OPCODE_INC_HL: u8 = 0x23;
+const OPCODE_LD_A_HLI: u8 = 0x2A;
+const OPCODE_AND_A: u8 = 0xA7;
+const OPCODE_OR_A: u8 = 0xB7;
+const OPCODE_CP_N: u8 = 0xFE;
+
+// how far into the callee to look for where it hands the advanced return address back
+const MAX_CALLEE_SCAN: usize = 0x40;
+
+// longest terminated inline data looked for
+const MAX_INLINE_LEN: usize = 0x100;
+
+// data a callee reads from after its call site, by popping the return address into hl and advancing it
+// (`pop hl / ld a, [hl+] / ... / push hl / ret`, or `jp hl` to return)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InlineArgs
+{
+    Fixed(usize),   // always the same number of bytes
+    Terminated(u8), // read until the given byte (included)
+}
+
+impl InlineArgs
+{
+    pub fn len_at(&self, info: &AnalInfo, xa: XAddr) -> Option<usize>
+    {
+        match self
+        {
+            InlineArgs::Fixed(len) => info.rom_slice(xa, *len).ok().map(|_| *len),
+
+            InlineArgs::Terminated(end) =>
+            {
+                let slice = info.rom_slice(xa, MAX_INLINE_LEN).or_else(|_| info.rom_slice(xa, 0)).unwrap_or(&[]);
+                slice.iter().position(|byte| byte == end).map(|i| i + 1)
+            }
+        }
+    }
+}
+
+// inline data following a call
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct InlineData
+{
+    pub xa: XAddr,
+    pub len: usize,
+    pub callee: XAddr,
+}
+
+pub fn match_callee(info: &AnalInfo, xa: XAddr, ctx: BankCtx) -> Option<InlineArgs>
+{
+    // walks the callee in address order (unconditional jumps are laid out around the loops they close)
+    // until it returns to hl, counting how far hl was advanced, or finding the terminator check of a read loop
+
+    let len = info.rom_slice(xa, MAX_CALLEE_SCAN).map(|slice| slice.len()).unwrap_or(0);
+    let mut emu = AnalEmu::with_ctx(info, xa, len, ctx);
+
+    match emu.next()
+    {
+        Some((_, Ok(ins))) if ins.opcode == OPCODE_POP_HL => {}
+        _ => return None,
+    }
+
+    let mut returns = false;
+    let mut advances = 0;
+    let mut branches = false;
+    let mut terminator = None;
+
+    // the byte just read into a, and the terminator it was compared with
+    let mut read = false;
+    let mut compared = None;
+
+    // hl just pushed, and whether a call followed: `push hl / call X / pop hl` only saves it around the call
+    let mut pushed = None;
+
+    while let Some((_, Ok(ins))) = emu.next()
+    {
+        let flags = ins.info().flags;
+
+        match (pushed, ins.opcode)
+        {
+            (Some(false), _) if (flags & gbasm::OPCODE_FLAG_CALL) != 0 && (flags & gbasm::OPCODE_FLAG_CONDITIONAL) == 0 =>
+            {
+                pushed = Some(true);
+                continue;
+            }
+
+            (Some(true), OPCODE_POP_HL) =>
+            {
+                pushed = None;
+                continue;
+            }
+
+            (Some(_), _) =>
+            {
+                returns = true;
+                break;
+            }
+
+            _ => {}
+        }
+
+        match ins.opcode
+        {
+            OPCODE_PUSH_HL => pushed = Some(false),
+
+            OPCODE_JP_HL =>
+            {
+                returns = true;
+                break;
+            }
+
+            OPCODE_INC_HL => advances += 1,
+            OPCODE_LD_A_HLI => advances += 1,
+
+            OPCODE_AND_A | OPCODE_OR_A if read => compared = Some(0),
+            OPCODE_CP_N if read => compared = Some(ins.operand as u8),
+
+            _ if (flags & gbasm::OPCODE_FLAG_JUMP) != 0 =>
+            {
+                if (flags & gbasm::OPCODE_FLAG_CALL) == 0 && ins.get_jump_target().is_none() {
+                    return None; }
+
+                if (flags & gbasm::OPCODE_FLAG_CONDITIONAL) != 0
+                {
+                    branches = true;
+                    terminator = terminator.or(compared);
+                }
+            }
+
+            _ if (regs::reg_effects(&ins).1 & regs::MASK_HL) != 0 => return None,
+
+            _ => {}
+        }
+
+        read = ins.opcode == OPCODE_LD_A_HLI;
+
+        if !read && ins.opcode != OPCODE_AND_A && ins.opcode != OPCODE_OR_A && ins.opcode != OPCODE_CP_N {
+            compared = None; }
+    }
+
+    match (branches, terminator)
+    {
+        _ if !returns => None,
+        (_, Some(end)) => Some(InlineArgs::Terminated(end)),
+        (false, None) if advances > 0 => Some(InlineArgs::Fixed(advances)),
+        _ => None,
+    }
+}
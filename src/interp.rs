@@ -0,0 +1,589 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::xaddr::prelude::*;
+use super::gbasm;
+use super::regs::{REG_A, REG_C, REG_H, REG_HL_MEM};
+use super::anal::{AnalInfo, BankCtx};
+use super::mbc::BankSelect;
+use super::func::{Function, FlowGraph};
+
+use std::collections::HashMap;
+
+// instructions run from each entry point at most
+const MAX_STEPS: usize = 10000;
+
+// where the stack is assumed to start, so pushed values can be popped back
+const STACK_TOP: u16 = 0xDFFE;
+
+const FLAG_Z: u8 = 0x80;
+const FLAG_N: u8 = 0x40;
+const FLAG_H: u8 = 0x20;
+const FLAG_C: u8 = 0x10;
+const FLAGS: u8 = FLAG_Z | FLAG_N | FLAG_H | FLAG_C;
+
+const OPCODE_JP_HL: u8 = 0xE9;
+
+// a small sm83 interpreter over the rom contents; registers, ram and i/o start unknown,
+// and it stops as soon as an unknown value decides where execution goes or where memory is accessed,
+// so whatever it finds really happens for any run reaching the entry point
+struct Cpu<'a>
+{
+    info: &'a AnalInfo<'a>,
+    regs: [Option<u8>; 8], // by register index, [hl] unused
+    f: u8,
+    f_known: u8, // flag bits with a known value
+    sp: u16,
+    pc: u16,
+    romb: Option<u16>, // unknown until the code selects one, when it's entered from home code
+    mode1: bool,
+    ram: HashMap<u16, Option<u8>>, // what was written, even when unknown
+}
+
+impl<'a> Cpu<'a>
+{
+    fn read(&self, addr: u16) -> Option<u8>
+    {
+        match addr
+        {
+            0x0000 ..= 0x7FFF => self.info.rom_slice(self.rom_xa(addr)?, 1).ok().map(|slice| slice[0]),
+            _ => self.ram.get(&addr).copied().flatten(),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: Option<u8>) -> Option<()>
+    {
+        match addr
+        {
+            0x0000 ..= 0x7FFF =>
+            {
+                // an unknown rom bank would leave everything in $4000-$7FFF unknown
+
+                match self.info.rom_info.mbc.bank_select(addr, value.unwrap_or(0), self.romb, self.info.rom_bank_count())
+                {
+                    Some(BankSelect::Rom(bank)) => { value?; self.romb = Some(bank); }
+                    Some(BankSelect::Mode(mode1)) => { value?; self.mode1 = mode1; }
+                    _ => {}
+                }
+            }
+
+            // i/o registers don't read back what was written
+            0xFF00 ..= 0xFF7F | 0xFFFF => {}
+
+            _ => { self.ram.insert(addr, value); }
+        }
+
+        Some(())
+    }
+
+    fn rom_xa(&self, addr: u16) -> Option<XAddr>
+    {
+        let ctx = BankCtx { romb: self.romb, mode1: self.mode1, ..BankCtx::default() };
+        self.info.expand_addr(&ctx, addr)
+    }
+
+    fn pair(&self, hi: u8) -> Option<u16>
+    {
+        Some((self.regs[hi as usize]? as u16) << 8 | self.regs[hi as usize + 1]? as u16)
+    }
+
+    fn set_pair(&mut self, hi: u8, value: Option<u16>)
+    {
+        self.regs[hi as usize] = value.map(|value| (value >> 8) as u8);
+        self.regs[hi as usize + 1] = value.map(|value| value as u8);
+    }
+
+    fn hl(&self) -> Option<u16>
+    {
+        self.pair(REG_H)
+    }
+
+    fn a(&self) -> Option<u8>
+    {
+        self.regs[REG_A as usize]
+    }
+
+    fn set_a(&mut self, value: Option<u8>)
+    {
+        self.regs[REG_A as usize] = value;
+    }
+
+    fn reg(&self, reg: u8) -> Option<u8>
+    {
+        if reg == REG_HL_MEM { self.read(self.hl()?) } else { self.regs[reg as usize] }
+    }
+
+    fn set_reg(&mut self, reg: u8, value: Option<u8>) -> Option<()>
+    {
+        if reg == REG_HL_MEM {
+            return self.write(self.hl()?, value); }
+
+        self.regs[reg as usize] = value;
+        Some(())
+    }
+
+    // rr operand of 16-bit loads, inc/dec and add hl (3 is sp)
+    fn pair_sp(&self, index: u8) -> Option<u16>
+    {
+        if index == 3 { Some(self.sp) } else { self.pair(index * 2) }
+    }
+
+    fn set_pair_sp(&mut self, index: u8, value: Option<u16>) -> Option<()>
+    {
+        if index == 3 { self.sp = value?; } else { self.set_pair(index * 2, value); }
+        Some(())
+    }
+
+    fn set_flags(&mut self, mask: u8, value: u8)
+    {
+        self.f = (self.f & !mask) | (value & mask);
+        self.f_known |= mask;
+    }
+
+    fn flag(&self, flag: u8) -> Option<bool>
+    {
+        if (self.f_known & flag) == 0 { None } else { Some((self.f & flag) != 0) }
+    }
+
+    fn push(&mut self, hi: Option<u8>, lo: Option<u8>) -> Option<()>
+    {
+        self.sp = self.sp.wrapping_sub(2);
+        self.write(self.sp, lo)?;
+        self.write(self.sp.wrapping_add(1), hi)
+    }
+
+    fn pop(&mut self) -> (Option<u8>, Option<u8>)
+    {
+        let (lo, hi) = (self.read(self.sp), self.read(self.sp.wrapping_add(1)));
+        self.sp = self.sp.wrapping_add(2);
+        (hi, lo)
+    }
+
+    fn pop_pc(&mut self) -> Option<()>
+    {
+        let (hi, lo) = self.pop();
+        self.pc = (hi? as u16) << 8 | lo? as u16;
+        Some(())
+    }
+
+    fn call(&mut self, target: u16) -> Option<()>
+    {
+        self.push(Some((self.pc >> 8) as u8), Some(self.pc as u8))?;
+        self.pc = target;
+        Some(())
+    }
+
+    fn condition(&self, op: u8) -> Option<bool>
+    {
+        // nz, z, nc, c
+
+        match (op >> 3) & 3
+        {
+            0 => self.flag(FLAG_Z).map(|z| !z),
+            1 => self.flag(FLAG_Z),
+            2 => self.flag(FLAG_C).map(|c| !c),
+            _ => self.flag(FLAG_C),
+        }
+    }
+
+    fn alu(&mut self, op: u8, value: Option<u8>) -> Option<()>
+    {
+        // add, adc, sub, sbc, and, xor, or, cp
+
+        let (a, value) = (self.a()?, value?);
+
+        let carry = match op & 7
+        {
+            1 | 3 => self.flag(FLAG_C)? as u8,
+            _ => 0,
+        };
+
+        let (result, f) = match op & 7
+        {
+            0 | 1 =>
+            {
+                let result = a as u16 + value as u16 + carry as u16;
+                let h = (a & 0x0F) + (value & 0x0F) + carry > 0x0F;
+                (result as u8, if h { FLAG_H } else { 0 } | if result > 0xFF { FLAG_C } else { 0 })
+            }
+
+            2 | 3 | 7 =>
+            {
+                let result = a as i16 - value as i16 - carry as i16;
+                let h = (a & 0x0F) as i16 - (value & 0x0F) as i16 - (carry as i16) < 0;
+                (result as u8, FLAG_N | if h { FLAG_H } else { 0 } | if result < 0 { FLAG_C } else { 0 })
+            }
+
+            4 => (a & value, FLAG_H),
+            5 => (a ^ value, 0),
+            _ => (a | value, 0),
+        };
+
+        self.set_flags(FLAGS, f | if result == 0 { FLAG_Z } else { 0 });
+
+        if op & 7 != 7 {
+            self.set_a(Some(result)); }
+
+        Some(())
+    }
+
+    fn add_sp_offset(&mut self, offset: u8) -> u16
+    {
+        // flags come from the low byte addition, as unsigned
+
+        let h = (self.sp & 0x0F) + (offset as u16 & 0x0F) > 0x0F;
+        let c = (self.sp & 0xFF) + offset as u16 > 0xFF;
+        self.set_flags(FLAGS, if h { FLAG_H } else { 0 } | if c { FLAG_C } else { 0 });
+
+        self.sp.wrapping_add(offset as i8 as u16)
+    }
+
+    fn bitop(&mut self, op: u8) -> Option<()>
+    {
+        let reg = op & 7;
+        let bit = (op >> 3) & 7;
+        let value = self.reg(reg)?;
+
+        let result = match op >> 6
+        {
+            0 =>
+            {
+                // rlc, rrc, rl, rr, sla, sra, swap, srl
+
+                let carry = if bit == 2 || bit == 3 { self.flag(FLAG_C)? as u8 } else { 0 };
+
+                let (result, c) = match bit
+                {
+                    0 => (value.rotate_left(1), value >> 7),
+                    1 => (value.rotate_right(1), value & 1),
+                    2 => (value << 1 | carry, value >> 7),
+                    3 => (value >> 1 | carry << 7, value & 1),
+                    4 => (value << 1, value >> 7),
+                    5 => (value >> 1 | (value & 0x80), value & 1),
+                    6 => (value.rotate_left(4), 0),
+                    _ => (value >> 1, value & 1),
+                };
+
+                self.set_flags(FLAGS, if result == 0 { FLAG_Z } else { 0 } | if c != 0 { FLAG_C } else { 0 });
+                result
+            }
+
+            1 =>
+            {
+                self.set_flags(FLAG_Z | FLAG_N | FLAG_H, FLAG_H | if (value & (1 << bit)) == 0 { FLAG_Z } else { 0 });
+                return Some(());
+            }
+
+            2 => value & !(1 << bit),
+            _ => value | (1 << bit),
+        };
+
+        self.set_reg(reg, Some(result))
+    }
+
+    fn step(&mut self, ins: &gbasm::Instruction) -> Option<()>
+    {
+        // runs an instruction, pc being already past it; None when an unknown value gets in the way
+
+        let op = ins.opcode;
+        let imm = ins.operand as u8;
+
+        match op
+        {
+            0x00 | 0xF3 | 0xFB => {}
+
+            0x01 | 0x11 | 0x21 | 0x31 => self.set_pair_sp(op >> 4, Some(ins.operand))?,
+
+            0x02 => self.write(self.pair(0)?, self.a())?,
+            0x12 => self.write(self.pair(2)?, self.a())?,
+            0x0A => self.set_a(self.read(self.pair(0)?)),
+            0x1A => self.set_a(self.read(self.pair(2)?)),
+
+            0x22 | 0x32 | 0x2A | 0x3A =>
+            {
+                let hl = self.hl()?;
+
+                if (op & 0x08) == 0 { self.write(hl, self.a())?; } else { self.set_a(self.read(hl)); }
+
+                self.set_pair(REG_H, Some(if (op & 0x10) == 0 { hl.wrapping_add(1) } else { hl.wrapping_sub(1) }));
+            }
+
+            0x03 | 0x13 | 0x23 | 0x33 => self.set_pair_sp(op >> 4, self.pair_sp(op >> 4).map(|value| value.wrapping_add(1)))?,
+            0x0B | 0x1B | 0x2B | 0x3B => self.set_pair_sp(op >> 4, self.pair_sp(op >> 4).map(|value| value.wrapping_sub(1)))?,
+
+            0x04 | 0x0C | 0x14 | 0x1C | 0x24 | 0x2C | 0x34 | 0x3C =>
+            {
+                let value = self.reg(op >> 3)?.wrapping_add(1);
+                self.set_reg(op >> 3, Some(value))?;
+                self.set_flags(FLAG_Z | FLAG_N | FLAG_H, if value == 0 { FLAG_Z } else { 0 } | if (value & 0x0F) == 0 { FLAG_H } else { 0 });
+            }
+
+            0x05 | 0x0D | 0x15 | 0x1D | 0x25 | 0x2D | 0x35 | 0x3D =>
+            {
+                let value = self.reg(op >> 3)?.wrapping_sub(1);
+                self.set_reg(op >> 3, Some(value))?;
+                self.set_flags(FLAG_Z | FLAG_N | FLAG_H, FLAG_N | if value == 0 { FLAG_Z } else { 0 } | if (value & 0x0F) == 0x0F { FLAG_H } else { 0 });
+            }
+
+            0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x36 | 0x3E => self.set_reg(op >> 3, Some(imm))?,
+
+            // rlca, rrca, rla, rra: like their cb counterparts on a, but z is always cleared
+            0x07 | 0x0F | 0x17 | 0x1F =>
+            {
+                self.bitop(op)?;
+                self.set_flags(FLAG_Z, 0);
+            }
+
+            0x08 =>
+            {
+                self.write(ins.operand, Some(self.sp as u8))?;
+                self.write(ins.operand.wrapping_add(1), Some((self.sp >> 8) as u8))?;
+            }
+
+            0x09 | 0x19 | 0x29 | 0x39 =>
+            {
+                let (hl, value) = (self.hl()?, self.pair_sp(op >> 4)?);
+                let h = (hl & 0x0FFF) + (value & 0x0FFF) > 0x0FFF;
+                let (result, c) = hl.overflowing_add(value);
+                self.set_pair(REG_H, Some(result));
+                self.set_flags(FLAG_N | FLAG_H | FLAG_C, if h { FLAG_H } else { 0 } | if c { FLAG_C } else { 0 });
+            }
+
+            0x18 | 0xC3 => self.pc = ins.operand,
+
+            0x20 | 0x28 | 0x30 | 0x38 | 0xC2 | 0xCA | 0xD2 | 0xDA =>
+            {
+                if self.condition(op)? {
+                    self.pc = ins.operand; }
+            }
+
+            0x27 =>
+            {
+                let mut a = self.a()?;
+                let (n, h, mut c) = (self.flag(FLAG_N)?, self.flag(FLAG_H)?, self.flag(FLAG_C)?);
+
+                if !n
+                {
+                    if c || a > 0x99 { a = a.wrapping_add(0x60); c = true; }
+                    if h || (a & 0x0F) > 0x09 { a = a.wrapping_add(0x06); }
+                }
+                else
+                {
+                    if c { a = a.wrapping_sub(0x60); }
+                    if h { a = a.wrapping_sub(0x06); }
+                }
+
+                self.set_a(Some(a));
+                self.set_flags(FLAG_Z | FLAG_H | FLAG_C, if a == 0 { FLAG_Z } else { 0 } | if c { FLAG_C } else { 0 });
+            }
+
+            0x2F =>
+            {
+                self.set_a(self.a().map(|a| !a));
+                self.set_flags(FLAG_N | FLAG_H, FLAG_N | FLAG_H);
+            }
+
+            0x37 => self.set_flags(FLAG_N | FLAG_H | FLAG_C, FLAG_C),
+
+            0x3F =>
+            {
+                let c = self.flag(FLAG_C)?;
+                self.set_flags(FLAG_N | FLAG_H | FLAG_C, if c { 0 } else { FLAG_C });
+            }
+
+            // halt is in the middle of the ld r, r' block, and waits for what isn't emulated
+            0x76 => return None,
+            0x40 ..= 0x7F => self.set_reg((op >> 3) & 7, self.reg(op & 7))?,
+
+            // sub a and xor a give zero whatever a was
+            0x97 =>
+            {
+                self.set_a(Some(0));
+                self.set_flags(FLAGS, FLAG_Z | FLAG_N);
+            }
+
+            0xAF =>
+            {
+                self.set_a(Some(0));
+                self.set_flags(FLAGS, FLAG_Z);
+            }
+
+            0x80 ..= 0xBF => self.alu(op >> 3, self.reg(op & 7))?,
+            0xC6 | 0xCE | 0xD6 | 0xDE | 0xE6 | 0xEE | 0xF6 | 0xFE => self.alu(op >> 3, Some(imm))?,
+
+            0xC0 | 0xC8 | 0xD0 | 0xD8 =>
+            {
+                if self.condition(op)? {
+                    self.pop_pc()?; }
+            }
+
+            0xC9 | 0xD9 => self.pop_pc()?,
+
+            0xC1 | 0xD1 | 0xE1 =>
+            {
+                let (hi, lo) = self.pop();
+                let index = ((op >> 4) & 3) as usize * 2;
+                self.regs[index] = hi;
+                self.regs[index + 1] = lo;
+            }
+
+            0xF1 =>
+            {
+                let (hi, lo) = self.pop();
+                self.set_a(hi);
+                self.f = lo.unwrap_or(0) & FLAGS;
+                self.f_known = if lo.is_some() { FLAGS } else { 0 };
+            }
+
+            0xC5 | 0xD5 | 0xE5 =>
+            {
+                let index = ((op >> 4) & 3) as usize * 2;
+                self.push(self.regs[index], self.regs[index + 1])?;
+            }
+
+            0xF5 => self.push(self.a(), if self.f_known == FLAGS { Some(self.f) } else { None })?,
+
+            0xCD => self.call(ins.operand)?,
+
+            0xC4 | 0xCC | 0xD4 | 0xDC =>
+            {
+                if self.condition(op)? {
+                    self.call(ins.operand)?; }
+            }
+
+            0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => self.call((op & 0x38) as u16)?,
+
+            0xCB => self.bitop(imm)?,
+
+            0xE0 | 0xEA => self.write(ins.operand, self.a())?,
+            0xF0 | 0xFA => self.set_a(self.read(ins.operand)),
+            0xE2 => self.write(0xFF00 | self.regs[REG_C as usize]? as u16, self.a())?,
+            0xF2 => self.set_a(self.read(0xFF00 | self.regs[REG_C as usize]? as u16)),
+
+            0xE8 => self.sp = self.add_sp_offset(imm),
+
+            0xF8 =>
+            {
+                let value = self.add_sp_offset(imm);
+                self.set_pair(REG_H, Some(value));
+            }
+
+            0xF9 => self.sp = self.hl()?,
+
+            OPCODE_JP_HL => self.pc = self.hl()?,
+
+            // stop, and invalid opcodes
+            _ => return None,
+        }
+
+        Some(())
+    }
+
+    fn pc_xa(&self) -> Option<XAddr>
+    {
+        // only rom is run, code copied to ram has to be found some other way
+
+        match self.pc
+        {
+            0x0000 ..= 0x7FFF => self.rom_xa(self.pc),
+            _ => None,
+        }
+    }
+}
+
+fn run(info: &AnalInfo, entry: XAddr, romb: Option<u16>, result: &mut Vec<(XAddr, XAddr)>)
+{
+    // runs from the entry point until an unknown value gets in the way (reading ram nothing was written to,
+    // or returning to the unknown caller), recording where each `jp hl` went
+
+    let mut cpu = Cpu
+    {
+        info: info,
+        regs: [None; 8],
+        f: 0,
+        f_known: 0,
+        sp: STACK_TOP,
+        pc: entry.addr,
+        romb: romb,
+        mode1: false,
+        ram: HashMap::new(),
+    };
+
+    for _ in 0 .. MAX_STEPS
+    {
+        let xa = match cpu.pc_xa()
+        {
+            Some(xa) => xa,
+            None => return,
+        };
+
+        let ins = match info.rom_slice(xa, 3).or_else(|_| info.rom_slice(xa, 1)).ok()
+            .and_then(|slice| gbasm::decode_stop(xa.addr, slice, info.stop_padding_at(xa)).ok())
+        {
+            Some(ins) => ins,
+            None => return,
+        };
+
+        cpu.pc = cpu.pc.wrapping_add(ins.encoded_len() as u16);
+
+        if cpu.step(&ins).is_none() {
+            return; }
+
+        if ins.opcode == OPCODE_JP_HL
+        {
+            if let Some(target) = cpu.pc_xa() {
+                result.push((xa, target)); }
+        }
+    }
+}
+
+pub fn find_computed_targets(info: &AnalInfo, graph: &FlowGraph, functions: &[Function]) -> Vec<(XAddr, XAddr)>
+{
+    // runs every function holding a `jp hl` that register tracking couldn't resolve,
+    // from the function entry (or the block itself when it belongs to none)
+
+    let mut owners: HashMap<usize, XAddr> = HashMap::new();
+
+    for function in functions
+    {
+        for &i in &function.blocks {
+            owners.entry(i).or_insert(function.xa); }
+    }
+
+    let mut entries = vec![];
+
+    for i in 0 .. graph.block_count()
+    {
+        let mut emu = graph.emu(i);
+
+        while let Some((_, Ok(ins))) = emu.next()
+        {
+            if ins.opcode != OPCODE_JP_HL || emu.computed_target(&ins).is_some() {
+                continue; }
+
+            let entry = owners.get(&i).copied().unwrap_or(graph.block(i).xa);
+
+            // the bank the entry was reached with, or its own; banked code or data is out of reach without one
+            let romb = graph.block_at(&entry).and_then(|j| graph.block(j).ctx.romb);
+            let romb = if entry.addr >= 0x4000 { Some(entry.bank) } else { romb };
+
+            entries.push((entry, romb));
+        }
+    }
+
+    entries.sort();
+    entries.dedup();
+
+    let mut result = vec![];
+
+    for (entry, romb) in entries {
+        run(info, entry, romb, &mut result); }
+
+    result.sort();
+    result.dedup();
+
+    result
+}
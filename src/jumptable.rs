@@ -0,0 +1,196 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::xaddr::prelude::*;
+use super::gbasm;
+use super::regs;
+use super::anal::{AnalInfo, AnalEmu, BankCtx, CodeBlock};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+const OPCODE_ADD_A_A: u8 = 0x87;
+const OPCODE_LD_HL_IMM: u8 = 0x21;
+const OPCODE_ADD_HL_BC: u8 = 0x09;
+const OPCODE_ADD_HL_DE: u8 = 0x19;
+const OPCODE_ADD_HL_HL: u8 = 0x29;
+const OPCODE_POP_HL: u8 = 0xE1;
+const OPCODE_JP_HL: u8 = 0xE9;
+const OPCODE_JP: u8 = 0xC3;
+
+const MAX_TABLE_LEN: usize = 0x100;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error, Serialize, Deserialize)]
+pub enum BadEntry
+{
+    #[error("doesn't point into ROM")]
+    OutsideRom,
+
+    #[error("points to a ROM bank that isn't known")]
+    UnknownBank,
+
+    #[error("doesn't point to valid code")]
+    InvalidCode,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JumpTable
+{
+    pub xa: XAddr,
+    pub ctx: BankCtx,
+    pub targets: Vec<(u16, Option<XAddr>)>, // each entry as written, and where it goes when the bank is known
+    pub bad_entries: Vec<(usize, BadEntry)>, // entries of tagged tables that aren't followed
+}
+
+impl JumpTable
+{
+    pub fn byte_len(&self) -> usize
+    {
+        self.targets.len() * 2
+    }
+
+    pub fn is_bad_entry(&self, i: usize) -> bool
+    {
+        self.bad_entries.iter().any(|(bad, _)| *bad == i)
+    }
+
+    pub fn code_targets(&self) -> impl Iterator<Item = XAddr> + '_
+    {
+        self.targets.iter().enumerate().filter(move |(i, _)| !self.is_bad_entry(*i)).filter_map(|(_, (_, xa))| *xa)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Dispatch
+{
+    Table(XAddr), // `ld hl, Table / ... / jp hl`
+    Inline,       // the table follows the call to this block (`pop hl / ... / jp hl`)
+}
+
+pub fn match_dispatch(info: &AnalInfo, block: &CodeBlock) -> Option<Dispatch>
+{
+    // recognizes the classic jump table dispatch code:
+    // index doubled with `add a` (or `add hl, hl`), table address in hl (loaded, added to the index or popped from the return address),
+    // entry read through hl and finally `jp hl`
+
+    let mut emu = AnalEmu::for_block(info, block);
+
+    let mut doubled = false;
+    let mut base = None;
+    let mut read_entry = false;
+
+    while let Some((_, Ok(ins))) = emu.next()
+    {
+        match ins.opcode
+        {
+            OPCODE_ADD_A_A | OPCODE_ADD_HL_HL => doubled = true,
+            OPCODE_LD_HL_IMM => { base = emu.expand_addr(ins.operand).map(Dispatch::Table); read_entry = false; }
+
+            // table address built some other way (`ld h, HIGH(Table) / ld l, LOW(Table)`)
+            // or in the other operand (`ld de, Table / add hl, de` with the index in hl)
+            OPCODE_ADD_HL_BC | OPCODE_ADD_HL_DE if base.is_none() =>
+            {
+                let pair = if ins.opcode == OPCODE_ADD_HL_BC { regs::RegPair::BC } else { regs::RegPair::DE };
+                let regs = emu.regs();
+
+                base = regs.hl().or_else(|| regs.pair(pair)).and_then(|addr| emu.expand_addr(addr)).map(Dispatch::Table);
+            }
+            OPCODE_POP_HL => { base = Some(Dispatch::Inline); read_entry = false; }
+
+            OPCODE_JP_HL =>
+                return if doubled && read_entry { base } else { None },
+
+            _ =>
+            {
+                let fmt = ins.info().fmt;
+
+                if base.is_some() && (ins.info().flags & gbasm::OPCODE_FLAG_READ_MEM) != 0 && fmt.contains("[hl") {
+                    read_entry = true; }
+            }
+        }
+    }
+
+    None
+}
+
+pub fn follow_jump(info: &AnalInfo, block: &CodeBlock) -> Option<XAddr>
+{
+    // target of a block that only consists of `jp nn` (typical of rst vectors)
+
+    let mut emu = AnalEmu::for_block(info, block);
+
+    match emu.next()
+    {
+        Some((_, Ok(ins))) if ins.opcode == OPCODE_JP => emu.expand_addr(ins.operand),
+        _ => None,
+    }
+}
+
+pub fn read_table(info: &AnalInfo, xa: XAddr, ctx: BankCtx, count: Option<usize>, stops: &[XAddr]) -> JumpTable
+{
+    // reads pointer entries until the given count or, if the count is unknown, until something
+    // that doesn't look like a table entry: a known code or data start, or a pointer to non-code
+
+    let emu = AnalEmu::with_ctx(info, xa, 0, ctx);
+    let slice = info.rom_slice(xa, count.unwrap_or(MAX_TABLE_LEN) * 2).unwrap_or(&[]);
+
+    let mut targets = vec![];
+    let mut bad_entries = vec![];
+
+    for (i, entry) in slice.chunks_exact(2).enumerate()
+    {
+        let entry_xa = xa + (i * 2) as u16;
+
+        if count.is_none() && i != 0 && stops.binary_search(&entry_xa).is_ok() {
+            break; }
+
+        let addr = entry[0] as u16 | (entry[1] as u16) << 8;
+
+        let target = match (addr, emu.expand_addr(addr))
+        {
+            (0x0000 ..= 0x7FFF, Some(target)) => target,
+
+            // tagged tables keep their length, but garbage (or what can't be told apart from it) isn't followed
+            (_, target) if count.is_some() =>
+            {
+                bad_entries.push((i, if addr < 0x8000 { BadEntry::UnknownBank } else { BadEntry::OutsideRom }));
+                targets.push((addr, target));
+                continue;
+            }
+
+            _ => break,
+        };
+
+        // the last instruction of a bank can be shorter than 3 bytes
+        let decodes = match info.rom_slice(target, 3).or_else(|_| info.rom_slice(target, 1))
+        {
+            Ok(slice) => gbasm::decode(addr, slice).is_ok(),
+            Err(_) => false,
+        };
+
+        if count.is_none()
+        {
+            // plausible entries point past the vectors, to something that decodes
+
+            if addr < 0x0100 || !decodes {
+                break; }
+        }
+        else if !decodes
+        {
+            bad_entries.push((i, BadEntry::InvalidCode));
+        }
+
+        targets.push((addr, Some(target)));
+    }
+
+    JumpTable
+    {
+        xa: xa,
+        ctx: ctx,
+        targets: targets,
+        bad_entries: bad_entries,
+    }
+}
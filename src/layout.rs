@@ -0,0 +1,63 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::io::Write;
+
+use super::xaddr::prelude::*;
+
+// a section of the listing, where the linker has to put it back
+pub struct Section
+{
+    pub name: String,
+    pub xa: XAddr,
+    pub len: usize,
+}
+
+fn bank_end(xa: XAddr, flat_rom: Option<usize>) -> usize
+{
+    // the end of the area the bank is mapped in, a rom without banks is mapped as one up to its end
+
+    match (xa.addr, flat_rom)
+    {
+        (0x0000 ..= 0x7FFF, Some(len)) => std::cmp::min(len, 0x8000),
+        (0x0000 ..= 0x3FFF, _) => 0x4000,
+        (0x4000 ..= 0x7FFF, _) => 0x8000,
+        (0x8000 ..= 0x9FFF, _) => 0xA000,
+        (0xA000 ..= 0xBFFF, _) => 0xC000,
+        (0xC000 ..= 0xCFFF, _) => 0xD000,
+        (0xD000 ..= 0xDFFF, _) => 0xE000,
+        _ => 0x10000,
+    }
+}
+
+pub fn write_map<W>(write: &mut W, sections: &[Section], flat_rom: Option<usize>) -> std::io::Result<()>
+    where W: Write
+{
+    // by bank then address, as a linker map has them; slack is the room up to the next section or the end of the bank
+    // `flat_rom` is the size of a rom without banks
+
+    let mut sections: Vec<&Section> = sections.iter().collect();
+    sections.sort_by_key(|section| section.xa);
+
+    for (i, section) in sections.iter().enumerate()
+    {
+        let end = section.xa.addr as usize + section.len;
+
+        if i == 0 || sections[i - 1].xa.bank != section.xa.bank {
+            writeln!(write, "bank ${:02X}:", section.xa.bank)?; }
+
+        let next = match sections.get(i + 1)
+        {
+            Some(next) if next.xa.bank == section.xa.bank && (next.xa.addr as usize) < bank_end(section.xa, flat_rom) => next.xa.addr as usize,
+            _ => bank_end(section.xa, flat_rom),
+        };
+
+        writeln!(write, "\t${:04X}-${:04X} (${:04X} bytes, ${:04X} of slack) {}",
+            section.xa.addr, end - 1, section.len, next.saturating_sub(end), section.name)?;
+    }
+
+    Ok(())
+}
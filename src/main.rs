@@ -9,6 +9,43 @@ pub mod gbasm;
 pub mod xaddr;
 pub mod tags;
 pub mod anal;
+pub mod rename;
+pub mod syntax;
+pub mod codec;
+pub mod jumptable;
+pub mod regs;
+pub mod func;
+pub mod relocate;
+pub mod signature;
+pub mod cluster;
+pub mod plausibility;
+pub mod entropy;
+pub mod trace;
+pub mod bgb;
+pub mod interp;
+pub mod coverage;
+pub mod stats;
+pub mod timing;
+pub mod wait;
+pub mod mbc;
+pub mod inline;
+pub mod script;
+pub mod mmm01;
+pub mod gbs;
+pub mod memmap;
+pub mod archive;
+pub mod cache;
+pub mod header;
+pub mod diff;
+pub mod patch;
+pub mod project;
+pub mod text;
+pub mod search;
+pub mod gfx;
+pub mod progress;
+pub mod nocash;
+pub mod debuginfo;
+pub mod layout;
 
 use xaddr::prelude::*;
 
@@ -16,16 +53,237 @@ use std::path::PathBuf;
 use structopt::StructOpt;
 use anyhow::Result;
 
-#[derive(StructOpt)]
+#[derive(StructOpt, Clone)]
+enum Command
+{
+    /// Print analysis statistics instead of the listing
+    Stats,
+
+    /// Print the decoded cartridge header instead of the listing
+    Info,
+
+    /// Check the header and global checksums, or write a copy of the ROM with both fixed
+    Checksum
+    {
+        /// Where to write the fixed ROM (which can be the ROM itself)
+        #[structopt(long, parse(from_os_str))]
+        fix: Option<PathBuf>,
+    },
+
+    /// Compare the functions and data regions of a ROM with another revision of it (`bub diff a.gb b.gb`)
+    Diff
+    {
+        #[structopt(parse(from_os_str))]
+        old: PathBuf,
+
+        #[structopt(parse(from_os_str))]
+        new: PathBuf,
+
+        /// Tags of the first ROM
+        #[structopt(long, parse(from_os_str))]
+        old_tags: Option<PathBuf>,
+
+        /// Tags of the second ROM, whose names pair functions and data regions with the ones tagged the same in the first
+        #[structopt(long, parse(from_os_str))]
+        new_tags: Option<PathBuf>,
+    },
+
+    /// Write an IPS or BPS patch (by the extension of the output) turning the ROM into another one
+    Patch
+    {
+        #[structopt(parse(from_os_str))]
+        modified: PathBuf,
+
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+    },
+
+    /// Print the text found in the ROM, in ASCII or through a charmap
+    Strings
+    {
+        /// rgbds charmap file (`charmap "A", $80` lines) for the game's encoding
+        #[structopt(long, parse(from_os_str))]
+        charmap: Option<PathBuf>,
+
+        /// Fewest characters a string is made of
+        #[structopt(long, default_value = "4")]
+        min_len: usize,
+    },
+
+    /// Print where hex bytes (`3E ?? E0 46`) or instructions (`ld a, ? / ldh [$46], a`) are found in the ROM
+    Search
+    {
+        pattern: String,
+    },
+
+    /// Decompress the data at an address (`bank:addr`) to a file
+    Decompress
+    {
+        at: XAddr,
+
+        /// Codec the data is compressed with (built in, or from a --codec-file)
+        #[structopt(long)]
+        codec: String,
+
+        #[structopt(long, short, parse(from_os_str))]
+        output: PathBuf,
+    },
+
+    /// Render 2bpp tiles at an address (`bank:addr`) to a PNG sheet
+    Tiles
+    {
+        at: XAddr,
+        count: usize,
+
+        /// Tiles per row of the sheet
+        #[structopt(long, default_value = "16")]
+        columns: usize,
+
+        #[structopt(long, short, parse(from_os_str))]
+        output: PathBuf,
+    },
+
+    /// Render a tilemap (`bank:addr`) with the tiles at another address to a PNG
+    Tilemap
+    {
+        map: XAddr,
+        tiles: XAddr,
+
+        /// Tiles per row of the map
+        #[structopt(long, default_value = "20")]
+        width: usize,
+
+        /// Rows of the map
+        #[structopt(long, default_value = "18")]
+        height: usize,
+
+        /// Tile indexes are signed, $80-$FF being the tiles before the address (LCDC bit 4 clear)
+        #[structopt(long)]
+        signed: bool,
+
+        /// CGB attributes, a byte per tile as the map has them
+        #[structopt(long)]
+        attrs: Option<XAddr>,
+
+        /// Tiles for attributes selecting VRAM bank 1
+        #[structopt(long)]
+        bank1_tiles: Option<XAddr>,
+
+        /// CGB palettes, 8 of 4 RGB555 colors
+        #[structopt(long)]
+        palettes: Option<XAddr>,
+
+        #[structopt(long, short, parse(from_os_str))]
+        output: PathBuf,
+    },
+
+    /// Print CGB palettes at an address (`bank:addr`) as `dw` values, optionally as a PNG of swatches
+    Palettes
+    {
+        at: XAddr,
+
+        /// Palettes of 4 colors
+        #[structopt(default_value = "8")]
+        count: usize,
+
+        #[structopt(long, short, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+
+    /// Print the symbols the listing would have, with what they are and how many places refer to them
+    Sym
+    {
+        /// Only the symbols whose name contains this (case doesn't matter)
+        filter: Option<String>,
+
+        /// Only the symbols of a kind
+        #[structopt(long, possible_values = &["code", "data", "ram"])]
+        kind: Option<String>,
+
+        /// Order of the symbols
+        #[structopt(long, default_value = "addr", possible_values = &["addr", "name", "xrefs"])]
+        sort: String,
+    },
+
+    /// Print the listing of a single function (a name or `bank:addr`)
+    Fn
+    {
+        symbol: String,
+    },
+
+    /// Print the callers and callees of a function (a name or `bank:addr`) instead of the listing
+    Calls
+    {
+        symbol: String,
+
+        /// How many levels of callers and callees are followed
+        #[structopt(long, default_value = "1")]
+        depth: usize,
+    },
+}
+
+#[derive(StructOpt, Clone)]
 #[structopt(name = "bub")]
 struct Opt
 {
+    #[structopt(subcommand)]
+    command: Option<Command>,
+
+    /// ROM to analyse, the project's when there is one
     #[structopt(name = "rom", parse(from_os_str))]
-    input_filename: PathBuf,
+    input_filename: Option<PathBuf>,
 
     #[structopt(name = "tags", parse(from_os_str))]
     tags_filename: Option<PathBuf>,
 
+    /// Project file giving the ROM, tags and options (`bub.toml` in the current directory by default)
+    #[structopt(long, parse(from_os_str))]
+    project: Option<PathBuf>,
+
+    /// Directory where the listing is written, as `<rom>.asm`, instead of the standard output
+    #[structopt(long, parse(from_os_str))]
+    output_dir: Option<PathBuf>,
+
+    /// Revision of the project to work on (by default, the listings of all of them are written)
+    #[structopt(long)]
+    revision: Option<String>,
+
+    // tags files of the project, read after the one on the command line
+    #[structopt(skip)]
+    project_tags: Vec<PathBuf>,
+
+    // revisions of the project, when none was picked
+    #[structopt(skip)]
+    revisions: Vec<project::Revision>,
+
+    // what the listing file is named after, instead of the rom
+    #[structopt(skip)]
+    listing_name: Option<String>,
+
+    /// File to analyse in a zip archive, when it holds more than one `.gb`/`.gbc` file
+    #[structopt(long)]
+    member: Option<String>,
+
+    /// The image is a DMG or CGB boot ROM, mapped at $0000 and entered there, instead of a cartridge
+    #[structopt(long)]
+    boot_rom: bool,
+
+    /// Load address (`bank:addr`) of a headerless image (an overlay, an extracted bank, a RAM dump) to analyse instead of a cartridge
+    #[structopt(long)]
+    org: Option<XAddr>,
+
+    /// File of extra mapped regions (`FF70-FF7F Flash`), named after them and left out of banking
+    #[structopt(long, parse(from_os_str))]
+    memory_map: Option<PathBuf>,
+
+    /// Dump of the cartridge RAM (banks of 8KiB one after the other), to analyse code copied there
+    #[structopt(long, parse(from_os_str))]
+    sram: Option<PathBuf>,
+
+    /// Game of an MMM01 multicart to analyse, by its index in the file (the menu is analysed otherwise)
+    #[structopt(long)]
+    sub_image: Option<usize>,
+
     #[structopt(long)]
     big_rom: Option<bool>,
 
@@ -34,6 +292,160 @@ struct Opt
 
     #[structopt(long)]
     sram_count: Option<usize>,
+
+    /// Whether `stop` is followed by a padding byte (true by default, `.stoplen` tags override it)
+    #[structopt(long)]
+    stop_padding: Option<bool>,
+
+    /// File of `regex => replacement` rules applied to automatic names
+    #[structopt(long, parse(from_os_str))]
+    rename_rules: Option<PathBuf>,
+
+    /// File of extra routine signatures (`Name: 2A 12 ?? C9`), may be repeated
+    #[structopt(long = "signatures", parse(from_os_str), number_of_values = 1)]
+    signature_files: Vec<PathBuf>,
+
+    /// Assembler syntax of the listing (`rgbds` or `wla`, `rgbds` by default)
+    #[structopt(long)]
+    syntax: Option<syntax::Syntax>,
+
+    /// Instruction spellings to use instead of those of the syntax (`ldi`, `hl+`, `ff00`, `ldh-c`, `implicit-a`), separated by commas
+    #[structopt(long, use_delimiter = true)]
+    spelling: Option<Vec<syntax::Spelling>>,
+
+    /// Opcode table of a script interpreter, used for `.script` tags naming it after the file (`vm.txt` is `vm`), may be repeated
+    #[structopt(long = "script-vm", parse(from_os_str), number_of_values = 1)]
+    script_vm_files: Vec<PathBuf>,
+
+    /// Additional analysis entry point (`bank:addr`), may be repeated
+    #[structopt(long = "entry", number_of_values = 1)]
+    entries: Vec<XAddr>,
+
+    /// Symbol file (`bank:addr Label`, as written by rgblink and loaded by BGB) whose labels are imported after those of the tags, may be repeated
+    #[structopt(long, number_of_values = 1, parse(from_os_str))]
+    import_sym: Vec<PathBuf>,
+
+    /// no$gmb symbol file whose labels (and data directives) are imported after those of --import-sym, may be repeated
+    #[structopt(long, number_of_values = 1, parse(from_os_str))]
+    import_nocash: Vec<PathBuf>,
+
+    /// Breakpoint list copied out of BGB (`bank:addr` first on each line) whose addresses are used as entry points, may be repeated
+    #[structopt(long, number_of_values = 1, parse(from_os_str))]
+    import_breakpoints: Vec<PathBuf>,
+
+    /// BGB savestate whose pc is used as an entry point, with the banks selected at the time
+    #[structopt(long, parse(from_os_str))]
+    savestate: Option<PathBuf>,
+
+    /// Emulator execution trace whose executed addresses are used as entry points, may be repeated
+    #[structopt(long = "trace", parse(from_os_str), number_of_values = 1)]
+    traces: Vec<PathBuf>,
+
+    /// Format of the traces: `bgb`, `sameboy`, `gambatte` or a regex with `pc` and optionally `bank` groups (hex)
+    #[structopt(long, default_value = "bgb")]
+    trace_format: trace::TraceFormat,
+
+    /// Seed analysis with the cartridge entry point and the rst/interrupt vectors even when tags are given
+    #[structopt(long)]
+    default_entry: bool,
+
+    /// Don't seed analysis with the cartridge entry point and the rst/interrupt vectors, even without tags
+    #[structopt(long)]
+    no_default_entry: bool,
+
+    /// Description of a command byte codec, used for `.compressed` tags naming it after the file (`lz.txt` is `lz`), may be repeated
+    #[structopt(long = "codec-file", parse(from_os_str), number_of_values = 1)]
+    codec_files: Vec<PathBuf>,
+
+    /// Directory where compressed regions are written out decompressed
+    #[structopt(long, parse(from_os_str))]
+    extract_dir: Option<PathBuf>,
+
+    /// Speculatively disassemble regions analysis never reached
+    #[structopt(long)]
+    sweep: bool,
+
+    /// Run functions ending in an unresolved `jp hl` in a bounded emulator to find where they jump
+    #[structopt(long)]
+    emulate: bool,
+
+    /// Report how many bytes of each bank are code, data or unknown to stderr
+    #[structopt(long)]
+    coverage: bool,
+
+    /// File where the per-bank coverage is written as JSON
+    #[structopt(long, parse(from_os_str))]
+    coverage_json: Option<PathBuf>,
+
+    /// Report unclassified regions that look like compressed data to stderr
+    #[structopt(long)]
+    entropy: bool,
+
+    /// ROM banks (in hex, separated by commas) known to hold only data, left out of analysis and the listing
+    #[structopt(long, use_delimiter = true, parse(try_from_str = parse_bank))]
+    exclude_banks: Vec<u16>,
+
+    /// Only list what starts within this range (`bank:addr-bank:addr`, `bank:addr-addr` or a whole `bank`), may be repeated
+    #[structopt(long = "range", number_of_values = 1)]
+    ranges: Vec<xaddr::XAddrRange>,
+
+    /// Only start analysis from entry points and `.code` tags within --range, for speed
+    #[structopt(long)]
+    range_analysis: bool,
+
+    /// Leave out code below this confidence level (`speculative`, `heuristic`, `flow` or `tagged`)
+    #[structopt(long)]
+    min_confidence: Option<anal::Confidence>,
+
+    /// Note best and worst case cycle counts on functions
+    #[structopt(long)]
+    cycles: bool,
+
+    /// Report groups of near-identical functions to stderr
+    #[structopt(long)]
+    similar_code: bool,
+
+    /// Move code not reachable from the cartridge entry point and vectors to the end of the listing
+    #[structopt(long)]
+    orphans_section: bool,
+
+    /// Fail (after writing the output) if there are unresolved xrefs, invalid opcodes reached as code or conflicting tags
+    #[structopt(long)]
+    strict: bool,
+
+    /// Directory where analysis results are kept by ROM, and reused while the tags and options they depend on don't change
+    #[structopt(long, parse(from_os_str))]
+    cache_dir: Option<PathBuf>,
+
+    /// Write the sections of the listing to this file, by bank, with their size and the room left after them
+    #[structopt(long, parse(from_os_str))]
+    section_map: Option<PathBuf>,
+
+    /// Write a symbol file (wla-dx's format) with the labels and the line of the listing each instruction and data row is on
+    #[structopt(long, parse(from_os_str))]
+    debug_map: Option<PathBuf>,
+
+    /// Don't show what analysis and the listing are up to on stderr (only shown when it's a terminal)
+    #[structopt(long)]
+    no_progress: bool,
+
+    /// Run again whenever the tags file (or another input edited while tagging) changes, writing over the listing in --output-dir
+    #[structopt(long)]
+    watch: bool,
+}
+
+impl Opt
+{
+    fn uses_default_entry(&self, rom_info: &anal::RomInfo) -> bool
+    {
+        // tags are expected to say where code is, so the cartridge entry point and vectors are only a default without them
+        // (other images start where they're loaded, tags or not)
+
+        let has_tags = self.tags_filename.is_some() || !self.project_tags.is_empty();
+        let cartridge = !rom_info.boot_rom && rom_info.org.is_none() && rom_info.gbs.is_none();
+
+        !self.no_default_entry && (self.default_entry || !has_tags || !cartridge)
+    }
 }
 
 const SRAM_COUNT_LUT: &[usize] = &[
@@ -44,13 +456,72 @@ const SRAM_COUNT_LUT: &[usize] = &[
     16, // $04: 128KiB, 16 banks
     8]; // $05: 64KiB, 8 banks
 
-use std::collections::HashMap;
+// MBC5 selects up to 512 banks, the most any cartridge has
+const MAX_ROM_LEN: usize = 0x800000;
+
+const BANNER_WIDTH: usize = 60;
+
+const OPCODE_STOP: u8 = 0x10;
+
+// --strict failing exits with this, so CI can tell unclean analysis from bub failing to run (1)
+const EXIT_NOT_CLEAN: i32 = 2;
+
+#[derive(thiserror::Error, Debug)]
+#[error("analysis of {rom} isn't clean: {unresolved_xrefs} unresolved xref(s), {decode_failures} decode failure(s), {tag_conflicts} tag conflict(s)")]
+struct NotClean
+{
+    rom: String,
+    unresolved_xrefs: usize,
+    decode_failures: usize,
+    tag_conflicts: usize,
+}
+
+fn check_strict(opt: &Opt, rom: &std::path::Path, stats: &stats::Stats) -> Result<()>
+{
+    // with --strict, whatever was asked for is written before failing
+
+    if opt.strict && !stats.is_clean()
+    {
+        return Err(NotClean
+        {
+            rom: rom.display().to_string(),
+            unresolved_xrefs: stats.unresolved_xrefs,
+            decode_failures: stats.decode_failures,
+            tag_conflicts: stats.tag_conflicts,
+        }.into());
+    }
+
+    Ok(())
+}
+
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 fn default_xaddr_name(xa: XAddr, base: &str) -> String
 {
     match xa.addr
     {
-        0xA000 ..= 0xAFFF => format!("s{}_{:02X}_{:04X}", base, xa.bank, xa.addr),
+        // nothing but data is in vram, named after the area it's in
+        0x8000 ..= 0x9FFF =>
+        {
+            let area = match (xa.addr, xa.bank)
+            {
+                (0x8000 ..= 0x87FF, _) => "Tiles0",
+                (0x8800 ..= 0x8FFF, _) => "Tiles1",
+                (0x9000 ..= 0x97FF, _) => "Tiles2",
+                (0x9800 ..= 0x9BFF, 0) => "BGMap0",
+                (0x9800 ..= 0x9BFF, _) => "AttrMap0",
+                (_, 0) => "BGMap1",
+                _ => "AttrMap1",
+            };
+
+            match (xa.addr, xa.bank)
+            {
+                (0x8000 ..= 0x97FF, 1 ..) => format!("v{}_{:02X}_{:04X}", area, xa.bank, xa.addr),
+                _ => format!("v{}_{:04X}", area, xa.addr),
+            }
+        }
+
+        0xA000 ..= 0xBFFF => format!("s{}_{:02X}_{:04X}", base, xa.bank, xa.addr),
         0xFF80 ..= 0xFFFE => format!("h{}{:04X}", base, xa.addr),
 
         0xC000 ..= 0xDFFF =>
@@ -73,186 +544,2344 @@ fn default_xaddr_name(xa: XAddr, base: &str) -> String
     }
 }
 
-fn update_name_map_with_code_refs(info: &anal::AnalInfo, code_blocks: &[(XAddr, usize)], name_map: &mut HashMap<XAddr, String>)
+fn ref_name(syntax: syntax::Syntax, scope: &[String], name_map: &HashMap<XAddr, String>, tags: &[(XAddr, tags::Tag)], xa: XAddr) -> Option<String>
 {
-    use log::warn;
-
-    for &(xa, len) in code_blocks
+    match (name_map.get(&xa), tags::find_var(tags, &xa))
     {
-        let mut emu = anal::AnalEmu::with_bound(info, xa, len);
-
-        while let Some((xa, Ok(ins))) = emu.next()
-        {
-            if let Some(addr) = ins.get_jump_target()
-            {
-                match emu.expand_addr(addr)
-                {
-                    Some(xa) => { name_map.entry(xa).or_insert(default_xaddr_name(xa, "Code")); }
-                    None => warn!("unresolved code xref at {}: {:04X}", xa, addr),
-                }
-            }
-            else if ins.is_addr_operand() || tags::get_tags_at(info.tags, &xa).iter().any(|(_, tag)| if let tags::Tag::OperandAddr = tag { true } else { false })
-            {
-                let addr = ins.operand;
-
-                match emu.expand_addr(addr)
-                {
-                    Some(xa) => { name_map.entry(xa).or_insert(default_xaddr_name(xa, "Unk")); }
-                    None => warn!("unresolved data xref at {}: {:04X}", xa, addr),
-                }
-            }
-        }
+        (Some(name), _) => Some(syntax.label_ref(scope, name)),
+        (None, Some((name, offset))) => Some(format!("{}+{}", syntax.label_ref(scope, name), offset)),
+        (None, None) => None,
     }
 }
 
-fn main() -> Result<()>
+enum Chunk<'a>
 {
-    use std::fs::File;
-    use std::io::Read;
-    use std::io::BufReader;
+    Code(anal::BankCtx, anal::Confidence),
+    Compressed(Box<dyn codec::Codec>, Vec<u8>),
+    JumpTable(jumptable::JumpTable),
+    Copied(relocate::CopiedCode),
+    InlineData(XAddr), // read by the callee
+    Script(usize, Vec<&'a script::ScriptIns>), // interpreter, instructions
+}
 
-    env_logger::builder().format_timestamp(None).init();
+fn covered_ranges(chunks: &[(XAddr, usize, Chunk)], tags: &[(XAddr, tags::Tag)]) -> Vec<(XAddr, usize)>
+{
+    // rom ranges already accounted for, sorted
 
-    // read options, init inputs
+    let mut covered: Vec<(XAddr, usize)> = chunks.iter().map(|(xa, len, _)| (*xa, *len)).collect();
+    covered.sort();
 
-    let opt = Opt::from_args();
+    // data tags cover everything up to what comes next
 
-    let rom_data =
+    for (xa, tag) in tags
     {
-        let mut file = File::open(opt.input_filename)?;
+        // only rom is listed, ram data tags cover nothing here
+        if let (tags::Tag::Data, 0x0000 ..= 0x7FFF) = (tag, xa.addr)
+        {
+            let next = covered.iter().find(|(next, _)| next.bank == xa.bank && next.addr > xa.addr).map_or(0x8000, |(next, _)| (next.addr as usize).min(0x8000));
+            covered.push((*xa, next.saturating_sub(xa.addr as usize)));
+        }
+    }
 
-        let mut rom_data = vec![];
-        file.read_to_end(&mut rom_data)?;
+    covered.sort();
+    covered
+}
 
-        rom_data
-    };
+fn update_name_map_with_code_refs(info: &anal::AnalInfo, analysis: &anal::Analysis, name_map: &mut HashMap<XAddr, String>)
+{
+    use log::warn;
 
-    let rom_info = anal::RomInfo
+    for block in &analysis.code_blocks
     {
-        big_rom: opt.big_rom.unwrap_or(rom_data.len() > 0x8000),
-        cgb_ram: opt.cgb_ram.unwrap_or(rom_data[0x143] == 0xC0),
-        sram_count: opt.sram_count.unwrap_or(*SRAM_COUNT_LUT.get(rom_data[0x149] as usize).unwrap_or(&0)),
-    };
+        // code reached with conflicting bank states refers to something else with each of them
 
-    let tags = match opt.tags_filename
-    {
-        Some(filename) => tags::parse_tags(&mut BufReader::new(File::open(filename)?))?,
-        None => vec![(XAddr::new(0, 0x0100), tags::Tag::Code)]
-    };
+        for ctx in analysis.bank_variants.get(&block.xa).into_iter().flatten()
+        {
+            let mut emu = anal::AnalEmu::with_ctx(info, block.xa, block.len, *ctx);
 
-    let entry_points =
-    {
-        use std::collections::BinaryHeap;
+            while let Some((_, Ok((_, operand)))) = emu.next_resolved()
+            {
+                if let Some(target) = operand.code() {
+                    name_map.entry(target).or_insert(default_xaddr_name(target, "Code")); }
+            }
+        }
 
-        let mut entry_points = BinaryHeap::new();
+        let mut emu = anal::AnalEmu::for_block(info, block);
 
-        for (xa, tag) in &tags
+        while let Some((xa, Ok((ins, operand)))) = emu.next_resolved()
         {
-            if let tags::Tag::Code = tag
+            // writes to rom are mbc commands, not data
+            let mbc_write = ins.operand < 0x8000 && (ins.info().flags & gbasm::OPCODE_FLAG_WRITE_MEM) != 0;
+
+            match operand
             {
-                entry_points.push(*xa);
+                anal::Operand::Code(xa) => { name_map.entry(xa).or_insert(default_xaddr_name(xa, "Code")); }
+                anal::Operand::UnresolvedCode(_) if analysis.bank_variants.contains_key(&block.xa) => {}
+                anal::Operand::UnresolvedCode(addr) => warn!("unresolved code xref at {}: {:04X}", xa, addr),
+
+                anal::Operand::Data(_) | anal::Operand::UnresolvedData(_) if mbc_write => {}
+
+                // declared variables cover their whole range, don't make new labels inside them
+                anal::Operand::Data(xa) if tags::find_var(info.tags, &xa).is_some() => {}
+                anal::Operand::Data(xa) => { name_map.entry(xa).or_insert(default_xaddr_name(xa, "Unk")); }
+                anal::Operand::UnresolvedData(addr) => warn!("unresolved data xref at {}: {:04X}", xa, addr),
+
+                anal::Operand::None => {}
             }
         }
+    }
+}
 
-        entry_points.into_sorted_vec()
-    };
+fn count_xrefs(info: &anal::AnalInfo, analysis: &anal::Analysis) -> HashMap<XAddr, (usize, usize)>
+{
+    // (code, data) references to each address, variables counting those to any of their bytes
 
-    // analysis
+    let mut result: HashMap<XAddr, (usize, usize)> = HashMap::new();
 
-    let anal_info = anal::AnalInfo::new(rom_info, &rom_data, &tags);
+    for block in &analysis.code_blocks
+    {
+        let mut emu = anal::AnalEmu::for_block(info, block);
 
-    let code_blocks = anal::anal(&anal_info, &entry_points);
+        while let Some((_, Ok((ins, operand)))) = emu.next_resolved()
+        {
+            match operand
+            {
+                anal::Operand::Code(target) => result.entry(target).or_default().0 += 1,
 
-    // do automatic names
+                // writes to rom are mbc commands, not data
+                anal::Operand::Data(_) if ins.operand < 0x8000 && (ins.info().flags & gbasm::OPCODE_FLAG_WRITE_MEM) != 0 => {}
 
-    let mut name_map = HashMap::new();
+                anal::Operand::Data(target) =>
+                {
+                    let target = match tags::find_var(info.tags, &target)
+                    {
+                        Some((_, offset)) => XAddr::new(target.bank, target.addr - offset),
+                        None => target,
+                    };
 
-    for (xa, tag) in &tags
-    {
-        if let tags::Tag::Name(name) = tag
-        {
-            name_map.entry(*xa).or_insert(name.clone());
+                    result.entry(target).or_default().1 += 1;
+                }
+
+                _ => {}
+            }
         }
     }
 
-    update_name_map_with_code_refs(&anal_info, &code_blocks, &mut name_map);
-
-    // print listing
+    for table in &analysis.jump_tables
+    {
+        for target in table.code_targets() {
+            result.entry(target).or_default().0 += 1; }
+    }
 
-    let mut last_xa = XAddr::new(0xFFFF, 0xFFFF);
-    let mut last_name = String::from("");
+    result
+}
 
-    let mut get_local_name = |name: String, update: bool|
+fn resolve_symbol(name_map: &HashMap<XAddr, String>, symbol: &str) -> Result<XAddr>
+{
+    match name_map.iter().find(|(_, name)| *name == symbol)
     {
-        let parts: Vec<_> = name.split('.').collect();
+        Some((xa, _)) => Ok(*xa),
 
-        if parts.len() == 2 && parts[0] == last_name
-        {
-            format!(".{}", parts[1])
-        }
-        else
+        None => match symbol.parse::<XAddr>()
         {
-            if update {
-                last_name = name.clone(); }
-            name
-        }
-    };
+            Ok(xa) => Ok(xa),
+            Err(_) => anyhow::bail!("unknown symbol `{}`", symbol),
+        },
+    }
+}
 
-    let print_object = |xa: XAddr, fmt: &str|
-    {
-        let mut comments = tags::get_tags_at(&tags, &xa).iter().filter_map(|tag|
-        {
-            match &tag.1
-            {
-                tags::Tag::Comment(comment) => Some(comment),
-                _ => None,
-            }
-        });
+fn print_call_tree(edges: &[(XAddr, XAddr)], callers: bool, depth: usize, path: &mut Vec<XAddr>, display: &dyn Fn(XAddr) -> String)
+{
+    // path goes from the root to the function whose callers or callees are printed
 
-        if let Some(head_comment) = comments.next()
-        {
-            println!("\t/* {} */ {} ; {}", xa, fmt, head_comment);
+    let xa = path[path.len() - 1];
 
-            for tail_comment in comments
-            {
-                println!("\t              {} ; {}", " ".repeat(fmt.len()), tail_comment);
-            }
-        }
-        else
-        {
-            println!("\t/* {} */ {}", xa, fmt);
-        }
-    };
+    let mut next: Vec<XAddr> = edges.iter().filter_map(|&(caller, callee)| match callers
+    {
+        true if callee == xa => Some(caller),
+        false if caller == xa => Some(callee),
+        _ => None,
+    }).collect();
 
-    for (xa, len) in code_blocks
+    next.sort();
+    next.dedup();
+
+    for other in next
     {
-        if last_xa != xa
+        let recursive = path.contains(&other);
+        println!("{}{}{}", "  ".repeat(path.len()), display(other), if recursive { " (recursive)" } else { "" });
+
+        if !recursive && path.len() < depth
         {
-            println!("\t; end: {}", last_xa);
-            println!("\tsection \"rom_{:02X}_{:04X}\"", xa.bank, xa.addr);
+            path.push(other);
+            print_call_tree(edges, callers, depth, path, display);
+            path.pop();
         }
+    }
+}
 
-        last_xa = xa + len as u16;
+fn read_rom(filename: &std::path::Path, member: Option<&str>) -> Result<Vec<u8>>
+{
+    use std::io::Read;
 
-        if let Some(name) = name_map.get(&xa)
-        {
-            let name = get_local_name(name.clone(), true);
-            println!("{}: ; {}", name, xa)
-        }
+    let mut rom_data = vec![];
+    std::fs::File::open(filename)?.read_to_end(&mut rom_data)?;
 
-        let mut emu = anal::AnalEmu::with_bound(&anal_info, xa, len);
+    // dumps are often kept zipped or gzipped
+
+    match archive::is_archive(&rom_data)
+    {
+        true => Ok(archive::extract_rom(&rom_data, member)?),
+        false => Ok(rom_data),
+    }
+}
+
+fn read_tags(filename: &std::path::Path, rom_info: &anal::RomInfo, rom_len: usize, known: &HashMap<String, XAddr>) -> Result<(Vec<(XAddr, tags::Tag)>, usize)>
+{
+    let rom_bank_count = (rom_len / 0x4000) as u16;
+
+    // a headerless image is all in the bank it was loaded with
+    let image_org = |addr: u16| rom_info.org.filter(|org| addr >= org.addr && ((addr - org.addr) as usize) < rom_len);
+
+    let banks_at = |addr: u16| match (image_org(addr), addr)
+    {
+        (Some(org), _) => org.bank .. org.bank + 1,
+        (None, 0x4000 ..= 0x7FFF) if rom_info.big_rom => 1 .. rom_bank_count,
+        (None, 0xA000 ..= 0xBFFF) => 0 .. rom_info.sram_count as u16,
+        (None, 0xD000 ..= 0xDFFF) if rom_info.cgb_ram => 1 .. 8,
+        _ => 0 .. 1,
+    };
+
+    tags::parse_tags_with_symbols(&mut std::io::BufReader::new(std::fs::File::open(filename)?), banks_at, known)
+        .map_err(|e| anyhow::anyhow!("{}: {}", filename.display(), e))
+}
+
+fn pad_to_banks(rom_data: Vec<u8>) -> Vec<u8>
+{
+    // not when there isn't even a header, which is an error later on
+
+    if rom_data.len().is_multiple_of(0x4000) || rom_data.len() < 0x150 {
+        return rom_data; }
+
+    use log::warn;
+    warn!("the ROM is {} bytes, not a whole number of 16KiB banks: padding it with $FF", rom_data.len());
+
+    let mut rom_data = rom_data;
+    rom_data.resize(rom_data.len().next_multiple_of(0x4000), 0xFF);
+    rom_data
+}
+
+fn parse_bank(text: &str) -> Result<u16, std::num::ParseIntError>
+{
+    // in hex, as in `bank:addr`
+    u16::from_str_radix(text, 16)
+}
+
+fn cartridge_rom_info(opt: &Opt, rom_data: &[u8]) -> Result<anal::RomInfo>
+{
+    // the header has to be there
+
+    if rom_data.len() < 0x150 {
+        anyhow::bail!("the ROM is {} bytes, too short to hold a cartridge header", rom_data.len()); }
+
+    if rom_data.len() > MAX_ROM_LEN {
+        anyhow::bail!("the ROM is {} bytes, more than the {} bytes (512 banks) an MBC can map", rom_data.len(), MAX_ROM_LEN); }
+
+    {
+        use log::warn;
+
+        match rom_data[0x148]
+        {
+            size @ 0x00 ..= 0x08 if (0x8000 << size) != rom_data.len() =>
+                warn!("the header says the ROM is {} bytes (${:02X} at $0148), but it is {}", 0x8000 << size, size, rom_data.len()),
+
+            0x00 ..= 0x08 => {}
+            size => warn!("unknown ROM size ${:02X} at $0148", size),
+        }
+    }
+
+    let mbc = mbc::Mbc::from_cartridge_type(rom_data[0x147]);
+
+    if mbc == mbc::Mbc::RomOnly && rom_data.len() > 0x8000
+    {
+        use log::warn;
+        warn!("the cartridge type says there is no MBC, but the ROM is {} bytes: banks are assumed anyway", rom_data.len());
+    }
+
+    Ok(anal::RomInfo
+    {
+        big_rom: opt.big_rom.unwrap_or(rom_data.len() > 0x8000),
+        cgb_ram: opt.cgb_ram.unwrap_or(rom_data[0x143] == 0xC0),
+        sram_count: opt.sram_count.or(mbc.default_sram_count()).unwrap_or(*SRAM_COUNT_LUT.get(rom_data[0x149] as usize).unwrap_or(&0)),
+        mbc: mbc,
+        stop_padding: opt.stop_padding.unwrap_or(true),
+        boot_rom: false,
+        org: None,
+        gbs: None,
+    })
+}
+
+fn main() -> Result<()>
+{
+    use std::io::Write;
+
+    // messages go on their own line, not after the progress status
+    env_logger::builder().format(|buf, record|
+    {
+        progress::clear();
+        writeln!(buf, "[{:<5} {}] {}", buf.default_styled_level(record.level()), record.module_path().unwrap_or_default(), record.args())
+    }).init();
+
+    let opt = with_project(with_diff_roms(Opt::from_args())?)?;
+
+    if !opt.no_progress {
+        progress::enable(); }
+
+    let result = match opt.watch
+    {
+        true => watch(&opt),
+        false => run_revisions(&opt),
+    };
+
+    progress::clear();
+
+    match result
+    {
+        Err(e) if e.downcast_ref::<NotClean>().is_some() =>
+        {
+            eprintln!("Error: {:?}", e);
+            std::process::exit(EXIT_NOT_CLEAN);
+        }
+
+        result => result,
+    }
+}
+
+fn with_diff_roms(opt: Opt) -> Result<Opt>
+{
+    // `bub diff a.gb b.gb` analyses the first rom (with its tags) as any other
+
+    let (old, old_tags) = match &opt.command
+    {
+        Some(Command::Diff { old, old_tags, .. }) => (old.clone(), old_tags.clone()),
+        _ => return Ok(opt),
+    };
+
+    if opt.input_filename.is_some() || opt.tags_filename.is_some() {
+        anyhow::bail!("diff takes both ROMs after it: bub [options] diff <old> <new>"); }
+
+    Ok(Opt { input_filename: Some(old), tags_filename: old_tags, ..opt })
+}
+
+fn with_project(opt: Opt) -> Result<Opt>
+{
+    // the command line wins over the project
+
+    let filename = match &opt.project
+    {
+        Some(filename) => Some(filename.clone()),
+        None => Some(PathBuf::from(project::PROJECT_FILENAME)).filter(|filename| filename.is_file()),
+    };
+
+    let project = match filename
+    {
+        Some(filename) => project::read_project(&filename).map_err(|e| anyhow::anyhow!("{}: {}", filename.display(), e))?,
+        None => project::Project::default(),
+    };
+
+    let opt = Opt
+    {
+        input_filename: opt.input_filename.or(project.rom.clone()),
+        output_dir: opt.output_dir.or(project.output.clone()),
+        cache_dir: opt.cache_dir.or(project.cache.clone()),
+        syntax: opt.syntax.or(project.syntax()?),
+        big_rom: opt.big_rom.or(project.big_rom),
+        cgb_ram: opt.cgb_ram.or(project.cgb_ram),
+        sram_count: opt.sram_count.or(project.sram_count),
+        stop_padding: opt.stop_padding.or(project.stop_padding),
+        project_tags: project.tags,
+        ..opt
+    };
+
+    // a revision brings its rom, and tags naming what the shared ones refer to at its addresses
+
+    let opt = match &opt.revision
+    {
+        Some(name) =>
+        {
+            let revision = match project.revisions.iter().find(|revision| revision.name == *name)
+            {
+                Some(revision) => revision,
+                None => anyhow::bail!("the project has no revision `{}`", name),
+            };
+
+            Opt
+            {
+                input_filename: opt.input_filename.or(Some(revision.rom.clone())),
+                project_tags: revision.tags.iter().chain(&opt.project_tags).cloned().collect(),
+                listing_name: Some(revision.name.clone()),
+                ..opt
+            }
+        }
+
+        None if opt.input_filename.is_none() => Opt { revisions: project.revisions, ..opt },
+        None => opt,
+    };
+
+    if opt.input_filename.is_none() && opt.revisions.is_empty() {
+        anyhow::bail!("no ROM given, and no {} here to take it from", project::PROJECT_FILENAME); }
+
+    Ok(opt)
+}
+
+fn read_all_tags(opt: &Opt, rom_info: &anal::RomInfo, rom_len: usize) -> Result<(Vec<(XAddr, tags::Tag)>, usize)>
+{
+    // names from the files before can be used in the ones after
+    // also returns the number of conflicting tags left out
+
+    let mut tags = vec![];
+    let mut located = vec![];
+    let mut conflicts = 0;
+
+    for filename in opt.tags_filename.iter().chain(&opt.project_tags)
+    {
+        let known = tags::symbols(&tags);
+        let (file_tags, file_conflicts) = read_tags(filename, rom_info, rom_len, &known)?;
+
+        tags.extend(file_tags.iter().cloned());
+        located.extend(file_tags.into_iter().map(|(xa, tag)| (xa, tag, filename.display())));
+        conflicts += file_conflicts;
+    }
+
+    // then between files, the one read first winning as within a file
+
+    located.sort_by_key(|(xa, _, _)| *xa);
+    let (tags, file_conflicts) = tags::resolve_conflicts(located);
+
+    Ok((tags, conflicts + file_conflicts))
+}
+
+fn run_revisions(opt: &Opt) -> Result<()>
+{
+    // the listing of every revision is written, then where the names they share ended up is compared
+
+    if opt.revisions.is_empty() {
+        return run(opt); }
+
+    if opt.command.is_some() {
+        anyhow::bail!("the project has several revisions, pick one with --revision"); }
+
+    let mut symbols = vec![];
+
+    for revision in &opt.revisions
+    {
+        let opt = Opt
+        {
+            input_filename: Some(revision.rom.clone()),
+            project_tags: revision.tags.iter().chain(&opt.project_tags).cloned().collect(),
+            output_dir: Some(opt.output_dir.clone().unwrap_or_default()),
+            listing_name: Some(revision.name.clone()),
+            revisions: vec![],
+            ..opt.clone()
+        };
+
+        run(&opt)?;
+
+        // user names only, automatic ones are made from the address
+        let rom_data = pad_to_banks(read_rom(&revision.rom, opt.member.as_deref())?);
+        let rom_info = cartridge_rom_info(&opt, &rom_data)?;
+
+        symbols.push(tags::symbols(&read_all_tags(&opt, &rom_info, rom_data.len())?.0));
+    }
+
+    let mut names: Vec<&String> = symbols.iter().flat_map(|symbols| symbols.keys()).collect();
+    names.sort_by_key(|name| (symbols.iter().find_map(|symbols| symbols.get(*name)), *name));
+    names.dedup();
+
+    let (mut moved, mut missing) = (0, 0);
+
+    for name in names
+    {
+        let xas: Vec<Option<&XAddr>> = symbols.iter().map(|symbols| symbols.get(name)).collect();
+
+        if xas.windows(2).all(|pair| pair[0] == pair[1]) {
+            continue; }
+
+        let kind = match xas.iter().all(Option::is_some)
+        {
+            true => { moved += 1; "moved" }
+            false => { missing += 1; "missing" }
+        };
+
+        let places: Vec<String> = opt.revisions.iter().zip(&xas).map(|(revision, xa)| match xa
+        {
+            Some(xa) => format!("{} {}", revision.name, xa),
+            None => format!("{} -", revision.name),
+        }).collect();
+
+        println!("{}: {}: {}", kind, name, places.join(", "));
+    }
+
+    println!("symbols: {} moved, {} missing from some revisions", moved, missing);
+    Ok(())
+}
+
+fn watched_files(opt: &Opt) -> Vec<PathBuf>
+{
+    // what is edited while tagging, the rom itself doesn't change
+
+    let mut result: Vec<PathBuf> = vec![];
+
+    result.extend(opt.tags_filename.iter().cloned());
+    result.extend(opt.project_tags.iter().cloned());
+    result.extend(opt.revisions.iter().flat_map(|revision| revision.tags.iter().cloned()));
+    result.extend(opt.memory_map.iter().cloned());
+    result.extend(opt.rename_rules.iter().cloned());
+    result.extend(opt.import_sym.iter().cloned());
+    result.extend(opt.import_nocash.iter().cloned());
+    result.extend(opt.import_breakpoints.iter().cloned());
+    result.extend(opt.signature_files.iter().cloned());
+    result.extend(opt.script_vm_files.iter().cloned());
+    result.extend(opt.codec_files.iter().cloned());
+
+    if let Some(Command::Diff { new_tags: Some(new_tags), .. }) = &opt.command {
+        result.push(new_tags.clone()); }
+
+    result
+}
+
+fn clear_terminal() -> Result<()>
+{
+    // the output of a run starts on a clear screen
+
+    use std::io::Write;
+
+    print!("\x1B[2J\x1B[H");
+    std::io::stdout().flush()?;
+
+    Ok(())
+}
+
+fn watch(opt: &Opt) -> Result<()>
+{
+    // polling is enough to be back within a second of a save
+
+    use log::error;
+    use std::io::IsTerminal;
+
+    // stdout redirected to a file would only grow with each run, the listing is written over in --output-dir instead
+    if opt.output_dir.is_none() && !std::io::stdout().is_terminal() {
+        anyhow::bail!("--watch writes the output again on every change: give --output-dir for the listing to be written over there, rather than redirecting it"); }
+
+    let files = watched_files(opt);
+
+    if files.is_empty() {
+        anyhow::bail!("--watch needs a tags file (or another input edited while tagging) to watch"); }
+
+    let modified = || -> Vec<Option<std::time::SystemTime>>
+    {
+        files.iter().map(|file| std::fs::metadata(file).and_then(|meta| meta.modified()).ok()).collect()
+    };
+
+    loop
+    {
+        let before = modified();
+
+        if std::io::stdout().is_terminal() {
+            clear_terminal()?; }
+
+        // mistakes in what's being edited are reported, and fixed by the next save
+        if let Err(e) = run_revisions(opt) {
+            error!("{:?}", e); }
+
+        std::io::Write::flush(&mut std::io::stdout())?;
+
+        while modified() == before {
+            std::thread::sleep(std::time::Duration::from_millis(200)); }
+    }
+}
+
+fn run(opt: &Opt) -> Result<()>
+{
+    use std::fs::File;
+    use std::io::{BufReader, Write};
+
+    if opt.range_analysis && opt.ranges.is_empty() {
+        anyhow::bail!("--range-analysis needs a --range to start from"); }
+
+    // init inputs
+
+    // there is one by now, from the command line or the project
+    let input_filename = opt.input_filename.as_deref().unwrap();
+
+    let rom_data = read_rom(input_filename, opt.member.as_deref())?;
+
+    // whole files, as they are distributed
+
+    if let Some(Command::Patch { modified, output }) = &opt.command
+    {
+        let modified_data = read_rom(modified, None)?;
+
+        let patch = match output.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_lowercase()).as_deref()
+        {
+            Some("ips") => patch::make_ips(&rom_data, &modified_data)?,
+            Some("bps") => patch::make_bps(&rom_data, &modified_data),
+            _ => anyhow::bail!("the patch file should end in `.ips` or `.bps`"),
+        };
+
+        std::fs::write(output, &patch)?;
+        return Ok(());
+    }
+
+    // sound rips are mapped as their player does
+
+    let gbs = match rom_data.starts_with(b"GBS") && !opt.boot_rom && opt.org.is_none()
+    {
+        true => Some(gbs::Gbs::parse(&rom_data)?),
+        false => None,
+    };
+
+    // MMM01 multicarts are analysed one image at a time, the menu or one of the games
+
+    let (rom_data, image_offset) = if let Some(gbs) = &gbs
+    {
+        use log::info;
+
+        info!("GBS: \"{}\" by {} ({}), {} songs from {}, load ${:04X}, init ${:04X}, play ${:04X}{}",
+            gbs::header_text(&rom_data, 0x10), gbs::header_text(&rom_data, 0x30), gbs::header_text(&rom_data, 0x50),
+            gbs.song_count, gbs.first_song, gbs.load, gbs.init, gbs.play, if gbs.uses_timer() { " on timer interrupt" } else { "" });
+
+        (gbs.build_rom(&rom_data), gbs.file_offset())
+    }
+    else if !opt.boot_rom && opt.org.is_none() && mmm01::is_mmm01(&rom_data)
+    {
+        use log::info;
+
+        let images = mmm01::find_sub_images(&rom_data);
+
+        for (i, image) in images.iter().enumerate() {
+            info!("MMM01 sub-image {}: \"{}\", {} bytes at ${:X}", i, image.title, image.len, image.offset); }
+
+        let (offset, len) = match opt.sub_image
+        {
+            Some(i) => match images.get(i)
+            {
+                Some(image) => (image.offset, image.len),
+                None => anyhow::bail!("there is no MMM01 sub-image {} ({} found)", i, images.len()),
+            },
+
+            None => (mmm01::menu_offset(&rom_data), rom_data.len() - mmm01::menu_offset(&rom_data)),
+        };
+
+        (rom_data[offset .. offset + len].to_vec(), offset as isize)
+    }
+    else
+    {
+        if opt.sub_image.is_some() {
+            anyhow::bail!("--sub-image is only for MMM01 multicarts"); }
+
+        (rom_data, 0)
+    };
+
+    // the header is all there is to show, no need for analysis
+
+    if let Some(Command::Info) = opt.command
+    {
+        let header = match header::Header::parse(&rom_data)
+        {
+            Some(header) if gbs.is_none() && !opt.boot_rom && opt.org.is_none() => header,
+            _ => anyhow::bail!("there is no cartridge header to show"),
+        };
+
+        let size = |len: Option<usize>| match len
+        {
+            Some(0) => "none".to_string(),
+            Some(len) if len >= 0x100000 => format!("{}MiB", len / 0x100000),
+            Some(len) => format!("{}KiB", len / 0x400),
+            None => "unknown".to_string(),
+        };
+
+        let check = |ok: bool, expected: String| if ok { "ok".to_string() } else { format!("bad, expected {}", expected) };
+
+        let expected_header_checksum = header::header_checksum(&rom_data);
+        let expected_global_checksum = header::global_checksum(&rom_data);
+
+        println!("title: {}", header.title);
+        println!("cgb: {} (${:02X})", header.cgb_support(), header.cgb_flag);
+        println!("sgb: {} (${:02X})", header.sgb_support(), header.sgb_flag);
+        println!("cartridge type: {} (${:02X})", header.cartridge_type_name(), header.cartridge_type);
+        println!("rom size: {} (${:02X}), file is {} bytes", size(header.rom_len()), header.rom_size, rom_data.len());
+        println!("ram size: {} (${:02X})", size(header.ram_len()), header.ram_size);
+        println!("destination: {} (${:02X})", header.destination_name(), header.destination);
+        println!("licensee: {}", header.licensee());
+        println!("version: ${:02X}", header.version);
+        println!("header checksum: ${:02X}, {}", header.header_checksum,
+            check(header.header_checksum == expected_header_checksum, format!("${:02X}", expected_header_checksum)));
+        println!("global checksum: ${:04X}, {}", header.global_checksum,
+            check(header.global_checksum == expected_global_checksum, format!("${:04X}", expected_global_checksum)));
+
+        return Ok(());
+    }
+
+    if let Some(Command::Checksum { fix }) = &opt.command
+    {
+        // on the whole file only, fixing a sub-image or a rebuilt sound rip would make no sense
+
+        if rom_data.len() < header::HEADER_END || image_offset != 0 || gbs.is_some() || opt.boot_rom || opt.org.is_some() {
+            anyhow::bail!("there is no cartridge header to check"); }
+
+        let header_checksum = header::header_checksum(&rom_data);
+        let global_checksum = header::global_checksum(&rom_data);
+
+        let header_ok = rom_data[0x14D] == header_checksum;
+        let global_ok = u16::from_be_bytes([rom_data[0x14E], rom_data[0x14F]]) == global_checksum;
+
+        println!("header checksum: ${:02X}{}", rom_data[0x14D], if header_ok { ", ok".to_string() } else { format!(", bad, expected ${:02X}", header_checksum) });
+        println!("global checksum: ${:02X}{:02X}{}", rom_data[0x14E], rom_data[0x14F],
+            if global_ok { ", ok".to_string() } else { format!(", bad, expected ${:04X}", global_checksum) });
+
+        match fix
+        {
+            Some(filename) =>
+            {
+                let mut rom_data = rom_data;
+                header::fix_checksums(&mut rom_data);
+
+                std::fs::write(filename, &rom_data)?;
+            }
+
+            None if !header_ok || !global_ok => anyhow::bail!("the checksums don't match the ROM"),
+            None => {}
+        }
+
+        return Ok(());
+    }
+
+    // trimmed roms (common with homebrew) and odd dumps are padded to whole banks, as flashing them would
+
+    let rom_data = match opt.boot_rom || opt.org.is_some()
+    {
+        true => rom_data,
+        false => pad_to_banks(rom_data),
+    };
+
+    let rom_info = if opt.boot_rom
+    {
+        // no header to go by: a dmg boot rom is 256 bytes, a cgb one 2304 (with the cartridge header showing through at $0100)
+
+        if rom_data.len() != 0x100 && rom_data.len() != 0x900 {
+            anyhow::bail!("a boot ROM is 256 (DMG) or 2304 (CGB) bytes long, not {}", rom_data.len()); }
+
+        anal::RomInfo
+        {
+            big_rom: false,
+            cgb_ram: opt.cgb_ram.unwrap_or(rom_data.len() == 0x900),
+            sram_count: 0,
+            mbc: mbc::Mbc::RomOnly,
+            stop_padding: opt.stop_padding.unwrap_or(true),
+            boot_rom: true,
+            org: None,
+            gbs: None,
+        }
+    }
+    else if let Some(org) = opt.org
+    {
+        // no header either: the bank given with the load address is the one its area has selected
+
+        if opt.boot_rom {
+            anyhow::bail!("--org and --boot-rom can't be used together"); }
+
+        if org.addr as usize + rom_data.len() > 0x10000 {
+            anyhow::bail!("{} bytes loaded at {} go past $FFFF", rom_data.len(), org); }
+
+        anal::RomInfo
+        {
+            big_rom: opt.big_rom.unwrap_or((0x4000 .. 0x8000).contains(&org.addr)),
+            cgb_ram: opt.cgb_ram.unwrap_or((0xD000 .. 0xE000).contains(&org.addr) && org.bank != 0),
+            sram_count: opt.sram_count.unwrap_or(if (0xA000 .. 0xC000).contains(&org.addr) { org.bank as usize + 1 } else { 0 }),
+            mbc: mbc::Mbc::RomOnly,
+            stop_padding: opt.stop_padding.unwrap_or(true),
+            boot_rom: false,
+            org: Some(org),
+            gbs: None,
+        }
+    }
+    else if let Some(gbs) = gbs
+    {
+        anal::RomInfo
+        {
+            big_rom: opt.big_rom.unwrap_or(rom_data.len() > 0x8000),
+            cgb_ram: opt.cgb_ram.unwrap_or(false),
+            sram_count: opt.sram_count.unwrap_or(1),
+            mbc: mbc::Mbc::Mbc1,
+            stop_padding: opt.stop_padding.unwrap_or(true),
+            boot_rom: false,
+            org: None,
+            gbs: Some(gbs),
+        }
+    }
+    else
+    {
+        cartridge_rom_info(opt, &rom_data)?
+    };
+
+    let (tags, tag_conflicts) = read_all_tags(opt, &rom_info, rom_data.len())?;
+
+    // bring in work done in the debugger
+
+    let tags =
+    {
+        let mut tags = tags;
+
+        // names and comments from the tags win, by address and by name, then those of the symbol files before
+
+        let (mut named, mut names): (HashSet<XAddr>, HashSet<String>) = tags.iter().filter_map(|(xa, tag)| match tag
+        {
+            tags::Tag::Name(name) | tags::Tag::Var { name, .. } => Some((*xa, name.clone())),
+            _ => None,
+        }).unzip();
+
+        let mut commented: HashSet<XAddr> = tags.iter().filter(|(_, tag)| matches!(tag, tags::Tag::Comment(_))).map(|(xa, _)| *xa).collect();
+
+        let mut import_name = |tags: &mut Vec<(XAddr, tags::Tag)>, xa: XAddr, name: String|
+        {
+            if !named.contains(&xa) && names.insert(name.clone())
+            {
+                named.insert(xa);
+                tags.push((xa, tags::Tag::Name(name)));
+            }
+        };
+
+        for filename in &opt.import_sym
+        {
+            let symbols = bgb::parse_sym(&mut BufReader::new(File::open(filename)?)).map_err(|e| anyhow::anyhow!("{}: {}", filename.display(), e))?;
+
+            for bgb::Symbol { xa, name, comment } in symbols
+            {
+                if let Some(name) = name {
+                    import_name(&mut tags, xa, name); }
+
+                if let Some(comment) = comment.filter(|_| commented.insert(xa)) {
+                    tags.push((xa, tags::Tag::Comment(comment))); }
+            }
+        }
+
+        for filename in &opt.import_nocash
+        {
+            let symbols = nocash::parse_sym(&mut BufReader::new(File::open(filename)?)).map_err(|e| anyhow::anyhow!("{}: {}", filename.display(), e))?;
+
+            for symbol in symbols
+            {
+                match symbol
+                {
+                    nocash::Symbol::Label(xa, name) => import_name(&mut tags, xa, name),
+                    nocash::Symbol::Data(xa) => tags.push((xa, tags::Tag::Data)),
+                }
+            }
+        }
+
+        // 32K roms have no banks, whatever bank the debugger shows
+        let rom_xa = |xa: XAddr| if rom_info.big_rom || xa.addr >= 0x8000 { xa } else { XAddr::new(0, xa.addr) };
+
+        for filename in &opt.import_breakpoints
+        {
+            let breakpoints = bgb::parse_breakpoints(&mut BufReader::new(File::open(filename)?)).map_err(|e| anyhow::anyhow!("{}: {}", filename.display(), e))?;
+
+            for bgb::Breakpoint { xa, comment } in breakpoints
+            {
+                let xa = rom_xa(xa);
+
+                if xa.addr < 0x8000 {
+                    tags.push((xa, tags::Tag::Code)); }
+
+                if let Some(comment) = comment.filter(|_| commented.insert(xa)) {
+                    tags.push((xa, tags::Tag::Comment(comment))); }
+            }
+        }
+
+        if let Some(filename) = &opt.savestate
+        {
+            let state = bgb::parse_savestate(&mut File::open(filename)?)?;
+
+            if let Some(xa) = state.pc_xa(rom_info.big_rom)
+            {
+                tags.push((xa, tags::Tag::Code));
+                tags.extend(state.banks().into_iter().map(|(kind, bank)| (xa, tags::Tag::EntryBank(kind, bank))));
+            }
+        }
+
+        tags.sort_by_key(|(xa, _)| *xa);
+        tags
+    };
+
+    // analysis
+
+    let sram_data = match &opt.sram
+    {
+        Some(filename) => std::fs::read(filename)?,
+        None => vec![],
+    };
+
+    let regions = match &opt.memory_map
+    {
+        Some(filename) => memmap::parse_memory_map(&mut BufReader::new(File::open(filename)?))?,
+        None => vec![],
+    };
+
+    let anal_info = anal::AnalInfo::new(rom_info, &rom_data, &tags).with_sram(&sram_data).with_regions(&regions).with_excluded_banks(&opt.exclude_banks);
+
+    // tags for banks the image doesn't have are for another version of the rom, or typos
+
+    if rom_info.big_rom && rom_info.org.is_none()
+    {
+        let bank_count = anal_info.rom_bank_count();
+
+        for (xa, tag) in &tags
+        {
+            let bank = match tag
+            {
+                tags::Tag::RomBank(bank) | tags::Tag::CallBank(tags::BankKind::Rom, bank) | tags::Tag::EntryBank(tags::BankKind::Rom, bank) => *bank,
+                _ if (0x4000 .. 0x8000).contains(&xa.addr) => xa.bank,
+                _ => continue,
+            };
+
+            if bank as usize >= bank_count {
+                anyhow::bail!("`{}` tag at {} refers to ROM bank ${:02X}, but the ROM only has {} banks", tag.keyword(), xa, bank, bank_count); }
+        }
+    }
+
+    // codecs described by the user
+
+    let codecs =
+    {
+        let mut codecs = vec![];
+
+        for filename in &opt.codec_files
+        {
+            let name = filename.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+            codecs.push(codec::parse_codec(&name, &mut BufReader::new(File::open(filename)?))?);
+        }
+
+        codecs
+    };
+
+    if let Some(Command::Decompress { at, codec, output }) = &opt.command
+    {
+        let codec = codec::find_codec(codec, &codecs)?;
+
+        let decompressed = match anal_info.rom_slice(*at, 0x10000)
+        {
+            Ok(slice) => codec.decompress(slice)?,
+            Err(e) => anyhow::bail!("{} is not in ROM: {:?}", at, e),
+        };
+
+        std::fs::write(output, &decompressed.data)?;
+        println!("{} bytes decompressed from {} bytes at {}", decompressed.data.len(), decompressed.consumed, at);
+
+        return Ok(());
+    }
+
+    // graphics, to tell what a region is at a glance
+
+    if let Some(Command::Tiles { at, count, columns, output }) = &opt.command
+    {
+        let data = match anal_info.rom_slice(*at, count * gfx::TILE_LEN)
+        {
+            Ok(data) if data.len() == count * gfx::TILE_LEN => data,
+            Ok(data) => anyhow::bail!("only {} tiles fit between {} and the end of its bank", data.len() / gfx::TILE_LEN, at),
+            Err(e) => anyhow::bail!("{} is not in ROM: {:?}", at, e),
+        };
+
+        if *count == 0 || *columns == 0 {
+            anyhow::bail!("a sheet needs at least one tile and one column"); }
+
+        gfx::render_tile_sheet(data, std::cmp::min(*columns, *count)).write_png(std::io::BufWriter::new(File::create(output)?))?;
+        return Ok(());
+    }
+
+    if let Some(Command::Tilemap { map, tiles, width, height, signed, attrs, bank1_tiles, palettes, output }) = &opt.command
+    {
+        let read = |xa: XAddr, len: usize, what: &str| match anal_info.rom_slice(xa, len)
+        {
+            Ok(data) if data.len() == len => Ok(data),
+            Ok(data) => Err(anyhow::anyhow!("only {} bytes of the {} fit between {} and the end of its bank", data.len(), what, xa)),
+            Err(e) => Err(anyhow::anyhow!("{} is not in ROM: {:?}", xa, e)),
+        };
+
+        // as many tiles as there are up to the end of the bank, a map rarely uses all 256
+        let read_tiles = |xa: XAddr| -> Result<&[u8]>
+        {
+            let beg = match *signed
+            {
+                true => XAddr::new(xa.bank, xa.addr.checked_sub(0x80 * gfx::TILE_LEN as u16).ok_or_else(|| anyhow::anyhow!("tile $80 would be before {}", xa))?),
+                false => xa,
+            };
+
+            anal_info.rom_slice(beg, 0x100 * gfx::TILE_LEN).map_err(|e| anyhow::anyhow!("{} is not in ROM: {:?}", beg, e))
+        };
+
+        if *width == 0 || *height == 0 {
+            anyhow::bail!("a map needs at least one row and one column"); }
+
+        let map_data = read(*map, width * height, "map")?;
+
+        let attrs = match attrs
+        {
+            Some(xa) => Some(read(*xa, width * height, "attributes")?),
+            None => None,
+        };
+
+        let palettes = match palettes
+        {
+            Some(xa) => Some(gfx::decode_palettes(read(*xa, gfx::PALETTES_LEN, "palettes")?)),
+            None => None,
+        };
+
+        let tile_data = gfx::TileData
+        {
+            banks: [Some(read_tiles(*tiles)?), bank1_tiles.map(read_tiles).transpose()?],
+            signed: *signed,
+        };
+
+        gfx::render_tilemap(map_data, attrs, *width, &tile_data, palettes.as_deref())?
+            .write_png(std::io::BufWriter::new(File::create(output)?))?;
+
+        return Ok(());
+    }
+
+    if let Some(Command::Palettes { at, count, output }) = &opt.command
+    {
+        use log::warn;
+
+        let len = count * gfx::PALETTE_LEN;
+
+        let data = match anal_info.rom_slice(*at, len)
+        {
+            Ok(data) if data.len() == len => data,
+            Ok(data) => anyhow::bail!("only {} palettes fit between {} and the end of its bank", data.len() / gfx::PALETTE_LEN, at),
+            Err(e) => anyhow::bail!("{} is not in ROM: {:?}", at, e),
+        };
+
+        if *count == 0 {
+            anyhow::bail!("at least one palette is needed"); }
+
+        for (i, palette) in data.chunks_exact(gfx::PALETTE_LEN).enumerate()
+        {
+            let colors: Vec<u16> = palette.chunks_exact(2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]])).collect();
+
+            // the PPU ignores it, data that isn't palettes often has it
+            if colors.iter().any(|color| color & 0x8000 != 0) {
+                warn!("palette {} has colors with bit 15 set, it may not be palette data", i); }
+
+            println!("\tdw {} ; palette {}", colors.iter().map(|color| format!("${:04X}", color)).collect::<Vec<_>>().join(", "), i);
+        }
+
+        if let Some(output) = output {
+            gfx::render_swatches(&gfx::decode_palettes(data), 16).write_png(std::io::BufWriter::new(File::create(output)?))?; }
+
+        return Ok(());
+    }
+
+    // text is looked for in raw bytes, analysis would only get in the way
+
+    if let Some(Command::Strings { charmap, min_len }) = &opt.command
+    {
+        let charmap = match charmap
+        {
+            Some(filename) => text::parse_charmap(&mut BufReader::new(File::open(filename)?))?,
+            None => text::Charmap::ascii(),
+        };
+
+        for found in text::find_strings(&anal_info, &charmap, *min_len) {
+            println!("{} {} \"{}\"", found.xa, found.len, found.text.replace('\\', "\\\\").replace('"', "\\\"")); }
+
+        return Ok(());
+    }
+
+    if let Some(Command::Search { pattern }) = &opt.command
+    {
+        for xa in search::search(&anal_info, &search::parse_pattern(pattern)?) {
+            println!("{}", xa); }
+
+        return Ok(());
+    }
+
+    // another revision, analysed the same way from its own tags
+
+    if let Some(Command::Diff { new: other, new_tags: other_tags, .. }) = &opt.command
+    {
+        if rom_info.boot_rom || rom_info.org.is_some() || rom_info.gbs.is_some() || image_offset != 0 {
+            anyhow::bail!("only cartridges can be compared"); }
+
+        let other_data = pad_to_banks(read_rom(other, None)?);
+        let other_info = cartridge_rom_info(opt, &other_data)?;
+
+        let (mut other_tags, other_tag_conflicts) = match other_tags
+        {
+            Some(filename) => read_tags(filename, &other_info, other_data.len(), &HashMap::new())?,
+            None => (vec![], 0),
+        };
+
+        other_tags.sort_by_key(|(xa, _)| *xa);
+
+        let other_anal_info = anal::AnalInfo::new(other_info, &other_data, &other_tags).with_regions(&regions).with_excluded_banks(&opt.exclude_banks);
+
+        let entry_points = |info: &anal::AnalInfo|
+        {
+            let mut entry_points: Vec<XAddr> = info.tags.iter().filter(|(_, tag)| matches!(tag, tags::Tag::Code)).map(|(xa, _)| *xa).collect();
+            entry_points.extend(opt.entries.iter().copied());
+
+            if opt.uses_default_entry(&info.rom_info) {
+                entry_points.extend(anal::default_entry_points(info)); }
+
+            entry_points.sort();
+            entry_points.dedup();
+            entry_points
+        };
+
+        let mut old = diff::summarize(&anal_info, &entry_points(&anal_info));
+        let mut new = diff::summarize(&other_anal_info, &entry_points(&other_anal_info));
+
+        old.stats.tag_conflicts = tag_conflicts;
+        new.stats.tag_conflicts = other_tag_conflicts;
+
+        for (kind, kinds, old_items, new_items) in [("function", "functions", &old.functions, &new.functions), ("data", "data regions", &old.data, &new.data)]
+        {
+            let changes = diff::diff_items(old_items, new_items);
+
+            for change in &changes {
+                println!("{} {}", kind, change); }
+
+            let count = |f: fn(&diff::Change) -> bool| changes.iter().filter(|change| f(change)).count();
+
+            println!("{}: {} changed, {} moved, {} added, {} removed", kinds,
+                count(|change| matches!(change, diff::Change::Changed(..))), count(|change| matches!(change, diff::Change::Moved(..))),
+                count(|change| matches!(change, diff::Change::Added(..))), count(|change| matches!(change, diff::Change::Removed(..))));
+        }
+
+        check_strict(opt, input_filename, &old.stats)?;
+        return check_strict(opt, other, &new.stats);
+    }
+
+    // what an emulator saw running is code for sure
+
+    let traced =
+    {
+        let mut traced = vec![];
+
+        for filename in &opt.traces {
+            traced.extend(trace::read_trace(&mut BufReader::new(File::open(filename)?), &opt.trace_format, &anal_info)?); }
+
+        traced
+    };
+
+    // scripts for interpreters described by the user, disassembled from their `.script` tags
+
+    let script_vms =
+    {
+        let mut script_vms = vec![];
+
+        for filename in &opt.script_vm_files
+        {
+            let name = filename.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+            script_vms.push(script::parse_vm(&name, &mut BufReader::new(File::open(filename)?))?);
+        }
+
+        script_vms
+    };
+
+    let scripts: Vec<(usize, Vec<script::ScriptIns>)> =
+    {
+        let mut entries: BTreeMap<usize, Vec<XAddr>> = BTreeMap::new();
+
+        for (xa, tag) in &tags
+        {
+            if let tags::Tag::Script(name) = tag
+            {
+                match script_vms.iter().position(|vm| vm.name == *name)
+                {
+                    Some(i) => entries.entry(i).or_default().push(*xa),
+                    None => anyhow::bail!("script at {} is for `{}`, which no --script-vm file describes", xa, name),
+                }
+            }
+        }
+
+        entries.into_iter().map(|(i, entries)| (i, script::disassemble(&anal_info, &script_vms[i], &entries))).collect()
+    };
+
+    let mut entry_points =
+    {
+        use std::collections::BinaryHeap;
+
+        let mut entry_points = BinaryHeap::new();
+
+        for (xa, tag) in &tags
+        {
+            if let tags::Tag::Code = tag
+            {
+                entry_points.push(*xa);
+            }
+        }
+
+        entry_points.extend(opt.entries.iter().copied());
+        entry_points.extend(traced.iter().copied());
+
+        if opt.uses_default_entry(&anal_info.rom_info) {
+            entry_points.extend(anal::default_entry_points(&anal_info)); }
+
+        // cpu code scripts refer to
+        for (_, instructions) in &scripts
+        {
+            for ins in instructions
+            {
+                entry_points.extend(ins.operands.iter().filter(|(kind, _)| *kind == script::OperandKind::Code)
+                    .filter_map(|(_, addr)| script::expand_addr(&anal_info, ins.xa, *addr)));
+            }
+        }
+
+        let mut entry_points = entry_points.into_sorted_vec();
+        entry_points.dedup();
+
+        if opt.range_analysis {
+            entry_points.retain(|xa| opt.ranges.iter().any(|range| range.contains(*xa))); }
+
+        entry_points
+    };
+
+    // what analysis depends on besides the rom itself, anything else changing makes cached results stale
+    // (renaming and commenting, most of what tagging is, keeps them; any other tag change analyses everything again:
+    // noreturn functions and bank states are only ever added to while analysing, so what is found depends on the
+    // order things were found in, and results kept for the untouched banks can't be told apart from fresh ones)
+
+    let cache_key =
+    {
+        let analysis_tags: Vec<_> = anal_info.tags.iter().filter(|(_, tag)| tag.affects_analysis()).collect();
+
+        let mut inputs = format!("{:?} {:?} {:?} {:?} {:?} {}", anal_info.rom_info, analysis_tags, anal_info.regions, anal_info.excluded_banks, entry_points, opt.emulate).into_bytes();
+        inputs.extend_from_slice(anal_info.sram);
+
+        format!("{}-{:08X}", env!("CARGO_PKG_VERSION"), util::crc32(&inputs))
+    };
+
+    let cached = match &opt.cache_dir
+    {
+        Some(dir) => cache::load(dir, anal_info.rom, &cache_key),
+        None => None,
+    };
+
+    let (analysis, emulated) = match cached
+    {
+        Some(entry) =>
+        {
+            use log::info;
+            info!("analysis results loaded from the cache");
+
+            (entry.analysis, entry.emulated)
+        }
+
+        None =>
+        {
+            // computed jumps emulation resolved, by site; their targets are analysed again until nothing new comes up
+
+            let mut emulated: BTreeMap<XAddr, Vec<XAddr>> = BTreeMap::new();
+
+            let analysis = loop
+            {
+                let analysis = anal::anal(&anal_info, &entry_points);
+
+                if !opt.emulate {
+                    break analysis; }
+
+                progress::status(|| format!("emulating computed jumps: {} resolved so far", emulated.len()));
+
+                let resolved =
+                {
+                    let flow_graph = func::FlowGraph::new(&anal_info, &analysis.code_blocks, &analysis.noreturn);
+                    interp::find_computed_targets(&anal_info, &flow_graph, &func::find_functions(&flow_graph))
+                };
+
+                let mut grown = false;
+
+                for (site, target) in resolved
+                {
+                    let targets = emulated.entry(site).or_default();
+
+                    if !targets.contains(&target) {
+                        targets.push(target); }
+
+                    if !entry_points.contains(&target)
+                    {
+                        entry_points.push(target);
+                        grown = true;
+                    }
+                }
+
+                if !grown {
+                    break analysis; }
+
+                entry_points.sort();
+            };
+
+            match &opt.cache_dir
+            {
+                Some(dir) =>
+                {
+                    let entry = cache::CacheEntry { key: cache_key, analysis: analysis, emulated: emulated };
+                    cache::store(dir, anal_info.rom, &entry)?;
+
+                    (entry.analysis, entry.emulated)
+                }
+
+                None => (analysis, emulated),
+            }
+        }
+    };
+
+    let flow_graph = func::FlowGraph::new(&anal_info, &analysis.code_blocks, &analysis.noreturn);
+    let functions = func::find_functions(&flow_graph);
+
+    for function in &functions {
+        func::check_stack_balance(&flow_graph, function); }
+
+    {
+        use log::warn;
+
+        for (xa, len) in &analysis.padding {
+            warn!("code reaches {} bytes of ${:02X} fill at {}, treating it as padding", len, anal_info.rom_slice(*xa, 1).unwrap()[0], xa); }
+
+        for xa in &analysis.decode_failures {
+            warn!("code reaches an invalid opcode at {}, leaving the block it is in out", xa); }
+    }
+
+    // blocks that look more like data than code
+
+    let suspicious: HashMap<XAddr, Vec<String>> =
+        plausibility::find_suspicious_blocks(&anal_info, &flow_graph, &functions).into_iter().map(|suspicion| (suspicion.xa, suspicion.reasons)).collect();
+
+    {
+        use log::warn;
+
+        let mut blocks: Vec<_> = suspicious.iter().collect();
+        blocks.sort();
+
+        for (xa, reasons) in blocks {
+            warn!("{} looks like data decoded as code: {}", xa, reasons.join(", ")); }
+    }
+
+    let halts_after_di: HashSet<XAddr> = anal::find_halts_after_di(&anal_info, &analysis.code_blocks).into_iter().collect();
+
+    {
+        use log::warn;
+
+        let mut halts: Vec<_> = halts_after_di.iter().collect();
+        halts.sort();
+
+        for xa in halts {
+            warn!("halt at {} follows di: on DMG the next byte is read twice if an interrupt is pending (tag it `.haltbug` if intended)", xa); }
+    }
+
+    let bank_loads: HashMap<XAddr, XAddr> = anal::find_bank_loads(&anal_info, &analysis.code_blocks).into_iter().collect();
+
+    let stray_rom_writes: HashSet<XAddr> =
+    {
+        use log::warn;
+
+        let mut writes = anal::find_stray_rom_writes(&anal_info, &analysis.code_blocks);
+        writes.sort();
+
+        for (xa, addr) in &writes {
+            warn!("write to ${:04X} at {} is not a register of the {} cartridge: data decoded as code, or a bug", addr, xa, anal_info.rom_info.mbc); }
+
+        writes.into_iter().map(|(xa, _)| xa).collect()
+    };
+
+    let reg_usage = func::infer_reg_usage(&flow_graph, &functions);
+    let data_accesses = func::find_data_accesses(&flow_graph, &functions);
+
+    let wait_loops: HashMap<XAddr, wait::WaitLoop> = wait::find_wait_loops(&flow_graph).into_iter().map(|wait| (wait.xa, wait)).collect();
+
+    let timings: HashMap<XAddr, timing::FunctionTiming> = if opt.cycles
+    {
+        functions.iter().map(|function| (function.xa, timing::function_timing(&flow_graph, function))).collect()
+    }
+    else
+    {
+        HashMap::new()
+    };
+
+    // code copied to ram to run there, unless it is also run in place
+
+    let mut copied_code: Vec<_> = relocate::find_copied_code(&flow_graph).into_iter().filter(|copy|
+    {
+        !analysis.code_blocks.iter().any(|block| block.xa.bank == copy.src.bank
+            && (block.xa.addr as usize) < copy.src.addr as usize + copy.len
+            && copy.src.addr < block.xa.addr + block.len as u16)
+    }).collect();
+
+    // the oam dma routine is in almost every rom, even when how it gets to hram wasn't recognized
+
+    let oam_dma = relocate::find_oam_dma(&anal_info, &flow_graph, &copied_code);
+
+    if let Some(dma) = oam_dma.filter(|dma| !copied_code.iter().any(|copy| copy.src == dma.src)) {
+        copied_code.push(dma); }
+
+    let runtime_xa = |addr: u16| anal_info.expand_addr(&anal::BankCtx::default(), addr).unwrap_or(XAddr::new(0, addr));
+
+    // code none of the entry points and tags analysis started from leads to (found by following guesses, or left behind by them)
+
+    let orphans: HashSet<XAddr> =
+    {
+        let mut roots = entry_points.clone();
+
+        roots.extend(tags.iter().filter_map(|(xa, tag)| if let tags::Tag::JumpTable(_) = tag { Some(*xa) } else { None }));
+        roots.extend(emulated.values().flatten().copied());
+
+        func::find_orphans(&flow_graph, &roots, &analysis.jump_tables).into_iter().map(|i| flow_graph.block(i).xa).collect()
+    };
+
+    let stats =
+    {
+        let resolved_sites: HashSet<XAddr> = emulated.keys().copied().collect();
+
+        let mut stats = stats::collect(&anal_info, &analysis, &flow_graph, &functions, &resolved_sites);
+        stats.orphans = orphans.len();
+        stats.tag_conflicts = tag_conflicts;
+        stats
+    };
+
+    if let Some(Command::Stats) = opt.command
+    {
+        print!("{}", stats);
+        return check_strict(opt, input_filename, &stats);
+    }
+
+    // do automatic names
+
+    let mut name_map = HashMap::new();
+
+    for (xa, tag) in &tags
+    {
+        match tag
+        {
+            tags::Tag::Name(name) | tags::Tag::Var { name, .. } => { name_map.entry(*xa).or_insert(name.clone()); }
+            _ => {}
+        }
+    }
+
+    let user_names: HashSet<XAddr> = name_map.keys().copied().collect();
+
+    if let Some(dma) = &oam_dma
+    {
+        name_map.entry(runtime_xa(dma.dest)).or_insert("hOAMDMA".to_string());
+        name_map.entry(dma.src).or_insert("OAMDMARoutine".to_string());
+    }
+
+    for (xa, name) in anal::find_interrupt_handlers(&anal_info)
+    {
+        if flow_graph.block_at(&xa).is_some() {
+            name_map.entry(xa).or_insert(name.to_string()); }
+    }
+
+    // known routines, by their bytes
+
+    {
+        let mut signatures = signature::builtin_signatures();
+
+        for filename in &opt.signature_files {
+            signatures.extend(signature::parse_signatures(&mut BufReader::new(File::open(filename)?))?); }
+
+        // copied code is named at the address it runs from
+        let candidates = functions.iter().map(|function| (function.xa, function.xa))
+            .chain(copied_code.iter().map(|copy| (copy.src, runtime_xa(copy.dest))));
+
+        for (xa, named_xa) in candidates
+        {
+            if name_map.contains_key(&named_xa) {
+                continue; }
+
+            if let Some(signature) = signature::find_match(&signatures, &anal_info, xa)
+            {
+                // several copies of the same routine are told apart by address
+                let name = if name_map.values().any(|name| *name == signature.name) { default_xaddr_name(named_xa, &signature.name) } else { signature.name.clone() };
+
+                name_map.insert(named_xa, name);
+            }
+        }
+    }
+
+    // routines doing nothing but waiting for the ppu
+
+    for function in &functions
+    {
+        let wait = match wait_loops.get(&function.xa)
+        {
+            Some(wait) if !name_map.contains_key(&function.xa) && wait::is_wait_routine(&flow_graph, function, wait) => wait,
+            _ => continue,
+        };
+
+        let name = wait.kind.name();
+        let name = if name_map.values().any(|other| *other == name) { default_xaddr_name(function.xa, &name) } else { name };
+
+        name_map.insert(function.xa, name);
+    }
+
+    for copy in &copied_code
+    {
+        name_map.entry(copy.src).or_insert(default_xaddr_name(copy.src, "CopiedCode"));
+        name_map.entry(runtime_xa(copy.dest)).or_insert(default_xaddr_name(runtime_xa(copy.dest), "Code"));
+
+        // jumps within the copy
+
+        let slice = anal_info.rom_slice(copy.src, copy.len).unwrap_or(&[]);
+
+        for (_, ins) in gbasm::decode_slice(copy.dest, slice)
+        {
+            let ins = match ins { Ok(ins) => ins, Err(_) => break };
+
+            if let Some(target) = ins.get_jump_target().filter(|addr| (copy.dest as usize .. copy.dest as usize + copy.len).contains(&(*addr as usize))) {
+                name_map.entry(runtime_xa(target)).or_insert(default_xaddr_name(runtime_xa(target), "Code")); }
+        }
+    }
+
+    for (_, instructions) in &scripts
+    {
+        if let Some(first) = instructions.first() {
+            name_map.entry(first.xa).or_insert(default_xaddr_name(first.xa, "Script")); }
+
+        for ins in instructions
+        {
+            for (kind, addr) in &ins.operands
+            {
+                let base = match kind
+                {
+                    script::OperandKind::Script => "Script",
+                    script::OperandKind::Code => "Code",
+                    _ => continue,
+                };
+
+                if let Some(target) = script::expand_addr(&anal_info, ins.xa, *addr) {
+                    name_map.entry(target).or_insert(default_xaddr_name(target, base)); }
+            }
+        }
+    }
+
+    update_name_map_with_code_refs(&anal_info, &analysis, &mut name_map);
+
+    for target in emulated.values().flatten() {
+        name_map.entry(*target).or_insert(default_xaddr_name(*target, "Code")); }
+
+    {
+        use log::warn;
+
+        let mut orphans: Vec<_> = orphans.iter().filter(|xa| name_map.contains_key(xa)).collect();
+        orphans.sort();
+
+        for xa in orphans {
+            warn!("{} ({}) is not referenced from reachable code", name_map[xa], xa); }
+    }
+
+    let mut chunks = vec![];
+
+    // how far each block can be trusted
+
+    let confidence =
+    {
+        let mut roots = vec![];
+
+        for (xa, tag) in &tags
+        {
+            if let tags::Tag::Code = tag {
+                roots.push((*xa, anal::Confidence::Tagged)); }
+        }
+
+        roots.extend(opt.entries.iter().chain(&traced).map(|xa| (*xa, anal::Confidence::Tagged)));
+
+        if opt.uses_default_entry(&anal_info.rom_info)
+        {
+            roots.extend(anal::default_entry_points(&anal_info).into_iter().map(|xa|
+                (xa, if xa.addr == 0x0100 || anal_info.rom_info.boot_rom || anal_info.rom_info.org == Some(xa) || anal_info.rom_info.gbs.is_some() { anal::Confidence::Flow } else { anal::Confidence::Heuristic })));
+        }
+
+        for table in &analysis.jump_tables
+        {
+            let tagged = tags::get_tags_at(&tags, &table.xa).iter().any(|(_, tag)| matches!(tag, tags::Tag::JumpTable(_)));
+            let confidence = if tagged { anal::Confidence::Tagged } else { anal::Confidence::Heuristic };
+
+            roots.extend(table.code_targets().map(|xa| (xa, confidence)));
+        }
+
+        roots.extend(emulated.values().flatten().map(|xa| (*xa, anal::Confidence::Heuristic)));
+
+        for (_, instructions) in &scripts
+        {
+            for ins in instructions
+            {
+                roots.extend(ins.operands.iter().filter(|(kind, _)| *kind == script::OperandKind::Code)
+                    .filter_map(|(_, addr)| script::expand_addr(&anal_info, ins.xa, *addr)).map(|xa| (xa, anal::Confidence::Tagged)));
+            }
+        }
+
+        // anything analysis found some other way is at least a good guess
+        func::block_confidence(&flow_graph, &roots).into_iter().map(|c| c.max(anal::Confidence::Heuristic)).collect::<Vec<_>>()
+    };
+
+    for (block, confidence) in analysis.code_blocks.iter().zip(confidence) {
+        chunks.push((block.xa, block.len, Chunk::Code(block.ctx, confidence))); }
+
+    for table in &analysis.jump_tables
+    {
+        use log::warn;
+
+        name_map.entry(table.xa).or_insert(default_xaddr_name(table.xa, "JumpTable"));
+
+        for target in table.code_targets() {
+            name_map.entry(target).or_insert(default_xaddr_name(target, "Code")); }
+
+        for (i, bad) in &table.bad_entries {
+            warn!("jump table at {}: entry {} (${:04X}) {}", table.xa, i, table.targets[*i].0, bad); }
+
+        chunks.push((table.xa, table.byte_len(), Chunk::JumpTable(table.clone())));
+    }
+
+    for copy in &copied_code {
+        chunks.push((copy.src, copy.len, Chunk::Copied(*copy))); }
+
+    // scripts, one chunk per run of contiguous instructions
+
+    for (vm, instructions) in &scripts
+    {
+        let mut runs: Vec<Vec<&script::ScriptIns>> = vec![];
+
+        for ins in instructions
+        {
+            match runs.last_mut()
+            {
+                Some(run) if run.last().is_some_and(|last| last.xa + last.len as u16 == ins.xa) => run.push(ins),
+                _ => runs.push(vec![ins]),
+            }
+        }
+
+        for run in runs
+        {
+            let len = run.iter().map(|ins| ins.len).sum();
+            chunks.push((run[0].xa, len, Chunk::Script(*vm, run)));
+        }
+    }
+
+    for data in &analysis.inline_data {
+        chunks.push((data.xa, data.len, Chunk::InlineData(data.callee))); }
+
+    // compressed regions
+
+    for (xa, tag) in &tags
+    {
+        if let tags::Tag::Compressed { codec, len } = tag
+        {
+            let codec = codec::find_codec(codec, &codecs)?;
+            let decompressed = match anal_info.rom_slice(*xa, len.unwrap_or(0x4000))
+            {
+                Ok(_) if anal_info.rom_offset(*xa).is_none() => anyhow::bail!("compressed region at {} is not in ROM", xa),
+                Ok(slice) => codec.decompress(slice)?,
+                Err(e) => anyhow::bail!("compressed region at {} is not in ROM: {:?}", xa, e),
+            };
+
+            name_map.entry(*xa).or_insert(default_xaddr_name(*xa, "Compressed"));
+            chunks.push((*xa, len.unwrap_or(decompressed.consumed), Chunk::Compressed(codec, decompressed.data)));
+        }
+    }
+
+    // what analysis and tags account for, before speculation
+
+    if opt.coverage || opt.coverage_json.is_some()
+    {
+        let code: Vec<_> = chunks.iter()
+            .filter(|(_, _, chunk)| matches!(chunk, Chunk::Code(..) | Chunk::Copied(_)))
+            .map(|(xa, len, _)| (*xa, *len)).collect();
+
+        let banks = coverage::bank_coverage(&anal_info, &code, &covered_ranges(&chunks, &tags));
+
+        if opt.coverage
+        {
+            for bank in &banks {
+                eprintln!("coverage: bank {:02X}: {}", bank.bank, bank); }
+
+            eprintln!("coverage: total:   {}", coverage::total(&banks));
+        }
+
+        if let Some(filename) = &opt.coverage_json {
+            std::fs::write(filename, coverage::to_json(&banks))?; }
+    }
+
+    // linear sweep of the rest
+
+    if opt.sweep
+    {
+        for block in anal::linear_sweep(&anal_info, &covered_ranges(&chunks, &tags)).into_iter().filter(|block| !anal_info.is_excluded(block.xa))
+        {
+            name_map.entry(block.xa).or_insert(default_xaddr_name(block.xa, "Sweep"));
+            chunks.push((block.xa, block.len, Chunk::Code(block.ctx, anal::Confidence::Speculative)));
+        }
+    }
+
+    if opt.entropy
+    {
+        for region in entropy::find_high_entropy(&anal_info, &anal::uncovered_ranges(&anal_info, &covered_ranges(&chunks, &tags)))
+        {
+            eprintln!("likely compressed data: {} - {} ({:.2} bits/byte)",
+                region.xa, region.xa + (region.len - 1) as u16, region.entropy);
+        }
+    }
+
+    // extra hardware is named after its region, before renaming rules see the names
+
+    for (xa, name) in name_map.iter_mut()
+    {
+        if user_names.contains(xa) {
+            continue; }
+
+        if let Some(region) = memmap::find_region(&regions, xa.addr).filter(|_| xa.bank == 0) {
+            *name = region.addr_name(xa.addr); }
+    }
+
+    if let Some(filename) = &opt.rename_rules
+    {
+        use log::warn;
+
+        let rules = rename::parse_rules(&mut BufReader::new(File::open(filename)?))?;
+
+        let mut renames: Vec<(XAddr, String)> = name_map.iter()
+            .filter(|(xa, _)| !user_names.contains(xa))
+            .filter_map(|(xa, name)| rename::apply_rules(&rules, *xa, name).map(|new_name| (*xa, new_name)))
+            .collect();
+
+        renames.sort();
+
+        // a rename can't take a name some other symbol has (or gets from an earlier rename), as with conflicting tags the first one wins
+        let renamed: HashSet<XAddr> = renames.iter().map(|(xa, _)| *xa).collect();
+        let mut taken: HashMap<String, XAddr> = name_map.iter().filter(|(xa, _)| !renamed.contains(xa)).map(|(xa, name)| (name.clone(), *xa)).collect();
+
+        for (xa, new_name) in renames
+        {
+            let name = name_map.get_mut(&xa).unwrap();
+
+            match taken.get(&new_name)
+            {
+                Some(other) =>
+                {
+                    warn!("renaming {} ({}) to {} conflicts with {} having that name, keeping {}", name, xa, new_name, other, name);
+                    taken.insert(name.clone(), xa);
+                }
+
+                None =>
+                {
+                    taken.insert(new_name.clone(), xa);
+                    *name = new_name;
+                }
+            }
+        }
+    }
+
+    if opt.similar_code
+    {
+        for cluster in cluster::find_clusters(&flow_graph, &functions)
+        {
+            let names: Vec<_> = cluster.members.iter().map(|xa| name_map.get(xa).cloned().unwrap_or(xa.to_string())).collect();
+            eprintln!("similar code ({} functions, {}+ instructions): {}", cluster.members.len(), cluster.len, names.join(", "));
+        }
+    }
+
+    // call graph exploration
+
+    if let Some(Command::Calls { symbol, depth }) = &opt.command
+    {
+        let xa = resolve_symbol(&name_map, symbol)?;
+
+        // code outside of functions is split where the user named it
+        let owners = func::find_owners(&flow_graph, &functions, &user_names);
+        let edges = func::find_call_edges(&flow_graph, &functions, &owners);
+
+        // calls are made and received by whole functions (or regions), a symbol inside one stands for it
+        let in_block = |i: usize|
+        {
+            let block = flow_graph.block(i);
+            block.xa.bank == xa.bank && block.xa.addr <= xa.addr && (xa.addr as usize) < block.xa.addr as usize + block.len
+        };
+
+        let root = match (0 .. flow_graph.block_count()).find(|&i| in_block(i))
+        {
+            Some(i) => owners.get(&i).copied().unwrap_or(flow_graph.block(i).xa),
+            None => anyhow::bail!("{} is not in code, so it makes and receives no calls", xa),
+        };
+
+        let display = |xa: XAddr| match name_map.get(&xa)
+        {
+            Some(name) => format!("{} ({})", name, xa),
+            None => xa.to_string(),
+        };
+
+        println!("{}", display(root));
+
+        for (title, callers) in &[("callers", true), ("callees", false)]
+        {
+            println!("{}:", title);
+            print_call_tree(&edges, *callers, *depth, &mut vec![root], &display);
+        }
+
+        return check_strict(opt, input_filename, &stats);
+    }
+
+    // the names as they are, without going through the listing
+
+    if let Some(Command::Sym { filter, kind, sort }) = &opt.command
+    {
+        let xrefs = count_xrefs(&anal_info, &analysis);
+
+        let kind_of = |xa: XAddr|
+        {
+            let in_code = chunks.iter().any(|(cxa, len, chunk)| matches!(chunk, Chunk::Code(..) | Chunk::Copied(_))
+                && cxa.bank == xa.bank && cxa.addr <= xa.addr && (xa.addr as usize) < cxa.addr as usize + len);
+
+            // copied code runs in ram
+            let in_copy = copied_code.iter().any(|copy| copy.dest <= xa.addr && (xa.addr as usize) < copy.dest as usize + copy.len);
+
+            match xa.addr
+            {
+                _ if in_code || in_copy => "code",
+                0x8000 ..= 0xFFFF => "ram",
+                _ => "data",
+            }
+        };
+
+        let filter = filter.as_ref().map(|filter| filter.to_lowercase());
+
+        let mut symbols: Vec<(XAddr, &str, &String, (usize, usize))> = name_map.iter()
+            .map(|(xa, name)| (*xa, kind_of(*xa), name, xrefs.get(xa).copied().unwrap_or_default()))
+            .filter(|(_, symbol_kind, name, _)| kind.as_ref().is_none_or(|kind| kind == symbol_kind)
+                && filter.as_ref().is_none_or(|filter| name.to_lowercase().contains(filter)))
+            .collect();
+
+        match sort.as_str()
+        {
+            "name" => symbols.sort_by(|a, b| a.2.cmp(b.2).then(a.0.cmp(&b.0))),
+            "xrefs" => symbols.sort_by(|a, b| (b.3.0 + b.3.1).cmp(&(a.3.0 + a.3.1)).then(a.0.cmp(&b.0))),
+            _ => symbols.sort_by_key(|symbol| symbol.0),
+        }
+
+        for (xa, symbol_kind, name, (code_xrefs, data_xrefs)) in symbols {
+            println!("{} {:4} {:3} {:3} {}", xa, symbol_kind, code_xrefs, data_xrefs, name); }
+
+        return check_strict(opt, input_filename, &stats);
+    }
+
+    // a single function is listed by leaving out anything before or after it: what's between its blocks
+    // (data it reads inline, jump tables, padding) is listed too, one range per bank it has blocks in
+
+    let function_ranges: Option<Vec<xaddr::XAddrRange>> = match &opt.command
+    {
+        Some(Command::Fn { symbol }) =>
+        {
+            let xa = resolve_symbol(&name_map, symbol)?;
+
+            // the one starting there (nothing needs to call it), or else one going through it
+            let functions = func::find_functions_from(&flow_graph, &[xa]);
+            let function = functions.iter().find(|function| function.xa == xa)
+                .or_else(|| functions.iter().find(|function| function.blocks.iter().any(|i| flow_graph.block(*i).xa == xa)));
+
+            match function
+            {
+                Some(function) =>
+                {
+                    let mut ranges: BTreeMap<u16, xaddr::XAddrRange> = BTreeMap::new();
+
+                    for block in function.blocks.iter().map(|i| flow_graph.block(*i))
+                    {
+                        let end = XAddr::new(block.xa.bank, (block.xa.addr as usize + block.len - 1) as u16);
+                        let range = ranges.entry(block.xa.bank).or_insert(xaddr::XAddrRange { beg: block.xa, end: end });
+
+                        range.beg = std::cmp::min(range.beg, block.xa);
+                        range.end = std::cmp::max(range.end, end);
+                    }
+
+                    Some(ranges.into_values().collect())
+                }
+
+                None => anyhow::bail!("no function starts at or goes through {}", xa),
+            }
+        }
+
+        _ => None,
+    };
+
+    // accesses are summed up by symbol, variables taking in accesses to any of their bytes
+
+    let symbol_accesses =
+    {
+        let mut symbol_accesses: BTreeMap<XAddr, func::DataAccess> = BTreeMap::new();
+
+        for (xa, access) in &data_accesses
+        {
+            let symbol = match (name_map.contains_key(xa), tags::find_var(&tags, xa))
+            {
+                (true, _) => *xa,
+                (false, Some((_, offset))) => XAddr::new(xa.bank, xa.addr - offset),
+                (false, None) => continue,
+            };
+
+            let entry = symbol_accesses.entry(symbol).or_default();
+            entry.readers.extend(access.readers.iter().copied());
+            entry.writers.extend(access.writers.iter().copied());
+        }
+
+        for access in symbol_accesses.values_mut()
+        {
+            access.readers.sort();
+            access.readers.dedup();
+            access.writers.sort();
+            access.writers.dedup();
+        }
+
+        symbol_accesses
+    };
+
+    // code outside of functions goes by the closest label before it
+    let sorted_names: BTreeMap<XAddr, &String> = name_map.iter().map(|(xa, name)| (*xa, name)).collect();
+
+    let access_lines = |access: &func::DataAccess| -> Vec<String>
+    {
+        let owner_name = |xa: &XAddr| match sorted_names.range(XAddr::new(xa.bank, 0) ..= *xa).next_back()
+        {
+            Some((_, name)) => name.to_string(),
+            None => xa.to_string(),
+        };
+
+        let names = |xas: &[XAddr]|
+        {
+            let mut names: Vec<_> = xas.iter().map(owner_name).collect();
+            names.dedup();
+            names.join(", ")
+        };
+
+        let mut lines = vec![];
+
+        if !access.readers.is_empty() {
+            lines.push(format!("read by {}", names(&access.readers))); }
+
+        if !access.writers.is_empty() {
+            lines.push(format!("written by {}", names(&access.writers))); }
+
+        lines
+    };
+
+    // print listing
+
+    let syntax = opt.syntax.unwrap_or(syntax::Syntax::Rgbds);
+    let spellings = opt.spelling.clone().unwrap_or_else(|| syntax.spellings().to_vec());
+
+    let listing_filename = opt.output_dir.as_ref().map(|dir|
+    {
+        let stem = match &opt.listing_name
+        {
+            Some(name) => name.clone(),
+            None => input_filename.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default(),
+        };
+
+        dir.join(format!("{}.asm", stem))
+    });
+
+    let out: Box<dyn Write> = match (&opt.output_dir, &listing_filename)
+    {
+        (Some(dir), Some(filename)) =>
+        {
+            std::fs::create_dir_all(dir)?;
+            Box::new(std::io::BufWriter::new(File::create(filename)?))
+        }
+
+        _ => Box::new(std::io::stdout()),
+    };
+
+    let mut out = debuginfo::ListingWriter::new(out);
+
+    let mut last_xa = XAddr::new(0xFFFF, 0xFFFF);
+    let mut scope = vec![];
+
+    let print_object = |out: &mut debuginfo::ListingWriter<Box<dyn Write>>, xa: XAddr, fmt: &str, note: Option<String>| -> std::io::Result<()>
+    {
+        out.mark(xa);
+
+        let mut comments = note.iter().map(|note| note.as_str()).chain(tags::get_tags_at(&tags, &xa).iter().filter_map(|tag|
+        {
+            match &tag.1
+            {
+                tags::Tag::Comment(comment) => Some(comment.lines()),
+                _ => None,
+            }
+        }).flatten());
+
+        if let Some(head_comment) = comments.next()
+        {
+            writeln!(out, "\t/* {} */ {} ; {}", xa, fmt, head_comment)?;
+
+            for tail_comment in comments
+            {
+                writeln!(out, "\t              {} ; {}", " ".repeat(fmt.len()), tail_comment)?;
+            }
+        }
+        else
+        {
+            writeln!(out, "\t/* {} */ {}", xa, fmt)?;
+        }
+
+        Ok(())
+    };
+
+    let print_preamble = |out: &mut dyn Write, xa: XAddr| -> std::io::Result<()>
+    {
+        let here = tags::get_tags_at(&tags, &xa);
+
+        let mut banner = here.iter().filter_map(|tag|
+        {
+            match &tag.1
+            {
+                tags::Tag::Banner(line) => Some(line),
+                _ => None,
+            }
+        }).peekable();
+
+        if banner.peek().is_some()
+        {
+            writeln!(out, "; {}", "-".repeat(BANNER_WIDTH))?;
+
+            for line in banner.flat_map(|banner| banner.split('\n'))
+            {
+                if line.is_empty() { writeln!(out, ";")?; }
+                else { writeln!(out, "; {}", line)?; }
+            }
+
+            writeln!(out, "; {}", "-".repeat(BANNER_WIDTH))?;
+        }
+
+        for (_, tag) in here
+        {
+            if let tags::Tag::PreComment(comment) = tag
+            {
+                for line in comment.lines() {
+                    writeln!(out, "; {}", line)?; }
+            }
+        }
+
+        Ok(())
+    };
+
+    if let Some(min_confidence) = opt.min_confidence {
+        chunks.retain(|(_, _, chunk)| !matches!(chunk, Chunk::Code(_, confidence) if *confidence < min_confidence)); }
+
+    let orphans_section = opt.orphans_section;
+    let is_orphan = |xa: &XAddr| orphans_section && orphans.contains(xa);
+
+    if let Some(ranges) = &function_ranges {
+        chunks.retain(|(xa, _, _)| ranges.iter().any(|range| range.contains(*xa))); }
+
+    if !opt.ranges.is_empty() {
+        chunks.retain(|(xa, _, _)| opt.ranges.iter().any(|range| range.contains(*xa))); }
+
+    chunks.retain(|(xa, _, _)| !anal_info.is_excluded(*xa));
+
+    chunks.sort_by_key(|chunk| (is_orphan(&chunk.0), chunk.0));
+
+    // scripts are written with one macro per opcode
+
+    for (vm, _) in scripts.iter().filter(|_| function_ranges.is_none())
+    {
+        let mut ops: Vec<_> = script_vms[*vm].ops.iter().collect();
+        ops.sort_by_key(|(opcode, _)| **opcode);
+
+        for (opcode, op) in ops
+        {
+            writeln!(out, "{}", syntax.macro_begin(&op.mnemonic))?;
+            writeln!(out, "\tdb ${:02X}", opcode)?;
+
+            for (i, kind) in op.operands.iter().enumerate() {
+                writeln!(out, "\t{} \\{}", if kind.byte_len() == 1 { "db" } else { "dw" }, i + 1)?; }
+
+            writeln!(out, "{}", syntax.macro_end())?;
+        }
+
+        writeln!(out)?;
+    }
+
+    let mut in_orphans = false;
+    let mut sections = vec![];
+    let chunk_count = chunks.len();
+
+    for (chunk_index, (xa, len, chunk)) in chunks.into_iter().enumerate()
+    {
+        progress::status(|| format!("writing the listing: bank ${:02X}, {}/{} chunks", xa.bank, chunk_index, chunk_count));
+        let orphan = is_orphan(&xa);
+
+        if last_xa != xa || orphan != in_orphans
+        {
+            writeln!(out, "\t; end: {}", last_xa)?;
+
+            if let (true, Some(end)) = (last_xa.bank != 0xFFFF, syntax.section_end()) {
+                writeln!(out, "{}", end)?; }
+
+            if orphan && !in_orphans
+            {
+                writeln!(out)?;
+                writeln!(out, "; {}", "=".repeat(BANNER_WIDTH))?;
+                writeln!(out, "; orphans: code not referenced from reachable code")?;
+                writeln!(out, "; {}", "=".repeat(BANNER_WIDTH))?;
+                writeln!(out)?;
+
+                in_orphans = true;
+            }
+
+            let prefix = match xa.addr
+            {
+                _ if orphan => "orphan",
+                0xA000 ..= 0xBFFF => "sram",
+                _ => "rom",
+            };
+
+            let name = format!("{}_{:02X}_{:04X}", prefix, xa.bank, xa.addr);
+            writeln!(out, "{}", syntax.section_begin(&name))?;
+
+            sections.push(layout::Section { name: name, xa: xa, len: 0 });
+        }
+
+        last_xa = xa + len as u16;
+
+        if let Some(section) = sections.last_mut() {
+            section.len = (last_xa.addr - section.xa.addr) as usize; }
+
+        print_preamble(&mut out, xa)?;
+
+        if let Chunk::Code(_, confidence @ (anal::Confidence::Speculative | anal::Confidence::Heuristic)) = chunk {
+            writeln!(out, "; confidence: {}", confidence)?; }
+
+        if let Some(reasons) = suspicious.get(&xa) {
+            writeln!(out, "; suspicious: {}", reasons.join(", "))?; }
+
+        if let Some(usage) = reg_usage.get(&xa)
+        {
+            let (inputs, clobbers) = (regs::mask_names(usage.inputs), regs::mask_names(usage.clobbers));
+
+            match (inputs.is_empty(), clobbers.is_empty())
+            {
+                (false, false) => writeln!(out, "; in: {}, clobbers: {}", inputs.join(", "), clobbers.join(", "))?,
+                (false, true) => writeln!(out, "; in: {}", inputs.join(", "))?,
+                (true, false) => writeln!(out, "; clobbers: {}", clobbers.join(", "))?,
+                (true, true) => {}
+            }
+        }
+
+        match timings.get(&xa).map(|timing| (timing.best, timing.worst))
+        {
+            Some((Some(best), Some(worst))) if best == worst => writeln!(out, "; cycles: {} (M-cycles, not counting callees)", best)?,
+            Some((Some(best), Some(worst))) => writeln!(out, "; cycles: {} to {} (M-cycles, not counting callees)", best, worst)?,
+            Some((Some(best), None)) => writeln!(out, "; cycles: {} or more, loops (M-cycles, not counting callees)", best)?,
+            _ => {}
+        }
+
+        if let Some(access) = symbol_accesses.get(&xa)
+        {
+            for line in access_lines(access) {
+                writeln!(out, "; {}", line)?; }
+        }
+
+        if let Some(name) = name_map.get(&xa)
+        {
+            let name = syntax.label_def(&mut scope, name);
+            writeln!(out, "{}: ; {}", name, xa)?
+        }
+
+        let ctx = match chunk
+        {
+            Chunk::Code(ctx, _) => ctx,
+
+            Chunk::JumpTable(table) =>
+            {
+                for (i, (addr, target)) in table.targets.iter().enumerate()
+                {
+                    let name = target.and_then(|target| ref_name(syntax, &scope, &name_map, &tags, target)).unwrap_or(format!("${:04X}", addr));
+                    let note = table.bad_entries.iter().find(|(bad, _)| *bad == i).map(|(_, bad)| format!("bad entry: {}", bad));
+
+                    print_object(&mut out, xa + (i * 2) as u16, &format!("dw {}", name), note)?;
+                }
+
+                writeln!(out)?;
+                continue;
+            }
+
+            Chunk::Copied(copy) =>
+            {
+                let slice = anal_info.rom_slice(xa, len).unwrap();
+
+                match syntax.load_begin(&name_map[&runtime_xa(copy.dest)], copy.dest)
+                {
+                    Some(load) => writeln!(out, "{}", load)?,
+                    None => writeln!(out, "\t; copied to ${:04X} to run there", copy.dest)?,
+                }
+
+                for (addr, decoded) in gbasm::decode_slice_gapless(copy.dest, slice)
+                {
+                    let rom_xa = xa + addr.wrapping_sub(copy.dest);
+
+                    let ins = match decoded
+                    {
+                        gbasm::Decoded::Instruction(ins) => ins,
+
+                        gbasm::Decoded::DataByte(byte) =>
+                        {
+                            print_object(&mut out, rom_xa, &format!("db ${:02X}", byte), None)?;
+                            continue;
+                        }
+                    };
+
+                    if let Some(name) = name_map.get(&runtime_xa(addr)) {
+                        writeln!(out, "{}: ; {}", syntax.label_def(&mut scope, name), runtime_xa(addr))?; }
+
+                    let ops = format!("${:X}", ins.operand);
+                    let ops = match ins.get_jump_target().or(if ins.is_addr_operand() { Some(ins.operand) } else { None })
+                    {
+                        Some(addr) => ref_name(syntax, &scope, &name_map, &tags, runtime_xa(addr)).unwrap_or(ops),
+                        None => ops,
+                    };
+
+                    print_object(&mut out, rom_xa, &syntax::spell(&spellings, ins.info().fmt, &ops), None)?;
+                }
+
+                if let Some(end) = syntax.load_end() {
+                    writeln!(out, "{}", end)?; }
+
+                writeln!(out)?;
+                continue;
+            }
+
+            Chunk::Script(vm, instructions) =>
+            {
+                let vm = &script_vms[vm];
+
+                for ins in instructions
+                {
+                    if ins.xa != xa
+                    {
+                        if let Some(name) = name_map.get(&ins.xa) {
+                            writeln!(out, "{}: ; {}", syntax.label_def(&mut scope, name), ins.xa)?; }
+                    }
+
+                    let operands: Vec<_> = ins.operands.iter().map(|(kind, value)| match kind
+                    {
+                        script::OperandKind::Byte => format!("${:02X}", value),
+                        _ if kind.is_addr() => script::expand_addr(&anal_info, ins.xa, *value)
+                            .and_then(|target| ref_name(syntax, &scope, &name_map, &tags, target))
+                            .unwrap_or(format!("${:04X}", value)),
+                        _ => format!("${:04X}", value),
+                    }).collect();
+
+                    let fmt = format!("{} {}", vm.ops[&ins.opcode].mnemonic, operands.join(", "));
+                    print_object(&mut out, ins.xa, fmt.trim_end(), None)?;
+                }
+
+                writeln!(out)?;
+                continue;
+            }
+
+            Chunk::InlineData(callee) =>
+            {
+                let note = ref_name(syntax, &scope, &name_map, &tags, callee).map(|name| format!("read by {}", name));
+                let slice = anal_info.rom_slice(xa, len).unwrap();
+
+                for (i, row) in slice.chunks(8).enumerate()
+                {
+                    let bytes: Vec<_> = row.iter().map(|byte| format!("${:02X}", byte)).collect();
+                    print_object(&mut out, xa + (i * 8) as u16, &format!("db {}", bytes.join(", ")), if i == 0 { note.clone() } else { None })?;
+                }
+
+                writeln!(out)?;
+                continue;
+            }
+
+            Chunk::Compressed(codec, data) =>
+            {
+                let offset = (anal_info.rom_offset(xa).unwrap() as isize + image_offset) as usize;
+
+                writeln!(out, "\t{} ; {} compressed, {} bytes decompressed", syntax.incbin(&input_filename.to_string_lossy(), offset, len), codec.name(), data.len())?;
+
+                if let Some(dir) = &opt.extract_dir
+                {
+                    let filename = dir.join(format!("{}.bin", name_map.get(&xa).unwrap()));
+                    std::fs::write(&filename, &data)?;
+                    writeln!(out, "\t; decompressed to {}", filename.display())?;
+                }
+
+                writeln!(out)?;
+                continue;
+            }
+        };
+
+        let block_xa = xa;
+        let mut emu = anal::AnalEmu::with_ctx(&anal_info, xa, len, ctx);
+
+        // the same code run with each of the bank states it is reached with, where they conflict
+        let mut variant_emus: Vec<_> = analysis.bank_variants.get(&xa).into_iter().flatten()
+            .map(|ctx| anal::AnalEmu::with_ctx(&anal_info, xa, len, *ctx)).collect();
+
+        while let Some((xa, Ok(ins))) = emu.next()
+        {
+            for variant in &mut variant_emus {
+                variant.next(); }
+
+            if xa != block_xa {
+                print_preamble(&mut out, xa)?; }
 
-        while let Some((xa, Ok(ins))) = emu.next()
-        {
             let fmt = ins.info().fmt;
+            let mut variant_note = None;
 
             let ops = format!("${:X}", ins.operand);
-            let ops = if ins.is_addr_operand() || tags::get_tags_at(&tags, &xa).iter().any(|(_, tag)| if let tags::Tag::OperandAddr = tag { true } else { false })
+            let ops = if let Some(name) = bank_loads.get(&xa).and_then(|target| name_map.get(target))
+            {
+                syntax.bank_of(&syntax.label_ref(&scope, name))
+            }
+            else if ins.operand < 0x8000 && ins.get_jump_target().is_none() && (ins.info().flags & gbasm::OPCODE_FLAG_WRITE_MEM) != 0
             {
-                match emu.expand_addr(ins.operand).map(|xa| name_map.get(&xa)).flatten()
+                // writes to rom are mbc commands, whatever the selected bank has there
+                ops
+            }
+            else if ins.is_addr_operand() || tags::get_tags_at(&tags, &xa).iter().any(|(_, tag)| if let tags::Tag::OperandAddr = tag { true } else { false })
+            {
+                let expand = |emu: &anal::AnalEmu| emu.resolve(xa, &ins).xaddr();
+
+                let target = expand(&emu);
+                let mut variant_targets: Vec<XAddr> = if target.is_none() { variant_emus.iter().filter_map(expand).collect() } else { vec![] };
+
+                // what every bank state agrees on stands for all of them, otherwise each is noted
+                let agreed = variant_targets.len() == variant_emus.len() && variant_targets.windows(2).all(|pair| pair[0] == pair[1]);
+
+                match (target, variant_targets.first())
                 {
-                    Some(name) => get_local_name(name.clone(), false),
-                    None => ops
+                    (Some(target), _) => match ref_name(syntax, &scope, &name_map, &tags, target)
+                    {
+                        // echoes of MBC2 ram are written relative to what they echo, so the operand stays the same
+                        Some(name) if anal_info.rom_info.mbc == mbc::Mbc::Mbc2 && (0xA200 ..= 0xBFFF).contains(&ins.operand) && ins.get_jump_target().is_none() =>
+                            format!("{}+${:X}", name, ins.operand - target.addr),
+                        name => name.unwrap_or(ops),
+                    },
+
+                    (None, Some(&first)) if agreed => ref_name(syntax, &scope, &name_map, &tags, first).unwrap_or(ops),
+
+                    (None, Some(_)) =>
+                    {
+                        variant_targets.dedup();
+
+                        let names: Vec<_> = variant_targets.iter().map(|target|
+                            ref_name(syntax, &scope, &name_map, &tags, *target).unwrap_or(target.to_string())).collect();
+
+                        variant_note = Some(format!("depending on the bank: {}", names.join(", ")));
+                        ops
+                    }
+
+                    (None, None) => ops,
                 }
             }
             else
@@ -260,13 +2889,132 @@ fn main() -> Result<()>
                 ops
             };
 
-            let fmt = fmt.replace("%", &ops);
+            let fmt = syntax::spell(&spellings, fmt, &ops);
+
+            // what the halt bug makes of the bytes isn't what an assembler would make of the instruction,
+            // so they are kept as is (a single byte instruction read twice just runs twice)
+
+            if ins.halt_bug
+            {
+                match anal_info.rom_slice(xa, ins.encoded_len())
+                {
+                    Ok(bytes) if !bytes.is_empty() =>
+                    {
+                        let bytes: Vec<_> = bytes.iter().map(|byte| format!("${:02X}", byte)).collect();
+                        print_object(&mut out, xa, &format!("db {}", bytes.join(", ")), Some(format!("halt bug: runs as {}", fmt)))?;
+                    }
+
+                    _ => writeln!(out, "\t; halt bug: {} runs twice", fmt)?,
+                }
+
+                continue;
+            }
+
+            // assemblers always pad `stop` with $00, anything else has to be spelled out
+
+            if ins.opcode == OPCODE_STOP && (ins.bare_stop || ins.operand != 0)
+            {
+                let fmt = if ins.bare_stop { "db $10".to_string() } else { format!("db $10, ${:02X}", ins.operand) };
+                print_object(&mut out, xa, &fmt, Some("stop".to_string()))?;
+                continue;
+            }
+
+            // name what known register values point to
+
+            let regs = emu.regs();
+
+            let note = if variant_note.is_some()
+            {
+                variant_note
+            }
+            else if let Some(target) = emu.computed_target(&ins).and_then(|addr| emu.expand_target(xa, addr))
+            {
+                ref_name(syntax, &scope, &name_map, &tags, target).map(|name| format!("to {}", name))
+            }
+            else if let Some(targets) = emulated.get(&xa)
+            {
+                let names: Vec<_> = targets.iter().filter_map(|target| ref_name(syntax, &scope, &name_map, &tags, *target)).collect();
+                if names.is_empty() { None } else { Some(format!("to {}", names.join(", "))) }
+            }
+            else if let Some(wait) = wait_loops.get(&xa)
+            {
+                Some(wait.kind.note())
+            }
+            else if let Some(addr) = regs.mem_write_addr(&ins).filter(|addr| *addr < 0x8000)
+            {
+                memmap::find_region(&regions, addr).map(|region| format!("write to {}", region.addr_name(addr)))
+                    .or_else(|| anal_info.rom_info.mbc.describe_write(addr, regs.mem_write(&ins).map(|(_, value)| value)))
+                    .or_else(|| if stray_rom_writes.contains(&xa) { Some("write to ROM: not an MBC register".to_string()) } else { None })
+            }
+            else
+            {
+                regs.mem_pointer(&ins)
+                    .and_then(|(pair, addr)| emu.expand_addr(addr).map(|target| (pair, target)))
+                    .and_then(|(pair, target)| ref_name(syntax, &scope, &name_map, &tags, target).map(|name| format!("{} = {}", pair, name)))
+                    .or_else(|| if halts_after_di.contains(&xa) { Some("interrupts disabled: DMG halt bug if one is pending".to_string()) } else { None })
+            };
+
+            print_object(&mut out, xa, &fmt, note)?;
+        }
+
+        writeln!(out)?;
+    }
+
+    if let (true, Some(end)) = (last_xa.bank != 0xFFFF, syntax.section_end()) {
+        writeln!(out, "{}", end)?; }
+
+    // ram (and other symbols outside of the listing) are summed up at the end
+
+    let outside: Vec<_> = symbol_accesses.iter().filter(|(xa, _)| xa.addr >= 0x8000 && function_ranges.is_none() && opt.ranges.is_empty()).collect();
+
+    if !outside.is_empty()
+    {
+        writeln!(out)?;
+        writeln!(out, "; {}", "=".repeat(BANNER_WIDTH))?;
+        writeln!(out, "; data accesses")?;
+        writeln!(out, "; {}", "=".repeat(BANNER_WIDTH))?;
+        writeln!(out)?;
+
+        for (xa, access) in outside
+        {
+            // variables say what they hold
+            let var = tags::get_tags_at(&tags, xa).iter().find_map(|(_, tag)| match tag
+            {
+                tags::Tag::Var { name, size, kind } => Some((name, size, kind)),
+                _ => None,
+            });
+
+            match var
+            {
+                Some((name, size, kind)) => writeln!(out, "; {} ({}): {}, {} byte{}", name, xa, kind, size, if *size == 1 { "" } else { "s" })?,
+                None => writeln!(out, "; {} ({})", name_map[xa], xa)?,
+            }
 
-            print_object(xa, &fmt);
+            for line in access_lines(access) {
+                writeln!(out, ";     {}", line)?; }
         }
+    }
 
-        println!("");
+    out.flush()?;
+    progress::clear();
+
+    if let Some(filename) = &opt.section_map
+    {
+        // a rom without banks (and not loaded elsewhere with --org) is mapped from $0000 up to its end
+        let rom_info = &anal_info.rom_info;
+        let flat_rom = if !rom_info.big_rom && rom_info.org.is_none() { Some(rom_data.len()) } else { None };
+
+        layout::write_map(&mut std::io::BufWriter::new(File::create(filename)?), &sections, flat_rom)?;
     }
 
-    Ok(())
+    if let Some(filename) = &opt.debug_map
+    {
+        // a listing on stdout has no name to give
+        let listing_name = listing_filename.map_or("-".to_string(), |filename| filename.display().to_string());
+        let labels: Vec<(XAddr, &str)> = name_map.iter().map(|(xa, name)| (*xa, name.as_str())).collect();
+
+        debuginfo::write_sym(&mut std::io::BufWriter::new(File::create(filename)?), &labels, out.marks(), &listing_name, out.crc(), util::crc32(&rom_data))?;
+    }
+
+    check_strict(opt, input_filename, &stats)
 }
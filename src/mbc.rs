@@ -0,0 +1,209 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+// cartridge memory bank controller, from the cartridge type byte of the header ($0147)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mbc
+{
+    RomOnly,
+    Mbc1,
+    Mbc2,
+    Mbc3,
+    Mbc5,
+    Huc1,
+    Huc3,
+    Other(u8), // MMM01, MBC6, MBC7... with their cartridge type
+}
+
+// what a write to a bank register selects
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BankSelect
+{
+    Rom(u16),
+    Sram(u16),
+    Mode(bool), // MBC1 banking mode 1: the upper rom bank bits also apply to $0000-$3FFF
+}
+
+impl Mbc
+{
+    pub fn from_cartridge_type(cartridge_type: u8) -> Self
+    {
+        match cartridge_type
+        {
+            0x00 | 0x08 | 0x09 => Mbc::RomOnly,
+            0x01 ..= 0x03 => Mbc::Mbc1,
+            0x05 | 0x06 => Mbc::Mbc2,
+            0x0F ..= 0x13 => Mbc::Mbc3,
+            0x19 ..= 0x1E => Mbc::Mbc5,
+            0xFE => Mbc::Huc3,
+            0xFF => Mbc::Huc1,
+            _ => Mbc::Other(cartridge_type),
+        }
+    }
+
+    pub fn default_sram_count(&self) -> Option<usize>
+    {
+        // for controllers with ram of their own, which the header doesn't count
+
+        match self
+        {
+            Mbc::Mbc2 => Some(1),
+            _ => None,
+        }
+    }
+
+    pub fn bank_select(&self, addr: u16, value: u8, romb: Option<u16>, rom_banks: usize) -> Option<BankSelect>
+    {
+        // romb is the rom bank selected so far, for registers setting only part of it
+
+        let nonzero = |bank: u8| if bank == 0 { 1 } else { bank as u16 };
+
+        match (self, addr)
+        {
+            (Mbc::RomOnly, _) => None,
+
+            // only the low 5 bits are checked against 0, so banks $20, $40 and $60 can't be selected
+            // $4000-$5FFF sets the upper 2 bits of the rom bank on roms over 512KiB, and the sram bank otherwise
+            (Mbc::Mbc1, 0x2000 ..= 0x3FFF) => Some(BankSelect::Rom((romb.unwrap_or(0) & 0x60) | nonzero(value & 0x1F))),
+            (Mbc::Mbc1, 0x4000 ..= 0x5FFF) if rom_banks > 0x20 => Some(BankSelect::Rom((romb.unwrap_or(1) & 0x1F) | ((value & 0x03) as u16) << 5)),
+            (Mbc::Mbc1, 0x4000 ..= 0x5FFF) => Some(BankSelect::Sram((value & 0x03) as u16)),
+            (Mbc::Mbc1, 0x6000 ..= 0x7FFF) => Some(BankSelect::Mode((value & 1) != 0)),
+
+            (Mbc::Mbc2, 0x0000 ..= 0x3FFF) if (addr & 0x0100) != 0 => Some(BankSelect::Rom(nonzero(value & 0x0F))),
+
+            (Mbc::Mbc3, 0x2000 ..= 0x3FFF) => Some(BankSelect::Rom(nonzero(value & 0x7F))),
+            (Mbc::Mbc3, 0x4000 ..= 0x5FFF) if value < 0x08 => Some(BankSelect::Sram(value as u16)),
+
+            // bank 0 can be selected, and bit 8 is assumed clear until set
+            (Mbc::Mbc5, 0x2000 ..= 0x2FFF) => Some(BankSelect::Rom((romb.unwrap_or(0) & 0x100) | value as u16)),
+            (Mbc::Mbc5, 0x3000 ..= 0x3FFF) => romb.map(|romb| BankSelect::Rom((romb & 0xFF) | ((value & 1) as u16) << 8)),
+            (Mbc::Mbc5, 0x4000 ..= 0x5FFF) => Some(BankSelect::Sram((value & 0x0F) as u16)),
+
+            (Mbc::Huc1, 0x2000 ..= 0x3FFF) => Some(BankSelect::Rom(nonzero(value & 0x3F))),
+            (Mbc::Huc1, 0x4000 ..= 0x5FFF) => Some(BankSelect::Sram((value & 0x03) as u16)),
+
+            (Mbc::Huc3, 0x2000 ..= 0x3FFF) => Some(BankSelect::Rom(nonzero(value & 0x7F))),
+            (Mbc::Huc3, 0x4000 ..= 0x5FFF) => Some(BankSelect::Sram((value & 0x03) as u16)),
+
+            // anything else is assumed to work like MBC1/MBC3
+            (Mbc::Other(_), 0x2000 ..= 0x3FFF) => Some(BankSelect::Rom(nonzero(value))),
+            (Mbc::Other(_), 0x4000 ..= 0x5FFF) => Some(BankSelect::Sram(value as u16)),
+
+            _ => None,
+        }
+    }
+
+    pub fn mapped_rom_bank(&self, bank: u16) -> u16
+    {
+        // the bank $4000-$7FFF really shows when the given one is selected (MBC1 maps $20, $40 and $60 to the next one)
+
+        match self
+        {
+            Mbc::Mbc1 if (bank & 0x1F) == 0 => bank | 1,
+            _ => bank,
+        }
+    }
+
+    pub fn describe_write(&self, addr: u16, value: Option<u8>) -> Option<String>
+    {
+        // what writing to rom does, None when it does nothing (not a register)
+
+        let enable = |what: &str| match value
+        {
+            Some(value) if (value & 0x0F) == 0x0A => format!("enable {}", what),
+            Some(_) => format!("disable {}", what),
+            None => format!("enable/disable {}", what),
+        };
+
+        let bank = |what: &str| match value
+        {
+            Some(value) => format!("switch {} bank to ${:02X}", what, value),
+            None => format!("switch {} bank", what),
+        };
+
+        let description = match (self, addr)
+        {
+            (Mbc::RomOnly, _) => return None,
+
+            (Mbc::Mbc1, 0x0000 ..= 0x1FFF) => enable("SRAM"),
+            (Mbc::Mbc1, 0x2000 ..= 0x3FFF) => bank("ROM"),
+            (Mbc::Mbc1, 0x4000 ..= 0x5FFF) => bank("SRAM (or upper ROM)"),
+            (Mbc::Mbc1, 0x6000 ..= 0x7FFF) => "select banking mode".to_string(),
+
+            // address bit 8 selects the register
+            (Mbc::Mbc2, 0x0000 ..= 0x3FFF) if (addr & 0x0100) == 0 => enable("SRAM"),
+            (Mbc::Mbc2, 0x0000 ..= 0x3FFF) => bank("ROM"),
+
+            (Mbc::Mbc3, 0x0000 ..= 0x1FFF) => enable("SRAM and RTC"),
+            (Mbc::Mbc3, 0x2000 ..= 0x3FFF) => bank("ROM"),
+
+            (Mbc::Mbc3, 0x4000 ..= 0x5FFF) => match value
+            {
+                Some(register @ 0x08 ..= 0x0C) => format!("select RTC register ${:02X}", register),
+                Some(_) => bank("SRAM"),
+                None => "switch SRAM bank or select RTC register".to_string(),
+            },
+
+            (Mbc::Mbc3, 0x6000 ..= 0x7FFF) => "latch RTC".to_string(),
+
+            (Mbc::Mbc5, 0x0000 ..= 0x1FFF) => enable("SRAM"),
+            (Mbc::Mbc5, 0x2000 ..= 0x2FFF) => bank("ROM"),
+            (Mbc::Mbc5, 0x3000 ..= 0x3FFF) => "set ROM bank bit 8".to_string(),
+            (Mbc::Mbc5, 0x4000 ..= 0x5FFF) => bank("SRAM"),
+
+            // HuC-1 maps its infrared port instead of sram at $A000-$BFFF with $0E
+            (Mbc::Huc1, 0x0000 ..= 0x1FFF) => match value
+            {
+                Some(0x0E) => "map IR port".to_string(),
+                Some(_) => "map SRAM".to_string(),
+                None => "map SRAM or IR port".to_string(),
+            },
+
+            (Mbc::Huc1, 0x2000 ..= 0x3FFF) => bank("ROM"),
+            (Mbc::Huc1, 0x4000 ..= 0x5FFF) => bank("SRAM"),
+
+            // HuC-3 selects what $A000-$BFFF maps: sram, the rtc command registers or the infrared port
+            (Mbc::Huc3, 0x0000 ..= 0x1FFF) => match value
+            {
+                Some(0x00) => "map SRAM (read only)".to_string(),
+                Some(0x0A) => "map SRAM".to_string(),
+                Some(0x0B) => "map RTC command".to_string(),
+                Some(0x0C) => "map RTC response".to_string(),
+                Some(0x0D) => "map RTC semaphore".to_string(),
+                Some(0x0E) => "map IR port".to_string(),
+                Some(value) => format!("map ${:02X} (unknown)", value),
+                None => "map SRAM, RTC or IR port".to_string(),
+            },
+
+            (Mbc::Huc3, 0x2000 ..= 0x3FFF) => bank("ROM"),
+            (Mbc::Huc3, 0x4000 ..= 0x5FFF) => bank("SRAM"),
+
+            (Mbc::Other(_), 0x0000 ..= 0x7FFF) => "MBC register write".to_string(),
+
+            _ => return None,
+        };
+
+        Some(description)
+    }
+}
+
+impl std::fmt::Display for Mbc
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        match self
+        {
+            Mbc::RomOnly => write!(f, "ROM only"),
+            Mbc::Mbc1 => write!(f, "MBC1"),
+            Mbc::Mbc2 => write!(f, "MBC2"),
+            Mbc::Mbc3 => write!(f, "MBC3"),
+            Mbc::Mbc5 => write!(f, "MBC5"),
+            Mbc::Huc1 => write!(f, "HuC-1"),
+            Mbc::Huc3 => write!(f, "HuC-3"),
+            Mbc::Other(cartridge_type) => write!(f, "cartridge type ${:02X}", cartridge_type),
+        }
+    }
+}
@@ -0,0 +1,95 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::io::BufRead;
+use thiserror::Error;
+
+// hardware mapped past the stock memory map (flash cart commands, rumble motors, extra i/o registers),
+// which doesn't follow the selected banks
+#[derive(Clone, Debug)]
+pub struct Region
+{
+    pub beg: u16,
+    pub end: u16, // included
+    pub name: String,
+}
+
+impl Region
+{
+    pub fn contains(&self, addr: u16) -> bool
+    {
+        addr >= self.beg && addr <= self.end
+    }
+
+    pub fn addr_name(&self, addr: u16) -> String
+    {
+        match self.beg == self.end
+        {
+            true => self.name.clone(),
+            false => format!("{}_{:04X}", self.name, addr),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ParseMemoryMapError
+{
+    #[error("IO error")]
+    Io(#[from] std::io::Error),
+
+    #[error("Parse Int error")]
+    ParseInt(#[from] std::num::ParseIntError),
+
+    #[error("Invalid address range `{0}`")]
+    InvalidRange(String),
+
+    #[error("Missing name for region `{0}`")]
+    MissingName(String),
+}
+
+pub fn find_region(regions: &[Region], addr: u16) -> Option<&Region>
+{
+    regions.iter().find(|region| region.contains(addr))
+}
+
+pub fn parse_memory_map<R>(read: &mut R) -> Result<Vec<Region>, ParseMemoryMapError>
+    where R: BufRead
+{
+    // one region per line: `FF70-FF7F Flash` (or a single address), earlier lines win where they overlap
+
+    let mut result = vec![];
+
+    for line in read.lines()
+    {
+        let line = line?;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with(';') {
+            continue; }
+
+        let mut split = line.split_whitespace();
+        let str_range = split.next().unwrap();
+
+        let (beg, end) = match str_range.split_once('-')
+        {
+            Some((str_beg, str_end)) => (u16::from_str_radix(str_beg, 16)?, u16::from_str_radix(str_end, 16)?),
+            None => { let addr = u16::from_str_radix(str_range, 16)?; (addr, addr) }
+        };
+
+        if end < beg {
+            return Err(ParseMemoryMapError::InvalidRange(str_range.to_string())); }
+
+        let name = match split.next()
+        {
+            Some(name) => name.to_string(),
+            None => return Err(ParseMemoryMapError::MissingName(str_range.to_string())),
+        };
+
+        result.push(Region { beg: beg, end: end, name: name });
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,67 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::header;
+
+// MMM01 multicarts: the menu is the last 32KiB of the file (mapped first on boot),
+// and each game is a complete sub-image, with its own header, somewhere before it
+
+const MENU_LEN: usize = 0x8000;
+
+// sub-images start on 32KiB boundaries
+const IMAGE_ALIGN: usize = 0x8000;
+
+#[derive(Clone, Debug)]
+pub struct SubImage
+{
+    pub offset: usize, // in the file
+    pub len: usize,
+    pub title: String,
+}
+
+fn is_header(image: &[u8]) -> bool
+{
+    image.len() >= header::HEADER_END && header::header_checksum(image) == image[0x14D]
+}
+
+pub fn is_mmm01(rom: &[u8]) -> bool
+{
+    rom.len() > MENU_LEN && matches!(rom[rom.len() - MENU_LEN + 0x147], 0x0B ..= 0x0D) && is_header(&rom[rom.len() - MENU_LEN ..])
+}
+
+pub fn menu_offset(rom: &[u8]) -> usize
+{
+    rom.len() - MENU_LEN
+}
+
+pub fn find_sub_images(rom: &[u8]) -> Vec<SubImage>
+{
+    // any valid header on a boundary before the menu starts a game, as long as the header's rom size says
+
+    let end = menu_offset(rom);
+    let mut result = vec![];
+    let mut offset = 0;
+
+    while offset < end
+    {
+        let image = &rom[offset .. end];
+
+        if !is_header(image)
+        {
+            offset += IMAGE_ALIGN;
+            continue;
+        }
+
+        let len = std::cmp::min(0x8000 << std::cmp::min(image[0x148], 8), image.len());
+        let title = image[0x134 .. 0x144].iter().take_while(|byte| **byte != 0).map(|byte| *byte as char).collect::<String>();
+
+        result.push(SubImage { offset: offset, len: len, title: title.trim().to_string() });
+
+        offset += len.next_multiple_of(IMAGE_ALIGN);
+    }
+
+    result
+}
@@ -0,0 +1,106 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::io::BufRead;
+use thiserror::Error;
+
+use super::xaddr::prelude::*;
+
+#[derive(Error, Debug)]
+pub enum NocashError
+{
+    #[error("IO error")]
+    Io(#[from] std::io::Error),
+
+    #[error("Parse Int error")]
+    ParseInt(#[from] std::num::ParseIntError),
+
+    #[error("Missing label in symbol file line `{0}`")]
+    MissingLabel(String),
+}
+
+pub enum Symbol
+{
+    Label(XAddr, String),
+    Data(XAddr), // from a `.byt`, `.wrd`, `.dbl` or `.asc` directive
+}
+
+const DATA_DIRECTIVES: [&str; 4] = [".byt", ".wrd", ".dbl", ".asc"];
+
+fn local_name(scope: &str, local: &str, xa: XAddr) -> String
+{
+    // as rgbds local labels; bare `@@` is anonymous and `@@1` wouldn't be a valid name
+
+    match local.chars().next()
+    {
+        None => format!("{}.anon_{:04X}", scope, xa.addr),
+        Some(c) if c.is_ascii_digit() => format!("{}._{}", scope, local),
+        Some(_) => format!("{}.{}", scope, local),
+    }
+}
+
+pub fn parse_sym<R>(read: &mut R) -> Result<Vec<Symbol>, NocashError>
+    where R: BufRead
+{
+    // the symbol files of no$gmb (and the other no$cash debuggers): `bank:addr Label` or `addr Label`, `;` comments
+    // - labels starting with `@@` are local to the label before them in the same bank
+    // - `.byt:len`, `.wrd:len`, `.dbl:len` and `.asc:len` mark data, other directives (`.code`...) are skipped
+
+    use log::warn;
+
+    let mut entries: Vec<(XAddr, String)> = vec![];
+
+    for line in read.lines()
+    {
+        let line = line?;
+        let line = line.split(';').next().unwrap_or("").trim();
+
+        if line.is_empty() {
+            continue; }
+
+        let (str_addr, label) = match line.split_once(char::is_whitespace)
+        {
+            Some((str_addr, label)) => (str_addr, label.trim()),
+            None => return Err(NocashError::MissingLabel(line.to_string())),
+        };
+
+        entries.push((str_addr.parse()?, label.to_string()));
+    }
+
+    // locals go with the closest label before them, which files usually (but not always) list first
+    entries.sort_by_key(|(xa, _)| *xa);
+
+    let mut result = vec![];
+    let mut scope: Option<(u16, String)> = None;
+
+    for (xa, label) in entries
+    {
+        if label.starts_with('.')
+        {
+            if DATA_DIRECTIVES.iter().any(|directive| label.split(':').next() == Some(*directive)) {
+                result.push(Symbol::Data(xa)); }
+
+            continue;
+        }
+
+        match label.strip_prefix("@@")
+        {
+            Some(local) => match &scope
+            {
+                Some((bank, global)) if *bank == xa.bank => result.push(Symbol::Label(xa, local_name(global, local, xa))),
+                _ => warn!("local label `{}` at {} has no label before it, skipping it", label, xa),
+            },
+
+            None =>
+            {
+                scope = Some((xa.bank, label.clone()));
+                result.push(Symbol::Label(xa, label));
+            }
+        }
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,169 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use thiserror::Error;
+
+use super::util;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const IPS_MAX_OFFSET: usize = 0xFFFFFF;
+const IPS_MAX_RECORD: usize = 0xFFFF;
+
+// a record there would read as the end of the patch
+const IPS_EOF_OFFSET: usize = 0x454F46;
+
+// runs of unchanged bytes shorter than a record header are cheaper to repeat than to skip
+const IPS_MIN_GAP: usize = 6;
+
+// runs of the same byte at least that long get a run-length record
+const IPS_MIN_RLE: usize = 8;
+
+const BPS_MAGIC: &[u8] = b"BPS1";
+
+const BPS_SOURCE_READ: usize = 0;
+const BPS_TARGET_READ: usize = 1;
+
+#[derive(Error, Debug)]
+pub enum PatchError
+{
+    #[error("IPS patches can't reach past 16MiB")]
+    TooLarge,
+}
+
+fn diff_runs(old: &[u8], new: &[u8], min_gap: usize) -> Vec<(usize, usize)>
+{
+    // (offset, len) of what differs in new, close runs merged
+
+    let mut result: Vec<(usize, usize)> = vec![];
+
+    for (i, byte) in new.iter().enumerate()
+    {
+        if old.get(i) == Some(byte) {
+            continue; }
+
+        match result.last_mut()
+        {
+            Some((beg, len)) if i - (*beg + *len) < min_gap => *len = i + 1 - *beg,
+            _ => result.push((i, 1)),
+        }
+    }
+
+    result
+}
+
+pub fn make_ips(old: &[u8], new: &[u8]) -> Result<Vec<u8>, PatchError>
+{
+    let mut result = IPS_MAGIC.to_vec();
+
+    let mut push_record = |offset: usize, data: &[u8]|
+    {
+        result.extend_from_slice(&(offset as u32).to_be_bytes()[1 ..]);
+
+        match data.len() >= IPS_MIN_RLE && data.iter().all(|byte| *byte == data[0])
+        {
+            true =>
+            {
+                result.extend_from_slice(&[0, 0]);
+                result.extend_from_slice(&(data.len() as u16).to_be_bytes());
+                result.push(data[0]);
+            }
+
+            false =>
+            {
+                result.extend_from_slice(&(data.len() as u16).to_be_bytes());
+                result.extend_from_slice(data);
+            }
+        }
+    };
+
+    for (beg, len) in diff_runs(old, new, IPS_MIN_GAP)
+    {
+        // starting a byte early is enough to keep clear of the offset reading as `EOF`
+        let (beg, len) = if beg == IPS_EOF_OFFSET { (beg - 1, len + 1) } else { (beg, len) };
+
+        if beg + len > IPS_MAX_OFFSET {
+            return Err(PatchError::TooLarge); }
+
+        let mut offset = beg;
+
+        while offset < beg + len
+        {
+            let mut chunk = std::cmp::min(beg + len - offset, IPS_MAX_RECORD);
+
+            if offset + chunk == IPS_EOF_OFFSET && offset + chunk < beg + len {
+                chunk -= 1; }
+
+            push_record(offset, &new[offset .. offset + chunk]);
+            offset += chunk;
+        }
+    }
+
+    result.extend_from_slice(IPS_EOF);
+
+    // truncation extension, for patches making the file shorter
+    if new.len() < old.len() {
+        result.extend_from_slice(&(new.len() as u32).to_be_bytes()[1 ..]); }
+
+    Ok(result)
+}
+
+fn push_bps_number(out: &mut Vec<u8>, value: usize)
+{
+    // 7 bits at a time, the last byte flagged, each continuation counting from one past the previous range
+
+    let mut value = value;
+
+    loop
+    {
+        let bits = (value & 0x7F) as u8;
+        value >>= 7;
+
+        if value == 0
+        {
+            out.push(0x80 | bits);
+            break;
+        }
+
+        out.push(bits);
+        value -= 1;
+    }
+}
+
+pub fn make_bps(old: &[u8], new: &[u8]) -> Vec<u8>
+{
+    // bytes where the source has the same ones are read from it, the rest is in the patch
+
+    let mut result = BPS_MAGIC.to_vec();
+
+    push_bps_number(&mut result, old.len());
+    push_bps_number(&mut result, new.len());
+    push_bps_number(&mut result, 0); // no metadata
+
+    let mut offset = 0;
+
+    for (beg, len) in diff_runs(old, new, 1)
+    {
+        if beg > offset {
+            push_bps_number(&mut result, ((beg - offset - 1) << 2) | BPS_SOURCE_READ); }
+
+        push_bps_number(&mut result, ((len - 1) << 2) | BPS_TARGET_READ);
+        result.extend_from_slice(&new[beg .. beg + len]);
+
+        offset = beg + len;
+    }
+
+    if new.len() > offset {
+        push_bps_number(&mut result, ((new.len() - offset - 1) << 2) | BPS_SOURCE_READ); }
+
+    result.extend_from_slice(&util::crc32(old).to_le_bytes());
+    result.extend_from_slice(&util::crc32(new).to_le_bytes());
+
+    let patch_crc = util::crc32(&result);
+    result.extend_from_slice(&patch_crc.to_le_bytes());
+
+    result
+}
@@ -0,0 +1,136 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::xaddr::prelude::*;
+use super::gbasm;
+use super::anal::{self, AnalInfo};
+use super::func::{Function, FlowGraph};
+
+use std::collections::{BTreeMap, HashMap};
+
+// blocks scoring this much look like data decoded as code
+const SUSPICIOUS_SCORE: u32 = 3;
+
+// repeats of an instruction doing nothing (`nop`, `ld b, b`...) starting to look odd
+const MIN_NOOP_RUN: usize = 3;
+
+// functions with at least this many control flow instructions are checked for having too few loads
+const MIN_CONTROL_FLOW: usize = 4;
+
+const OPCODE_NOP: u8 = 0x00;
+const OPCODE_RST_MASK: u8 = 0xC7;
+const OPCODE_RST: u8 = 0xC7;
+
+// a block that doesn't look like code, and why
+#[derive(Clone, Debug)]
+pub struct Suspicion
+{
+    pub xa: XAddr,
+    pub reasons: Vec<String>,
+}
+
+fn is_noop(opcode: u8) -> bool
+{
+    // `nop` and the `ld r, r` moving a register onto itself
+    matches!(opcode, OPCODE_NOP | 0x40 | 0x49 | 0x52 | 0x5B | 0x64 | 0x6D | 0x7F)
+}
+
+pub fn find_suspicious_blocks(info: &AnalInfo, graph: &FlowGraph, functions: &[Function]) -> Vec<Suspicion>
+{
+    // adds up signs of misdecoded data for each block:
+    // - runs of instructions that do nothing
+    // - `rst` to vectors that hold no code
+    // - jumps into the middle of another instruction
+    // - functions made mostly of control flow, with hardly any loads (data is full of `ret cc` and `jr`)
+
+    let mut starts: BTreeMap<XAddr, usize> = BTreeMap::new();
+
+    for i in 0 .. graph.block_count()
+    {
+        let mut emu = graph.emu(i);
+
+        while let Some((xa, Ok(ins))) = emu.next() {
+            starts.insert(xa, ins.encoded_len()); }
+    }
+
+    let mid_instruction = |target: XAddr| match starts.range(XAddr::new(target.bank, 0) .. target).next_back()
+    {
+        Some((xa, len)) => xa.bank == target.bank && target.addr < xa.addr + *len as u16,
+        None => false,
+    };
+
+    let mut scores: HashMap<usize, (u32, Vec<String>)> = HashMap::new();
+
+    for i in 0 .. graph.block_count()
+    {
+        let mut emu = graph.emu(i);
+        let mut run = (0, 0);
+
+        let mut add = |points: u32, reason: String|
+        {
+            let score = scores.entry(i).or_default();
+            score.0 += points;
+            score.1.push(reason);
+        };
+
+        while let Some((xa, Ok(ins))) = emu.next()
+        {
+            // runs of nothing
+
+            run = if is_noop(ins.opcode) && run.0 == ins.opcode { (ins.opcode, run.1 + 1) } else { (ins.opcode, 1) };
+
+            if is_noop(ins.opcode) && run.1 == MIN_NOOP_RUN {
+                add(2, format!("run of `{}`", ins.info().fmt)); }
+
+            // rst to nowhere
+
+            if (ins.opcode & OPCODE_RST_MASK) == OPCODE_RST && !anal::is_plausible_vector(info, (ins.opcode & !OPCODE_RST_MASK) as u16) {
+                add(3, format!("`{}` to an unused vector", ins.info().fmt)); }
+
+            // overlapping instructions
+
+            if (ins.info().flags & gbasm::OPCODE_FLAG_JUMP) != 0
+            {
+                if let Some(target) = ins.get_jump_target().and_then(|addr| emu.expand_target(xa, addr)).filter(|target| mid_instruction(*target)) {
+                    add(3, format!("jumps into the middle of an instruction at {}", target)); }
+            }
+        }
+    }
+
+    for function in functions
+    {
+        let (mut loads, mut control_flow) = (0, 0);
+
+        for &i in &function.blocks
+        {
+            let mut emu = graph.emu(i);
+
+            while let Some((_, Ok(ins))) = emu.next()
+            {
+                if (ins.info().flags & gbasm::OPCODE_FLAG_JUMP) != 0 {
+                    control_flow += 1; }
+                else if ins.info().fmt.starts_with("ld") {
+                    loads += 1; }
+            }
+        }
+
+        if control_flow >= MIN_CONTROL_FLOW && loads < control_flow
+        {
+            let score = scores.entry(function.blocks[0]).or_default();
+            score.0 += 3;
+            score.1.push(format!("{} loads for {} jumps, calls and returns", loads, control_flow));
+        }
+    }
+
+    let mut result: Vec<Suspicion> = scores.into_iter()
+        .filter(|(_, (score, _))| *score >= SUSPICIOUS_SCORE)
+        .map(|(i, (_, reasons))| Suspicion { xa: graph.block(i).xa, reasons: reasons })
+        .collect();
+
+    result.sort_by_key(|suspicion| suspicion.xa);
+
+    result
+}
@@ -0,0 +1,68 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::io::{IsTerminal, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// a status line on stderr, written over in place; like logging, it is global so any pass can report
+
+const REFRESH: Duration = Duration::from_millis(100);
+
+struct State
+{
+    last: Option<Instant>,
+    shown: bool,
+}
+
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+pub fn enable()
+{
+    // only for a terminal, a redirected stderr would get every update on one line
+
+    if std::io::stderr().is_terminal() {
+        *STATE.lock().unwrap() = Some(State { last: None, shown: false }); }
+}
+
+pub fn status<F>(text: F)
+    where F: FnOnce() -> String
+{
+    // text is only made when shown, at most every REFRESH
+
+    let mut state = STATE.lock().unwrap();
+
+    let state = match state.as_mut()
+    {
+        Some(state) => state,
+        None => return,
+    };
+
+    if state.last.is_some_and(|last| last.elapsed() < REFRESH) {
+        return; }
+
+    let mut stderr = std::io::stderr().lock();
+    let _ = write!(stderr, "\r\x1B[K{}", text());
+    let _ = stderr.flush();
+
+    state.last = Some(Instant::now());
+    state.shown = true;
+}
+
+pub fn clear()
+{
+    // before anything else is written to stderr, and once done
+
+    let mut state = STATE.lock().unwrap();
+
+    if let Some(state) = state.as_mut().filter(|state| state.shown)
+    {
+        let _ = write!(std::io::stderr(), "\r\x1B[K");
+
+        state.last = None;
+        state.shown = false;
+    }
+}
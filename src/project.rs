@@ -0,0 +1,102 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::syntax;
+
+pub const PROJECT_FILENAME: &str = "bub.toml";
+
+// what would otherwise be on the command line, for a directory holding a disassembly
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Project
+{
+    pub rom: Option<PathBuf>,
+
+    #[serde(default)]
+    pub tags: Vec<PathBuf>,
+
+    pub output: Option<PathBuf>, // directory the listing is written to
+    pub cache: Option<PathBuf>,  // directory analysis results are kept in
+    pub syntax: Option<String>,
+
+    pub big_rom: Option<bool>,
+    pub cgb_ram: Option<bool>,
+    pub sram_count: Option<usize>,
+    pub stop_padding: Option<bool>,
+
+    // revisions or localizations of the same game, sharing the tags above
+    #[serde(default)]
+    pub revisions: Vec<Revision>,
+}
+
+// tags of a revision are read before those of the project, so they can name what these refer to
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Revision
+{
+    pub name: String,
+    pub rom: PathBuf,
+
+    #[serde(default)]
+    pub tags: Vec<PathBuf>,
+}
+
+#[derive(Error, Debug)]
+pub enum ProjectError
+{
+    #[error("IO error")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid project file: {0}")]
+    Toml(#[from] toml::de::Error),
+
+    #[error("Invalid project file: {0}")]
+    Syntax(#[from] syntax::ParseSyntaxError),
+}
+
+impl Project
+{
+    pub fn syntax(&self) -> Result<Option<syntax::Syntax>, ProjectError>
+    {
+        Ok(match &self.syntax
+        {
+            Some(name) => Some(name.parse()?),
+            None => None,
+        })
+    }
+}
+
+pub fn read_project(filename: &Path) -> Result<Project, ProjectError>
+{
+    // paths are relative to the directory of the project file, not to where bub is run from
+
+    let mut project: Project = toml::from_str(&std::fs::read_to_string(filename)?)?;
+    let dir = filename.parent().unwrap_or(Path::new(""));
+
+    project.rom = project.rom.map(|rom| dir.join(rom));
+    project.output = project.output.map(|output| dir.join(output));
+    project.cache = project.cache.map(|cache| dir.join(cache));
+
+    for tags in &mut project.tags {
+        *tags = dir.join(&tags); }
+
+    for revision in &mut project.revisions
+    {
+        revision.rom = dir.join(&revision.rom);
+
+        for tags in &mut revision.tags {
+            *tags = dir.join(&tags); }
+    }
+
+    // checked now rather than when the listing is about to be written
+    project.syntax()?;
+
+    Ok(project)
+}
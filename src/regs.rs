@@ -0,0 +1,427 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::gbasm;
+
+// register indices as encoded in opcodes (6 is [hl])
+pub const REG_B: u8 = 0;
+pub const REG_C: u8 = 1;
+pub const REG_D: u8 = 2;
+pub const REG_E: u8 = 3;
+pub const REG_H: u8 = 4;
+pub const REG_L: u8 = 5;
+pub const REG_HL_MEM: u8 = 6;
+pub const REG_A: u8 = 7;
+
+// set of registers, one bit per register index
+pub type RegMask = u8;
+
+pub const MASK_A: RegMask = 1 << REG_A;
+pub const MASK_BC: RegMask = 1 << REG_B | 1 << REG_C;
+pub const MASK_DE: RegMask = 1 << REG_D | 1 << REG_E;
+pub const MASK_HL: RegMask = 1 << REG_H | 1 << REG_L;
+
+pub fn mask_names(mask: RegMask) -> Vec<&'static str>
+{
+    // pairs are named as pairs when both halves are in the set
+
+    let mut result = vec![];
+
+    if (mask & MASK_A) != 0 {
+        result.push("a"); }
+
+    for &(pair, name, hi, lo) in &[(MASK_BC, "bc", REG_B, REG_C), (MASK_DE, "de", REG_D, REG_E), (MASK_HL, "hl", REG_H, REG_L)]
+    {
+        if (mask & pair) == pair {
+            result.push(name); }
+        else if (mask & 1 << hi) != 0 {
+            result.push(&name[0 .. 1]); }
+        else if (mask & 1 << lo) != 0 {
+            result.push(&name[1 .. 2]); }
+    }
+
+    result
+}
+
+fn reg_mask(reg: u8) -> RegMask
+{
+    // [hl] as an operand reads hl
+
+    if reg == REG_HL_MEM { MASK_HL } else { 1 << reg }
+}
+
+pub fn reg_effects(ins: &gbasm::Instruction) -> (RegMask, RegMask)
+{
+    // registers read and written by an instruction (flags and sp aside, calls read and write nothing here)
+
+    let op = ins.opcode;
+    let pair = |index: u8| [MASK_BC, MASK_DE, MASK_HL, 0][(index & 0x03) as usize];
+    let dst = (op >> 3) & 0x07;
+    let src = op & 0x07;
+
+    match op
+    {
+        OPCODE_BITOPS =>
+        {
+            let reg = (ins.operand & 0x07) as u8;
+
+            match ins.operand
+            {
+                0x40 ..= 0x7F => (reg_mask(reg), 0),
+                _ if reg == REG_HL_MEM => (MASK_HL, 0),
+                _ => (reg_mask(reg), reg_mask(reg)),
+            }
+        }
+
+        0x01 | 0x11 | 0x21 => (0, pair(op >> 4)),
+        0x02 => (MASK_BC | MASK_A, 0),
+        0x12 => (MASK_DE | MASK_A, 0),
+        0x22 | 0x32 => (MASK_HL | MASK_A, MASK_HL),
+        0x03 | 0x13 | 0x23 | 0x0B | 0x1B | 0x2B => (pair(op >> 4), pair(op >> 4)),
+        0x34 ..= 0x36 => (MASK_HL, 0),
+        0x04 | 0x0C | 0x14 | 0x1C | 0x24 | 0x2C | 0x3C | 0x05 | 0x0D | 0x15 | 0x1D | 0x25 | 0x2D | 0x3D => (reg_mask(dst), reg_mask(dst)),
+        0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x3E => (0, reg_mask(dst)),
+        0x07 | 0x0F | 0x17 | 0x1F | 0x27 | OPCODE_CPL => (MASK_A, MASK_A),
+        0x09 | 0x19 | 0x29 | 0x39 => (MASK_HL | pair(op >> 4), MASK_HL),
+        0x0A => (MASK_BC, MASK_A),
+        0x1A => (MASK_DE, MASK_A),
+        0x2A | 0x3A => (MASK_HL, MASK_A | MASK_HL),
+
+        0x76 => (0, 0),
+        0x40 ..= 0x7F if dst == REG_HL_MEM => (MASK_HL | reg_mask(src), 0),
+        0x40 ..= 0x7F => (reg_mask(src), reg_mask(dst)),
+
+        // sub a / xor a only zero a
+        0x97 | 0xAF => (0, MASK_A),
+        0xB8 ..= 0xBF => (MASK_A | reg_mask(src), 0),
+        0x80 ..= 0xB7 => (MASK_A | reg_mask(src), MASK_A),
+        0xFE => (MASK_A, 0),
+        0xC6 | 0xCE | 0xD6 | 0xDE | 0xE6 | 0xEE | 0xF6 => (MASK_A, MASK_A),
+
+        0xC1 | 0xD1 | 0xE1 => (0, pair(op >> 4)),
+        OPCODE_POP_AF => (0, MASK_A),
+        0xC5 | 0xD5 | 0xE5 => (pair(op >> 4), 0),
+        OPCODE_PUSH_AF => (MASK_A, 0),
+
+        0xE0 | 0xEA => (MASK_A, 0),
+        0xE2 => (MASK_A | 1 << REG_C, 0),
+        0xF0 | 0xFA => (0, MASK_A),
+        0xF2 => (1 << REG_C, MASK_A),
+        OPCODE_JP_HL | 0xF9 => (MASK_HL, 0),
+        OPCODE_LD_HL_SP_OFS => (0, MASK_HL),
+
+        _ => (0, 0),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RegPair
+{
+    BC,
+    DE,
+    HL,
+}
+
+impl std::fmt::Display for RegPair
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        match self
+        {
+            RegPair::BC => write!(f, "bc"),
+            RegPair::DE => write!(f, "de"),
+            RegPair::HL => write!(f, "hl"),
+        }
+    }
+}
+
+impl RegPair
+{
+    fn regs(self) -> (u8, u8)
+    {
+        match self
+        {
+            RegPair::BC => (REG_B, REG_C),
+            RegPair::DE => (REG_D, REG_E),
+            RegPair::HL => (REG_H, REG_L),
+        }
+    }
+
+    fn from_encoding(index: u8) -> Option<RegPair>
+    {
+        // the rr field of `ld rr, nn`, `inc rr`, `add hl, rr`... (3 is sp)
+
+        match index
+        {
+            0 => Some(RegPair::BC),
+            1 => Some(RegPair::DE),
+            2 => Some(RegPair::HL),
+            _ => None,
+        }
+    }
+}
+
+const OPCODE_BITOPS: u8 = 0xCB;
+const OPCODE_PUSH_AF: u8 = 0xF5;
+const OPCODE_POP_AF: u8 = 0xF1;
+const OPCODE_LD_HL_SP_OFS: u8 = 0xF8;
+const OPCODE_CPL: u8 = 0x2F;
+const OPCODE_RET: u8 = 0xC9;
+const OPCODE_JP_HL: u8 = 0xE9;
+
+// constant values of registers, as far as they can be followed through straight-line code
+// (anything unknown or possibly changed by a call is None)
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct RegState
+{
+    regs: [Option<u8>; 8],
+    // known value pushed on top of the stack
+    stack_top: Option<u16>,
+}
+
+impl RegState
+{
+    pub fn get(&self, reg: u8) -> Option<u8>
+    {
+        // [hl] is never known
+        self.regs[reg as usize]
+    }
+
+    pub fn a(&self) -> Option<u8>
+    {
+        self.get(REG_A)
+    }
+
+    pub fn pair(&self, pair: RegPair) -> Option<u16>
+    {
+        let (hi, lo) = pair.regs();
+
+        match (self.get(hi), self.get(lo))
+        {
+            (Some(hi), Some(lo)) => Some((hi as u16) << 8 | lo as u16),
+            _ => None,
+        }
+    }
+
+    pub fn hl(&self) -> Option<u16>
+    {
+        self.pair(RegPair::HL)
+    }
+
+    pub fn stack_top(&self) -> Option<u16>
+    {
+        self.stack_top
+    }
+
+    fn set(&mut self, reg: u8, value: Option<u8>)
+    {
+        if reg != REG_HL_MEM {
+            self.regs[reg as usize] = value; }
+    }
+
+    fn set_pair(&mut self, pair: RegPair, value: Option<u16>)
+    {
+        let (hi, lo) = pair.regs();
+
+        self.set(hi, value.map(|v| (v >> 8) as u8));
+        self.set(lo, value.map(|v| v as u8));
+    }
+
+    fn offset_pair(&mut self, pair: RegPair, offset: u16)
+    {
+        self.set_pair(pair, self.pair(pair).map(|v| v.wrapping_add(offset)));
+    }
+
+    pub fn forget(&mut self)
+    {
+        *self = Self::default();
+    }
+
+    pub fn mem_write(&self, ins: &gbasm::Instruction) -> Option<(u16, u8)>
+    {
+        // address and value of a memory write, when both are known
+
+        match ins.opcode
+        {
+            0x02 => self.pair(RegPair::BC).zip(self.a()),
+            0x12 => self.pair(RegPair::DE).zip(self.a()),
+            0x22 | 0x32 | 0x77 => self.hl().zip(self.a()),
+            0x36 => self.hl().map(|addr| (addr, ins.operand as u8)),
+            0x70 ..= 0x75 => self.hl().zip(self.get(ins.opcode & 0x07)),
+            0xE0 | 0xEA => self.a().map(|value| (ins.operand, value)),
+            _ => None,
+        }
+    }
+
+    pub fn mem_write_addr(&self, ins: &gbasm::Instruction) -> Option<u16>
+    {
+        // address of a memory write, when known even if the value isn't
+
+        match ins.opcode
+        {
+            0x02 => self.pair(RegPair::BC),
+            0x12 => self.pair(RegPair::DE),
+            0x22 | 0x32 | 0x34 | 0x35 | 0x36 | 0x70 ..= 0x75 | 0x77 => self.hl(),
+            0xE0 | 0xEA => Some(ins.operand),
+            _ => None,
+        }
+    }
+
+    pub fn mem_pointer(&self, ins: &gbasm::Instruction) -> Option<(RegPair, u16)>
+    {
+        // address of a memory access through a register pair, when it is known
+
+        let pair = match ins.opcode
+        {
+            0x02 | 0x0A => RegPair::BC,
+            0x12 | 0x1A => RegPair::DE,
+            0x22 | 0x2A | 0x32 | 0x3A | 0x34 | 0x35 | 0x36 => RegPair::HL,
+            0x46 | 0x4E | 0x56 | 0x5E | 0x66 | 0x6E | 0x7E => RegPair::HL,
+            0x70 ..= 0x75 | 0x77 => RegPair::HL,
+            0x86 | 0x8E | 0x96 | 0x9E | 0xA6 | 0xAE | 0xB6 | 0xBE => RegPair::HL,
+            OPCODE_BITOPS if (ins.operand & 0x07) as u8 == REG_HL_MEM => RegPair::HL,
+            _ => return None,
+        };
+
+        self.pair(pair).map(|addr| (pair, addr))
+    }
+
+    pub fn computed_target(&self, ins: &gbasm::Instruction) -> Option<u16>
+    {
+        // target of a `jp hl` whose hl is known or of a `ret` returning to a known pushed address
+
+        match ins.opcode
+        {
+            OPCODE_JP_HL => self.hl(),
+            OPCODE_RET => self.stack_top,
+            _ => None,
+        }
+    }
+
+    pub fn step(&mut self, ins: &gbasm::Instruction)
+    {
+        // calls may change anything
+
+        if (ins.info().flags & gbasm::OPCODE_FLAG_CALL) != 0
+        {
+            self.forget();
+            return;
+        }
+
+        let op = ins.opcode;
+        let imm = ins.operand as u8;
+        let before = *self;
+
+        self.step_stack(ins, &before);
+
+        match op
+        {
+            OPCODE_BITOPS => self.step_bitop(ins.operand as u8),
+
+            // ld rr, nn
+            0x01 | 0x11 | 0x21 => self.set_pair(RegPair::from_encoding(op >> 4).unwrap(), Some(ins.operand)),
+
+            // inc rr / dec rr
+            0x03 | 0x13 | 0x23 => self.offset_pair(RegPair::from_encoding(op >> 4).unwrap(), 1),
+            0x0B | 0x1B | 0x2B => self.offset_pair(RegPair::from_encoding(op >> 4).unwrap(), 0xFFFF),
+
+            // inc r / dec r / ld r, n
+            0x04 | 0x0C | 0x14 | 0x1C | 0x24 | 0x2C | 0x3C => self.set(op >> 3, before.get(op >> 3).map(|v| v.wrapping_add(1))),
+            0x05 | 0x0D | 0x15 | 0x1D | 0x25 | 0x2D | 0x3D => self.set(op >> 3, before.get(op >> 3).map(|v| v.wrapping_sub(1))),
+            0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x3E => self.set(op >> 3, Some(imm)),
+
+            // add hl, rr
+            0x09 | 0x19 | 0x29 => self.set_pair(RegPair::HL, match (before.hl(), before.pair(RegPair::from_encoding(op >> 4).unwrap()))
+            {
+                (Some(hl), Some(rr)) => Some(hl.wrapping_add(rr)),
+                _ => None,
+            }),
+            0x39 | OPCODE_LD_HL_SP_OFS => self.set_pair(RegPair::HL, None),
+
+            // ld [hli], a / ld [hld], a / ld a, [hli] / ld a, [hld]
+            0x22 => self.offset_pair(RegPair::HL, 1),
+            0x32 => self.offset_pair(RegPair::HL, 0xFFFF),
+            0x2A => { self.set(REG_A, None); self.offset_pair(RegPair::HL, 1); }
+            0x3A => { self.set(REG_A, None); self.offset_pair(RegPair::HL, 0xFFFF); }
+
+            // loads into a from memory, rotates, daa
+            0x07 | 0x0A | 0x0F | 0x17 | 0x1A | 0x1F | 0x27 | 0xF0 | 0xF2 | 0xFA => self.set(REG_A, None),
+            OPCODE_CPL => self.set(REG_A, before.a().map(|v| !v)),
+
+            // halt is in the middle of the ld r, r' block
+            0x76 => {}
+
+            // ld r, r' / ld r, [hl]
+            0x40 ..= 0x7F =>
+            {
+                let (dst, src) = ((op >> 3) & 0x07, op & 0x07);
+                self.set(dst, before.get(src));
+            }
+
+            // sub a / xor a / alu a, r / alu a, n
+            0x97 | 0xAF => self.set(REG_A, Some(0)),
+            0x80 ..= 0xBF => self.set(REG_A, alu((op >> 3) & 0x07, before.a(), before.get(op & 0x07))),
+            0xC6 | 0xCE | 0xD6 | 0xDE | 0xE6 | 0xEE | 0xF6 | 0xFE => self.set(REG_A, alu((op >> 3) & 0x07, before.a(), Some(imm))),
+
+            _ => {}
+        }
+    }
+
+    fn step_stack(&mut self, ins: &gbasm::Instruction, before: &RegState)
+    {
+        // `ld hl, Target / push hl / ret` style jumps and `push hl / pop de` style copies
+
+        match ins.opcode
+        {
+            0xC5 | 0xD5 | 0xE5 => self.stack_top = before.pair(RegPair::from_encoding((ins.opcode >> 4) & 0x03).unwrap()),
+
+            0xC1 | 0xD1 | 0xE1 =>
+            {
+                self.set_pair(RegPair::from_encoding((ins.opcode >> 4) & 0x03).unwrap(), before.stack_top);
+                self.stack_top = None;
+            }
+
+            OPCODE_POP_AF => { self.set(REG_A, before.stack_top.map(|v| (v >> 8) as u8)); self.stack_top = None; }
+
+            // anything else changing sp, including conditional returns
+            OPCODE_PUSH_AF | 0x31 | 0x33 | 0x3B | 0xE8 | 0xF9 | 0xC0 | 0xC8 | 0xD0 | 0xD8 | 0xD9 => self.stack_top = None,
+
+            _ => {}
+        }
+    }
+
+    fn step_bitop(&mut self, op: u8)
+    {
+        let (reg, bit) = (op & 0x07, (op >> 3) & 0x07);
+
+        match op
+        {
+            // bit n, r
+            0x40 ..= 0x7F => {}
+            // res n, r / set n, r
+            0x80 ..= 0xBF => self.set(reg, self.get(reg).map(|v| v & !(1 << bit))),
+            0xC0 ..= 0xFF => self.set(reg, self.get(reg).map(|v| v | (1 << bit))),
+            // rotates, shifts, swap
+            _ => self.set(reg, None),
+        }
+    }
+}
+
+fn alu(op: u8, a: Option<u8>, value: Option<u8>) -> Option<u8>
+{
+    // cp leaves a alone, adc/sbc depend on the unknown carry
+
+    match (op, a, value)
+    {
+        (7, a, _) => a,
+        (0, Some(a), Some(v)) => Some(a.wrapping_add(v)),
+        (2, Some(a), Some(v)) => Some(a.wrapping_sub(v)),
+        (4, Some(a), Some(v)) => Some(a & v),
+        (5, Some(a), Some(v)) => Some(a ^ v),
+        (6, Some(a), Some(v)) => Some(a | v),
+        _ => None,
+    }
+}
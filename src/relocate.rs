@@ -0,0 +1,254 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::xaddr::prelude::*;
+use super::gbasm;
+use super::regs::{self, RegPair};
+use super::anal::AnalInfo;
+use super::func::FlowGraph;
+
+const OPCODE_LDH_N_A: u8 = 0xE0;
+const OPCODE_DEC_B: u8 = 0x05;
+const OPCODE_JR_NZ: u8 = 0x20;
+const OPCODE_RET: u8 = 0xC9;
+const OPCODE_RETI: u8 = 0xD9;
+
+const REG_DMA: u16 = 0xFF46;
+
+// instructions allowed around the wait loop of the oam dma routine (saving a, loading the source page)
+const MAX_DMA_EXTRA_INSTRUCTIONS: usize = 3;
+
+// code stored in ROM and copied to RAM (typically the OAM DMA routine copied to HRAM) to run there
+#[derive(Clone, Copy, Debug)]
+pub struct CopiedCode
+{
+    pub src: XAddr,
+    pub dest: u16,
+    pub len: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Pointer
+{
+    Pair(RegPair),
+    HighC, // [$FF00+c]
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Counter
+{
+    Reg(u8),
+    Pair(RegPair),
+}
+
+struct CopyLoop
+{
+    src: Pointer,
+    dest: Pointer,
+    counter: Counter,
+}
+
+fn match_copy_loop(graph: &FlowGraph, i: usize) -> Option<CopyLoop>
+{
+    // a block looping onto itself that reads a byte through one pointer, writes it through another
+    // and counts down with a `dec r / jr nz` (or `dec rr / ld a, r / or r' / jr nz`) tail
+
+    let block = graph.block(i);
+    let mut emu = graph.emu(i);
+
+    let mut src = None;
+    let mut dest = None;
+    let mut counter = None;
+    let mut loops = false;
+
+    while let Some((xa, Ok(ins))) = emu.next()
+    {
+        match ins.opcode
+        {
+            0x2A | 0x3A => src = Some(Pointer::Pair(RegPair::HL)),
+            0x1A => src = Some(Pointer::Pair(RegPair::DE)),
+            0x0A => src = Some(Pointer::Pair(RegPair::BC)),
+
+            0x12 => dest = Some(Pointer::Pair(RegPair::DE)),
+            0x22 | 0x32 | 0x77 => dest = Some(Pointer::Pair(RegPair::HL)),
+            0xE2 => dest = Some(Pointer::HighC),
+
+            0x05 | 0x0D | 0x15 | 0x1D => counter = Some(Counter::Reg(ins.opcode >> 3)),
+            0x0B => counter = Some(Counter::Pair(RegPair::BC)),
+            0x1B => counter = Some(Counter::Pair(RegPair::DE)),
+
+            _ => {}
+        }
+
+        if (ins.info().flags & gbasm::OPCODE_FLAG_JUMP) != 0
+        {
+            loops = (ins.info().flags & gbasm::OPCODE_FLAG_CONDITIONAL) != 0
+                && ins.get_jump_target().and_then(|addr| emu.expand_target(xa, addr)) == Some(block.xa);
+        }
+    }
+
+    match (loops, src, dest, counter)
+    {
+        (true, Some(src), Some(dest), Some(counter)) if src != dest => Some(CopyLoop { src: src, dest: dest, counter: counter }),
+        _ => None,
+    }
+}
+
+fn pointer_value(state: &regs::RegState, pointer: Pointer) -> Option<u16>
+{
+    match pointer
+    {
+        Pointer::Pair(pair) => state.pair(pair),
+        Pointer::HighC => state.get(regs::REG_C).map(|c| 0xFF00 | c as u16),
+    }
+}
+
+fn counter_value(state: &regs::RegState, counter: Counter) -> Option<usize>
+{
+    // counting down from 0 goes all the way around
+
+    match counter
+    {
+        Counter::Reg(reg) => state.get(reg).map(|n| if n == 0 { 0x100 } else { n as usize }),
+        Counter::Pair(pair) => state.pair(pair).map(|n| if n == 0 { 0x10000 } else { n as usize }),
+    }
+}
+
+pub fn find_copied_code(graph: &FlowGraph) -> Vec<CopiedCode>
+{
+    // copy loops are entered by falling through from the block setting up their registers
+
+    use std::collections::HashMap;
+
+    // blocks by where they end, the first one if several do
+    let mut ending_at = HashMap::new();
+    for j in 0 .. graph.block_count() {
+        ending_at.entry(graph.block(j).xa + graph.block(j).len as u16).or_insert(j); }
+
+    let mut result = vec![];
+
+    for i in 0 .. graph.block_count()
+    {
+        let copy = match match_copy_loop(graph, i)
+        {
+            Some(copy) => copy,
+            None => continue,
+        };
+
+        let loop_xa = graph.block(i).xa;
+
+        let setup = match ending_at.get(&loop_xa)
+        {
+            Some(&j) => j,
+            None => continue,
+        };
+
+        let mut emu = graph.emu(setup);
+        while emu.next().is_some() {}
+
+        // register values on entering the loop
+        let state = *emu.regs();
+
+        let (src, dest, len) = match (pointer_value(&state, copy.src), pointer_value(&state, copy.dest), counter_value(&state, copy.counter))
+        {
+            (Some(src), Some(dest), Some(len)) => (src, dest, len),
+            _ => continue,
+        };
+
+        // wram or hram, all of the copy fitting there (a counter of 0 runs $10000 times)
+        let region_end = match dest
+        {
+            0xC000 ..= 0xDFFF => 0xE000,
+            0xFF80 ..= 0xFFFE => 0xFFFF,
+            _ => continue,
+        };
+
+        if src >= 0x8000 || dest as usize + len > region_end {
+            continue; }
+
+        if let Some(src) = emu.expand_addr(src) {
+            result.push(CopiedCode { src: src, dest: dest, len: len }); }
+    }
+
+    result.sort_by_key(|copy| copy.src);
+    result.dedup_by_key(|copy| copy.src);
+
+    result
+}
+
+pub fn oam_dma_len(info: &AnalInfo, src: XAddr) -> Option<usize>
+{
+    // `ldh [rDMA], a / ld r, n / dec r / jr nz, @-1 / ret`, the canonical routine starting oam dma
+    // and waiting the 160 cycles it takes from hram, with a few instructions allowed before and after the loop
+
+    let slice = info.rom_slice(src, 0x20).or_else(|_| info.rom_slice(src, 0x10)).ok()?;
+    let instructions: Vec<(u16, gbasm::Instruction)> = gbasm::decode_slice(src.addr, slice)
+        .map_while(|(addr, ins)| ins.ok().map(|ins| (addr, ins)))
+        .take(5 + 2 * MAX_DMA_EXTRA_INSTRUCTIONS)
+        .collect();
+
+    let is_jump = |ins: &gbasm::Instruction| (ins.info().flags & gbasm::OPCODE_FLAG_JUMP) != 0;
+
+    let start = instructions.iter().take(MAX_DMA_EXTRA_INSTRUCTIONS + 1)
+        .position(|(_, ins)| ins.opcode == OPCODE_LDH_N_A && ins.operand == REG_DMA)?;
+
+    if instructions[.. start].iter().any(|(_, ins)| is_jump(ins)) {
+        return None; }
+
+    let (_, load) = instructions.get(start + 1)?;
+    let (dec_addr, dec) = instructions.get(start + 2)?;
+    let (_, jr) = instructions.get(start + 3)?;
+
+    // `ld r, n` for r in b, c, d, e, h, l, a
+    let reg = load.opcode >> 3;
+
+    if (load.opcode & 0xC7) != 0x06 || reg == regs::REG_HL_MEM || dec.opcode != OPCODE_DEC_B | reg << 3
+        || jr.opcode != OPCODE_JR_NZ || jr.get_jump_target() != Some(*dec_addr) {
+        return None; }
+
+    let (ret_addr, ret) = instructions[start + 4 ..].iter().take(MAX_DMA_EXTRA_INSTRUCTIONS + 1).find(|(_, ins)| is_jump(ins))?;
+
+    if ret.opcode != OPCODE_RET && ret.opcode != OPCODE_RETI {
+        return None; }
+
+    Some((ret_addr - src.addr) as usize + 1)
+}
+
+pub fn find_oam_dma(info: &AnalInfo, graph: &FlowGraph, copies: &[CopiedCode]) -> Option<CopiedCode>
+{
+    // among the code copied to ram, or else wherever the routine is in rom when hram is called into
+    // at a single address (the routine being copied some way that wasn't recognized)
+
+    if let Some(copy) = copies.iter().find(|copy| oam_dma_len(info, copy.src).is_some()) {
+        return Some(*copy); }
+
+    let mut hram_targets: Vec<u16> = graph.call_targets().into_iter().map(|xa| xa.addr).filter(|addr| (0xFF80 ..= 0xFFFE).contains(addr)).collect();
+    hram_targets.dedup();
+
+    let dest = match hram_targets.as_slice()
+    {
+        [dest] => *dest,
+        _ => return None,
+    };
+
+    for (bank_xa, bank_len) in info.rom_bank_blocks()
+    {
+        let slice = info.rom_slice(bank_xa, bank_len).ok()?;
+
+        for (offset, bytes) in slice.windows(2).enumerate()
+        {
+            if bytes != [OPCODE_LDH_N_A, REG_DMA as u8] {
+                continue; }
+
+            let src = bank_xa + offset as u16;
+
+            if let Some(len) = oam_dma_len(info, src) {
+                return Some(CopiedCode { src: src, dest: dest, len: len }); }
+        }
+    }
+
+    None
+}
@@ -0,0 +1,108 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::io::BufRead;
+use regex::Regex;
+use thiserror::Error;
+
+use super::xaddr::prelude::*;
+
+pub struct RenameRule
+{
+    pub pattern: Regex,
+    pub replacement: String,
+    pub range: Option<(XAddr, XAddr)>,
+}
+
+impl RenameRule
+{
+    pub fn applies_to(&self, xa: XAddr, name: &str) -> bool
+    {
+        if let Some((beg, end)) = self.range
+        {
+            if xa < beg || xa > end {
+                return false; }
+        }
+
+        self.pattern.is_match(name)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ParseRulesError
+{
+    #[error("IO error")]
+    Io(#[from] std::io::Error),
+
+    #[error("Parse Int error")]
+    ParseInt(#[from] std::num::ParseIntError),
+
+    #[error("Regex error")]
+    Regex(#[from] regex::Error),
+
+    #[error("Missing `=>` in rule")]
+    MissingArrow,
+
+    #[error("Invalid address range")]
+    InvalidRange,
+}
+
+pub fn parse_rules<R>(read: &mut R) -> Result<Vec<RenameRule>, ParseRulesError>
+    where R: BufRead
+{
+    // one rule per line: `regex => replacement [in bank:addr-bank:addr]`
+
+    let mut result = vec![];
+
+    for line in read.lines()
+    {
+        let line = line?;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with(';') {
+            continue; }
+
+        let (str_pattern, rhs) = match line.split_once("=>")
+        {
+            Some(parts) => parts,
+            None => return Err(ParseRulesError::MissingArrow),
+        };
+
+        // the range is the last ` in `, and only if it looks like one, so replacements can say ` in ` too
+        let (str_replacement, range) = match rhs.rsplit_once(" in ").filter(|(_, str_range)| str_range.contains('-'))
+        {
+            Some((str_replacement, str_range)) =>
+            {
+                let (str_beg, str_end) = match str_range.trim().split_once('-')
+                {
+                    Some(parts) => parts,
+                    None => return Err(ParseRulesError::InvalidRange),
+                };
+
+                (str_replacement, Some((str_beg.trim().parse()?, str_end.trim().parse()?)))
+            }
+
+            None => (rhs, None)
+        };
+
+        result.push(RenameRule
+        {
+            pattern: Regex::new(str_pattern.trim())?,
+            replacement: str_replacement.trim().to_string(),
+            range: range,
+        });
+    }
+
+    Ok(result)
+}
+
+pub fn apply_rules(rules: &[RenameRule], xa: XAddr, name: &str) -> Option<String>
+{
+    // first matching rule wins
+
+    rules.iter().find(|rule| rule.applies_to(xa, name)).map(|rule|
+        rule.pattern.replace_all(name, rule.replacement.as_str()).into_owned())
+}
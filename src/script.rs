@@ -0,0 +1,235 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::io::BufRead;
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+
+use super::xaddr::prelude::*;
+use super::tags;
+use super::anal::{AnalInfo, BankCtx};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OperandKind
+{
+    Byte,
+    Word,
+    Script, // address of more script, followed
+    Data,   // address of data
+    Code,   // address of cpu code, analysed
+}
+
+impl OperandKind
+{
+    pub fn byte_len(&self) -> usize
+    {
+        match self
+        {
+            OperandKind::Byte => 1,
+            _ => 2,
+        }
+    }
+
+    pub fn is_addr(&self) -> bool
+    {
+        matches!(self, OperandKind::Script | OperandKind::Data | OperandKind::Code)
+    }
+}
+
+pub struct ScriptOp
+{
+    pub mnemonic: String,
+    pub operands: Vec<OperandKind>,
+    pub end: bool, // doesn't continue with what follows
+}
+
+impl ScriptOp
+{
+    pub fn byte_len(&self) -> usize
+    {
+        1 + self.operands.iter().map(OperandKind::byte_len).sum::<usize>()
+    }
+}
+
+// the opcode table of a script interpreter embedded in the game
+pub struct ScriptVm
+{
+    pub name: String,
+    pub ops: HashMap<u8, ScriptOp>,
+}
+
+#[derive(Error, Debug)]
+pub enum ParseScriptVmError
+{
+    #[error("IO error")]
+    Io(#[from] std::io::Error),
+
+    #[error("Parse Int error")]
+    ParseInt(#[from] std::num::ParseIntError),
+
+    #[error("Missing mnemonic for opcode ${0:02X}")]
+    MissingMnemonic(u8),
+
+    #[error("Unknown operand kind `{0}` (expected `byte`, `word`, `addr`, `ptr` or `code`)")]
+    UnknownOperandKind(String),
+
+    #[error("Opcode ${0:02X} is defined twice")]
+    DuplicateOpcode(u8),
+}
+
+pub fn parse_vm<R>(name: &str, read: &mut R) -> Result<ScriptVm, ParseScriptVmError>
+    where R: BufRead
+{
+    // one opcode per line: `03 text_jump addr end` (opcode in hex, mnemonic, operand kinds, `end` when it doesn't fall through)
+
+    let mut ops = HashMap::new();
+
+    for line in read.lines()
+    {
+        let line = line?;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with(';') {
+            continue; }
+
+        let mut split = line.split_whitespace();
+
+        let opcode = u8::from_str_radix(split.next().unwrap(), 16)?;
+
+        let mnemonic = match split.next()
+        {
+            Some(mnemonic) => mnemonic.to_string(),
+            None => return Err(ParseScriptVmError::MissingMnemonic(opcode)),
+        };
+
+        let mut operands = vec![];
+        let mut end = false;
+
+        for str_kind in split
+        {
+            operands.push(match str_kind
+            {
+                "byte" => OperandKind::Byte,
+                "word" => OperandKind::Word,
+                "addr" => OperandKind::Script,
+                "ptr" => OperandKind::Data,
+                "code" => OperandKind::Code,
+
+                "end" =>
+                {
+                    end = true;
+                    continue;
+                }
+
+                _ => return Err(ParseScriptVmError::UnknownOperandKind(str_kind.to_string())),
+            });
+        }
+
+        if ops.insert(opcode, ScriptOp { mnemonic: mnemonic, operands: operands, end: end }).is_some() {
+            return Err(ParseScriptVmError::DuplicateOpcode(opcode)); }
+    }
+
+    Ok(ScriptVm { name: name.to_string(), ops: ops })
+}
+
+// a decoded script instruction, with its operand values
+pub struct ScriptIns
+{
+    pub xa: XAddr,
+    pub opcode: u8,
+    pub operands: Vec<(OperandKind, u16)>,
+    pub len: usize,
+}
+
+pub fn expand_addr(info: &AnalInfo, xa: XAddr, addr: u16) -> Option<XAddr>
+{
+    // scripts in a switchable bank are assumed to refer to their own bank
+
+    let ctx = match xa.addr
+    {
+        0x4000 ..= 0x7FFF => BankCtx::default().with_bank(tags::BankKind::Rom, xa.bank),
+        _ => BankCtx::default(),
+    };
+
+    info.expand_addr(&ctx, addr)
+}
+
+pub fn disassemble(info: &AnalInfo, vm: &ScriptVm, entries: &[XAddr]) -> Vec<ScriptIns>
+{
+    // follows scripts from their entries, along `addr` operands and into what follows each instruction
+    // until an `end` instruction or an opcode missing from the table
+
+    use log::warn;
+
+    let mut result = vec![];
+    let mut visited = HashSet::new();
+    let mut pending = entries.to_vec();
+
+    while let Some(mut xa) = pending.pop()
+    {
+        while visited.insert(xa)
+        {
+            let opcode = match info.rom_slice(xa, 1)
+            {
+                Ok(slice) => slice[0],
+                Err(_) => break,
+            };
+
+            let op = match vm.ops.get(&opcode)
+            {
+                Some(op) => op,
+
+                None =>
+                {
+                    warn!("script at {}: opcode ${:02X} isn't in the `{}` table", xa, opcode, vm.name);
+                    break;
+                }
+            };
+
+            // rom_slice stops at the end of the bank, operands can't go past it
+            let slice = match info.rom_slice(xa, op.byte_len())
+            {
+                Ok(slice) if slice.len() >= op.byte_len() => &slice[1 ..],
+
+                Ok(_) =>
+                {
+                    warn!("script at {}: the operands of `{}` run past the end of the bank", xa, op.mnemonic);
+                    break;
+                }
+
+                Err(_) => break,
+            };
+
+            let mut operands = vec![];
+            let mut offset = 0;
+
+            for &kind in &op.operands
+            {
+                let value = match kind.byte_len()
+                {
+                    1 => slice[offset] as u16,
+                    _ => u16::from_le_bytes([slice[offset], slice[offset + 1]]),
+                };
+
+                if kind == OperandKind::Script {
+                    pending.extend(expand_addr(info, xa, value)); }
+
+                operands.push((kind, value));
+                offset += kind.byte_len();
+            }
+
+            result.push(ScriptIns { xa: xa, opcode: opcode, operands: operands, len: op.byte_len() });
+
+            if op.end {
+                break; }
+
+            xa += op.byte_len() as u16;
+        }
+    }
+
+    result.sort_by_key(|ins| ins.xa);
+    result
+}
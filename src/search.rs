@@ -0,0 +1,160 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use thiserror::Error;
+
+use super::xaddr::prelude::*;
+use super::anal::AnalInfo;
+use super::gbasm::{OperandKind, candidates, parse_number};
+
+const OPCODE_BITOPS: u8 = 0xCB;
+
+// bytes to look for, None matching any; each step of a pattern can have several encodings
+pub type BytePattern = Vec<Option<u8>>;
+
+#[derive(Error, Debug)]
+pub enum ParsePatternError
+{
+    #[error("Parse Int error")]
+    ParseInt(#[from] std::num::ParseIntError),
+
+    #[error("Empty pattern")]
+    Empty,
+
+    #[error("No instruction is written `{0}`")]
+    UnknownInstruction(String),
+
+    #[error("`{0}` doesn't fit in the operand of `{1}`")]
+    OperandTooLarge(String, String),
+
+    #[error("Relative jump targets depend on where the jump is, use `?` in `{0}`")]
+    RelativeTarget(String),
+}
+
+fn encodings(text: &str) -> Result<Vec<BytePattern>, ParsePatternError>
+{
+    // every instruction whose format gives the text, with `?` standing for any operand
+
+    let mut result = vec![];
+
+    for (ins, operand) in candidates(text)
+    {
+        let info = ins.info();
+
+        let mut pattern: BytePattern = vec![Some(ins.opcode)];
+
+        if ins.opcode == OPCODE_BITOPS
+        {
+            pattern.push(Some(ins.operand as u8));
+            result.push(pattern);
+            continue;
+        }
+
+        let operand = match operand
+        {
+            Some(operand) => operand,
+
+            None =>
+            {
+                // `stop` and its padding byte
+                pattern.extend(std::iter::repeat_n(None, info.operand_len as usize));
+                result.push(pattern);
+                continue;
+            }
+        };
+
+        if operand == "?"
+        {
+            pattern.extend(std::iter::repeat_n(None, info.operand_len as usize));
+            result.push(pattern);
+            continue;
+        }
+
+        let value = match parse_number(&operand)
+        {
+            Ok(value) => value,
+            Err(_) => continue, // another instruction reads the same up to the operand (`ld a, [hl]` for `ld a, %`)
+        };
+
+        let value = match info.operand_kind
+        {
+            OperandKind::CodeRelative => return Err(ParsePatternError::RelativeTarget(text.to_string())),
+            OperandKind::DataHram if value >= 0xFF00 => value - 0xFF00,
+            _ => value,
+        };
+
+        if info.operand_len == 1 && value > 0xFF {
+            return Err(ParsePatternError::OperandTooLarge(operand, text.to_string())); }
+
+        pattern.extend(value.to_le_bytes()[.. info.operand_len as usize].iter().map(|byte| Some(*byte)));
+        result.push(pattern);
+    }
+
+    match result.is_empty()
+    {
+        true => Err(ParsePatternError::UnknownInstruction(text.trim().to_string())),
+        false => Ok(result),
+    }
+}
+
+pub fn parse_pattern(text: &str) -> Result<Vec<Vec<BytePattern>>, ParsePatternError>
+{
+    // either hex bytes (`3E ?? E0 46`) or instructions separated by `/` (`ld a, ? / ldh [$46], a`)
+
+    let is_hex = text.split_whitespace().all(|token| token == "??" || (token.len() == 2 && token.chars().all(|c| c.is_ascii_hexdigit())));
+
+    let result = match is_hex
+    {
+        true => text.split_whitespace().map(|token| match token
+        {
+            "??" => Ok(vec![vec![None]]),
+            _ => Ok(vec![vec![Some(u8::from_str_radix(token, 16)?)]]),
+        }).collect::<Result<Vec<_>, ParsePatternError>>()?,
+
+        false => text.split('/').map(encodings).collect::<Result<Vec<_>, _>>()?,
+    };
+
+    match result.is_empty()
+    {
+        true => Err(ParsePatternError::Empty),
+        false => Ok(result),
+    }
+}
+
+fn matches_at(steps: &[Vec<BytePattern>], bytes: &[u8]) -> bool
+{
+    let step = match steps.first()
+    {
+        Some(step) => step,
+        None => return true,
+    };
+
+    step.iter().any(|pattern|
+        bytes.len() >= pattern.len() && pattern.iter().zip(bytes).all(|(p, b)| p.is_none_or(|p| p == *b))
+            && matches_at(&steps[1 ..], &bytes[pattern.len() ..]))
+}
+
+pub fn search(info: &AnalInfo, steps: &[Vec<BytePattern>]) -> Vec<XAddr>
+{
+    let mut result = vec![];
+
+    for (bank_xa, bank_len) in info.rom_bank_blocks()
+    {
+        let bytes = match info.rom_slice(bank_xa, bank_len)
+        {
+            Ok(bytes) => bytes,
+            Err(_) => continue,
+        };
+
+        for off in 0 .. bytes.len()
+        {
+            if matches_at(steps, &bytes[off ..]) {
+                result.push(bank_xa + off as u16); }
+        }
+    }
+
+    result
+}
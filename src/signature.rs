@@ -0,0 +1,103 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::io::BufRead;
+use thiserror::Error;
+
+use super::xaddr::prelude::*;
+use super::anal::AnalInfo;
+
+// signatures shipped with bub, in the same format as user signature files
+const BUILTIN_SIGNATURES: &str = include_str!("signatures.txt");
+
+pub struct Signature
+{
+    pub name: String,
+    pub pattern: Vec<Option<u8>>, // None matches any byte
+}
+
+impl Signature
+{
+    pub fn matches(&self, bytes: &[u8]) -> bool
+    {
+        bytes.len() >= self.pattern.len() && self.pattern.iter().zip(bytes).all(|(p, b)| p.is_none_or(|p| p == *b))
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ParseSignaturesError
+{
+    #[error("IO error")]
+    Io(#[from] std::io::Error),
+
+    #[error("Parse Int error")]
+    ParseInt(#[from] std::num::ParseIntError),
+
+    #[error("Missing `:` in signature")]
+    MissingColon,
+
+    #[error("Signature `{0}` has no fixed bytes")]
+    NoFixedBytes(String),
+}
+
+pub fn parse_signatures<R>(read: &mut R) -> Result<Vec<Signature>, ParseSignaturesError>
+    where R: BufRead
+{
+    // one signature per line: `Name: 2A 12 13 ?? C9`
+
+    let mut result = vec![];
+
+    for line in read.lines()
+    {
+        let line = line?;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with(';') {
+            continue; }
+
+        let (str_name, str_pattern) = match line.split_once(':')
+        {
+            Some(parts) => parts,
+            None => return Err(ParseSignaturesError::MissingColon),
+        };
+
+        let mut pattern = vec![];
+
+        for str_byte in str_pattern.split_whitespace()
+        {
+            pattern.push(match str_byte
+            {
+                "??" => None,
+                _ => Some(u8::from_str_radix(str_byte, 16)?),
+            });
+        }
+
+        if pattern.iter().all(Option::is_none) {
+            return Err(ParseSignaturesError::NoFixedBytes(str_name.trim().to_string())); }
+
+        result.push(Signature
+        {
+            name: str_name.trim().to_string(),
+            pattern: pattern,
+        });
+    }
+
+    Ok(result)
+}
+
+pub fn builtin_signatures() -> Vec<Signature>
+{
+    parse_signatures(&mut BUILTIN_SIGNATURES.as_bytes()).expect("built-in signatures are valid")
+}
+
+pub fn find_match<'a>(signatures: &'a [Signature], info: &AnalInfo, xa: XAddr) -> Option<&'a Signature>
+{
+    // longest matching signature wins, as shorter ones tend to be prefixes of more specific routines
+
+    signatures.iter()
+        .filter(|signature| info.rom_slice(xa, signature.pattern.len()).is_ok_and(|bytes| signature.matches(bytes)))
+        .max_by_key(|signature| signature.pattern.len())
+}
@@ -0,0 +1,100 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::xaddr::prelude::*;
+use super::anal::{AnalInfo, Analysis, Operand};
+use super::func::{Function, FlowGraph};
+
+use std::collections::{BTreeMap, HashSet};
+
+const OPCODE_JP_HL: u8 = 0xE9;
+
+// a summary of the state of a disassembly, to keep track of it from one run to the next
+#[derive(Default, Debug)]
+pub struct Stats
+{
+    pub functions: usize,
+    pub blocks: usize,
+    pub code_bytes: usize,
+    pub jump_tables: usize,
+    pub code_xrefs: usize,
+    pub data_xrefs: usize,
+    pub unresolved_xrefs: usize, // addresses in switchable banks with no known bank
+    pub unresolved_jumps: usize, // `jp hl` with no single known target, jump table dispatchers included
+    pub decode_failures: usize,  // invalid opcodes reached as code
+    pub tag_conflicts: usize,    // tags left out for disagreeing with another at the same address
+    pub orphans: usize,
+    pub tags: BTreeMap<&'static str, usize>,
+}
+
+impl Stats
+{
+    pub fn is_clean(&self) -> bool
+    {
+        // what --strict fails on; unresolved computed jumps and orphans are common in correct disassemblies
+
+        self.unresolved_xrefs == 0 && self.decode_failures == 0 && self.tag_conflicts == 0
+    }
+}
+
+impl std::fmt::Display for Stats
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        writeln!(f, "functions: {}", self.functions)?;
+        writeln!(f, "blocks: {} ({} bytes)", self.blocks, self.code_bytes)?;
+        writeln!(f, "jump tables: {}", self.jump_tables)?;
+        writeln!(f, "code xrefs: {}", self.code_xrefs)?;
+        writeln!(f, "data xrefs: {}", self.data_xrefs)?;
+        writeln!(f, "unresolved xrefs: {}", self.unresolved_xrefs)?;
+        writeln!(f, "unresolved computed jumps: {}", self.unresolved_jumps)?;
+        writeln!(f, "decode failures: {}", self.decode_failures)?;
+        writeln!(f, "tag conflicts: {}", self.tag_conflicts)?;
+        writeln!(f, "orphan blocks: {}", self.orphans)?;
+
+        for (keyword, count) in &self.tags {
+            writeln!(f, "tags {}: {}", keyword, count)?; }
+
+        Ok(())
+    }
+}
+
+pub fn collect(info: &AnalInfo, analysis: &Analysis, graph: &FlowGraph, functions: &[Function], resolved_sites: &HashSet<XAddr>) -> Stats
+{
+    // resolved_sites are computed jumps resolved some other way than register tracking (emulation)
+
+    let mut result = Stats
+    {
+        functions: functions.len(),
+        blocks: graph.block_count(),
+        jump_tables: analysis.jump_tables.len(),
+        decode_failures: analysis.decode_failures.len(),
+        ..Stats::default()
+    };
+
+    for i in 0 .. graph.block_count()
+    {
+        let mut emu = graph.emu(i);
+        result.code_bytes += graph.block(i).len;
+
+        while let Some((xa, Ok((ins, operand)))) = emu.next_resolved()
+        {
+            match operand
+            {
+                Operand::Code(_) => result.code_xrefs += 1,
+                Operand::Data(_) => result.data_xrefs += 1,
+                Operand::UnresolvedCode(_) | Operand::UnresolvedData(_) => result.unresolved_xrefs += 1,
+                Operand::None if ins.opcode == OPCODE_JP_HL && !resolved_sites.contains(&xa) => result.unresolved_jumps += 1,
+                Operand::None => {}
+            }
+        }
+    }
+
+    for (_, tag) in info.tags {
+        *result.tags.entry(tag.keyword()).or_default() += 1; }
+
+    result
+}
@@ -0,0 +1,296 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Syntax
+{
+    Rgbds,
+    Wla,
+}
+
+#[derive(Error, Debug)]
+#[error("Unknown syntax `{0}` (expected `rgbds` or `wla`)")]
+pub struct ParseSyntaxError(String);
+
+impl FromStr for Syntax
+{
+    type Err = ParseSyntaxError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "rgbds" => Ok(Syntax::Rgbds),
+            "wla" | "wla-dx" => Ok(Syntax::Wla),
+            _ => Err(ParseSyntaxError(s.to_string())),
+        }
+    }
+}
+
+// spellings assemblers and communities disagree on, all for the same encodings
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Spelling
+{
+    Ldi,       // `ldi [hl], a` and `ldd a, [hl]` for `ld [hli], a` and `ld a, [hld]`
+    HlPlus,    // `[hl+]` and `[hl-]` for `[hli]` and `[hld]`
+    LdFF00,    // `ld [$FF00+$40], a` for `ldh [$FF40], a`
+    LdhC,      // `ldh [c], a` for `ld [$FF00+c], a`
+    ImplicitA, // `cp 5` and `and a` for `cp a, 5` and `and a, a`
+}
+
+#[derive(Error, Debug)]
+#[error("Unknown spelling `{0}` (expected `ldi`, `hl+`, `ff00`, `ldh-c` or `implicit-a`)")]
+pub struct ParseSpellingError(String);
+
+impl FromStr for Spelling
+{
+    type Err = ParseSpellingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "ldi" => Ok(Spelling::Ldi),
+            "hl+" => Ok(Spelling::HlPlus),
+            "ff00" => Ok(Spelling::LdFF00),
+            "ldh-c" => Ok(Spelling::LdhC),
+            "implicit-a" => Ok(Spelling::ImplicitA),
+            _ => Err(ParseSpellingError(s.to_string())),
+        }
+    }
+}
+
+const ALU_MNEMONICS: [&str; 8] = ["add", "adc", "sub", "sbc", "and", "xor", "or", "cp"];
+
+pub fn spell(spellings: &[Spelling], fmt: &str, ops: &str) -> String
+{
+    // the instruction fmt (as in the opcode tables) with its operand, spelled as asked
+    // `ldi` goes before `hl+`, which is left with nothing to respell
+
+    let mut text = fmt.to_string();
+    let mut ops = ops.to_string();
+
+    if spellings.contains(&Spelling::Ldi)
+    {
+        text = match text.as_str()
+        {
+            "ld [hli], a" => "ldi [hl], a".to_string(),
+            "ld [hld], a" => "ldd [hl], a".to_string(),
+            "ld a, [hli]" => "ldi a, [hl]".to_string(),
+            "ld a, [hld]" => "ldd a, [hl]".to_string(),
+            _ => text,
+        };
+    }
+
+    if spellings.contains(&Spelling::HlPlus) {
+        text = text.replace("[hli]", "[hl+]").replace("[hld]", "[hl-]"); }
+
+    if spellings.contains(&Spelling::LdhC) {
+        text = text.replace("ld [$FF00+c]", "ldh [c]").replace("ld a, [$FF00+c]", "ldh a, [c]"); }
+
+    if spellings.contains(&Spelling::LdFF00) && text.starts_with("ldh ") && text.contains('%')
+    {
+        // the operand is the full address, only its low byte goes after $FF00
+
+        text = text.replacen("ldh ", "ld ", 1).replace("[%]", "[$FF00+%]");
+
+        ops = match ops.strip_prefix("$FF").filter(|low| low.len() == 2 && low.chars().all(|c| c.is_ascii_hexdigit()))
+        {
+            Some(low) => format!("${}", low),
+            None => format!("({} & $FF)", ops),
+        };
+    }
+
+    if spellings.contains(&Spelling::ImplicitA)
+    {
+        if let Some((mnemonic, operand)) = text.split_once(" a, ").filter(|(mnemonic, _)| ALU_MNEMONICS.contains(mnemonic)) {
+            text = format!("{} {}", mnemonic, operand); }
+    }
+
+    text.replace('%', &ops)
+}
+
+pub fn split_scopes(name: &str) -> Vec<&str>
+{
+    // `Audio::Update::.loop` is a nested scope path (the leading dot marking a local is optional)
+    // `Parent.child` is the older single-level form and is read the same as `Parent::child`
+
+    if name.contains("::")
+    {
+        name.split("::").map(|part| part.trim_start_matches('.')).filter(|part| !part.is_empty()).collect()
+    }
+    else
+    {
+        name.splitn(2, '.').filter(|part| !part.is_empty()).collect()
+    }
+}
+
+impl Syntax
+{
+    pub fn spellings(&self) -> &'static [Spelling]
+    {
+        // what the assembler's own documentation uses
+
+        match self
+        {
+            Syntax::Rgbds => &[],
+            Syntax::Wla => &[Spelling::Ldi],
+        }
+    }
+
+    pub fn label_def(&self, scope: &mut Vec<String>, name: &str) -> String
+    {
+        // defines a label, updating the current scope accordingly
+
+        let path = split_scopes(name);
+
+        match self
+        {
+            Syntax::Rgbds =>
+            {
+                // rgbds has a single level of locals, deeper scopes are flattened into the local part
+
+                let label = self.label_ref(scope, name);
+
+                if path.len() == 1 {
+                    *scope = vec![path[0].to_string()]; }
+
+                label
+            }
+
+            Syntax::Wla =>
+            {
+                // wla child labels (`@child`, `@@grandchild`) nest under the previous label of the level above
+
+                // a name with nothing but separators (`::`) has no path to nest, it's left as it is
+                let (last, parent) = match path.split_last()
+                {
+                    Some(split) => split,
+                    None => return name.to_string(),
+                };
+
+                let in_scope = scope.len() >= parent.len() && scope.iter().zip(parent).all(|(a, b)| a == b);
+
+                let label = if in_scope {
+                    format!("{}{}", "@".repeat(parent.len()), last) }
+                else {
+                    path.join("@") };
+
+                *scope = path.iter().map(|part| part.to_string()).collect();
+
+                label
+            }
+        }
+    }
+
+    pub fn label_ref(&self, scope: &[String], name: &str) -> String
+    {
+        let path = split_scopes(name);
+
+        if path.len() <= 1 {
+            return name.to_string(); }
+
+        match self
+        {
+            Syntax::Rgbds =>
+            {
+                let local = path[1 ..].join("_");
+
+                if scope.first().map(|s| s.as_str()) == Some(path[0]) {
+                    format!(".{}", local) }
+                else {
+                    format!("{}.{}", path[0], local) }
+            }
+
+            Syntax::Wla => path.join("@"),
+        }
+    }
+
+    pub fn bank_of(&self, label: &str) -> String
+    {
+        match self
+        {
+            Syntax::Rgbds => format!("BANK({})", label),
+            Syntax::Wla => format!(":{}", label),
+        }
+    }
+
+    pub fn macro_begin(&self, name: &str) -> String
+    {
+        match self
+        {
+            Syntax::Rgbds => format!("MACRO {}", name),
+            Syntax::Wla => format!(".macro {}", name),
+        }
+    }
+
+    pub fn macro_end(&self) -> &'static str
+    {
+        match self
+        {
+            Syntax::Rgbds => "ENDM",
+            Syntax::Wla => ".endm",
+        }
+    }
+
+    pub fn section_begin(&self, name: &str) -> String
+    {
+        match self
+        {
+            Syntax::Rgbds => format!("\tsection \"{}\"", name),
+            Syntax::Wla => format!("\t.section \"{}\" force", name),
+        }
+    }
+
+    pub fn incbin(&self, filename: &str, offset: usize, len: usize) -> String
+    {
+        match self
+        {
+            Syntax::Rgbds => format!("incbin \"{}\", ${:X}, ${:X}", filename, offset, len),
+            Syntax::Wla => format!(".incbin \"{}\" skip ${:X} read ${:X}", filename, offset, len),
+        }
+    }
+
+    pub fn load_begin(&self, name: &str, addr: u16) -> Option<String>
+    {
+        // code assembled to run at another address than where it is stored
+
+        let region = match addr
+        {
+            0xC000 ..= 0xCFFF => "wram0",
+            0xD000 ..= 0xDFFF => "wramx",
+            0xFF80 ..= 0xFFFE => "hram",
+            _ => return None,
+        };
+
+        match self
+        {
+            Syntax::Rgbds => Some(format!("\tload \"{}\", {}[${:04X}]", name, region, addr)),
+            Syntax::Wla => None,
+        }
+    }
+
+    pub fn load_end(&self) -> Option<&'static str>
+    {
+        match self
+        {
+            Syntax::Rgbds => Some("\tendl"),
+            Syntax::Wla => None,
+        }
+    }
+
+    pub fn section_end(&self) -> Option<&'static str>
+    {
+        match self
+        {
+            Syntax::Rgbds => None,
+            Syntax::Wla => Some("\t.ends"),
+        }
+    }
+}
@@ -5,21 +5,82 @@
  */
 
 use std::io::BufRead;
+use std::collections::HashMap;
+use std::ops::Range;
 use thiserror::Error;
 
 use super::xaddr::prelude::*;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum BankKind
+{
+    Rom,
+    Ram,
+    Srm,
+    Vram,
+}
+
+#[derive(Clone, Debug)]
 pub enum Tag
 {
     Name(String),
     Code,
+    Data,
     NoReturn,
     RomBank(u16),
     RamBank(u16),
     SrmBank(u16),
     OperandAddr,
     Comment(String),
+    PreComment(String),
+    Banner(String),
+    Var { name: String, size: u16, kind: String },
+    Compressed { codec: String, len: Option<usize> },
+    JumpTable(Option<usize>),
+    CallBank(BankKind, u16),
+    EntryBank(BankKind, u16),
+    HaltBug,
+    StopPadding(bool),
+    Script(String), // script for the named interpreter starts here
+}
+
+impl Tag
+{
+    pub fn keyword(&self) -> &'static str
+    {
+        // what the tag is written as in tag files (names have none)
+
+        match self
+        {
+            Tag::Name(_) => "name",
+            Tag::Code => ".code",
+            Tag::Data => ".data",
+            Tag::NoReturn => ".noreturn",
+            Tag::RomBank(_) => ".rombank",
+            Tag::RamBank(_) => ".rambank",
+            Tag::SrmBank(_) => ".srambank",
+            Tag::OperandAddr => ".addr",
+            Tag::Comment(_) => ".comment",
+            Tag::PreComment(_) => ".precomment",
+            Tag::Banner(_) => ".banner",
+            Tag::Var { .. } => ".var",
+            Tag::Compressed { .. } => ".compressed",
+            Tag::JumpTable(_) => ".jumptable",
+            Tag::CallBank(..) => ".callbank",
+            Tag::EntryBank(..) => ".entrybank",
+            Tag::HaltBug => ".haltbug",
+            Tag::StopPadding(_) => ".stoplen",
+            Tag::Script(_) => ".script",
+        }
+    }
+
+    pub fn affects_analysis(&self) -> bool
+    {
+        // names, comments and how operands are shown don't change what is code
+        // (analysis results are reused across changes to those, keep this in sync with what anal reads)
+
+        !matches!(self, Tag::Name(_) | Tag::Comment(_) | Tag::PreComment(_) | Tag::Banner(_) | Tag::Var { .. } | Tag::OperandAddr)
+    }
 }
 
 pub fn get_tags_at<'a>(dict: &'a [(XAddr, Tag)], xa: &XAddr) -> &'a [(XAddr, Tag)]
@@ -28,6 +89,22 @@ pub fn get_tags_at<'a>(dict: &'a [(XAddr, Tag)], xa: &XAddr) -> &'a [(XAddr, Tag
     &dict[dict.equal_range_by_key(xa, |xt| xt.0)]
 }
 
+pub fn find_var<'a>(dict: &'a [(XAddr, Tag)], xa: &XAddr) -> Option<(&'a str, u16)>
+{
+    // returns the variable covering xa, along with the offset of xa into it
+
+    for (var_xa, tag) in dict
+    {
+        if let Tag::Var { name, size, .. } = tag
+        {
+            if var_xa.bank == xa.bank && xa.addr >= var_xa.addr && (xa.addr - var_xa.addr) < *size {
+                return Some((name, xa.addr - var_xa.addr)); }
+        }
+    }
+
+    None
+}
+
 #[derive(Error, Debug)]
 pub enum ParseTagsError
 {
@@ -45,14 +122,302 @@ pub enum ParseTagsError
 
     #[error("Missing tag argument")]
     MissingTagArgument,
+
+    #[error("Unterminated string")]
+    UnterminatedString,
+
+    #[error("Invalid escape sequence")]
+    InvalidEscape,
+
+    #[error("Unknown symbol `{0}`")]
+    UnknownSymbol(String),
+
+    #[error("Unknown bank kind `{0}` (expected `rom`, `ram`, `sram` or `vram`)")]
+    UnknownBankKind(String),
+
+    #[error("Invalid `stop` length `{0}` (expected 1 or 2)")]
+    InvalidStopLen(String),
+
+    #[error("Wildcard address `{0}` can't be named, every address it matches would have the same name")]
+    NameOnPattern(String),
+}
+
+fn parse_bank_arg<'a, I>(split: &mut I) -> Result<(BankKind, u16), ParseTagsError>
+    where I: Iterator<Item = &'a str>
+{
+    let kind = match split.next()
+    {
+        None => return Err(ParseTagsError::MissingTagArgument),
+        Some("rom") => BankKind::Rom,
+        Some("ram") => BankKind::Ram,
+        Some("sram") => BankKind::Srm,
+        Some("vram") => BankKind::Vram,
+        Some(str_kind) => return Err(ParseTagsError::UnknownBankKind(str_kind.to_string())),
+    };
+
+    match split.next()
+    {
+        None => Err(ParseTagsError::MissingTagArgument),
+        Some(str_bank) => Ok((kind, str_bank.parse()?)),
+    }
 }
 
-pub fn parse_tags<R>(read: &mut R) -> Result<Vec<(XAddr, Tag)>, ParseTagsError>
-    where R: BufRead
+fn parse_address(str_addr: &str, symbols: &HashMap<String, XAddr>) -> Result<XAddr, ParseTagsError>
 {
+    // either `bank:addr`, `addr` or `Symbol` with an optional `+offset` or `-offset` (all hex)
+
+    // the offset is after the last sign, and only if what follows it is a number (symbols can have `-` in them)
+    let is_offset = |i: usize| i > 0 && i + 1 < str_addr.len() && str_addr[i + 1 ..].chars().all(|c| c.is_ascii_hexdigit());
+
+    let (str_base, offset) = match str_addr.rfind(['+', '-']).filter(|i| is_offset(*i))
+    {
+        Some(i) =>
+        {
+            let offset = i32::from_str_radix(&str_addr[i + 1 ..], 16)?;
+            (&str_addr[.. i], if str_addr[i ..].starts_with('-') { -offset } else { offset })
+        }
+
+        None => (str_addr, 0)
+    };
+
+    let str_addr_components: Vec<&str> = str_base.split(':').collect();
+
+    let xa = match str_addr_components.len()
+    {
+        1 => match symbols.get(str_base)
+        {
+            Some(xa) => *xa,
+
+            None => match u16::from_str_radix(str_base, 16)
+            {
+                Ok(addr) => XAddr::new(0, addr),
+                Err(_) => return Err(ParseTagsError::UnknownSymbol(str_base.to_string())),
+            }
+        },
+
+        2 => XAddr::new(u16::from_str_radix(&str_addr_components[0], 16)?, u16::from_str_radix(&str_addr_components[1], 16)?),
+        _ => return Err(ParseTagsError::InvalidAddressField),
+    };
+
+    // an offset going past either end of the address space is a mistake, not a wrap around
+
+    use std::convert::TryFrom;
+
+    match u16::try_from(xa.addr as i32 + offset)
+    {
+        Ok(addr) => Ok(XAddr::new(xa.bank, addr)),
+        Err(_) => Err(ParseTagsError::InvalidAddressField),
+    }
+}
+
+fn expand_hex_pattern(pattern: &str) -> Result<Vec<u16>, ParseTagsError>
+{
+    // every value matching a hex pattern where `?` stands for any digit
+
+    let mut result = vec![0u16];
+
+    for c in pattern.chars()
+    {
+        let digits: Vec<u16> = match c
+        {
+            '?' => (0 .. 16).collect(),
+            _ => vec![c.to_digit(16).ok_or(ParseTagsError::InvalidAddressField)? as u16],
+        };
+
+        result = result.iter().flat_map(|value| digits.iter().map(move |digit| (value << 4) | digit)).collect();
+    }
+
+    Ok(result)
+}
+
+fn expand_address_pattern<F>(str_addr: &str, banks_at: &F) -> Result<Vec<XAddr>, ParseTagsError>
+    where F: Fn(u16) -> Range<u16>
+{
+    // `??:4000` (every bank mapping $4000), `03:4??0`, `4?00`...
+
+    let (str_bank, str_addr) = match str_addr.split_once(':')
+    {
+        Some((str_bank, str_addr)) => (Some(str_bank), str_addr),
+        None => (None, str_addr),
+    };
+
     let mut result = vec![];
 
-    for line in read.lines()
+    for addr in expand_hex_pattern(str_addr)?
+    {
+        let valid_banks = banks_at(addr);
+
+        match str_bank
+        {
+            None => result.push(XAddr::new(0, addr)),
+
+            Some(str_bank) => for bank in expand_hex_pattern(str_bank)?
+            {
+                if !str_bank.contains('?') || valid_banks.contains(&bank) {
+                    result.push(XAddr::new(bank, addr)); }
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+fn rest_of_line(line: &str) -> &str
+{
+    // everything past the address and tag fields, verbatim
+    line.splitn(3, char::is_whitespace).nth(2).unwrap_or("").trim_end()
+}
+
+fn parse_text_arg(rest: &str) -> Result<String, ParseTagsError>
+{
+    // quoted strings support \n, \t, \" and \\ escapes
+    // unquoted text is taken verbatim, as before
+
+    let rest = rest.trim_start();
+
+    if !rest.starts_with('"') {
+        return Ok(rest.to_string()); }
+
+    let mut result = String::new();
+    let mut chars = rest[1 ..].chars();
+
+    loop
+    {
+        match chars.next()
+        {
+            None => return Err(ParseTagsError::UnterminatedString),
+            Some('"') => break,
+
+            Some('\\') => match chars.next()
+            {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some('"') => result.push('"'),
+                Some('\\') => result.push('\\'),
+                _ => return Err(ParseTagsError::InvalidEscape),
+            },
+
+            Some(c) => result.push(c),
+        }
+    }
+
+    Ok(result)
+}
+
+enum Winner
+{
+    First,
+    Second,
+}
+
+fn conflict_winner(first: &Tag, second: &Tag) -> Option<Winner>
+{
+    // priority order for disagreeing tags at the same address:
+    // - `.code` wins over `.data`, `.compressed`, `.jumptable` and `.script` (an explicit entry point is the stronger claim)
+    // - otherwise the tag appearing first in the file wins (names, variables, bank assumptions)
+
+    match (first, second)
+    {
+        (Tag::Code, Tag::Data | Tag::Compressed { .. } | Tag::JumpTable(_) | Tag::Script(_)) => Some(Winner::First),
+        (Tag::Data | Tag::Compressed { .. } | Tag::JumpTable(_) | Tag::Script(_), Tag::Code) => Some(Winner::Second),
+
+        (Tag::Name(a) | Tag::Var { name: a, .. }, Tag::Name(b) | Tag::Var { name: b, .. }) if a != b => Some(Winner::First),
+        (Tag::Var { size: a, .. }, Tag::Var { size: b, .. }) if a != b => Some(Winner::First),
+
+        (Tag::RomBank(a), Tag::RomBank(b)) |
+        (Tag::RamBank(a), Tag::RamBank(b)) |
+        (Tag::SrmBank(a), Tag::SrmBank(b)) if a != b => Some(Winner::First),
+
+        (Tag::Compressed { .. }, Tag::Compressed { .. }) => Some(Winner::First),
+
+        _ => None,
+    }
+}
+
+pub fn resolve_conflicts<L>(tags: Vec<(XAddr, Tag, L)>) -> (Vec<(XAddr, Tag)>, usize)
+    where L: std::fmt::Display
+{
+    // tags at the same address are in the order they were read, each with where it was read from (`line 12`, a file)
+    // also returns how many conflicts there were
+
+    use log::warn;
+
+    let mut result: Vec<(XAddr, Tag, L)> = Vec::with_capacity(tags.len());
+    let mut conflicts = 0;
+
+    'lop_tags:
+    for (xa, tag, line) in tags
+    {
+        let mut i = result.len();
+
+        while i > 0 && result[i - 1].0 == xa
+        {
+            i -= 1;
+
+            let (_, other, other_line) = &result[i];
+
+            match conflict_winner(other, &tag)
+            {
+                Some(Winner::First) =>
+                {
+                    warn!("conflicting tags at {}: {} ({:?}) conflicts with {} ({:?}), keeping {}",
+                        xa, line, tag, other_line, other, other_line);
+
+                    conflicts += 1;
+                    continue 'lop_tags;
+                }
+
+                Some(Winner::Second) =>
+                {
+                    warn!("conflicting tags at {}: {} ({:?}) conflicts with {} ({:?}), keeping {}",
+                        xa, other_line, other, line, tag, line);
+
+                    conflicts += 1;
+                    result.remove(i);
+                }
+
+                None => {}
+            }
+        }
+
+        result.push((xa, tag, line));
+    }
+
+    (result.into_iter().map(|(xa, tag, _)| (xa, tag)).collect(), conflicts)
+}
+
+pub fn parse_tags<R, F>(read: &mut R, banks_at: F) -> Result<Vec<(XAddr, Tag)>, ParseTagsError>
+    where R: BufRead, F: Fn(u16) -> Range<u16>
+{
+    parse_tags_with_symbols(read, banks_at, &HashMap::new()).map(|(tags, _)| tags)
+}
+
+pub fn symbols(dict: &[(XAddr, Tag)]) -> HashMap<String, XAddr>
+{
+    // names and variables, the first one of a name winning as it does within a file
+
+    let mut result = HashMap::new();
+
+    for (xa, tag) in dict
+    {
+        if let Tag::Name(name) | Tag::Var { name, .. } = tag {
+            result.entry(name.clone()).or_insert(*xa); }
+    }
+
+    result
+}
+
+pub fn parse_tags_with_symbols<R, F>(read: &mut R, banks_at: F, known: &HashMap<String, XAddr>) -> Result<(Vec<(XAddr, Tag)>, usize), ParseTagsError>
+    where R: BufRead, F: Fn(u16) -> Range<u16>
+{
+    // banks_at gives the range of valid banks for an address, used to expand bank wildcards
+    // known symbols (from tag files read before) can be used as addresses too
+    // also returns the number of conflicting tags left out
+
+    let mut result = vec![];
+    let mut symbols = known.clone();
+
+    for (line_index, line) in read.lines().enumerate()
     {
         let line = line?;
         let line = line.trim();
@@ -64,19 +429,13 @@ pub fn parse_tags<R>(read: &mut R) -> Result<Vec<(XAddr, Tag)>, ParseTagsError>
 
         // parse address
 
-        let xa =
-        {
-            let opt_str_addr = split.next();
-            let str_addr = opt_str_addr.unwrap(); // since trimmed line is not empty, there must be at least one part in the line
-
-            let str_addr_components: Vec<&str> = str_addr.split(':').collect();
+        let str_addr = split.next().unwrap(); // since trimmed line is not empty, there must be at least one part in the line
+        let is_pattern = str_addr.contains('?');
 
-            match str_addr_components.len()
-            {
-                1 => XAddr::new(0, u16::from_str_radix(&str_addr_components[0], 16)?),
-                2 => XAddr::new(u16::from_str_radix(&str_addr_components[0], 16)?, u16::from_str_radix(&str_addr_components[1], 16)?),
-                _ => return Err(ParseTagsError::InvalidAddressField),
-            }
+        let xas = match is_pattern
+        {
+            true => expand_address_pattern(str_addr, &banks_at)?,
+            false => vec![parse_address(str_addr, &symbols)?],
         };
 
         // parse tag
@@ -89,6 +448,7 @@ pub fn parse_tags<R>(read: &mut R) -> Result<Vec<(XAddr, Tag)>, ParseTagsError>
         let tag = match opt_str_tag.unwrap()
         {
             ".code" => Tag::Code,
+            ".data" => Tag::Data,
             ".noreturn" => Tag::NoReturn,
 
             ".bank" | ".rombank" => Tag::RomBank(match split.next() {
@@ -104,16 +464,79 @@ pub fn parse_tags<R>(read: &mut R) -> Result<Vec<(XAddr, Tag)>, ParseTagsError>
                 Some(str_bank) => str_bank.parse()? }),
 
             ".addr" => Tag::OperandAddr,
+            ".haltbug" => Tag::HaltBug,
+
+            ".stoplen" => Tag::StopPadding(match split.next() {
+                None => return Err(ParseTagsError::MissingTagArgument),
+                Some("1") => false,
+                Some("2") => true,
+                Some(str_len) => return Err(ParseTagsError::InvalidStopLen(str_len.to_string())) }),
+
+            ".jumptable" => Tag::JumpTable(match split.next() {
+                None => None,
+                Some(str_count) => Some(str_count.parse()?) }),
+
+            ".callbank" => { let (kind, bank) = parse_bank_arg(&mut split)?; Tag::CallBank(kind, bank) }
+            ".entrybank" => { let (kind, bank) = parse_bank_arg(&mut split)?; Tag::EntryBank(kind, bank) }
+
+            ".var" => Tag::Var
+            {
+                name: match split.next() {
+                    None => return Err(ParseTagsError::MissingTagArgument),
+                    Some(name) => name.to_string() },
+                size: match split.next() {
+                    None => 1,
+                    Some(str_size) => str_size.parse()? },
+                kind: split.next().unwrap_or("byte").to_string(),
+            },
+
+            ".compressed" => Tag::Compressed
+            {
+                codec: match split.next() {
+                    None => return Err(ParseTagsError::MissingTagArgument),
+                    Some(codec) => codec.to_string() },
+                len: match split.next() {
+                    None => None,
+                    Some(str_len) => Some(str_len.parse()?) },
+            },
+
+            ".script" => Tag::Script(match split.next() {
+                None => return Err(ParseTagsError::MissingTagArgument),
+                Some(vm) => vm.to_string() }),
+
+            ".comment" => Tag::Comment(parse_text_arg(rest_of_line(line))?),
+            ".precomment" => Tag::PreComment(parse_text_arg(rest_of_line(line))?),
+
+            // banners are free-form text: quoted like comments, or else the rest of the line verbatim (indentation included)
+            ".banner" => Tag::Banner(match rest_of_line(line)
+            {
+                rest if rest.trim_start().starts_with('"') => parse_text_arg(rest)?,
+                rest => rest.to_string(),
+            }),
 
-            // NOTE: TODO: FIXME: this doesn't account for varied whitespaces in comment string
-            ".comment" => Tag::Comment(split.collect::<Vec<_>>().join(" ")),
             str_tag => Tag::Name(str_tag.to_string()),
         };
 
-        result.push((xa, tag));
+        // every address a wildcard matches would get the same name, which can't be assembled
+        if is_pattern && matches!(tag, Tag::Name(_) | Tag::Var { .. }) {
+            return Err(ParseTagsError::NameOnPattern(str_addr.to_string())); }
+
+        // names become usable as addresses on the following lines
+
+        for xa in xas
+        {
+            match &tag
+            {
+                Tag::Name(name) | Tag::Var { name, .. } => { symbols.entry(name.clone()).or_insert(xa); }
+                _ => {}
+            }
+
+            result.push((xa, tag.clone(), format!("line {}", line_index + 1)));
+        }
     }
 
-    result.sort_by_key(|&(xa, _)| xa);
+    // stable sort: tags at the same address keep their file order
+    result.sort_by_key(|&(xa, _, _)| xa);
 
-    Ok(result)
+    Ok(resolve_conflicts(result))
 }
@@ -0,0 +1,160 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::collections::HashMap;
+use std::io::BufRead;
+use thiserror::Error;
+
+use super::xaddr::prelude::*;
+use super::anal::AnalInfo;
+
+// from byte sequences to the text they stand for, as rgbds `charmap` directives give it
+pub struct Charmap
+{
+    entries: HashMap<Vec<u8>, String>,
+    max_len: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct FoundString
+{
+    pub xa: XAddr,
+    pub len: usize, // in bytes
+    pub text: String,
+}
+
+#[derive(Error, Debug)]
+pub enum ParseCharmapError
+{
+    #[error("IO error")]
+    Io(#[from] std::io::Error),
+
+    #[error("Parse Int error")]
+    ParseInt(#[from] std::num::ParseIntError),
+
+    #[error("Invalid charmap line `{0}`")]
+    InvalidLine(String),
+}
+
+impl Charmap
+{
+    pub fn ascii() -> Self
+    {
+        Self
+        {
+            entries: (0x20u8 .. 0x7F).map(|byte| (vec![byte], (byte as char).to_string())).collect(),
+            max_len: 1,
+        }
+    }
+
+    pub fn decode_at(&self, bytes: &[u8]) -> Option<(usize, &str)>
+    {
+        // longest sequence first
+
+        (1 ..= std::cmp::min(self.max_len, bytes.len())).rev()
+            .find_map(|len| self.entries.get(&bytes[.. len]).map(|text| (len, text.as_str())))
+    }
+}
+
+fn parse_number(str_number: &str) -> Result<u8, ParseCharmapError>
+{
+    // rgbds notation ($hex, %binary, decimal), and 0x for good measure
+
+    let str_number = str_number.trim();
+
+    Ok(if let Some(hex) = str_number.strip_prefix('$').or_else(|| str_number.strip_prefix("0x")) {
+        u8::from_str_radix(hex, 16)? }
+    else if let Some(bin) = str_number.strip_prefix('%') {
+        u8::from_str_radix(bin, 2)? }
+    else {
+        str_number.parse()? })
+}
+
+pub fn parse_charmap<R>(read: &mut R) -> Result<Charmap, ParseCharmapError>
+    where R: BufRead
+{
+    // `charmap "A", $80` lines (`charmap "<PLAYER>", $52`, `charmap "é", $E0, $01`...), anything else is skipped
+
+    let mut result = Charmap { entries: HashMap::new(), max_len: 1 };
+
+    for line in read.lines()
+    {
+        let line = line?;
+        let line = line.trim();
+
+        let rest = match line.strip_prefix("charmap")
+        {
+            Some(rest) => rest.trim_start(),
+            None => continue,
+        };
+
+        let invalid = || ParseCharmapError::InvalidLine(line.to_string());
+
+        // the quoted text, with \" and \\ escapes
+
+        let mut chars = rest.strip_prefix('"').ok_or_else(invalid)?.char_indices();
+        let mut text = String::new();
+
+        let values = loop
+        {
+            match chars.next()
+            {
+                Some((_, '\\')) => text.push(chars.next().ok_or_else(invalid)?.1),
+                Some((i, '"')) => break &rest[i + 2 ..],
+                Some((_, c)) => text.push(c),
+                None => return Err(invalid()),
+            }
+        };
+
+        // the values, up to a comment
+
+        let values = values.split(';').next().unwrap().trim().strip_prefix(',').ok_or_else(invalid)?;
+        let bytes = values.split(',').map(parse_number).collect::<Result<Vec<u8>, _>>()?;
+
+        result.max_len = std::cmp::max(result.max_len, bytes.len());
+        result.entries.insert(bytes, text);
+    }
+
+    Ok(result)
+}
+
+pub fn find_strings(info: &AnalInfo, charmap: &Charmap, min_len: usize) -> Vec<FoundString>
+{
+    // runs of at least min_len characters, leaving out a single one repeated (padding, blank lines)
+
+    let mut result = vec![];
+
+    for (bank_xa, bank_len) in info.rom_bank_blocks()
+    {
+        let bytes = match info.rom_slice(bank_xa, bank_len)
+        {
+            Ok(bytes) => bytes,
+            Err(_) => continue,
+        };
+
+        let mut off = 0;
+
+        while off < bytes.len()
+        {
+            let beg = off;
+            let mut chars = vec![];
+
+            while let Some((len, text)) = charmap.decode_at(&bytes[off ..])
+            {
+                chars.push(text);
+                off += len;
+            }
+
+            if chars.len() >= min_len && chars.iter().any(|c| *c != chars[0]) {
+                result.push(FoundString { xa: bank_xa + beg as u16, len: off - beg, text: chars.concat() }); }
+
+            if off == beg {
+                off += 1; }
+        }
+    }
+
+    result
+}
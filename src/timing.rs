@@ -0,0 +1,172 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::xaddr::prelude::*;
+use super::gbasm;
+use super::func::{Function, FlowGraph};
+
+use std::collections::HashMap;
+
+// cycles a function may take, from its entry to where it returns or leaves
+#[derive(Clone, Copy, Debug)]
+pub struct FunctionTiming
+{
+    pub best: Option<u32>, // None when it never leaves
+    pub worst: Option<u32>, // None when a loop makes it unbounded
+}
+
+enum Exit
+{
+    Block(usize),
+    Leave,
+}
+
+fn block_exits(graph: &FlowGraph, i: usize, in_function: &HashMap<XAddr, usize>) -> Vec<(Exit, u32, u32)>
+{
+    // where control leaves the block, with the best and worst cycles taken to get there
+
+    let block = graph.block(i);
+    let mut emu = graph.emu(i);
+
+    let exit_to = |target: Option<XAddr>| match target.and_then(|target| in_function.get(&target))
+    {
+        Some(&j) => Exit::Block(j),
+        None => Exit::Leave,
+    };
+
+    let mut result = vec![];
+    let (mut best, mut worst) = (0, 0);
+
+    while let Some((xa, Ok(ins))) = emu.next()
+    {
+        let (taken, not_taken) = ins.cycles();
+        let flags = ins.info().flags;
+
+        if (flags & gbasm::OPCODE_FLAG_JUMP) == 0
+        {
+            best += taken;
+            worst += taken;
+            continue;
+        }
+
+        let target = emu.target(xa, &ins);
+
+        if (flags & gbasm::OPCODE_FLAG_CALL) != 0
+        {
+            // the callee itself isn't counted
+
+            if target.is_some_and(|target| graph.is_noreturn(&target))
+            {
+                result.push((Exit::Leave, best + taken, worst + taken));
+                return result;
+            }
+
+            best += not_taken;
+            worst += taken;
+        }
+        else
+        {
+            result.push((exit_to(target), best + taken, worst + taken));
+
+            if (flags & gbasm::OPCODE_FLAG_CONDITIONAL) == 0 {
+                return result; }
+
+            best += not_taken;
+            worst += not_taken;
+        }
+    }
+
+    result.push((exit_to(Some(block.xa + block.len as u16)), best, worst));
+    result
+}
+
+pub fn function_timing(graph: &FlowGraph, function: &Function) -> FunctionTiming
+{
+    // shortest path to any exit for the best case, longest for the worst case (if there are no loops)
+
+    let in_function: HashMap<XAddr, usize> = function.blocks.iter().map(|&i| (graph.block(i).xa, i)).collect();
+    let exits: HashMap<usize, Vec<(Exit, u32, u32)>> = function.blocks.iter().map(|&i| (i, block_exits(graph, i, &in_function))).collect();
+
+    // best: relax until nothing changes (cycle counts are never negative)
+
+    let mut dist: HashMap<usize, u32> = HashMap::new();
+    dist.insert(function.blocks[0], 0);
+
+    let mut best = None;
+    let mut changed = true;
+
+    while changed
+    {
+        changed = false;
+
+        for &i in &function.blocks
+        {
+            let here = match dist.get(&i)
+            {
+                Some(&here) => here,
+                None => continue,
+            };
+
+            for (exit, cost, _) in &exits[&i]
+            {
+                match exit
+                {
+                    Exit::Leave => best = Some(best.unwrap_or(u32::MAX).min(here + cost)),
+
+                    Exit::Block(j) =>
+                    {
+                        if dist.get(j).is_none_or(|&there| here + cost < there)
+                        {
+                            dist.insert(*j, here + cost);
+                            changed = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // worst: longest path by depth first search, giving up on loops
+
+    fn longest(i: usize, exits: &HashMap<usize, Vec<(Exit, u32, u32)>>, memo: &mut HashMap<usize, Option<u32>>, path: &mut Vec<usize>) -> Option<u32>
+    {
+        if let Some(&result) = memo.get(&i) {
+            return result; }
+
+        if path.contains(&i) {
+            return None; }
+
+        path.push(i);
+
+        let mut result = Some(0);
+
+        for (exit, _, cost) in &exits[&i]
+        {
+            let rest = match exit
+            {
+                Exit::Leave => Some(0),
+                Exit::Block(j) => longest(*j, exits, memo, path),
+            };
+
+            result = match (result, rest)
+            {
+                (Some(result), Some(rest)) => Some(result.max(cost + rest)),
+                _ => None,
+            };
+        }
+
+        path.pop();
+        memo.insert(i, result);
+
+        result
+    }
+
+    FunctionTiming
+    {
+        best: best,
+        worst: longest(function.blocks[0], &exits, &mut HashMap::new(), &mut vec![]),
+    }
+}
@@ -0,0 +1,146 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use std::io::BufRead;
+use std::str::FromStr;
+use regex::Regex;
+use thiserror::Error;
+
+use super::xaddr::prelude::*;
+use super::gbasm;
+use super::anal::AnalInfo;
+
+// where the executed pc (and rom bank, when logged) is found on each line of an execution trace
+#[derive(Clone)]
+pub struct TraceFormat
+{
+    pattern: Regex,
+}
+
+// presets for common emulators: `bank:pc` line starts (BGB), `$pc` with an optional `(bank $NN)` (SameBoy)
+// and `PC: pc` with an optional `ROM: bank` further on the line (Gambatte)
+const PRESETS: &[(&str, &str)] = &[
+    ("bgb", r"^\s*(?:ROM)?(?P<bank>[0-9A-Fa-f]{1,3}):(?P<pc>[0-9A-Fa-f]{4})\b"),
+    ("sameboy", r"\$(?P<pc>[0-9A-Fa-f]{4})\b(?:\s*\((?i:bank)\s*\$?(?P<bank>[0-9A-Fa-f]+)\))?"),
+    ("gambatte", r"\bPC[:=]\s*\$?(?P<pc>[0-9A-Fa-f]{4})\b(?:.*?\bROM(?:BANK)?[:=]\s*\$?(?P<bank>[0-9A-Fa-f]+))?"),
+];
+
+#[derive(Error, Debug)]
+pub enum ParseTraceFormatError
+{
+    #[error("Regex error")]
+    Regex(#[from] regex::Error),
+
+    #[error("Trace format `{0}` is neither `bgb`, `sameboy`, `gambatte` nor a regex with a `pc` group")]
+    MissingPc(String),
+}
+
+impl FromStr for TraceFormat
+{
+    type Err = ParseTraceFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        let pattern = match PRESETS.iter().find(|(name, _)| *name == s)
+        {
+            Some((_, pattern)) => Regex::new(pattern)?,
+            None => Regex::new(s)?,
+        };
+
+        if !pattern.capture_names().any(|name| name == Some("pc")) {
+            return Err(ParseTraceFormatError::MissingPc(s.to_string())); }
+
+        Ok(TraceFormat { pattern: pattern })
+    }
+}
+
+pub fn read_trace<R>(read: &mut R, format: &TraceFormat, info: &AnalInfo) -> Result<Vec<XAddr>, std::io::Error>
+    where R: BufRead
+{
+    // only where execution didn't just run on from the previous instruction is kept,
+    // which gives every entry point and jump target without cutting code into single instructions
+
+    use log::info;
+
+    let mut result = vec![];
+    let mut next: Option<XAddr> = None;
+    let mut skipped = 0;
+
+    for line in read.lines()
+    {
+        let line = line?;
+
+        let captures = match format.pattern.captures(&line)
+        {
+            Some(captures) => captures,
+            None => continue,
+        };
+
+        let pc = u16::from_str_radix(&captures["pc"], 16).ok();
+        let bank = captures.name("bank").and_then(|bank| u16::from_str_radix(bank.as_str(), 16).ok());
+
+        // code running from ram, or from the banked area with no bank to tell which
+        let xa = match (pc, bank)
+        {
+            (Some(pc @ 0x0000 ..= 0x3FFF), _) => Some(XAddr::new(0, pc)),
+            (Some(pc @ 0x4000 ..= 0x7FFF), _) if !info.rom_info.big_rom => Some(XAddr::new(0, pc)), // whatever bank the emulator logs
+            (Some(pc @ 0x4000 ..= 0x7FFF), Some(bank)) => Some(XAddr::new(bank, pc)),
+            _ => None,
+        };
+
+        let xa = match xa.filter(|xa| info.rom_offset(*xa).is_some())
+        {
+            Some(xa) => xa,
+
+            None =>
+            {
+                skipped += 1;
+                next = None;
+                continue;
+            }
+        };
+
+        if next != Some(xa) {
+            result.push(xa); }
+
+        next = info.rom_slice(xa, 3).or_else(|_| info.rom_slice(xa, 1)).ok()
+            .and_then(|slice| gbasm::decode_stop(xa.addr, slice, info.stop_padding_at(xa)).ok())
+            .map(|ins| xa + ins.encoded_len() as u16);
+    }
+
+    if skipped != 0 {
+        info!("{} trace line(s) outside of rom or without a rom bank were skipped", skipped); }
+
+    result.sort();
+    result.dedup();
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::mbc::Mbc;
+    use crate::anal::RomInfo;
+
+    #[test]
+    fn small_rom_trace()
+    {
+        // a 32K rom has no banks, whatever the emulator says
+
+        let rom = vec![0x00; 0x8000];
+        let rom_info = RomInfo::for_test(false, Mbc::RomOnly);
+        let info = AnalInfo::new(rom_info, &rom, &[]);
+
+        let format: TraceFormat = "bgb".parse().unwrap();
+        let trace = "01:4000\n00:0150\n01:5000\n";
+
+        let result = read_trace(&mut std::io::Cursor::new(trace), &format, &info).unwrap();
+
+        assert_eq!(result, vec![XAddr::new(0, 0x0150), XAddr::new(0, 0x4000), XAddr::new(0, 0x5000)]);
+    }
+}
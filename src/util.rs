@@ -49,3 +49,10 @@ pub fn sorted_merge<T>(a: &[T], b: &[T]) -> Vec<T>
 
     sorted
 }
+
+pub fn crc32(data: &[u8]) -> u32
+{
+    let mut crc = flate2::Crc::new();
+    crc.update(data);
+    crc.sum()
+}
@@ -0,0 +1,175 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::xaddr::prelude::*;
+use super::gbasm;
+use super::func::{Function, FlowGraph};
+
+const REG_LY: u16 = 0xFF44;
+const REG_STAT: u16 = 0xFF41;
+
+const OPCODE_LDH_A_N: u8 = 0xF0;
+const OPCODE_LD_A_NN: u8 = 0xFA;
+const OPCODE_CP_N: u8 = 0xFE;
+const OPCODE_AND_N: u8 = 0xE6;
+const OPCODE_BITOPS: u8 = 0xCB;
+const OPCODE_RET: u8 = 0xC9;
+
+const BITOP_BIT_1_A: u8 = 0x4F;
+
+// conditions of the loops branching back to wait
+const COND_NZ: u8 = 0;
+const COND_C: u8 = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WaitKind
+{
+    Scanline(u8),        // until ly is N
+    ScanlineAtLeast(u8), // until ly reaches N
+    Mode(u8),            // until stat reports the given ppu mode
+    VramAccess,          // until stat bit 1 clears (mode 0 or 1)
+}
+
+impl WaitKind
+{
+    pub fn note(&self) -> String
+    {
+        match self
+        {
+            WaitKind::Scanline(line) => format!("wait for scanline ${:02X}", line),
+            WaitKind::ScanlineAtLeast(line) => format!("wait for scanline ${:02X} or later", line),
+            WaitKind::Mode(0) => "wait for hblank".to_string(),
+            WaitKind::Mode(1) => "wait for vblank".to_string(),
+            WaitKind::Mode(mode) => format!("wait for ppu mode {}", mode),
+            WaitKind::VramAccess => "wait until vram is accessible".to_string(),
+        }
+    }
+
+    pub fn name(&self) -> String
+    {
+        match self
+        {
+            WaitKind::Scanline(line) => format!("WaitForLY_{:02X}", line),
+            WaitKind::ScanlineAtLeast(line) => format!("WaitForLYAtLeast_{:02X}", line),
+            WaitKind::Mode(0) => "WaitForHBlank".to_string(),
+            WaitKind::Mode(1) => "WaitForVBlankMode".to_string(),
+            WaitKind::Mode(mode) => format!("WaitForMode{}", mode),
+            WaitKind::VramAccess => "WaitForVRAM".to_string(),
+        }
+    }
+}
+
+// a loop polling ly or stat, starting at xa
+#[derive(Clone, Copy, Debug)]
+pub struct WaitLoop
+{
+    pub xa: XAddr,
+    pub len: usize, // instructions, the branch back included
+    pub kind: WaitKind,
+}
+
+fn branch_condition(ins: &gbasm::Instruction) -> Option<u8>
+{
+    // conditional jr and jp only
+
+    match ins.opcode
+    {
+        0x20 | 0x28 | 0x30 | 0x38 | 0xC2 | 0xCA | 0xD2 | 0xDA => Some((ins.opcode >> 3) & 3),
+        _ => None,
+    }
+}
+
+fn match_wait(instructions: &[gbasm::Instruction]) -> Option<(WaitKind, usize)>
+{
+    // the loop, branch back included (where it goes is checked by the caller), and its instruction count
+
+    let reg = match instructions.first()
+    {
+        Some(ins) if ins.opcode == OPCODE_LDH_A_N || ins.opcode == OPCODE_LD_A_NN => ins.operand,
+        _ => return None,
+    };
+
+    let ops: Vec<(u8, u8)> = instructions[1 ..].iter().take(3).map(|ins| (ins.opcode, ins.operand as u8)).collect();
+    let cond = |i: usize| instructions.get(i).and_then(branch_condition);
+
+    match (reg, ops.as_slice())
+    {
+        (REG_LY, [(OPCODE_CP_N, line), ..]) => match cond(2)?
+        {
+            COND_NZ => Some((WaitKind::Scanline(*line), 3)),
+            COND_C => Some((WaitKind::ScanlineAtLeast(*line), 3)),
+            _ => None,
+        },
+
+        (REG_STAT, [(OPCODE_AND_N, 3), (OPCODE_CP_N, mode), ..]) if cond(3)? == COND_NZ && *mode < 4 => Some((WaitKind::Mode(*mode), 4)),
+        (REG_STAT, [(OPCODE_AND_N, 3), ..]) if cond(2)? == COND_NZ => Some((WaitKind::Mode(0), 3)),
+        (REG_STAT, [(OPCODE_AND_N, 2), ..]) if cond(2)? == COND_NZ => Some((WaitKind::VramAccess, 3)),
+        (REG_STAT, [(OPCODE_BITOPS, BITOP_BIT_1_A), ..]) if cond(2)? == COND_NZ => Some((WaitKind::VramAccess, 3)),
+
+        _ => None,
+    }
+}
+
+pub fn find_wait_loops(graph: &FlowGraph) -> Vec<WaitLoop>
+{
+    // loops are cut into blocks at their start, so they're looked for at the start of blocks
+
+    let mut result = vec![];
+
+    for i in 0 .. graph.block_count()
+    {
+        let mut emu = graph.emu(i);
+        let mut instructions = vec![];
+
+        while let Some((xa, Ok(ins))) = emu.next()
+        {
+            instructions.push(ins);
+
+            if instructions.len() > 4 {
+                break; }
+
+            if branch_condition(&ins).is_none() {
+                continue; }
+
+            let target = ins.get_jump_target().and_then(|addr| emu.expand_target(xa, addr));
+
+            if target != Some(graph.block(i).xa) {
+                break; }
+
+            if let Some((kind, len)) = match_wait(&instructions).filter(|(_, len)| *len == instructions.len()) {
+                result.push(WaitLoop { xa: graph.block(i).xa, len: len, kind: kind }); }
+
+            break;
+        }
+    }
+
+    result
+}
+
+pub fn is_wait_routine(graph: &FlowGraph, function: &Function, wait: &WaitLoop) -> bool
+{
+    // a function made of the loop and a return
+
+    if function.xa != wait.xa {
+        return false; }
+
+    let mut count = 0;
+
+    for &i in &function.blocks
+    {
+        let mut emu = graph.emu(i);
+
+        while let Some((_, Ok(ins))) = emu.next()
+        {
+            count += 1;
+
+            if count > wait.len && ins.opcode != OPCODE_RET {
+                return false; }
+        }
+    }
+
+    true
+}
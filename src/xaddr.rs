@@ -5,6 +5,7 @@
  */
 
 use std::ops::{Add, AddAssign};
+use thiserror::Error;
 
 #[derive(Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct XAddr
@@ -33,6 +34,22 @@ impl std::fmt::Display for XAddr
     }
 }
 
+impl std::str::FromStr for XAddr
+{
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        // `bank:addr` or `addr`, in hex
+
+        match s.split_once(':')
+        {
+            Some((str_bank, str_addr)) => Ok(XAddr::new(u16::from_str_radix(str_bank, 16)?, u16::from_str_radix(str_addr, 16)?)),
+            None => Ok(XAddr::new(0, u16::from_str_radix(s, 16)?)),
+        }
+    }
+}
+
 impl From<XAddr> for u16
 {
     fn from(xa: XAddr) -> u16
@@ -69,6 +86,92 @@ impl Add<XAddr> for u16
     }
 }
 
+// written as `bank:addr`, which keeps it usable as a map key in formats that only have string keys
+
+impl serde::Serialize for XAddr
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: serde::Serializer
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for XAddr
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: serde::Deserializer<'de>
+    {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(|_| serde::de::Error::custom(format!("invalid address `{}`", text)))
+    }
+}
+
+// inclusive, as it is written on the command line
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct XAddrRange
+{
+    pub beg: XAddr,
+    pub end: XAddr,
+}
+
+#[derive(Error, Debug)]
+pub enum ParseRangeError
+{
+    #[error("Parse Int error")]
+    ParseInt(#[from] std::num::ParseIntError),
+
+    #[error("Range `{0}` ends before it begins")]
+    Reversed(String),
+}
+
+impl XAddrRange
+{
+    pub fn contains(&self, xa: XAddr) -> bool
+    {
+        self.beg <= xa && xa <= self.end
+    }
+}
+
+impl std::str::FromStr for XAddrRange
+{
+    type Err = ParseRangeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        // `bank:addr-bank:addr`, `bank:addr-addr` (within the same bank), or a whole `bank`
+
+        let (beg, end) = match s.split_once('-')
+        {
+            Some((str_beg, str_end)) =>
+            {
+                let beg: XAddr = str_beg.parse()?;
+
+                let end = match str_end.contains(':')
+                {
+                    true => str_end.parse()?,
+                    false => XAddr::new(beg.bank, u16::from_str_radix(str_end, 16)?),
+                };
+
+                (beg, end)
+            }
+
+            None if !s.contains(':') =>
+            {
+                let bank = u16::from_str_radix(s, 16)?;
+                (XAddr::new(bank, 0), XAddr::new(bank, 0xFFFF))
+            }
+
+            None => { let xa = s.parse()?; (xa, xa) }
+        };
+
+        if end < beg {
+            return Err(ParseRangeError::Reversed(s.to_string())); }
+
+        Ok(Self { beg: beg, end: end })
+    }
+}
+
 pub mod prelude
 {
     pub use super::XAddr;